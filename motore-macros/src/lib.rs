@@ -4,7 +4,7 @@
 
 use proc_macro::TokenStream;
 use quote::quote;
-use syn::{parse_macro_input, parse_quote, spanned::Spanned, ItemImpl, PatType, Type};
+use syn::{parse_macro_input, parse_quote, spanned::Spanned, ItemImpl, PatType, Stmt, Type};
 
 /// This macro can help you to write a `Service` in a more efficient way.
 ///
@@ -124,19 +124,20 @@ fn expand(item: &mut ItemImpl) -> Result<(), syn::Error> {
             ))
         }
     };
+    // The `Send` bound of the future, with the `service_send` feature of motore, is
+    // checked against `Service::call`. The future only captures the arguments the
+    // body uses, unlike the one of an `async fn`.
     sig.asyncness = None;
-    // sig.generics.where_clause = Some(parse_quote!(where 's: 'cx));
-    #[cfg(feature = "service_send")]
-    {
-        sig.output = parse_quote!(-> impl ::std::future::Future<Output = Result<Self::Response, Self::Error>> + Send);
-    }
-    #[cfg(not(feature = "service_send"))]
-    {
-        sig.output = parse_quote!(-> impl ::std::future::Future<Output = Result<Self::Response, Self::Error>>);
-    }
+    sig.output =
+        parse_quote!(-> impl ::std::future::Future<Output = Result<Self::Response, Self::Error>>);
     sig.inputs[0] = parse_quote!(&self);
     let old_stmts = &call_method.block.stmts;
-    call_method.block.stmts = vec![parse_quote!(async move { #(#old_stmts)* })];
+    // Bound to a variable, so that clippy doesn't suggest turning the method back
+    // into an `async fn` in the code of the users.
+    call_method.block.stmts = vec![
+        parse_quote!(let fut = async move { #(#old_stmts)* };),
+        Stmt::Expr(parse_quote!(fut)),
+    ];
 
     item.items.push(parse_quote!(
         type Response = #res_ty;
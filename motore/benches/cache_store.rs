@@ -0,0 +1,56 @@
+//! Measures the throughput of the in-memory cache store under a mixed load of
+//! reads and writes from every available thread.
+//!
+//! Run with `cargo bench --bench cache_store`.
+
+use std::{
+    hint::black_box,
+    num::NonZeroUsize,
+    thread,
+    time::{Duration, Instant},
+};
+
+use futures::executor::block_on;
+use motore::cache::{CacheStore, InMemoryStore};
+
+const OPS: u64 = 1_000_000;
+const KEYS: u64 = 20_000;
+const CAPACITY: usize = 10_000;
+
+/// Runs [`OPS`] operations on `store` from each of `threads` threads, nine reads
+/// for a write, and prints the mean time per operation.
+fn bench(name: &str, store: &InMemoryStore<u64, u64>, threads: usize) {
+    let start = Instant::now();
+    thread::scope(|scope| {
+        for seed in 0..threads as u64 {
+            scope.spawn(move || {
+                // A xorshift generator, so the keys differ between the threads.
+                let mut x = seed * 0x9e37_79b9_7f4a_7c15 + 1;
+                for _ in 0..OPS {
+                    x ^= x << 13;
+                    x ^= x >> 7;
+                    x ^= x << 17;
+                    let key = x % KEYS;
+                    if x % 10 == 0 {
+                        block_on(store.put(key, x));
+                    } else {
+                        black_box(block_on(store.get(&key)));
+                    }
+                }
+            });
+        }
+    });
+    let ns = start.elapsed().as_nanos() as f64 / (OPS * threads as u64) as f64;
+    println!("{name:<16} {threads:>3} threads {ns:>8.2}ns per operation");
+}
+
+fn main() {
+    let threads = thread::available_parallelism().map_or(1, NonZeroUsize::get);
+    let ttl = Duration::from_secs(60);
+    bench(
+        "single shard",
+        &InMemoryStore::new(CAPACITY).ttl(ttl).shards(1),
+        threads,
+    );
+    bench("sharded", &InMemoryStore::new(CAPACITY).ttl(ttl), threads);
+}
@@ -0,0 +1,327 @@
+//! Writes a line to an access log for every call of a service.
+//!
+//! Once a call completes, [`AccessLog`] builds an [`AccessRecord`] of it, with its
+//! latency, its outcome and the fields extracted from its context and request
+//! before the call, like the method or the peer. An [`AccessLogFormat`] turns the
+//! record into a line, written to an [`AccessLogSink`], like a file, the standard
+//! output or a logging library. A call cancelled before completing is not logged.
+//!
+//! [`DefaultFormat`] writes the records in the `logfmt` style.
+
+use std::{
+    fmt,
+    task::{Context, Poll},
+    time::Duration,
+};
+
+use crate::{
+    layer::Layer,
+    load::{Load, Ready},
+    service::Service,
+    timer::{DefaultTimer, Timer},
+    MaybeSend, MaybeSync,
+};
+
+/// The record of a completed call.
+#[derive(Debug)]
+pub struct AccessRecord<'a, T, E> {
+    /// The fields extracted from the context and the request.
+    pub fields: T,
+    /// How long the call took.
+    pub latency: Duration,
+    /// The outcome of the call, with the error when it failed.
+    pub result: Result<(), &'a E>,
+}
+
+/// Turns the record of a call into a line of the access log.
+///
+/// This is implemented for closures taking the record, and by [`DefaultFormat`].
+pub trait AccessLogFormat<T, E> {
+    /// Returns the line of `record`, without a line break.
+    fn format(&self, record: &AccessRecord<'_, T, E>) -> String;
+}
+
+impl<F, T, E> AccessLogFormat<T, E> for F
+where
+    F: Fn(&AccessRecord<'_, T, E>) -> String,
+{
+    fn format(&self, record: &AccessRecord<'_, T, E>) -> String {
+        self(record)
+    }
+}
+
+/// The [`AccessLogFormat`] used when none is given, writing the fields, then the
+/// latency and the outcome in the `logfmt` style.
+///
+/// ```text
+/// method=echo latency=1.2ms outcome=ok
+/// method=echo latency=31µs outcome=error error="invalid request"
+/// ```
+#[derive(Clone, Copy, Debug, Default)]
+pub struct DefaultFormat;
+
+impl<T, E> AccessLogFormat<T, E> for DefaultFormat
+where
+    T: fmt::Display,
+    E: fmt::Display,
+{
+    fn format(&self, record: &AccessRecord<'_, T, E>) -> String {
+        let fields = record.fields.to_string();
+        let sep = if fields.is_empty() { "" } else { " " };
+        match record.result {
+            Ok(()) => format!("{fields}{sep}latency={:?} outcome=ok", record.latency),
+            Err(e) => format!(
+                "{fields}{sep}latency={:?} outcome=error error={:?}",
+                record.latency,
+                e.to_string()
+            ),
+        }
+    }
+}
+
+/// Where the lines of an access log are written.
+///
+/// This is implemented for closures taking the line.
+pub trait AccessLogSink {
+    /// Writes a line of the access log.
+    fn write(&self, line: String);
+}
+
+impl<F> AccessLogSink for F
+where
+    F: Fn(String),
+{
+    fn write(&self, line: String) {
+        self(line)
+    }
+}
+
+/// Writes a line to an access log for every completed call to the inner service.
+///
+/// The fields of a call are given by a closure taking the context and the request,
+/// `Fn(&Cx, &Req) -> T`. See the [module level docs](self) for details.
+#[derive(Clone)]
+pub struct AccessLog<S, F, W, Fmt = DefaultFormat> {
+    inner: S,
+    fields: F,
+    sink: W,
+    format: Fmt,
+}
+
+impl<S, F, W> AccessLog<S, F, W> {
+    /// Creates an `AccessLog` writing the calls to `sink`, with the fields returned
+    /// by `fields`, in the [`DefaultFormat`].
+    pub const fn new(inner: S, fields: F, sink: W) -> Self {
+        AccessLog {
+            inner,
+            fields,
+            sink,
+            format: DefaultFormat,
+        }
+    }
+}
+
+impl<Cx, Req, S, F, W, Fmt, T> Service<Cx, Req> for AccessLog<S, F, W, Fmt>
+where
+    Req: 'static + MaybeSend,
+    S: Service<Cx, Req> + 'static + MaybeSend + MaybeSync,
+    F: Fn(&Cx, &Req) -> T + MaybeSync,
+    T: MaybeSend,
+    W: AccessLogSink + MaybeSync,
+    Fmt: AccessLogFormat<T, S::Error> + MaybeSync,
+    Cx: 'static + MaybeSend,
+{
+    type Response = S::Response;
+
+    type Error = S::Error;
+
+    async fn call(&self, cx: &mut Cx, req: Req) -> Result<Self::Response, Self::Error> {
+        let fields = (self.fields)(cx, &req);
+        let timer = DefaultTimer::new();
+        let start = timer.now();
+        let res = self.inner.call(cx, req).await;
+        let record = AccessRecord {
+            fields,
+            latency: timer.now().saturating_duration_since(start),
+            result: res.as_ref().map(|_| ()),
+        };
+        self.sink.write(self.format.format(&record));
+        res
+    }
+}
+
+impl<S, F, W, Fmt> Ready for AccessLog<S, F, W, Fmt>
+where
+    S: Ready,
+{
+    fn poll_ready(&self, cx: &mut Context<'_>) -> Poll<()> {
+        self.inner.poll_ready(cx)
+    }
+}
+
+impl<S, F, W, Fmt> Load for AccessLog<S, F, W, Fmt>
+where
+    S: Load,
+{
+    type Metric = S::Metric;
+
+    fn load(&self) -> Self::Metric {
+        self.inner.load()
+    }
+}
+
+impl<S: fmt::Debug, F, W, Fmt> fmt::Debug for AccessLog<S, F, W, Fmt> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("AccessLog")
+            .field("inner", &self.inner)
+            .field("fields", &format_args!("{}", std::any::type_name::<F>()))
+            .field("sink", &format_args!("{}", std::any::type_name::<W>()))
+            .field("format", &format_args!("{}", std::any::type_name::<Fmt>()))
+            .finish()
+    }
+}
+
+/// Applies an [`AccessLog`] to a service.
+///
+/// # Example
+///
+/// ```rust
+/// use std::sync::{Arc, Mutex};
+///
+/// use motore::{
+///     access_log::AccessLogLayer, builder::ServiceBuilder, service::service_fn, BoxError,
+///     Service,
+/// };
+///
+/// struct Cx {
+///     method: &'static str,
+/// }
+///
+/// # #[tokio::main(flavor = "current_thread")]
+/// # async fn main() {
+/// async fn echo(_cx: &mut Cx, req: String) -> Result<String, BoxError> {
+///     if req.is_empty() {
+///         return Err("empty request".into());
+///     }
+///     Ok(req)
+/// }
+///
+/// let log = Arc::new(Mutex::new(Vec::new()));
+/// let svc = ServiceBuilder::new()
+///     .layer(AccessLogLayer::new(
+///         |cx: &Cx, req: &String| format!("method={} len={}", cx.method, req.len()),
+///         {
+///             let log = log.clone();
+///             move |line| log.lock().unwrap().push(line)
+///         },
+///     ))
+///     .service(service_fn(echo));
+///
+/// let mut cx = Cx { method: "echo" };
+/// svc.call(&mut cx, "ping".into()).await.unwrap();
+/// svc.call(&mut cx, "".into()).await.unwrap_err();
+///
+/// let log = log.lock().unwrap();
+/// assert!(log[0].starts_with("method=echo len=4 latency="));
+/// assert!(log[1].ends_with("outcome=error error=\"empty request\""));
+/// # }
+/// ```
+#[derive(Clone)]
+pub struct AccessLogLayer<F, W, Fmt = DefaultFormat> {
+    fields: F,
+    sink: W,
+    format: Fmt,
+}
+
+impl<F, W> AccessLogLayer<F, W> {
+    /// Creates a layer writing the calls to `sink`, with the fields returned by
+    /// `fields`, in the [`DefaultFormat`].
+    pub const fn new(fields: F, sink: W) -> Self {
+        AccessLogLayer {
+            fields,
+            sink,
+            format: DefaultFormat,
+        }
+    }
+}
+
+impl<F, W, Fmt> AccessLogLayer<F, W, Fmt> {
+    /// Sets the format of the lines.
+    pub fn format<G>(self, format: G) -> AccessLogLayer<F, W, G> {
+        AccessLogLayer {
+            fields: self.fields,
+            sink: self.sink,
+            format,
+        }
+    }
+}
+
+impl<F, W, Fmt> fmt::Debug for AccessLogLayer<F, W, Fmt> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("AccessLogLayer")
+            .field("fields", &format_args!("{}", std::any::type_name::<F>()))
+            .field("sink", &format_args!("{}", std::any::type_name::<W>()))
+            .field("format", &format_args!("{}", std::any::type_name::<Fmt>()))
+            .finish()
+    }
+}
+
+impl<S, F, W, Fmt> Layer<S> for AccessLogLayer<F, W, Fmt> {
+    type Service = AccessLog<S, F, W, Fmt>;
+
+    fn layer(self, inner: S) -> Self::Service {
+        AccessLog {
+            inner,
+            fields: self.fields,
+            sink: self.sink,
+            format: self.format,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{Arc, Mutex};
+
+    use super::*;
+    use crate::service::service_fn;
+
+    #[tokio::test(start_paused = true)]
+    async fn writes_a_line_per_call() {
+        let log = Arc::new(Mutex::new(Vec::new()));
+        let sink = {
+            let log = log.clone();
+            move |line| log.lock().unwrap().push(line)
+        };
+        let inner = service_fn(|_cx: &mut (), millis: u64| async move {
+            tokio::time::sleep(Duration::from_millis(millis)).await;
+            if millis == 0 {
+                return Err("too fast");
+            }
+            Ok(millis)
+        });
+
+        let svc = AccessLogLayer::new(|_cx: &(), req: &u64| format!("req={req}"), sink.clone())
+            .layer(inner);
+        svc.call(&mut (), 12).await.unwrap();
+        svc.call(&mut (), 0).await.unwrap_err();
+
+        let svc = AccessLogLayer::new(|_cx: &(), _req: &u64| (), sink)
+            .format(|record: &AccessRecord<'_, (), &str>| {
+                format!("{} {:?}", record.latency.as_millis(), record.result)
+            })
+            .layer(inner);
+        svc.call(&mut (), 5).await.unwrap();
+        svc.call(&mut (), 0).await.unwrap_err();
+
+        assert_eq!(
+            *log.lock().unwrap(),
+            [
+                "req=12 latency=12ms outcome=ok",
+                "req=0 latency=0ns outcome=error error=\"too fast\"",
+                "5 Ok(())",
+                "0 Err(\"too fast\")",
+            ]
+        );
+    }
+}
@@ -0,0 +1,228 @@
+//! Authorizes requests before they reach a service.
+//!
+//! The decision is made by a [`Policy`], which sees the context and the request and
+//! may deny it. Denied requests never reach the inner service, and the denial is
+//! returned as [`AuthorizeError::Denied`]. Protocols that answer denials with a
+//! response, like an HTTP 403, can convert the error in an outer layer.
+
+use std::{
+    error::Error,
+    fmt,
+    future::Future,
+    sync::Arc,
+    task::{Context, Poll},
+};
+
+use crate::{
+    layer::Layer,
+    load::{Load, Ready},
+    service::Service,
+    MaybeSend, MaybeSync,
+};
+
+/// Decides whether a request is allowed to reach a service.
+///
+/// # Example
+///
+/// ```rust
+/// use motore::auth::Policy;
+///
+/// struct Cx {
+///     user: Option<String>,
+/// }
+///
+/// struct Authenticated;
+///
+/// impl<Req: Sync> Policy<Cx, Req> for Authenticated {
+///     type Denial = &'static str;
+///
+///     async fn check(&self, cx: &Cx, _req: &Req) -> Result<(), Self::Denial> {
+///         match cx.user {
+///             Some(_) => Ok(()),
+///             None => Err("unauthenticated"),
+///         }
+///     }
+/// }
+/// ```
+pub trait Policy<Cx, Req> {
+    /// The reason a request was denied.
+    type Denial;
+
+    /// Checks whether the request is allowed.
+    #[cfg(feature = "service_send")]
+    fn check(&self, cx: &Cx, req: &Req) -> impl Future<Output = Result<(), Self::Denial>> + Send;
+    /// Checks whether the request is allowed.
+    #[cfg(not(feature = "service_send"))]
+    fn check(&self, cx: &Cx, req: &Req) -> impl Future<Output = Result<(), Self::Denial>>;
+}
+
+impl<Cx, Req, P> Policy<Cx, Req> for Arc<P>
+where
+    P: Policy<Cx, Req>,
+{
+    type Denial = P::Denial;
+
+    #[cfg(feature = "service_send")]
+    fn check(&self, cx: &Cx, req: &Req) -> impl Future<Output = Result<(), Self::Denial>> + Send {
+        (**self).check(cx, req)
+    }
+    #[cfg(not(feature = "service_send"))]
+    fn check(&self, cx: &Cx, req: &Req) -> impl Future<Output = Result<(), Self::Denial>> {
+        (**self).check(cx, req)
+    }
+}
+
+/// The error returned by [`Authorize`].
+#[derive(Debug, PartialEq, Eq)]
+pub enum AuthorizeError<D, E> {
+    /// The policy denied the request.
+    Denied(D),
+    /// The inner service failed.
+    Service(E),
+}
+
+impl<D: fmt::Display, E: fmt::Display> fmt::Display for AuthorizeError<D, E> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            AuthorizeError::Denied(d) => write!(f, "request denied: {d}"),
+            AuthorizeError::Service(e) => fmt::Display::fmt(e, f),
+        }
+    }
+}
+
+impl<D, E> Error for AuthorizeError<D, E>
+where
+    D: fmt::Debug + fmt::Display,
+    E: Error + 'static,
+{
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            AuthorizeError::Denied(_) => None,
+            AuthorizeError::Service(e) => Some(e),
+        }
+    }
+}
+
+/// Checks every request against a [`Policy`] before calling the inner service.
+#[derive(Clone)]
+pub struct Authorize<S, P> {
+    inner: S,
+    policy: P,
+}
+
+impl<S, P> Authorize<S, P> {
+    pub const fn new(inner: S, policy: P) -> Self {
+        Self { inner, policy }
+    }
+}
+
+impl<Cx, Req, S, P> Service<Cx, Req> for Authorize<S, P>
+where
+    Req: 'static + MaybeSend + MaybeSync,
+    S: Service<Cx, Req> + 'static + MaybeSend + MaybeSync,
+    P: Policy<Cx, Req> + MaybeSend + MaybeSync,
+    Cx: 'static + MaybeSend + MaybeSync,
+{
+    type Response = S::Response;
+
+    type Error = AuthorizeError<P::Denial, S::Error>;
+
+    async fn call(&self, cx: &mut Cx, req: Req) -> Result<Self::Response, Self::Error> {
+        self.policy
+            .check(cx, &req)
+            .await
+            .map_err(AuthorizeError::Denied)?;
+        self.inner
+            .call(cx, req)
+            .await
+            .map_err(AuthorizeError::Service)
+    }
+}
+
+impl<S, P> Ready for Authorize<S, P>
+where
+    S: Ready,
+{
+    fn poll_ready(&self, cx: &mut Context<'_>) -> Poll<()> {
+        self.inner.poll_ready(cx)
+    }
+}
+
+impl<S, P> Load for Authorize<S, P>
+where
+    S: Load,
+{
+    type Metric = S::Metric;
+
+    fn load(&self) -> Self::Metric {
+        self.inner.load()
+    }
+}
+
+/// Applies an [`Authorize`] middleware with the given policy.
+#[derive(Clone)]
+pub struct AuthorizeLayer<P> {
+    policy: P,
+}
+
+impl<P> AuthorizeLayer<P> {
+    pub const fn new(policy: P) -> Self {
+        AuthorizeLayer { policy }
+    }
+}
+
+impl<S, P> Layer<S> for AuthorizeLayer<P> {
+    type Service = Authorize<S, P>;
+
+    fn layer(self, inner: S) -> Self::Service {
+        Authorize {
+            inner,
+            policy: self.policy,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    use super::*;
+    use crate::service::service_fn;
+
+    struct Cx {
+        admin: bool,
+    }
+
+    struct AdminOnly;
+
+    impl Policy<Cx, &'static str> for AdminOnly {
+        type Denial = String;
+
+        async fn check(&self, cx: &Cx, req: &&'static str) -> Result<(), String> {
+            if cx.admin {
+                Ok(())
+            } else {
+                Err(format!("{req} requires admin"))
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn denied_requests_do_not_reach_the_service() {
+        let calls = Arc::new(AtomicUsize::new(0));
+        let svc = AuthorizeLayer::new(AdminOnly).layer(service_fn({
+            let calls = calls.clone();
+            move |_cx: &mut Cx, req: &'static str| {
+                calls.fetch_add(1, Ordering::SeqCst);
+                async move { Ok::<_, std::convert::Infallible>(req.len()) }
+            }
+        }));
+
+        assert_eq!(svc.call(&mut Cx { admin: true }, "drop").await, Ok(4));
+        assert_eq!(
+            svc.call(&mut Cx { admin: false }, "drop").await,
+            Err(AuthorizeError::Denied("drop requires admin".to_string()))
+        );
+        assert_eq!(calls.load(Ordering::SeqCst), 1);
+    }
+}
@@ -0,0 +1,320 @@
+//! Strategies for how long to wait between attempts.
+//!
+//! A [`Backoff`] yields the delay before each new attempt of an operation, like a
+//! retried request or a periodic task that keeps failing, and `None` once the
+//! operation should be given up. Strategies compose through [`BackoffExt`]:
+//!
+//! ```rust
+//! use std::time::Duration;
+//!
+//! use motore::backoff::{Backoff, BackoffExt, Exponential};
+//!
+//! let mut backoff = Exponential::new(Duration::from_millis(100), Duration::from_secs(10))
+//!     .full_jitter()
+//!     .max_attempts(5);
+//!
+//! let mut delays = 0;
+//! while let Some(delay) = backoff.next_backoff() {
+//!     assert!(delay <= Duration::from_secs(10));
+//!     delays += 1;
+//! }
+//! assert_eq!(delays, 5);
+//! ```
+
+use std::time::Duration;
+
+use crate::{
+    timer::{DefaultTimer, Instant, Timer},
+    utils::rng::Rng,
+};
+
+/// Yields the delays between the attempts of an operation.
+pub trait Backoff {
+    /// Returns how long to wait before the next attempt, or `None` to give up.
+    fn next_backoff(&mut self) -> Option<Duration>;
+
+    /// Starts over, after the operation succeeded.
+    fn reset(&mut self);
+}
+
+impl<B: Backoff + ?Sized> Backoff for Box<B> {
+    fn next_backoff(&mut self) -> Option<Duration> {
+        (**self).next_backoff()
+    }
+
+    fn reset(&mut self) {
+        (**self).reset()
+    }
+}
+
+/// An extension trait for [`Backoff`]s that provides jitter and limits.
+pub trait BackoffExt: Backoff + Sized {
+    /// Waits a random duration between zero and each delay, so that clients that
+    /// failed together don't retry in lockstep.
+    fn full_jitter(self) -> FullJitter<Self> {
+        FullJitter {
+            inner: self,
+            rng: Rng::new(),
+        }
+    }
+
+    /// Gives up after `attempts` delays.
+    fn max_attempts(self, attempts: usize) -> MaxAttempts<Self> {
+        MaxAttempts {
+            inner: self,
+            max: attempts,
+            attempts: 0,
+        }
+    }
+
+    /// Gives up once `elapsed` passed since the first delay.
+    fn max_elapsed(self, elapsed: Duration) -> MaxElapsed<Self> {
+        MaxElapsed {
+            inner: self,
+            max: elapsed,
+            start: None,
+            timer: DefaultTimer::new(),
+        }
+    }
+}
+
+impl<B: Backoff> BackoffExt for B {}
+
+/// A backoff doubling the delay after each attempt, up to a maximum.
+#[derive(Clone, Debug)]
+pub struct Exponential {
+    initial: Duration,
+    max: Duration,
+    factor: u32,
+    attempts: u32,
+}
+
+impl Exponential {
+    /// Creates a backoff waiting `initial`, then doubling the delay after each
+    /// attempt, up to `max`.
+    pub const fn new(initial: Duration, max: Duration) -> Self {
+        Exponential {
+            initial,
+            max,
+            factor: 2,
+            attempts: 0,
+        }
+    }
+
+    /// Multiplies the delay by `factor` instead of 2 after each attempt.
+    pub const fn factor(mut self, factor: u32) -> Self {
+        self.factor = factor;
+        self
+    }
+}
+
+impl Backoff for Exponential {
+    fn next_backoff(&mut self) -> Option<Duration> {
+        let multiplier = self.factor.saturating_pow(self.attempts);
+        self.attempts = self.attempts.saturating_add(1);
+        Some(self.initial.saturating_mul(multiplier).min(self.max))
+    }
+
+    fn reset(&mut self) {
+        self.attempts = 0;
+    }
+}
+
+/// A backoff where each delay is random, between a base delay and three times the
+/// previous delay, up to a maximum.
+///
+/// This is the "decorrelated jitter" strategy: it spreads clients out like
+/// [`FullJitter`], while keeping delays growing. A clone draws its own delays.
+#[derive(Debug)]
+pub struct Decorrelated {
+    base: Duration,
+    max: Duration,
+    prev: Duration,
+    rng: Rng,
+}
+
+impl Decorrelated {
+    /// Creates a backoff waiting at least `base` and at most `max`.
+    pub fn new(base: Duration, max: Duration) -> Self {
+        Decorrelated {
+            base,
+            max,
+            prev: base,
+            rng: Rng::new(),
+        }
+    }
+}
+
+impl Clone for Decorrelated {
+    fn clone(&self) -> Self {
+        // Reseeded, so that the clones handed to concurrent operations don't wait
+        // in lockstep.
+        Decorrelated {
+            base: self.base,
+            max: self.max,
+            prev: self.prev,
+            rng: Rng::new(),
+        }
+    }
+}
+
+impl Backoff for Decorrelated {
+    fn next_backoff(&mut self) -> Option<Duration> {
+        let upper = self.prev.saturating_mul(3).max(self.base);
+        let delay = self.base + (upper - self.base).mul_f64(self.rng.next_f64());
+        self.prev = delay.min(self.max);
+        Some(self.prev)
+    }
+
+    fn reset(&mut self) {
+        self.prev = self.base;
+    }
+}
+
+/// Backoff returned by [`BackoffExt::full_jitter`].
+///
+/// A clone draws its own delays.
+#[derive(Debug)]
+pub struct FullJitter<B> {
+    inner: B,
+    rng: Rng,
+}
+
+impl<B: Clone> Clone for FullJitter<B> {
+    fn clone(&self) -> Self {
+        // Reseeded, so that the clones handed to concurrent operations don't wait
+        // in lockstep.
+        FullJitter {
+            inner: self.inner.clone(),
+            rng: Rng::new(),
+        }
+    }
+}
+
+impl<B: Backoff> Backoff for FullJitter<B> {
+    fn next_backoff(&mut self) -> Option<Duration> {
+        let delay = self.inner.next_backoff()?;
+        Some(delay.mul_f64(self.rng.next_f64()))
+    }
+
+    fn reset(&mut self) {
+        self.inner.reset()
+    }
+}
+
+/// Backoff returned by [`BackoffExt::max_attempts`].
+#[derive(Clone, Debug)]
+pub struct MaxAttempts<B> {
+    inner: B,
+    max: usize,
+    attempts: usize,
+}
+
+impl<B: Backoff> Backoff for MaxAttempts<B> {
+    fn next_backoff(&mut self) -> Option<Duration> {
+        if self.attempts >= self.max {
+            return None;
+        }
+        self.attempts += 1;
+        self.inner.next_backoff()
+    }
+
+    fn reset(&mut self) {
+        self.attempts = 0;
+        self.inner.reset()
+    }
+}
+
+/// Backoff returned by [`BackoffExt::max_elapsed`].
+#[derive(Clone, Debug)]
+pub struct MaxElapsed<B, T = DefaultTimer> {
+    inner: B,
+    max: Duration,
+    start: Option<Instant>,
+    timer: T,
+}
+
+impl<B, T> MaxElapsed<B, T> {
+    /// Sets the timer measuring the elapsed time, [`DefaultTimer`] by default.
+    pub fn timer<U>(self, timer: U) -> MaxElapsed<B, U> {
+        MaxElapsed {
+            inner: self.inner,
+            max: self.max,
+            start: None,
+            timer,
+        }
+    }
+}
+
+impl<B: Backoff, T: Timer> Backoff for MaxElapsed<B, T> {
+    fn next_backoff(&mut self) -> Option<Duration> {
+        let now = self.timer.now();
+        let start = *self.start.get_or_insert(now);
+        let delay = self.inner.next_backoff()?;
+        // Give up when the next attempt would start past the deadline.
+        (now.saturating_duration_since(start) + delay <= self.max).then_some(delay)
+    }
+
+    fn reset(&mut self) {
+        self.start = None;
+        self.inner.reset()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ms(ms: u64) -> Duration {
+        Duration::from_millis(ms)
+    }
+
+    #[test]
+    fn exponential_doubles_up_to_the_max() {
+        let mut backoff = Exponential::new(ms(100), ms(500)).max_attempts(5);
+        let delays: Vec<_> = std::iter::from_fn(|| backoff.next_backoff()).collect();
+        assert_eq!(delays, [ms(100), ms(200), ms(400), ms(500), ms(500)]);
+
+        backoff.reset();
+        assert_eq!(backoff.next_backoff(), Some(ms(100)));
+    }
+
+    #[test]
+    fn jitter_stays_in_bounds() {
+        let mut full = Exponential::new(ms(100), ms(1000)).full_jitter();
+        let mut decorrelated = Decorrelated::new(ms(100), ms(1000));
+        for _ in 0..100 {
+            assert!(full.next_backoff().unwrap() <= ms(1000));
+            let delay = decorrelated.next_backoff().unwrap();
+            assert!(ms(100) <= delay && delay <= ms(1000));
+        }
+    }
+
+    #[test]
+    fn clones_draw_their_own_delays() {
+        fn delays(backoff: &mut impl Backoff) -> Vec<Duration> {
+            std::iter::from_fn(|| backoff.next_backoff())
+                .take(8)
+                .collect()
+        }
+
+        let full = Exponential::new(ms(100), ms(10_000)).full_jitter();
+        assert_ne!(delays(&mut full.clone()), delays(&mut full.clone()));
+        let decorrelated = Decorrelated::new(ms(100), ms(10_000));
+        assert_ne!(
+            delays(&mut decorrelated.clone()),
+            delays(&mut decorrelated.clone())
+        );
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn gives_up_past_the_max_elapsed_time() {
+        let mut backoff = Exponential::new(ms(100), ms(1000)).max_elapsed(ms(500));
+        let mut waited = Vec::new();
+        while let Some(delay) = backoff.next_backoff() {
+            tokio::time::sleep(delay).await;
+            waited.push(delay);
+        }
+        assert_eq!(waited, [ms(100), ms(200)]);
+    }
+}
@@ -0,0 +1,369 @@
+//! Active health checks of the discovered endpoints.
+//!
+//! [`HealthCheck`] wraps a [`Discover`] and probes every endpoint it discovers on
+//! a task of its own, calling a check service with the key of the endpoint, like
+//! its address, every `interval`. The services are discovered wrapped in a
+//! [`Checked`], which isn't [`Ready`] while its endpoint is unhealthy, so the
+//! balancers stop calling it until the probes succeed again.
+//!
+//! An endpoint is healthy when discovered. It becomes unhealthy after
+//! [`unhealthy_threshold`](HealthCheck::unhealthy_threshold) probes failed in a
+//! row, and healthy again after
+//! [`healthy_threshold`](HealthCheck::healthy_threshold) probes succeeded in a row.
+//! The probes of an endpoint stop once it is removed, or once the `HealthCheck` is
+//! dropped.
+
+use std::{
+    fmt,
+    pin::Pin,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    task::{Context, Poll},
+    time::Duration,
+};
+
+use futures::{task::AtomicWaker, Stream};
+use pin_project::pin_project;
+use tokio::task::JoinHandle;
+
+use super::spawn;
+use crate::{
+    discover::{Change, Discover},
+    load::{Load, Ready},
+    service::Service,
+    timer::{DefaultTimer, Timer},
+    MaybeSend, MaybeSync,
+};
+
+/// The health of an endpoint, shared by its [`Checked`] service and its probes.
+#[derive(Debug, Default)]
+struct Health {
+    unhealthy: AtomicBool,
+    waker: AtomicWaker,
+}
+
+impl Health {
+    fn set_healthy(&self, healthy: bool) {
+        self.unhealthy.store(!healthy, Ordering::Release);
+        if healthy {
+            self.waker.wake();
+        }
+    }
+}
+
+/// A discovered service, [`Ready`] only while its endpoint is healthy.
+pub struct Checked<S> {
+    inner: S,
+    health: Arc<Health>,
+}
+
+impl<S> Checked<S> {
+    /// Returns whether the last probes found the endpoint healthy.
+    pub fn is_healthy(&self) -> bool {
+        !self.health.unhealthy.load(Ordering::Acquire)
+    }
+
+    /// Returns the service.
+    pub fn get_ref(&self) -> &S {
+        &self.inner
+    }
+}
+
+impl<Cx, Req, S> Service<Cx, Req> for Checked<S>
+where
+    Req: 'static + MaybeSend,
+    S: Service<Cx, Req> + 'static + MaybeSend + MaybeSync,
+    Cx: 'static + MaybeSend,
+{
+    type Response = S::Response;
+
+    type Error = S::Error;
+
+    async fn call(&self, cx: &mut Cx, req: Req) -> Result<Self::Response, Self::Error> {
+        self.inner.call(cx, req).await
+    }
+}
+
+impl<S: Ready> Ready for Checked<S> {
+    fn poll_ready(&self, cx: &mut Context<'_>) -> Poll<()> {
+        self.health.waker.register(cx.waker());
+        if !self.is_healthy() {
+            return Poll::Pending;
+        }
+        self.inner.poll_ready(cx)
+    }
+}
+
+impl<S: Load> Load for Checked<S> {
+    type Metric = S::Metric;
+
+    fn load(&self) -> Self::Metric {
+        self.inner.load()
+    }
+}
+
+impl<S: fmt::Debug> fmt::Debug for Checked<S> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Checked")
+            .field("inner", &self.inner)
+            .field("healthy", &self.is_healthy())
+            .finish()
+    }
+}
+
+/// Aborts the probes of an endpoint once it is removed.
+struct AbortOnDrop(JoinHandle<()>);
+
+impl Drop for AbortOnDrop {
+    fn drop(&mut self) {
+        self.0.abort();
+    }
+}
+
+/// Probes the endpoints of the inner [`Discover`] with a check service, and
+/// discovers their services wrapped in a [`Checked`].
+///
+/// The check service is called with the key of the endpoint, `Service<(), K>`, and
+/// a probe succeeds when it returns `Ok`. It is usually wrapped in a
+/// [`Timeout`](crate::timeout::Timeout), so a probe left unanswered fails. The
+/// probes run on tasks spawned on the current runtime, or on the current
+/// [`LocalSet`](tokio::task::LocalSet) without the `service_send` feature. See the
+/// [module level docs](self) for details.
+///
+/// # Example
+///
+/// ```rust
+/// use std::time::Duration;
+///
+/// use motore::{
+///     balance::{health::HealthCheck, round_robin::Balance},
+///     discover::ServiceList,
+///     service::service_fn,
+///     BoxError, Service,
+/// };
+///
+/// # #[tokio::main(flavor = "current_thread")]
+/// # async fn main() {
+/// # tokio::task::LocalSet::new().run_until(async {
+/// async fn echo(_cx: &mut (), req: String) -> Result<String, BoxError> {
+///     Ok(req)
+/// }
+///
+/// // Pings the endpoint with the given index.
+/// async fn ping(_cx: &mut (), _endpoint: usize) -> Result<(), BoxError> {
+///     Ok(())
+/// }
+///
+/// let endpoints = ServiceList::new([service_fn(echo), service_fn(echo)]);
+/// let discover = HealthCheck::new(endpoints, service_fn(ping), Duration::from_secs(5))
+///     .unhealthy_threshold(3);
+/// let svc = Balance::new(discover);
+/// assert_eq!(svc.call(&mut (), "ping".into()).await.unwrap(), "ping");
+/// # }).await;
+/// # }
+/// ```
+#[pin_project]
+pub struct HealthCheck<D: Discover, C, T = DefaultTimer> {
+    #[pin]
+    discover: D,
+    check: Arc<C>,
+    interval: Duration,
+    unhealthy_threshold: u32,
+    healthy_threshold: u32,
+    timer: T,
+    probes: Vec<(D::Key, AbortOnDrop)>,
+}
+
+impl<D: Discover, C> HealthCheck<D, C> {
+    /// Probes the endpoints of `discover` with `check` every `interval`.
+    pub fn new(discover: D, check: C, interval: Duration) -> Self {
+        HealthCheck {
+            discover,
+            check: Arc::new(check),
+            interval,
+            unhealthy_threshold: 1,
+            healthy_threshold: 1,
+            timer: DefaultTimer::new(),
+            probes: Vec::new(),
+        }
+    }
+}
+
+impl<D: Discover, C, T> HealthCheck<D, C, T> {
+    /// Sets the number of probes failing in a row making an endpoint unhealthy, 1
+    /// by default.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `threshold` is zero.
+    pub fn unhealthy_threshold(mut self, threshold: u32) -> Self {
+        assert!(threshold > 0, "the unhealthy threshold must be positive");
+        self.unhealthy_threshold = threshold;
+        self
+    }
+
+    /// Sets the number of probes succeeding in a row making an endpoint healthy
+    /// again, 1 by default.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `threshold` is zero.
+    pub fn healthy_threshold(mut self, threshold: u32) -> Self {
+        assert!(threshold > 0, "the healthy threshold must be positive");
+        self.healthy_threshold = threshold;
+        self
+    }
+
+    /// Sets the timer spacing the probes, [`DefaultTimer`] by default.
+    pub fn timer<U>(self, timer: U) -> HealthCheck<D, C, U> {
+        HealthCheck {
+            discover: self.discover,
+            check: self.check,
+            interval: self.interval,
+            unhealthy_threshold: self.unhealthy_threshold,
+            healthy_threshold: self.healthy_threshold,
+            timer,
+            probes: self.probes,
+        }
+    }
+}
+
+/// Probes an endpoint forever, updating its health.
+async fn probe<C, K, T>(
+    check: Arc<C>,
+    key: K,
+    health: Arc<Health>,
+    interval: Duration,
+    thresholds: (u32, u32),
+    timer: T,
+) where
+    C: Service<(), K>,
+    K: Clone,
+    T: Timer,
+{
+    let (unhealthy_threshold, healthy_threshold) = thresholds;
+    let (mut failures, mut successes) = (0, 0);
+    loop {
+        if check.call(&mut (), key.clone()).await.is_ok() {
+            failures = 0;
+            successes += 1;
+            if successes == healthy_threshold {
+                health.set_healthy(true);
+            }
+        } else {
+            successes = 0;
+            failures += 1;
+            if failures == unhealthy_threshold {
+                health.set_healthy(false);
+            }
+        }
+        timer.sleep(interval).await;
+    }
+}
+
+impl<D, C, T> Stream for HealthCheck<D, C, T>
+where
+    D: Discover,
+    D::Key: Clone + MaybeSend + MaybeSync + 'static,
+    C: Service<(), D::Key> + MaybeSend + MaybeSync + 'static,
+    T: Timer + Clone + MaybeSend + MaybeSync + 'static,
+{
+    type Item = Result<Change<D::Key, Checked<D::Service>>, D::Error>;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let this = self.project();
+        let change = match futures::ready!(this.discover.poll_discover(cx)) {
+            Some(Ok(change)) => change,
+            Some(Err(e)) => return Poll::Ready(Some(Err(e))),
+            None => return Poll::Ready(None),
+        };
+        // Replacing or removing an endpoint stops its probes.
+        this.probes.retain(|(key, _)| key != change.key());
+        Poll::Ready(Some(Ok(match change {
+            Change::Insert(key, inner) => {
+                let health = Arc::new(Health::default());
+                let task = probe(
+                    this.check.clone(),
+                    key.clone(),
+                    health.clone(),
+                    *this.interval,
+                    (*this.unhealthy_threshold, *this.healthy_threshold),
+                    this.timer.clone(),
+                );
+                this.probes.push((key.clone(), AbortOnDrop(spawn(task))));
+                Change::Insert(key, Checked { inner, health })
+            }
+            Change::Remove(key) => Change::Remove(key),
+        })))
+    }
+}
+
+impl<D: Discover, C, T> fmt::Debug for HealthCheck<D, C, T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("HealthCheck")
+            .field("interval", &self.interval)
+            .field("unhealthy_threshold", &self.unhealthy_threshold)
+            .field("healthy_threshold", &self.healthy_threshold)
+            .field("endpoints", &self.probes.len())
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::convert::Infallible;
+
+    use super::*;
+    use crate::{balance::round_robin::Balance, discover::ServiceList, service::service_fn};
+
+    #[tokio::test(start_paused = true)]
+    async fn skips_the_unhealthy_endpoints() {
+        tokio::task::LocalSet::new()
+            .run_until(async {
+                let down = Arc::new(AtomicBool::new(false));
+                let check = service_fn({
+                    let down = down.clone();
+                    move |_cx: &mut (), endpoint: usize| {
+                        let up = endpoint == 0 || !down.load(Ordering::Relaxed);
+                        async move {
+                            if up {
+                                Ok(())
+                            } else {
+                                Err("down")
+                            }
+                        }
+                    }
+                });
+                let endpoints = ServiceList::new([0, 1].map(|id| {
+                    service_fn(move |_cx: &mut (), _req: ()| async move { Ok::<_, Infallible>(id) })
+                }));
+                let svc = Balance::new(
+                    HealthCheck::new(endpoints, check, Duration::from_secs(1)).healthy_threshold(2),
+                );
+                let calls = || async {
+                    let mut ids = Vec::new();
+                    for _ in 0..4 {
+                        ids.push(svc.call(&mut (), ()).await.unwrap());
+                    }
+                    ids.sort();
+                    ids
+                };
+
+                assert_eq!(calls().await, [0, 0, 1, 1]);
+
+                down.store(true, Ordering::Relaxed);
+                tokio::time::sleep(Duration::from_millis(1500)).await;
+                assert_eq!(calls().await, [0, 0, 0, 0]);
+
+                // Healthy again after two probes.
+                down.store(false, Ordering::Relaxed);
+                tokio::time::sleep(Duration::from_secs(1)).await;
+                assert_eq!(calls().await, [0, 0, 0, 0]);
+                tokio::time::sleep(Duration::from_secs(1)).await;
+                assert_eq!(calls().await, [0, 0, 1, 1]);
+            })
+            .await;
+    }
+}
@@ -0,0 +1,272 @@
+//! Load balancers, spreading the requests across the endpoints of a [`Discover`].
+//!
+//! A balancer is a [`Service`](crate::Service) built from a [`Discover`] of the
+//! services of the endpoints, rather than a layer: each call picks one of the
+//! endpoints discovered so far and calls it. The changes of the discovery are
+//! applied as the calls are made, so endpoints can come and go while the balancer
+//! is in use.
+//!
+//! The balancers only pick the endpoints [`Ready`] for calls, tracked in a
+//! [`ReadyCache`], so an endpoint slow to connect doesn't hold back the others. A
+//! call made while no endpoint is ready waits for one, and fails with
+//! [`NoEndpoints`] if the discovery ends without any endpoint. Wrapping the
+//! discovery in a [`HealthCheck`](health::HealthCheck) keeps the balancers off the
+//! endpoints failing their health checks, and in an
+//! [`OutlierDetection`](outlier::OutlierDetection) off the endpoints failing the
+//! requests they are sent.
+//!
+//! - [`p2c::Balance`] calls the less loaded of two random endpoints, by their
+//!   [`Load`](crate::load::Load).
+//! - [`round_robin::Balance`] calls the endpoints one after the other.
+//! - [`weighted::Balance`] calls the endpoints in proportion to the weights they
+//!   were discovered with.
+//!
+//! [`pool::Pool`] balances across services to a single target instead, making more
+//! of them as its load grows.
+
+use std::{
+    fmt,
+    future::Future,
+    pin::Pin,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, Mutex, MutexGuard,
+    },
+    task::{Context, Poll},
+};
+
+use futures::task::{self, ArcWake};
+use tokio::task::JoinHandle;
+
+use crate::{
+    discover::{Change, Discover},
+    load::Ready,
+    utils::{arc_cell::ArcCell, waiters::Waiters},
+    BoxError,
+};
+
+pub mod health;
+pub mod outlier;
+pub mod p2c;
+pub mod pool;
+mod ready_cache;
+pub mod round_robin;
+pub mod weighted;
+pub use self::ready_cache::ReadyCache;
+
+/// The error returned by a balancer when its discovery ended without any
+/// endpoint.
+///
+/// It is boxed into a [`BoxError`], and can be recognized with
+/// `err.is::<NoEndpoints>()`.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct NoEndpoints;
+
+impl fmt::Display for NoEndpoints {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("no endpoint to balance across")
+    }
+}
+
+impl std::error::Error for NoEndpoints {}
+
+/// Spawns a background task of the balancer middlewares, on the current
+/// [`LocalSet`](tokio::task::LocalSet) without the `service_send` feature.
+#[cfg(feature = "service_send")]
+fn spawn(task: impl Future<Output = ()> + Send + 'static) -> JoinHandle<()> {
+    tokio::spawn(task)
+}
+
+/// Spawns a background task of the balancer middlewares, on the current
+/// [`LocalSet`](tokio::task::LocalSet) without the `service_send` feature.
+#[cfg(not(feature = "service_send"))]
+fn spawn(task: impl Future<Output = ()> + 'static) -> JoinHandle<()> {
+    tokio::task::spawn_local(task)
+}
+
+/// The endpoints discovered so far by a [`Discover`], shared by the balancers.
+///
+/// The calls pick their endpoint from a snapshot of the ready endpoints, swapped
+/// in whenever the discovery or the readiness of an endpoint changes. The
+/// discovery and the pending endpoints are polled with the waker of [`Changes`],
+/// so the calls only take the lock of the endpoints to apply the changes once
+/// they were signalled, and every call waiting for an endpoint is woken whichever
+/// call polled them last.
+struct Endpoints<D: Discover> {
+    state: Mutex<State<D>>,
+    snapshot: ArcCell<Snapshot<D::Service>>,
+    changes: Arc<Changes>,
+}
+
+/// Signals the changes of the discovery or of the readiness of the endpoints.
+struct Changes {
+    pending: AtomicBool,
+    waiters: Waiters,
+}
+
+impl ArcWake for Changes {
+    fn wake_by_ref(arc_self: &Arc<Self>) {
+        arc_self.pending.store(true, Ordering::Release);
+        arc_self.waiters.wake_all();
+    }
+}
+
+struct State<D: Discover> {
+    // `None` once the discovery ended.
+    discover: Option<Pin<Box<D>>>,
+    services: ReadyCache<D::Key, Arc<D::Service>>,
+}
+
+/// The endpoints as of the last changes applied.
+struct Snapshot<S> {
+    ready: Vec<Arc<S>>,
+    len: usize,
+    ended: bool,
+}
+
+impl<D: Discover> Endpoints<D> {
+    fn new(discover: D) -> Self {
+        Endpoints {
+            state: Mutex::new(State {
+                discover: Some(Box::pin(discover)),
+                services: ReadyCache::new(),
+            }),
+            snapshot: ArcCell::new(Snapshot {
+                ready: Vec::new(),
+                len: 0,
+                ended: false,
+            }),
+            // The discovery was never polled.
+            changes: Arc::new(Changes {
+                pending: AtomicBool::new(true),
+                waiters: Waiters::default(),
+            }),
+        }
+    }
+
+    fn lock(&self) -> MutexGuard<'_, State<D>> {
+        self.state.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    fn snapshot(&self) -> Arc<Snapshot<D::Service>> {
+        self.snapshot.load()
+    }
+
+    /// Returns the number of endpoints discovered so far, ready or not.
+    fn len(&self) -> usize {
+        self.snapshot().len
+    }
+
+    /// Applies the changes of the discovery and of the readiness of the endpoints
+    /// to `state`, and publishes them if `changed` or if there were any.
+    fn update(&self, mut state: MutexGuard<'_, State<D>>, changed: bool) -> Result<(), BoxError>
+    where
+        D::Service: Ready,
+        D::Error: Into<BoxError>,
+    {
+        // Cleared before polling, so that the changes signalled meanwhile are
+        // applied by the next call.
+        self.changes.pending.store(false, Ordering::Release);
+        let waker = task::waker(self.changes.clone());
+        let mut cx = Context::from_waker(&waker);
+        let changed = state.poll_discover(&mut cx)? || changed;
+        let ready_len = state.services.ready_len();
+        let _ = state.services.poll_pending(&mut cx);
+        if changed || state.services.ready_len() != ready_len {
+            let snapshot = Snapshot {
+                ready: state
+                    .services
+                    .iter_ready()
+                    .map(|(_, svc)| svc.clone())
+                    .collect(),
+                len: state.services.len(),
+                ended: state.discover.is_none(),
+            };
+            self.snapshot.store(snapshot);
+            drop(state);
+            self.changes.waiters.wake_all();
+        }
+        Ok(())
+    }
+
+    /// Returns a ready endpoint, at the index returned by `pick` among the ready
+    /// ones, once the changes discovered since the last call are applied.
+    ///
+    /// Waits for an endpoint to be ready if there is none, and picks again if the
+    /// picked endpoint is no longer ready.
+    async fn pick<F>(&self, mut pick: F) -> Result<Arc<D::Service>, BoxError>
+    where
+        D::Service: Ready,
+        D::Error: Into<BoxError>,
+        F: FnMut(&[Arc<D::Service>]) -> usize,
+    {
+        futures::future::poll_fn(|cx| {
+            if self.changes.pending.load(Ordering::Acquire) {
+                self.update(self.lock(), false)?;
+            }
+            loop {
+                let snapshot = self.snapshot();
+                if snapshot.ready.is_empty() {
+                    // Registered before the changes are polled, so that the call is
+                    // woken by the next ones.
+                    self.changes.waiters.register(cx.waker());
+                    self.update(self.lock(), false)?;
+                    let snapshot = self.snapshot();
+                    if !snapshot.ready.is_empty() {
+                        continue;
+                    }
+                    return if snapshot.ended && snapshot.len == 0 {
+                        Poll::Ready(Err(NoEndpoints.into()))
+                    } else {
+                        Poll::Pending
+                    };
+                }
+                let svc = &snapshot.ready[pick(&snapshot.ready)];
+                if svc.poll_ready(cx).is_ready() {
+                    return Poll::Ready(Ok(svc.clone()));
+                }
+                // Moves the endpoint back to the pending ones, unless another call
+                // already did.
+                let mut state = self.lock();
+                let index = state
+                    .services
+                    .iter_ready()
+                    .position(|(_, ready)| Arc::ptr_eq(ready, svc));
+                let changed = index.is_some_and(|index| {
+                    let waker = task::waker(self.changes.clone());
+                    !state
+                        .services
+                        .check_ready_index(&mut Context::from_waker(&waker), index)
+                });
+                self.update(state, changed)?;
+            }
+        })
+        .await
+    }
+}
+
+impl<D: Discover> State<D> {
+    /// Applies the changes discovered since the last call, returning whether there
+    /// were any.
+    fn poll_discover(&mut self, cx: &mut Context<'_>) -> Result<bool, BoxError>
+    where
+        D::Error: Into<BoxError>,
+    {
+        let mut changed = false;
+        while let Some(discover) = self.discover.as_mut() {
+            match discover.as_mut().poll_discover(cx) {
+                Poll::Ready(Some(Ok(Change::Insert(key, svc)))) => {
+                    self.services.push(key, Arc::new(svc))
+                }
+                Poll::Ready(Some(Ok(Change::Remove(key)))) => {
+                    self.services.evict(&key);
+                }
+                Poll::Ready(Some(Err(e))) => return Err(e.into()),
+                Poll::Ready(None) => self.discover = None,
+                Poll::Pending => break,
+            }
+            changed = true;
+        }
+        Ok(changed)
+    }
+}
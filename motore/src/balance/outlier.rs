@@ -0,0 +1,470 @@
+//! Passive outlier detection, from the requests sent to the endpoints.
+//!
+//! [`OutlierDetection`] wraps a [`Discover`], and discovers its services wrapped in
+//! an [`Ejectable`], which counts the calls failing in a row. Once an endpoint
+//! failed [`consecutive_failures`](OutlierDetection::consecutive_failures) calls in
+//! a row, it is ejected: it isn't [`Ready`] for a delay given by a [`Backoff`], so
+//! the balancers stop calling it, then returns to the rotation.
+//!
+//! The delays of an endpoint grow with the ejections following each other, and
+//! start over once it served a call again. At most
+//! [`max_ejected_ratio`](OutlierDetection::max_ejected_ratio) of the endpoints are
+//! ejected at the same time, so that a failure of the backend as a whole doesn't
+//! empty the balancer.
+//!
+//! Unlike a [`HealthCheck`](super::health::HealthCheck), it doesn't send any
+//! request of its own, and only notices the failures of the endpoints called. The
+//! two are usually combined.
+
+use std::{
+    fmt,
+    pin::Pin,
+    sync::{Arc, Mutex, Weak},
+    task::{Context, Poll},
+    time::Duration,
+};
+
+use futures::{task::AtomicWaker, Stream};
+use pin_project::pin_project;
+
+use super::spawn;
+use crate::{
+    backoff::Backoff,
+    discover::{Change, Discover},
+    load::{Load, Ready},
+    service::Service,
+    timer::{DefaultTimer, Timer},
+    MaybeSend, MaybeSync,
+};
+
+/// The number of endpoints and ejected endpoints of an [`OutlierDetection`].
+#[derive(Debug, Default)]
+struct Ejections {
+    endpoints: usize,
+    ejected: usize,
+}
+
+#[derive(Debug)]
+struct State<B> {
+    failures: u32,
+    ejected: bool,
+    backoff: B,
+}
+
+/// The failures of an endpoint, shared by its [`Ejectable`] service and the task
+/// returning it to the rotation.
+struct Outlier<B> {
+    state: Mutex<State<B>>,
+    waker: AtomicWaker,
+    ejections: Arc<Mutex<Ejections>>,
+    consecutive_failures: u32,
+    max_ejected_ratio: f64,
+}
+
+impl<B: Backoff> Outlier<B> {
+    fn new(
+        backoff: B,
+        ejections: Arc<Mutex<Ejections>>,
+        consecutive_failures: u32,
+        max_ejected_ratio: f64,
+    ) -> Self {
+        ejections
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .endpoints += 1;
+        Outlier {
+            state: Mutex::new(State {
+                failures: 0,
+                ejected: false,
+                backoff,
+            }),
+            waker: AtomicWaker::new(),
+            ejections,
+            consecutive_failures,
+            max_ejected_ratio,
+        }
+    }
+
+    fn is_ejected(&self) -> bool {
+        self.state.lock().unwrap_or_else(|e| e.into_inner()).ejected
+    }
+
+    /// Records the outcome of a call, returning how long to eject the endpoint
+    /// for, if it should be.
+    fn record(&self, success: bool) -> Option<Duration> {
+        let mut state = self.state.lock().unwrap_or_else(|e| e.into_inner());
+        if success {
+            state.failures = 0;
+            state.backoff.reset();
+            return None;
+        }
+        state.failures = state.failures.saturating_add(1);
+        if state.ejected || state.failures < self.consecutive_failures {
+            return None;
+        }
+        let mut ejections = self.ejections.lock().unwrap_or_else(|e| e.into_inner());
+        let max_ejected = (ejections.endpoints as f64 * self.max_ejected_ratio) as usize;
+        if ejections.ejected >= max_ejected {
+            return None;
+        }
+        let delay = state.backoff.next_backoff()?;
+        ejections.ejected += 1;
+        state.ejected = true;
+        state.failures = 0;
+        Some(delay)
+    }
+
+    /// Returns the endpoint to the rotation.
+    fn restore(&self) {
+        let mut state = self.state.lock().unwrap_or_else(|e| e.into_inner());
+        if std::mem::replace(&mut state.ejected, false) {
+            self.ejections
+                .lock()
+                .unwrap_or_else(|e| e.into_inner())
+                .ejected -= 1;
+        }
+        drop(state);
+        self.waker.wake();
+    }
+}
+
+impl<B> Drop for Outlier<B> {
+    fn drop(&mut self) {
+        let ejected = self
+            .state
+            .get_mut()
+            .unwrap_or_else(|e| e.into_inner())
+            .ejected;
+        let mut ejections = self.ejections.lock().unwrap_or_else(|e| e.into_inner());
+        ejections.endpoints -= 1;
+        if ejected {
+            ejections.ejected -= 1;
+        }
+    }
+}
+
+/// A discovered service, ejected from the rotation once its calls fail in a row.
+///
+/// It isn't [`Ready`] while ejected. See the [module level docs](self) for details.
+pub struct Ejectable<S, B, T = DefaultTimer> {
+    inner: S,
+    outlier: Arc<Outlier<B>>,
+    timer: T,
+}
+
+impl<S, B: Backoff, T> Ejectable<S, B, T> {
+    /// Returns whether the endpoint is ejected.
+    pub fn is_ejected(&self) -> bool {
+        self.outlier.is_ejected()
+    }
+
+    /// Returns the service.
+    pub fn get_ref(&self) -> &S {
+        &self.inner
+    }
+}
+
+impl<Cx, Req, S, B, T> Service<Cx, Req> for Ejectable<S, B, T>
+where
+    Req: 'static + MaybeSend,
+    S: Service<Cx, Req> + 'static + MaybeSend + MaybeSync,
+    B: Backoff + MaybeSend + 'static,
+    T: Timer + Clone + MaybeSend + MaybeSync + 'static,
+    Cx: 'static + MaybeSend,
+{
+    type Response = S::Response;
+
+    type Error = S::Error;
+
+    async fn call(&self, cx: &mut Cx, req: Req) -> Result<Self::Response, Self::Error> {
+        let res = self.inner.call(cx, req).await;
+        if let Some(delay) = self.outlier.record(res.is_ok()) {
+            let outlier = Arc::downgrade(&self.outlier);
+            let timer = self.timer.clone();
+            spawn(restore_after(outlier, timer, delay));
+        }
+        res
+    }
+}
+
+/// Returns an ejected endpoint to the rotation once `delay` elapsed, unless it was
+/// removed meanwhile.
+async fn restore_after<B: Backoff, T: Timer>(outlier: Weak<Outlier<B>>, timer: T, delay: Duration) {
+    timer.sleep(delay).await;
+    if let Some(outlier) = outlier.upgrade() {
+        outlier.restore();
+    }
+}
+
+impl<S: Ready, B: Backoff, T> Ready for Ejectable<S, B, T> {
+    fn poll_ready(&self, cx: &mut Context<'_>) -> Poll<()> {
+        self.outlier.waker.register(cx.waker());
+        if self.is_ejected() {
+            return Poll::Pending;
+        }
+        self.inner.poll_ready(cx)
+    }
+}
+
+impl<S: Load, B, T> Load for Ejectable<S, B, T> {
+    type Metric = S::Metric;
+
+    fn load(&self) -> Self::Metric {
+        self.inner.load()
+    }
+}
+
+impl<S: fmt::Debug, B: Backoff, T> fmt::Debug for Ejectable<S, B, T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Ejectable")
+            .field("inner", &self.inner)
+            .field("ejected", &self.is_ejected())
+            .finish()
+    }
+}
+
+/// Ejects the endpoints of the inner [`Discover`] failing their calls in a row,
+/// discovering their services wrapped in an [`Ejectable`].
+///
+/// Every endpoint gets a clone of the [`Backoff`], giving the delays it is ejected
+/// for; once it gives up, the endpoint stays in the rotation. Ejected endpoints
+/// return to the rotation from tasks spawned on the current runtime, or on the
+/// current [`LocalSet`](tokio::task::LocalSet) without the `service_send` feature.
+/// See the [module level docs](self) for details.
+///
+/// # Example
+///
+/// ```rust
+/// use std::time::Duration;
+///
+/// use motore::{
+///     backoff::Exponential,
+///     balance::{outlier::OutlierDetection, round_robin::Balance},
+///     discover::ServiceList,
+///     service::service_fn,
+///     BoxError, Service,
+/// };
+///
+/// # #[tokio::main(flavor = "current_thread")]
+/// # async fn main() {
+/// async fn echo(_cx: &mut (), req: String) -> Result<String, BoxError> {
+///     Ok(req)
+/// }
+///
+/// let endpoints = ServiceList::new([service_fn(echo), service_fn(echo)]);
+/// // Ejected for 30s, then 1min, 2min... up to 5min.
+/// let backoff = Exponential::new(Duration::from_secs(30), Duration::from_secs(300));
+/// let discover = OutlierDetection::new(endpoints, backoff).consecutive_failures(3);
+/// let svc = Balance::new(discover);
+/// assert_eq!(svc.call(&mut (), "ping".into()).await.unwrap(), "ping");
+/// # }
+/// ```
+#[pin_project]
+pub struct OutlierDetection<D, B, T = DefaultTimer> {
+    #[pin]
+    discover: D,
+    backoff: B,
+    consecutive_failures: u32,
+    max_ejected_ratio: f64,
+    timer: T,
+    ejections: Arc<Mutex<Ejections>>,
+}
+
+impl<D, B> OutlierDetection<D, B> {
+    /// Ejects the endpoints of `discover` for the delays of `backoff`.
+    pub fn new(discover: D, backoff: B) -> Self {
+        OutlierDetection {
+            discover,
+            backoff,
+            consecutive_failures: 5,
+            max_ejected_ratio: 0.5,
+            timer: DefaultTimer::new(),
+            ejections: Default::default(),
+        }
+    }
+}
+
+impl<D, B, T> OutlierDetection<D, B, T> {
+    /// Sets the number of calls failing in a row ejecting an endpoint, 5 by
+    /// default.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `failures` is zero.
+    pub fn consecutive_failures(mut self, failures: u32) -> Self {
+        assert!(failures > 0, "the consecutive failures must be positive");
+        self.consecutive_failures = failures;
+        self
+    }
+
+    /// Sets the largest share of the endpoints ejected at the same time, rounded
+    /// down, 0.5 by default.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `ratio` isn't between 0 and 1.
+    pub fn max_ejected_ratio(mut self, ratio: f64) -> Self {
+        assert!(
+            (0.0..=1.0).contains(&ratio),
+            "the max ejected ratio must be between 0 and 1"
+        );
+        self.max_ejected_ratio = ratio;
+        self
+    }
+
+    /// Sets the timer of the ejections, [`DefaultTimer`] by default.
+    pub fn timer<U>(self, timer: U) -> OutlierDetection<D, B, U> {
+        OutlierDetection {
+            discover: self.discover,
+            backoff: self.backoff,
+            consecutive_failures: self.consecutive_failures,
+            max_ejected_ratio: self.max_ejected_ratio,
+            timer,
+            ejections: self.ejections,
+        }
+    }
+
+    /// Returns the number of endpoints currently ejected.
+    pub fn ejected(&self) -> usize {
+        self.ejections
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .ejected
+    }
+}
+
+impl<D, B, T> Stream for OutlierDetection<D, B, T>
+where
+    D: Discover,
+    B: Backoff + Clone,
+    T: Clone,
+{
+    type Item = Result<Change<D::Key, Ejectable<D::Service, B, T>>, D::Error>;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let this = self.project();
+        let change = match futures::ready!(this.discover.poll_discover(cx)) {
+            Some(Ok(change)) => change,
+            Some(Err(e)) => return Poll::Ready(Some(Err(e))),
+            None => return Poll::Ready(None),
+        };
+        Poll::Ready(Some(Ok(match change {
+            Change::Insert(key, inner) => {
+                let outlier = Outlier::new(
+                    this.backoff.clone(),
+                    this.ejections.clone(),
+                    *this.consecutive_failures,
+                    *this.max_ejected_ratio,
+                );
+                Change::Insert(
+                    key,
+                    Ejectable {
+                        inner,
+                        outlier: Arc::new(outlier),
+                        timer: this.timer.clone(),
+                    },
+                )
+            }
+            Change::Remove(key) => Change::Remove(key),
+        })))
+    }
+}
+
+impl<D, B, T> fmt::Debug for OutlierDetection<D, B, T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let ejections = self.ejections.lock().unwrap_or_else(|e| e.into_inner());
+        f.debug_struct("OutlierDetection")
+            .field("consecutive_failures", &self.consecutive_failures)
+            .field("max_ejected_ratio", &self.max_ejected_ratio)
+            .field("endpoints", &ejections.endpoints)
+            .field("ejected", &ejections.ejected)
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicBool, Ordering};
+
+    use super::*;
+    use crate::{
+        backoff::Exponential, balance::round_robin::Balance, discover::ServiceList,
+        service::service_fn, BoxError,
+    };
+
+    /// Two endpoints answering their index, unless set down.
+    fn endpoints(
+        down: &Arc<[AtomicBool; 2]>,
+    ) -> ServiceList<impl Service<(), (), Response = usize, Error = &'static str> + Ready> {
+        ServiceList::new([0, 1].map(|id| {
+            let down = down.clone();
+            service_fn(move |_cx: &mut (), _req: ()| {
+                let up = !down[id].load(Ordering::Relaxed);
+                async move {
+                    if up {
+                        Ok(id)
+                    } else {
+                        Err("down")
+                    }
+                }
+            })
+        }))
+    }
+
+    async fn calls(
+        svc: &impl Service<(), (), Response = usize, Error = BoxError>,
+    ) -> Vec<Option<usize>> {
+        let mut ids = Vec::new();
+        for _ in 0..4 {
+            ids.push(svc.call(&mut (), ()).await.ok());
+        }
+        ids.sort();
+        ids
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn ejects_the_failing_endpoints() {
+        tokio::task::LocalSet::new()
+            .run_until(async {
+                let down = Arc::new([AtomicBool::new(false), AtomicBool::new(true)]);
+                let backoff = Exponential::new(Duration::from_secs(10), Duration::from_secs(60));
+                let svc = Balance::new(
+                    OutlierDetection::new(endpoints(&down), backoff).consecutive_failures(2),
+                );
+
+                assert_eq!(calls(&svc).await, [None, None, Some(0), Some(0)]);
+                assert_eq!(calls(&svc).await, [Some(0); 4]);
+
+                // Back after the delay, then ejected twice as long.
+                tokio::time::sleep(Duration::from_secs(11)).await;
+                assert_eq!(calls(&svc).await, [None, None, Some(0), Some(0)]);
+                tokio::time::sleep(Duration::from_secs(10)).await;
+                assert_eq!(calls(&svc).await, [Some(0); 4]);
+
+                down[1].store(false, Ordering::Relaxed);
+                tokio::time::sleep(Duration::from_secs(11)).await;
+                assert_eq!(calls(&svc).await, [Some(0), Some(0), Some(1), Some(1)]);
+            })
+            .await;
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn caps_the_ejected_endpoints() {
+        tokio::task::LocalSet::new()
+            .run_until(async {
+                let down = Arc::new([AtomicBool::new(true), AtomicBool::new(true)]);
+                let backoff = Exponential::new(Duration::from_secs(10), Duration::from_secs(60));
+                let svc = Balance::new(
+                    OutlierDetection::new(endpoints(&down), backoff).consecutive_failures(1),
+                );
+
+                // Only one of the two endpoints is ejected.
+                assert_eq!(calls(&svc).await, [None; 4]);
+                down[0].store(false, Ordering::Relaxed);
+                down[1].store(false, Ordering::Relaxed);
+                let ids = calls(&svc).await;
+                assert!(ids == [Some(0); 4] || ids == [Some(1); 4]);
+            })
+            .await;
+    }
+}
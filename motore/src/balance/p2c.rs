@@ -0,0 +1,169 @@
+//! The power of two choices balancer.
+
+use std::{fmt, sync::Mutex};
+
+use super::Endpoints;
+use crate::{
+    discover::Discover,
+    load::{Load, Ready},
+    service::Service,
+    utils::rng::Rng,
+    BoxError, MaybeSend, MaybeSync,
+};
+
+/// Calls the less loaded of two ready endpoints picked at random.
+///
+/// Comparing two random endpoints avoids both the herding on the least loaded
+/// endpoint of comparing them all, and the cost of doing so, while steering the
+/// calls away from the loaded endpoints almost as well. The services are usually
+/// wrapped in a [`PendingRequests`](crate::load::PendingRequests) or a
+/// [`PeakEwma`](crate::load::PeakEwma) to measure their load. See the [module
+/// level docs](super) for details.
+///
+/// # Example
+///
+/// ```rust
+/// use motore::{
+///     balance::p2c::Balance, discover::ServiceList, load::PendingRequests, service::service_fn,
+///     BoxError, Service,
+/// };
+///
+/// # #[tokio::main(flavor = "current_thread")]
+/// # async fn main() {
+/// async fn echo(_cx: &mut (), req: String) -> Result<String, BoxError> {
+///     Ok(req)
+/// }
+///
+/// let endpoints = ServiceList::new([
+///     PendingRequests::new(service_fn(echo)),
+///     PendingRequests::new(service_fn(echo)),
+/// ]);
+/// let svc = Balance::new(endpoints);
+/// assert_eq!(svc.call(&mut (), "ping".into()).await.unwrap(), "ping");
+/// # }
+/// ```
+pub struct Balance<D: Discover> {
+    endpoints: Endpoints<D>,
+    rng: Mutex<Rng>,
+}
+
+impl<D: Discover> Balance<D> {
+    /// Creates a balancer across the endpoints of `discover`.
+    pub fn new(discover: D) -> Self {
+        Balance {
+            endpoints: Endpoints::new(discover),
+            rng: Mutex::new(Rng::new()),
+        }
+    }
+
+    /// Returns the number of endpoints discovered so far.
+    pub fn len(&self) -> usize {
+        self.endpoints.len()
+    }
+
+    /// Returns whether no endpoint was discovered so far.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Returns the indices of two distinct endpoints out of `n`, at least two.
+    fn pick_two(&self, n: usize) -> (usize, usize) {
+        let mut rng = self.rng.lock().unwrap_or_else(|e| e.into_inner());
+        let a = rng.below(n as u64) as usize;
+        let mut b = rng.below(n as u64 - 1) as usize;
+        if b >= a {
+            b += 1;
+        }
+        (a, b)
+    }
+}
+
+impl<Cx, Req, D> Service<Cx, Req> for Balance<D>
+where
+    Req: 'static + MaybeSend,
+    D: Discover + MaybeSend,
+    D::Key: MaybeSend + MaybeSync,
+    D::Service: Service<Cx, Req> + Load + Ready + 'static + MaybeSend + MaybeSync,
+    D::Error: Into<BoxError>,
+    <D::Service as Service<Cx, Req>>::Error: Into<BoxError>,
+    Cx: 'static + MaybeSend,
+{
+    type Response = <D::Service as Service<Cx, Req>>::Response;
+
+    type Error = BoxError;
+
+    async fn call(&self, cx: &mut Cx, req: Req) -> Result<Self::Response, Self::Error> {
+        let svc = self
+            .endpoints
+            .pick(|services| {
+                let n = services.len();
+                if n == 1 {
+                    return 0;
+                }
+                let (a, b) = self.pick_two(n);
+                let load = |index: usize| services[index].load();
+                if load(b) < load(a) {
+                    b
+                } else {
+                    a
+                }
+            })
+            .await?;
+        svc.call(cx, req).await.map_err(Into::into)
+    }
+}
+
+impl<D: Discover> fmt::Debug for Balance<D> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Balance")
+            .field("endpoints", &self.len())
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{convert::Infallible, time::Duration};
+
+    use futures::stream;
+
+    use super::*;
+    use crate::{
+        balance::NoEndpoints,
+        discover::{Change, ServiceList},
+        load::PendingRequests,
+        service::service_fn,
+    };
+
+    fn endpoint(
+        id: usize,
+    ) -> PendingRequests<impl Service<(), (), Response = usize, Error = Infallible> + Ready> {
+        PendingRequests::new(service_fn(move |_cx: &mut (), _req: ()| async move {
+            tokio::time::sleep(Duration::from_millis(10)).await;
+            Ok::<_, Infallible>(id)
+        }))
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn calls_the_less_loaded_endpoint() {
+        let svc = Balance::new(ServiceList::new([endpoint(0), endpoint(1)]));
+        let (a, b) = tokio::join!(async { svc.call(&mut (), ()).await }, async {
+            svc.call(&mut (), ()).await
+        });
+        let mut ids = [a.unwrap(), b.unwrap()];
+        ids.sort();
+        assert_eq!(ids, [0, 1]);
+        assert_eq!(svc.len(), 2);
+    }
+
+    #[tokio::test]
+    async fn fails_without_endpoints() {
+        let discover = stream::iter([
+            Ok::<_, Infallible>(Change::Insert("a", endpoint(0))),
+            Ok(Change::Remove("a")),
+        ]);
+        let svc = Balance::new(discover);
+        let err = svc.call(&mut (), ()).await.unwrap_err();
+        assert!(err.is::<NoEndpoints>());
+    }
+}
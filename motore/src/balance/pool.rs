@@ -0,0 +1,393 @@
+//! A pool of services to a single target, sized by its load.
+//!
+//! Some clients call their backend through services that can only handle a few
+//! calls at a time, like a service per HTTP/1 connection. [`Pool`] makes such
+//! services on demand with a maker, a [`UnaryService`] turning a target into a
+//! service, and spreads the calls across them like the [`p2c`](super::p2c)
+//! balancer.
+//!
+//! The pool keeps an exponentially weighted moving average of the calls in flight
+//! per service, sampled on every call. Above
+//! [`loaded_above`](Pool::loaded_above), the pool makes one more service,
+//! concurrently with the call, up to [`max_services`](Pool::max_services). Below
+//! [`underutilized_below`](Pool::underutilized_below), it drops its least loaded
+//! service, down to [`min_services`](Pool::min_services); the calls in flight on
+//! that service still complete. [`urgency`](Pool::urgency) sets how quickly the
+//! average follows the samples.
+
+use std::{fmt, sync::Arc};
+
+use crate::{
+    load::{Load, PendingRequests},
+    service::Service,
+    utils::{
+        rng::Rng,
+        sync::{Mutex, MutexGuard},
+    },
+    BoxError, MaybeSend, MaybeSync, UnaryService,
+};
+
+struct State<S> {
+    services: Vec<Arc<PendingRequests<S>>>,
+    // The average of the calls in flight per service.
+    level: f64,
+    making: bool,
+}
+
+/// Makes services to a target on demand, and spreads the calls across them.
+///
+/// See the [module level docs](self) for details.
+///
+/// # Example
+///
+/// ```rust
+/// use std::future::Future;
+///
+/// use motore::{balance::pool::Pool, BoxError, Service, UnaryService};
+///
+/// // A service over a connection, handling a call at a time.
+/// struct Connection;
+///
+/// impl Service<(), String> for Connection {
+///     type Response = String;
+///     type Error = BoxError;
+///
+///     async fn call(&self, _cx: &mut (), req: String) -> Result<String, BoxError> {
+///         Ok(req)
+///     }
+/// }
+///
+/// // Connects to an address.
+/// struct Connector;
+///
+/// impl UnaryService<&'static str> for Connector {
+///     type Response = Connection;
+///     type Error = BoxError;
+///
+///     # #[cfg(feature = "service_send")]
+///     fn call(
+///         &self,
+///         _addr: &'static str,
+///     ) -> impl Future<Output = Result<Connection, BoxError>> + Send {
+///         async { Ok(Connection) }
+///     }
+///     # #[cfg(not(feature = "service_send"))]
+///     # fn call(
+///     #     &self,
+///     #     _addr: &'static str,
+///     # ) -> impl Future<Output = Result<Connection, BoxError>> {
+///     #     async { Ok(Connection) }
+///     # }
+/// }
+///
+/// # #[tokio::main(flavor = "current_thread")]
+/// # async fn main() {
+/// let svc = Pool::new(Connector, "127.0.0.1:8080").max_services(16);
+/// assert_eq!(svc.call(&mut (), "ping".into()).await.unwrap(), "ping");
+/// assert_eq!(svc.len(), 1);
+/// # }
+/// ```
+pub struct Pool<M: UnaryService<Target>, Target> {
+    make: M,
+    target: Target,
+    min_services: usize,
+    max_services: usize,
+    loaded_above: f64,
+    underutilized_below: f64,
+    urgency: f64,
+    state: Mutex<State<M::Response>>,
+    rng: Mutex<Rng>,
+}
+
+impl<M: UnaryService<Target>, Target> Pool<M, Target> {
+    /// Creates a pool of the services made by `make` to `target`.
+    ///
+    /// It holds between 1 and an unbounded number of services, grows above 0.5
+    /// calls in flight per service on average, and shrinks below 0.01, with an
+    /// urgency of 0.01.
+    pub fn new(make: M, target: Target) -> Self {
+        let (loaded_above, underutilized_below) = (0.5, 0.01);
+        Pool {
+            make,
+            target,
+            min_services: 1,
+            max_services: usize::MAX,
+            loaded_above,
+            underutilized_below,
+            urgency: 0.01,
+            state: Mutex::new(State {
+                services: Vec::new(),
+                level: (loaded_above + underutilized_below) / 2.0,
+                making: false,
+            }),
+            rng: Mutex::new(Rng::new()),
+        }
+    }
+
+    /// Sets the number of services the pool doesn't shrink below, 1 by default.
+    ///
+    /// The services are made one per call, so the pool only reaches it once called.
+    pub fn min_services(mut self, min: usize) -> Self {
+        self.min_services = min;
+        self
+    }
+
+    /// Sets the number of services the pool doesn't grow above, unbounded by
+    /// default.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `max` is zero.
+    pub fn max_services(mut self, max: usize) -> Self {
+        assert!(max > 0, "the max services must be positive");
+        self.max_services = max;
+        self
+    }
+
+    /// Sets the average of calls in flight per service above which the pool
+    /// grows, 0.5 by default.
+    pub fn loaded_above(mut self, level: f64) -> Self {
+        self.loaded_above = level;
+        self.reset_level();
+        self
+    }
+
+    /// Sets the average of calls in flight per service below which the pool
+    /// shrinks, 0.01 by default.
+    pub fn underutilized_below(mut self, level: f64) -> Self {
+        self.underutilized_below = level;
+        self.reset_level();
+        self
+    }
+
+    /// Sets the weight of every sample in the average, between 0 and 1, 0.01 by
+    /// default.
+    ///
+    /// The higher, the quicker the pool resizes after a change of its load.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `urgency` isn't between 0 and 1.
+    pub fn urgency(mut self, urgency: f64) -> Self {
+        assert!(
+            (0.0..=1.0).contains(&urgency),
+            "the urgency must be between 0 and 1"
+        );
+        self.urgency = urgency;
+        self
+    }
+
+    /// Returns the number of services in the pool.
+    pub fn len(&self) -> usize {
+        self.lock().services.len()
+    }
+
+    /// Returns whether the pool holds no service, before its first call.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    fn lock(&self) -> MutexGuard<'_, State<M::Response>> {
+        self.state.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// Returns the average the pool starts over from after resizing, between the
+    /// thresholds.
+    fn initial_level(&self) -> f64 {
+        (self.loaded_above + self.underutilized_below) / 2.0
+    }
+
+    fn reset_level(&mut self) {
+        let level = self.initial_level();
+        self.state
+            .get_mut()
+            .unwrap_or_else(|e| e.into_inner())
+            .level = level;
+    }
+
+    /// Samples the load, resizes the pool if needed, and picks the less loaded of
+    /// two random services.
+    ///
+    /// Returns whether the pool should grow, along with the service.
+    fn pick(&self) -> (Option<Arc<PendingRequests<M::Response>>>, bool) {
+        let mut state = self.lock();
+        let n = state.services.len();
+        if n > 0 {
+            let in_flight: usize = state.services.iter().map(|svc| svc.load()).sum();
+            let sample = in_flight as f64 / n as f64;
+            state.level += self.urgency * (sample - state.level);
+        }
+
+        let grow = n == 0
+            || (!state.making
+                && n < self.max_services
+                && (n < self.min_services || state.level > self.loaded_above));
+        if grow {
+            state.making = true;
+        } else if n > self.min_services && state.level < self.underutilized_below {
+            let (index, _) = state
+                .services
+                .iter()
+                .enumerate()
+                .min_by_key(|(_, svc)| svc.load())
+                .unwrap();
+            state.services.swap_remove(index);
+            state.level = self.initial_level();
+        }
+
+        let svc = match state.services.len() {
+            0 => None,
+            1 => Some(state.services[0].clone()),
+            n => {
+                let mut rng = self.rng.lock().unwrap_or_else(|e| e.into_inner());
+                let a = rng.below(n as u64) as usize;
+                let mut b = rng.below(n as u64 - 1) as usize;
+                if b >= a {
+                    b += 1;
+                }
+                let (a, b) = (&state.services[a], &state.services[b]);
+                Some(if b.load() < a.load() { b } else { a }.clone())
+            }
+        };
+        (svc, grow)
+    }
+}
+
+impl<M, Target> Pool<M, Target>
+where
+    M: UnaryService<Target>,
+    M::Error: Into<BoxError>,
+    Target: Clone,
+{
+    /// Makes a service and adds it to the pool.
+    async fn grow(&self) -> Result<Arc<PendingRequests<M::Response>>, BoxError> {
+        let res = self.make.call(self.target.clone()).await;
+        let mut state = self.lock();
+        state.making = false;
+        let svc = Arc::new(PendingRequests::new(res.map_err(Into::into)?));
+        state.services.push(svc.clone());
+        state.level = self.initial_level();
+        Ok(svc)
+    }
+}
+
+impl<Cx, Req, M, Target> Service<Cx, Req> for Pool<M, Target>
+where
+    Req: 'static + MaybeSend,
+    M: UnaryService<Target> + MaybeSend + MaybeSync,
+    M::Response: Service<Cx, Req> + 'static + MaybeSend + MaybeSync,
+    M::Error: Into<BoxError>,
+    <M::Response as Service<Cx, Req>>::Response: MaybeSend,
+    <M::Response as Service<Cx, Req>>::Error: MaybeSend + Into<BoxError>,
+    Target: Clone + MaybeSend + MaybeSync,
+    Cx: 'static + MaybeSend,
+{
+    type Response = <M::Response as Service<Cx, Req>>::Response;
+
+    type Error = BoxError;
+
+    async fn call(&self, cx: &mut Cx, req: Req) -> Result<Self::Response, Self::Error> {
+        match self.pick() {
+            (Some(svc), false) => svc.call(cx, req).await.map_err(Into::into),
+            // Growing doesn't hold back the call, and a service failing to be made
+            // is retried by the next calls.
+            (Some(svc), true) => {
+                let (_, res) = futures::join!(self.grow(), svc.call(cx, req));
+                res.map_err(Into::into)
+            }
+            (None, _) => {
+                let svc = self.grow().await?;
+                svc.call(cx, req).await.map_err(Into::into)
+            }
+        }
+    }
+}
+
+impl<M: UnaryService<Target>, Target: fmt::Debug> fmt::Debug for Pool<M, Target> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let state = self.lock();
+        f.debug_struct("Pool")
+            .field("target", &self.target)
+            .field("services", &state.services.len())
+            .field("level", &state.level)
+            .field("min_services", &self.min_services)
+            .field("max_services", &self.max_services)
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        convert::Infallible,
+        future::Future,
+        sync::atomic::{AtomicUsize, Ordering},
+        time::Duration,
+    };
+
+    use super::*;
+
+    /// A service answering its index after 10ms.
+    struct Endpoint(usize);
+
+    impl Service<(), ()> for Endpoint {
+        type Response = usize;
+
+        type Error = Infallible;
+
+        async fn call(&self, _cx: &mut (), _req: ()) -> Result<usize, Infallible> {
+            tokio::time::sleep(Duration::from_millis(10)).await;
+            Ok(self.0)
+        }
+    }
+
+    #[derive(Default)]
+    struct Maker {
+        made: AtomicUsize,
+    }
+
+    impl Maker {
+        fn make(&self) -> std::future::Ready<Result<Endpoint, Infallible>> {
+            std::future::ready(Ok(Endpoint(self.made.fetch_add(1, Ordering::Relaxed))))
+        }
+    }
+
+    impl UnaryService<()> for Maker {
+        type Response = Endpoint;
+
+        type Error = Infallible;
+
+        #[cfg(feature = "service_send")]
+        fn call(&self, _req: ()) -> impl Future<Output = Result<Endpoint, Infallible>> + Send {
+            self.make()
+        }
+
+        #[cfg(not(feature = "service_send"))]
+        fn call(&self, _req: ()) -> impl Future<Output = Result<Endpoint, Infallible>> {
+            self.make()
+        }
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn resizes_with_the_load() {
+        let svc = Pool::new(Maker::default(), ()).max_services(3).urgency(1.0);
+        assert!(svc.is_empty());
+
+        // Every call waiting on another one grows the pool.
+        let (a, b, c) = tokio::join!(
+            async { svc.call(&mut (), ()).await },
+            async { svc.call(&mut (), ()).await },
+            async { svc.call(&mut (), ()).await },
+        );
+        assert_eq!(a.unwrap(), 0);
+        assert_eq!(b.unwrap(), 0);
+        assert_eq!(c.unwrap(), 1);
+        assert_eq!(svc.len(), 3);
+
+        // Calls one at a time shrink it back.
+        for _ in 0..3 {
+            svc.call(&mut (), ()).await.unwrap();
+        }
+        assert_eq!(svc.len(), 1);
+    }
+}
@@ -0,0 +1,219 @@
+use std::{
+    fmt,
+    task::{Context, Poll},
+};
+
+use crate::load::Ready;
+
+/// A set of keyed services, split between the ones [`Ready`] for calls and the
+/// ones still pending, like endpoints still connecting.
+///
+/// The pending services are polled together by
+/// [`poll_pending`](Self::poll_pending), and move to the ready set as soon as
+/// they are ready, so an endpoint slow to set up doesn't hold back the others.
+/// The ready services are addressed by index, for a balancer to pick one in
+/// constant time, and go back to the pending set when a check finds them no
+/// longer ready.
+///
+/// # Example
+///
+/// ```rust
+/// use std::task::Context;
+///
+/// use futures::task::noop_waker_ref;
+/// use motore::{balance::ReadyCache, service::service_fn, BoxError};
+///
+/// let mut cache = ReadyCache::new();
+/// cache.push("a", service_fn(|_cx: &mut (), req: u32| async move { Ok::<_, BoxError>(req) }));
+/// assert_eq!((cache.ready_len(), cache.pending_len()), (0, 1));
+///
+/// let mut cx = Context::from_waker(noop_waker_ref());
+/// assert!(cache.poll_pending(&mut cx).is_ready());
+/// assert_eq!(cache.get_ready_index(0).map(|(key, _)| *key), Some("a"));
+/// ```
+pub struct ReadyCache<K, S> {
+    ready: Vec<(K, S)>,
+    pending: Vec<(K, S)>,
+}
+
+impl<K, S> ReadyCache<K, S> {
+    /// Creates an empty cache.
+    pub const fn new() -> Self {
+        ReadyCache {
+            ready: Vec::new(),
+            pending: Vec::new(),
+        }
+    }
+
+    /// Returns the number of services, ready or pending.
+    pub fn len(&self) -> usize {
+        self.ready.len() + self.pending.len()
+    }
+
+    /// Returns whether the cache holds no service.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Returns the number of ready services.
+    pub fn ready_len(&self) -> usize {
+        self.ready.len()
+    }
+
+    /// Returns the number of pending services.
+    pub fn pending_len(&self) -> usize {
+        self.pending.len()
+    }
+
+    /// Returns the ready service at `index`, with its key.
+    pub fn get_ready_index(&self, index: usize) -> Option<(&K, &S)> {
+        self.ready.get(index).map(|(key, svc)| (key, svc))
+    }
+
+    /// Returns the ready services, with their keys, in index order.
+    pub fn iter_ready(&self) -> impl Iterator<Item = (&K, &S)> {
+        self.ready.iter().map(|(key, svc)| (key, svc))
+    }
+}
+
+impl<K: Eq, S> ReadyCache<K, S> {
+    /// Adds a pending service, replacing the one under the same key.
+    pub fn push(&mut self, key: K, svc: S) {
+        self.evict(&key);
+        self.pending.push((key, svc));
+    }
+
+    /// Removes the service under `key`, returning whether there was one.
+    ///
+    /// This shifts the index of the ready services after it.
+    pub fn evict(&mut self, key: &K) -> bool {
+        for services in [&mut self.ready, &mut self.pending] {
+            if let Some(index) = services.iter().position(|(k, _)| k == key) {
+                services.remove(index);
+                return true;
+            }
+        }
+        false
+    }
+
+    /// Returns the index of the ready service under `key`.
+    pub fn ready_index(&self, key: &K) -> Option<usize> {
+        self.ready.iter().position(|(k, _)| k == key)
+    }
+}
+
+impl<K, S: Ready> ReadyCache<K, S> {
+    /// Polls the pending services, moving the ready ones to the ready set.
+    ///
+    /// Returns `Poll::Ready` once no service is pending. Otherwise, the current task
+    /// is woken once a pending service may have become ready.
+    pub fn poll_pending(&mut self, cx: &mut Context<'_>) -> Poll<()> {
+        for (key, svc) in std::mem::take(&mut self.pending) {
+            if svc.poll_ready(cx).is_ready() {
+                self.ready.push((key, svc));
+            } else {
+                self.pending.push((key, svc));
+            }
+        }
+        if self.pending.is_empty() {
+            Poll::Ready(())
+        } else {
+            Poll::Pending
+        }
+    }
+
+    /// Checks that the ready service at `index` is still ready, moving it to the
+    /// pending set otherwise.
+    ///
+    /// Moving it changes the index of the last ready service.
+    pub fn check_ready_index(&mut self, cx: &mut Context<'_>, index: usize) -> bool {
+        if self.ready[index].1.poll_ready(cx).is_ready() {
+            return true;
+        }
+        let service = self.ready.swap_remove(index);
+        self.pending.push(service);
+        false
+    }
+}
+
+impl<K, S> Default for ReadyCache<K, S> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<K: fmt::Debug, S> fmt::Debug for ReadyCache<K, S> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ReadyCache")
+            .field(
+                "ready",
+                &self.ready.iter().map(|(k, _)| k).collect::<Vec<_>>(),
+            )
+            .field(
+                "pending",
+                &self.pending.iter().map(|(k, _)| k).collect::<Vec<_>>(),
+            )
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    };
+
+    use futures::task::noop_waker_ref;
+
+    use super::*;
+
+    #[derive(Clone, Default)]
+    struct Toggle(Arc<AtomicBool>);
+
+    impl Toggle {
+        fn set(&self, ready: bool) {
+            self.0.store(ready, Ordering::Relaxed);
+        }
+    }
+
+    impl Ready for Toggle {
+        fn poll_ready(&self, _cx: &mut Context<'_>) -> Poll<()> {
+            if self.0.load(Ordering::Relaxed) {
+                Poll::Ready(())
+            } else {
+                Poll::Pending
+            }
+        }
+    }
+
+    #[test]
+    fn moves_the_services_between_the_sets() {
+        let mut cx = Context::from_waker(noop_waker_ref());
+        let (a, b) = (Toggle::default(), Toggle::default());
+        let mut cache = ReadyCache::new();
+        cache.push("a", a.clone());
+        cache.push("b", b.clone());
+        assert!(cache.poll_pending(&mut cx).is_pending());
+        assert_eq!(cache.ready_len(), 0);
+
+        // One slow endpoint doesn't hold back the other.
+        b.set(true);
+        assert!(cache.poll_pending(&mut cx).is_pending());
+        assert_eq!(cache.get_ready_index(0).map(|(k, _)| *k), Some("b"));
+
+        a.set(true);
+        assert!(cache.poll_pending(&mut cx).is_ready());
+        assert_eq!(cache.ready_len(), 2);
+
+        b.set(false);
+        let index = cache.ready_index(&"b").unwrap();
+        assert!(!cache.check_ready_index(&mut cx, index));
+        assert_eq!((cache.ready_len(), cache.pending_len()), (1, 1));
+
+        assert!(cache.evict(&"b"));
+        assert!(!cache.evict(&"b"));
+        cache.push("a", Toggle::default());
+        assert_eq!((cache.ready_len(), cache.pending_len()), (0, 1));
+    }
+}
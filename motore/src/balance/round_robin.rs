@@ -0,0 +1,162 @@
+//! The round-robin balancer.
+
+use std::{
+    fmt,
+    sync::atomic::{AtomicUsize, Ordering},
+};
+
+use super::Endpoints;
+use crate::{discover::Discover, load::Ready, service::Service, BoxError, MaybeSend, MaybeSync};
+
+/// Calls the ready endpoints one after the other.
+///
+/// An endpoint inserted, removed, or no longer ready changes the order of the
+/// others, so a few of them may be called twice in a row, or skipped once, after
+/// a change. See the [module
+/// level docs](super) for details.
+///
+/// # Example
+///
+/// ```rust
+/// use motore::{
+///     balance::round_robin::Balance, discover::ServiceList, service::service_fn, BoxError,
+///     Service,
+/// };
+///
+/// # #[tokio::main(flavor = "current_thread")]
+/// # async fn main() {
+/// let endpoints = ServiceList::new(["a", "b"].map(|name| {
+///     service_fn(move |_cx: &mut (), _req: ()| async move { Ok::<_, BoxError>(name) })
+/// }));
+/// let svc = Balance::new(endpoints);
+///
+/// assert_eq!(svc.call(&mut (), ()).await.unwrap(), "a");
+/// assert_eq!(svc.call(&mut (), ()).await.unwrap(), "b");
+/// assert_eq!(svc.call(&mut (), ()).await.unwrap(), "a");
+/// # }
+/// ```
+pub struct Balance<D: Discover> {
+    endpoints: Endpoints<D>,
+    next: AtomicUsize,
+}
+
+impl<D: Discover> Balance<D> {
+    /// Creates a balancer across the endpoints of `discover`.
+    pub fn new(discover: D) -> Self {
+        Balance {
+            endpoints: Endpoints::new(discover),
+            next: AtomicUsize::new(0),
+        }
+    }
+
+    /// Returns the number of endpoints discovered so far.
+    pub fn len(&self) -> usize {
+        self.endpoints.len()
+    }
+
+    /// Returns whether no endpoint was discovered so far.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+impl<Cx, Req, D> Service<Cx, Req> for Balance<D>
+where
+    Req: 'static + MaybeSend,
+    D: Discover + MaybeSend,
+    D::Key: MaybeSend + MaybeSync,
+    D::Service: Service<Cx, Req> + Ready + 'static + MaybeSend + MaybeSync,
+    D::Error: Into<BoxError>,
+    <D::Service as Service<Cx, Req>>::Error: Into<BoxError>,
+    Cx: 'static + MaybeSend,
+{
+    type Response = <D::Service as Service<Cx, Req>>::Response;
+
+    type Error = BoxError;
+
+    async fn call(&self, cx: &mut Cx, req: Req) -> Result<Self::Response, Self::Error> {
+        let svc = self
+            .endpoints
+            .pick(|services| self.next.fetch_add(1, Ordering::Relaxed) % services.len())
+            .await?;
+        svc.call(cx, req).await.map_err(Into::into)
+    }
+}
+
+impl<D: Discover> fmt::Debug for Balance<D> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Balance")
+            .field("endpoints", &self.len())
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{convert::Infallible, rc::Rc, time::Duration};
+
+    use futures::{channel::mpsc, SinkExt};
+
+    use super::*;
+    use crate::{
+        discover::{Change, WatchDiscover},
+        service::service_fn,
+    };
+
+    fn endpoint(id: u32) -> impl Service<(), (), Response = u32, Error = Infallible> + Ready {
+        service_fn(move |_cx: &mut (), _req: ()| async move { Ok::<_, Infallible>(id) })
+    }
+
+    #[tokio::test]
+    async fn follows_the_changes() {
+        let (mut tx, rx) = mpsc::unbounded();
+        let svc = Balance::new(rx);
+
+        tx.send(Ok::<_, Infallible>(Change::Insert(1, endpoint(1))))
+            .await
+            .unwrap();
+        tx.send(Ok(Change::Insert(2, endpoint(2)))).await.unwrap();
+        let mut ids = Vec::new();
+        for _ in 0..4 {
+            ids.push(svc.call(&mut (), ()).await.unwrap());
+        }
+        assert_eq!(ids, [1, 2, 1, 2]);
+
+        tx.send(Ok(Change::Remove(1))).await.unwrap();
+        tx.send(Ok(Change::Insert(3, endpoint(3)))).await.unwrap();
+        ids.clear();
+        for _ in 0..4 {
+            ids.push(svc.call(&mut (), ()).await.unwrap());
+        }
+        assert_eq!(ids, [2, 3, 2, 3]);
+    }
+
+    #[tokio::test]
+    async fn wakes_every_call_waiting_for_an_endpoint() {
+        let (tx, rx) = tokio::sync::watch::channel(Vec::new());
+        let svc = Rc::new(Balance::new(WatchDiscover::from_watch(rx, |&id: &u32| {
+            endpoint(id)
+        })));
+
+        // The calls are separate tasks, each with its own waker.
+        let local = tokio::task::LocalSet::new();
+        let calls = [(); 2].map(|()| {
+            let svc = svc.clone();
+            local.spawn_local(async move { svc.call(&mut (), ()).await.unwrap() })
+        });
+        local
+            .run_until(async {
+                tokio::task::yield_now().await;
+                tx.send(vec![1]).unwrap();
+                let ids = tokio::time::timeout(
+                    Duration::from_secs(2),
+                    futures::future::try_join_all(calls),
+                )
+                .await
+                .unwrap()
+                .unwrap();
+                assert_eq!(ids, [1, 1]);
+            })
+            .await;
+    }
+}
@@ -0,0 +1,230 @@
+//! The weighted round-robin balancer.
+
+use std::{
+    fmt,
+    sync::{
+        atomic::{AtomicI64, Ordering},
+        Mutex,
+    },
+    task::{Context, Poll},
+};
+
+use super::Endpoints;
+use crate::{discover::Discover, load::Ready, service::Service, BoxError, MaybeSend, MaybeSync};
+
+/// A service with the weight of its endpoint, the service discovered by the
+/// [`Discover`] of a weighted [`Balance`].
+pub struct Weighted<S> {
+    service: S,
+    weight: u32,
+    // The smooth weighted round-robin state, only changed while the balancer holds
+    // its pick lock.
+    current: AtomicI64,
+}
+
+impl<S> Weighted<S> {
+    /// Gives `weight` to `service`. An endpoint of weight zero is only called when
+    /// all the endpoints have a weight of zero.
+    pub const fn new(service: S, weight: u32) -> Self {
+        Weighted {
+            service,
+            weight,
+            current: AtomicI64::new(0),
+        }
+    }
+
+    /// Returns the service.
+    pub fn service(&self) -> &S {
+        &self.service
+    }
+
+    /// Returns the weight of the service.
+    pub fn weight(&self) -> u32 {
+        self.weight
+    }
+}
+
+impl<S: Clone> Clone for Weighted<S> {
+    fn clone(&self) -> Self {
+        Self::new(self.service.clone(), self.weight)
+    }
+}
+
+impl<S: Ready> Ready for Weighted<S> {
+    fn poll_ready(&self, cx: &mut Context<'_>) -> Poll<()> {
+        self.service.poll_ready(cx)
+    }
+}
+
+impl<S: fmt::Debug> fmt::Debug for Weighted<S> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Weighted")
+            .field("service", &self.service)
+            .field("weight", &self.weight)
+            .finish()
+    }
+}
+
+/// Calls the ready endpoints in proportion to their weights.
+///
+/// The endpoints are picked by smooth weighted round-robin: every pick adds its
+/// weight to the score of each endpoint, calls the endpoint with the highest
+/// score, and takes the sum of the weights off its score. With weights of 3 and 1,
+/// the calls go `a, a, b, a` rather than `a, a, a, b`, so a burst of calls is
+/// spread as evenly as the weights allow. This is useful for canaries, or for
+/// endpoints of different capacities. See the [module level docs](super) for
+/// details.
+///
+/// # Example
+///
+/// ```rust
+/// use motore::{
+///     balance::weighted::{Balance, Weighted},
+///     discover::ServiceList,
+///     service::service_fn,
+///     BoxError, Service,
+/// };
+///
+/// # #[tokio::main(flavor = "current_thread")]
+/// # async fn main() {
+/// let endpoints = ServiceList::new([("stable", 9), ("canary", 1)].map(|(name, weight)| {
+///     let svc = service_fn(move |_cx: &mut (), _req: ()| async move { Ok::<_, BoxError>(name) });
+///     Weighted::new(svc, weight)
+/// }));
+/// let svc = Balance::new(endpoints);
+///
+/// let mut canary = 0;
+/// for _ in 0..100 {
+///     if svc.call(&mut (), ()).await.unwrap() == "canary" {
+///         canary += 1;
+///     }
+/// }
+/// assert_eq!(canary, 10);
+/// # }
+/// ```
+pub struct Balance<D: Discover> {
+    endpoints: Endpoints<D>,
+    // Serializes the picks, which update the scores of all the endpoints.
+    pick: Mutex<()>,
+}
+
+impl<D, S> Balance<D>
+where
+    D: Discover<Service = Weighted<S>>,
+{
+    /// Creates a balancer across the endpoints of `discover`.
+    pub fn new(discover: D) -> Self {
+        Balance {
+            endpoints: Endpoints::new(discover),
+            pick: Mutex::new(()),
+        }
+    }
+
+    /// Returns the number of endpoints discovered so far.
+    pub fn len(&self) -> usize {
+        self.endpoints.len()
+    }
+
+    /// Returns whether no endpoint was discovered so far.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+impl<Cx, Req, D, S> Service<Cx, Req> for Balance<D>
+where
+    Req: 'static + MaybeSend,
+    D: Discover<Service = Weighted<S>> + MaybeSend,
+    D::Key: MaybeSend + MaybeSync,
+    D::Error: Into<BoxError>,
+    S: Service<Cx, Req> + Ready + 'static + MaybeSend + MaybeSync,
+    S::Error: Into<BoxError>,
+    Cx: 'static + MaybeSend,
+{
+    type Response = S::Response;
+
+    type Error = BoxError;
+
+    async fn call(&self, cx: &mut Cx, req: Req) -> Result<Self::Response, Self::Error> {
+        let svc = self
+            .endpoints
+            .pick(|services| {
+                let _pick = self.pick.lock().unwrap_or_else(|e| e.into_inner());
+                let mut total = 0;
+                let mut best = (0, i64::MIN);
+                for (index, svc) in services.iter().enumerate() {
+                    let weight = i64::from(svc.weight);
+                    total += weight;
+                    let current = svc.current.load(Ordering::Relaxed) + weight;
+                    svc.current.store(current, Ordering::Relaxed);
+                    if current > best.1 {
+                        best = (index, current);
+                    }
+                }
+                services[best.0].current.fetch_sub(total, Ordering::Relaxed);
+                best.0
+            })
+            .await?;
+        svc.service.call(cx, req).await.map_err(Into::into)
+    }
+}
+
+impl<D: Discover> fmt::Debug for Balance<D> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Balance")
+            .field("endpoints", &self.endpoints.len())
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::convert::Infallible;
+
+    use futures::{channel::mpsc, SinkExt};
+
+    use super::*;
+    use crate::{discover::Change, service::service_fn};
+
+    fn endpoint(
+        id: char,
+        weight: u32,
+    ) -> Weighted<impl Service<(), (), Response = char, Error = Infallible> + Ready> {
+        Weighted::new(
+            service_fn(move |_cx: &mut (), _req: ()| async move { Ok::<_, Infallible>(id) }),
+            weight,
+        )
+    }
+
+    #[tokio::test]
+    async fn spreads_the_calls_smoothly() {
+        let (mut tx, rx) = mpsc::unbounded();
+        let svc = Balance::new(rx);
+        tx.send(Ok::<_, Infallible>(Change::Insert('a', endpoint('a', 5))))
+            .await
+            .unwrap();
+        tx.send(Ok(Change::Insert('b', endpoint('b', 1))))
+            .await
+            .unwrap();
+        tx.send(Ok(Change::Insert('c', endpoint('c', 1))))
+            .await
+            .unwrap();
+
+        let mut calls = String::new();
+        for _ in 0..7 {
+            calls.push(svc.call(&mut (), ()).await.unwrap());
+        }
+        assert_eq!(calls, "aabacaa");
+
+        // The new endpoints join the rotation.
+        tx.send(Ok(Change::Remove('a'))).await.unwrap();
+        tx.send(Ok(Change::Insert('d', endpoint('d', 0))))
+            .await
+            .unwrap();
+        calls.clear();
+        for _ in 0..4 {
+            calls.push(svc.call(&mut (), ()).await.unwrap());
+        }
+        assert_eq!(calls, "bcbc");
+    }
+}
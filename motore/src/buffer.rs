@@ -0,0 +1,450 @@
+//! Runs a service on its own task, behind a cloneable handle.
+//!
+//! A [`Buffer`] owns no service: the service is moved into a worker task, and the
+//! buffer sends it the requests through a bounded channel. This makes it possible
+//! to share a service that isn't [`Clone`] between many callers, and to own
+//! everything the service needs, like a connection, in the worker instead of
+//! behind an [`Arc`].
+//!
+//! The worker calls the service concurrently, in the order the requests arrive.
+//! When the channel is full, callers wait for room in it, so the bound caps the
+//! requests waiting for the worker, not the calls in flight; a
+//! [`ConcurrencyLimit`](crate::limit::ConcurrencyLimit) under the buffer caps those.
+//!
+//! The context is moved to the worker along with the request, and moved back once
+//! the call completed, so it must implement [`Default`] to fill the caller's
+//! context in the meantime.
+//!
+//! Once a buffer is warm, its requests don't allocate their reply: the worker
+//! writes it to a slot taken from a pool shared by the buffer and its clones, and
+//! the caller puts the slot back once it read the reply. The requests themselves
+//! are kept in the blocks of the channel, which are reused as well, so only the
+//! worker still allocates, for the future of every call it makes concurrently.
+//! [`Buffer::pool_stats`] tells how often the requests found a slot in the pool.
+
+use std::{
+    error::Error,
+    fmt,
+    future::{poll_fn, Future},
+    marker::PhantomData,
+    mem,
+    sync::Arc,
+};
+
+use futures::{stream::FuturesUnordered, StreamExt};
+use tokio::sync::mpsc;
+
+use crate::{
+    layer::Layer,
+    load::Ready,
+    service::Service,
+    utils::reply::{Pool, Responder},
+    MaybeSend,
+};
+
+/// The error returned by [`Buffer`].
+#[derive(Debug, PartialEq, Eq)]
+pub enum BufferError<E> {
+    /// The worker is gone, because its task was dropped or the service panicked.
+    Closed,
+    /// The inner service failed.
+    Service(E),
+}
+
+impl<E: fmt::Display> fmt::Display for BufferError<E> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            BufferError::Closed => f.write_str("buffer's worker closed unexpectedly"),
+            BufferError::Service(e) => fmt::Display::fmt(e, f),
+        }
+    }
+}
+
+impl<E> Error for BufferError<E>
+where
+    E: Error + 'static,
+{
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            BufferError::Closed => None,
+            BufferError::Service(e) => Some(e),
+        }
+    }
+}
+
+/// How often the requests of a [`Buffer`] and its clones reused the slot of a
+/// previous reply, rather than allocating one.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct PoolStats {
+    hits: u64,
+    misses: u64,
+}
+
+impl PoolStats {
+    /// Returns the number of requests which reused a slot.
+    pub fn hits(&self) -> u64 {
+        self.hits
+    }
+
+    /// Returns the number of requests which allocated a slot.
+    pub fn misses(&self) -> u64 {
+        self.misses
+    }
+
+    /// Returns the share of the requests which reused a slot, between 0 and 1, or
+    /// 0 before the first request.
+    pub fn hit_rate(&self) -> f64 {
+        let requests = self.hits + self.misses;
+        if requests == 0 {
+            return 0.0;
+        }
+        self.hits as f64 / requests as f64
+    }
+}
+
+type Reply<Cx, Resp, E> = (Cx, Result<Resp, E>);
+
+type Message<Cx, Req, Resp, E> = (Cx, Req, Responder<Reply<Cx, Resp, E>>);
+
+/// A cloneable handle sending requests to a service running on a worker task.
+///
+/// See the [module level docs](self) for details.
+pub struct Buffer<Cx, Req, Resp, E> {
+    tx: mpsc::Sender<Message<Cx, Req, Resp, E>>,
+    pool: Arc<Pool<Reply<Cx, Resp, E>>>,
+}
+
+impl<Cx, Req, Resp, E> Buffer<Cx, Req, Resp, E> {
+    /// Creates a buffer holding up to `bound` requests, and spawns the worker
+    /// calling `service` on the current runtime.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `bound` is zero, or if called outside of a tokio runtime.
+    #[cfg(feature = "service_send")]
+    pub fn new<S>(service: S, bound: usize) -> Self
+    where
+        S: Service<Cx, Req, Response = Resp, Error = E> + Send + Sync + 'static,
+        Cx: Send + 'static,
+        Req: Send + 'static,
+        Resp: Send + 'static,
+        E: Send + 'static,
+    {
+        let (buffer, worker) = Self::pair(service, bound);
+        tokio::spawn(worker);
+        buffer
+    }
+
+    /// Creates a buffer holding up to `bound` requests, and spawns the worker
+    /// calling `service` on the current [`LocalSet`](tokio::task::LocalSet).
+    ///
+    /// # Panics
+    ///
+    /// Panics if `bound` is zero, or if called outside of a `LocalSet`.
+    #[cfg(not(feature = "service_send"))]
+    pub fn new<S>(service: S, bound: usize) -> Self
+    where
+        S: Service<Cx, Req, Response = Resp, Error = E> + 'static,
+        Cx: 'static,
+        Req: 'static,
+        Resp: 'static,
+        E: 'static,
+    {
+        let (buffer, worker) = Self::pair(service, bound);
+        tokio::task::spawn_local(worker);
+        buffer
+    }
+
+    /// Creates a buffer holding up to `bound` requests, and returns it with the
+    /// worker calling `service`, for the caller to spawn.
+    ///
+    /// The worker completes once the buffer and all its clones are dropped, and
+    /// the calls in flight completed.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `bound` is zero.
+    pub fn pair<S>(service: S, bound: usize) -> (Self, impl Future<Output = ()>)
+    where
+        S: Service<Cx, Req, Response = Resp, Error = E>,
+    {
+        let (tx, rx) = mpsc::channel(bound);
+        let pool = Arc::new(Pool::new(bound));
+        (Buffer { tx, pool }, run(service, rx))
+    }
+
+    /// Returns how often the requests of the buffer and its clones reused the
+    /// slot of a previous reply so far.
+    pub fn pool_stats(&self) -> PoolStats {
+        PoolStats {
+            hits: self.pool.hits(),
+            misses: self.pool.misses(),
+        }
+    }
+}
+
+async fn run<Cx, Req, S>(
+    service: S,
+    mut rx: mpsc::Receiver<Message<Cx, Req, S::Response, S::Error>>,
+) where
+    S: Service<Cx, Req>,
+{
+    let service = &service;
+    let mut in_flight = FuturesUnordered::new();
+    loop {
+        tokio::select! {
+            msg = rx.recv() => match msg {
+                Some((mut cx, req, responder)) => in_flight.push(async move {
+                    let res = service.call(&mut cx, req).await;
+                    responder.send((cx, res));
+                }),
+                None => break,
+            },
+            Some(()) = in_flight.next(), if !in_flight.is_empty() => {}
+        }
+    }
+    while in_flight.next().await.is_some() {}
+}
+
+impl<Cx, Req, Resp, E> Service<Cx, Req> for Buffer<Cx, Req, Resp, E>
+where
+    Cx: Default + MaybeSend + 'static,
+    Req: MaybeSend + 'static,
+    Resp: MaybeSend + 'static,
+    E: MaybeSend + 'static,
+{
+    type Response = Resp;
+
+    type Error = BufferError<E>;
+
+    async fn call(&self, cx: &mut Cx, req: Req) -> Result<Self::Response, Self::Error> {
+        let slot = self.pool.take();
+        let responder = Responder::new(slot.clone());
+        if let Err(mpsc::error::SendError((owned, _, _))) =
+            self.tx.send((mem::take(cx), req, responder)).await
+        {
+            *cx = owned;
+            return Err(BufferError::Closed);
+        }
+        let reply = poll_fn(|task| slot.poll_reply(task)).await;
+        // A caller going away drops its slot instead, as the worker may still
+        // reply to it.
+        self.pool.put(slot);
+        // When the worker is gone, the context is lost along with the call.
+        let (owned, res) = reply.ok_or(BufferError::Closed)?;
+        *cx = owned;
+        res.map_err(BufferError::Service)
+    }
+}
+
+/// A buffer is always ready, as callers wait for room in the channel.
+impl<Cx, Req, Resp, E> Ready for Buffer<Cx, Req, Resp, E> {}
+
+impl<Cx, Req, Resp, E> Clone for Buffer<Cx, Req, Resp, E> {
+    fn clone(&self) -> Self {
+        Buffer {
+            tx: self.tx.clone(),
+            pool: self.pool.clone(),
+        }
+    }
+}
+
+impl<Cx, Req, Resp, E> fmt::Debug for Buffer<Cx, Req, Resp, E> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Buffer")
+            .field("capacity", &self.tx.capacity())
+            .field("closed", &self.tx.is_closed())
+            .field("pool", &self.pool_stats())
+            .finish()
+    }
+}
+
+/// Applies a [`Buffer`] to a service, spawning its worker.
+///
+/// # Example
+///
+/// ```rust
+/// use std::{
+///     convert::Infallible,
+///     sync::atomic::{AtomicU64, Ordering},
+/// };
+///
+/// use motore::{buffer::BufferLayer, layer::Layer, Service};
+///
+/// /// Counts the requests; this isn't `Clone`, so it can't be shared as is.
+/// struct Counter(AtomicU64);
+///
+/// impl Service<(), ()> for Counter {
+///     type Response = u64;
+///     type Error = Infallible;
+///
+///     async fn call(&self, _cx: &mut (), _req: ()) -> Result<u64, Infallible> {
+///         Ok(self.0.fetch_add(1, Ordering::Relaxed) + 1)
+///     }
+/// }
+///
+/// # #[tokio::main(flavor = "current_thread")]
+/// # async fn main() {
+/// # tokio::task::LocalSet::new().run_until(async {
+/// let svc = BufferLayer::new(16).layer(Counter(AtomicU64::new(0)));
+/// let clone = svc.clone();
+/// assert_eq!(svc.call(&mut (), ()).await, Ok(1));
+/// assert_eq!(clone.call(&mut (), ()).await, Ok(2));
+/// # }).await;
+/// # }
+/// ```
+pub struct BufferLayer<Cx, Req> {
+    bound: usize,
+    _phantom: PhantomData<fn(Cx, Req)>,
+}
+
+impl<Cx, Req> BufferLayer<Cx, Req> {
+    /// Creates a layer whose buffers hold up to `bound` requests.
+    pub const fn new(bound: usize) -> Self {
+        BufferLayer {
+            bound,
+            _phantom: PhantomData,
+        }
+    }
+}
+
+impl<Cx, Req> Clone for BufferLayer<Cx, Req> {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+impl<Cx, Req> Copy for BufferLayer<Cx, Req> {}
+
+impl<Cx, Req> fmt::Debug for BufferLayer<Cx, Req> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("BufferLayer")
+            .field("bound", &self.bound)
+            .finish()
+    }
+}
+
+#[cfg(feature = "service_send")]
+impl<S, Cx, Req> Layer<S> for BufferLayer<Cx, Req>
+where
+    S: Service<Cx, Req> + Send + Sync + 'static,
+    Cx: Send + 'static,
+    Req: Send + 'static,
+    S::Response: Send + 'static,
+    S::Error: Send + 'static,
+{
+    type Service = Buffer<Cx, Req, S::Response, S::Error>;
+
+    fn layer(self, inner: S) -> Self::Service {
+        Buffer::new(inner, self.bound)
+    }
+}
+
+#[cfg(not(feature = "service_send"))]
+impl<S, Cx, Req> Layer<S> for BufferLayer<Cx, Req>
+where
+    S: Service<Cx, Req> + 'static,
+    Cx: 'static,
+    Req: 'static,
+    S::Response: 'static,
+    S::Error: 'static,
+{
+    type Service = Buffer<Cx, Req, S::Response, S::Error>;
+
+    fn layer(self, inner: S) -> Self::Service {
+        Buffer::new(inner, self.bound)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use tokio::time::Instant;
+
+    use super::*;
+    use crate::service::service_fn;
+
+    #[derive(Debug, Default, PartialEq)]
+    struct Cx {
+        calls: u32,
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn calls_concurrently_and_returns_the_context() {
+        tokio::task::LocalSet::new()
+            .run_until(async {
+                let svc = Buffer::new(
+                    service_fn(|cx: &mut Cx, req: u32| {
+                        cx.calls += 1;
+                        async move {
+                            tokio::time::sleep(Duration::from_millis(100)).await;
+                            Ok::<_, &'static str>(req * 2)
+                        }
+                    }),
+                    4,
+                );
+
+                let start = Instant::now();
+                let responses = futures::future::join_all((0..4).map(|i| {
+                    let svc = svc.clone();
+                    async move {
+                        let mut cx = Cx { calls: i };
+                        let res = svc.call(&mut cx, i).await;
+                        (res, cx)
+                    }
+                }))
+                .await;
+                assert_eq!(start.elapsed(), Duration::from_millis(100));
+                for (i, (res, cx)) in responses.into_iter().enumerate() {
+                    let i = i as u32;
+                    assert_eq!(res, Ok(i * 2));
+                    assert_eq!(cx, Cx { calls: i + 1 });
+                }
+            })
+            .await;
+    }
+
+    #[tokio::test]
+    async fn reuses_the_reply_slots() {
+        tokio::task::LocalSet::new()
+            .run_until(async {
+                let svc = Buffer::new(
+                    service_fn(|_cx: &mut Cx, req: u32| async move {
+                        tokio::task::yield_now().await;
+                        Ok::<_, &'static str>(req)
+                    }),
+                    2,
+                );
+                let clone = svc.clone();
+
+                // Two requests in flight at once need a slot each.
+                let (a, b) = tokio::join!(async { svc.call(&mut Cx::default(), 1).await }, async {
+                    clone.call(&mut Cx::default(), 2).await
+                },);
+                assert_eq!((a, b), (Ok(1), Ok(2)));
+                assert_eq!(svc.pool_stats().misses(), 2);
+
+                for i in 0..8 {
+                    assert_eq!(clone.call(&mut Cx::default(), i).await, Ok(i));
+                }
+                let stats = svc.pool_stats();
+                assert_eq!((stats.hits(), stats.misses()), (8, 2));
+                assert_eq!(stats.hit_rate(), 0.8);
+            })
+            .await;
+    }
+
+    #[tokio::test]
+    async fn fails_when_the_worker_is_gone() {
+        let (svc, worker) = Buffer::pair(
+            service_fn(|_cx: &mut Cx, req: u32| async move { Ok::<_, &'static str>(req) }),
+            1,
+        );
+        drop(worker);
+
+        let mut cx = Cx { calls: 7 };
+        assert_eq!(svc.call(&mut cx, 1).await, Err(BufferError::Closed));
+        assert_eq!(cx, Cx { calls: 7 });
+    }
+}
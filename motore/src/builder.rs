@@ -86,6 +86,23 @@ impl<L> ServiceBuilder<L> {
         self.layer(crate::layer::MapErrLayer::new(f))
     }
 
+    /// Erase the type of the service produced by the layers added after this one.
+    ///
+    /// This inserts a boxing boundary in the middle of the stack: the layers added
+    /// before this call are applied to a [`BoxCloneService`] instead of the concrete
+    /// service type, which keeps their types small at the cost of a dynamic dispatch.
+    ///
+    /// This wraps the inner service with an instance of the [`EraseLayer`]
+    /// middleware.
+    ///
+    /// [`BoxCloneService`]: crate::service::BoxCloneService
+    /// [`EraseLayer`]: crate::layer::EraseLayer
+    pub fn erase_layer<Cx, Req>(
+        self,
+    ) -> ServiceBuilder<Stack<crate::layer::EraseLayer<Cx, Req>, L>> {
+        self.layer(crate::layer::EraseLayer::new())
+    }
+
     /// Returns the underlying `Layer` implementation.
     pub fn into_inner(self) -> L {
         self.layer
@@ -0,0 +1,224 @@
+//! An in-memory [`CacheStore`], evicting the least recently used entries.
+//!
+//! The entries are spread across shards by the hash of their key, each with its
+//! own lock, least recently used order and share of the capacity, so that the
+//! concurrent calls of a cache mostly touch different shards instead of all
+//! waiting for a single lock.
+//!
+//! All the values of a store live for the same ttl, so they expire in the order
+//! they were stored: every shard keeps that order, and drops its expired values
+//! from the front whenever a value is stored, rather than letting them hold on to
+//! the capacity until they are read or evicted.
+
+use std::{fmt, hash::Hash, time::Duration};
+
+use super::CacheStore;
+use crate::{
+    timer::{DefaultTimer, Timer},
+    utils::{
+        lru::Lru,
+        sharded::{default_shards, Sharded},
+    },
+    MaybeSend, MaybeSync,
+};
+
+/// The fewest values a shard keeps by default, for its least recently used order
+/// to stay close to the one of the whole store.
+const MIN_SHARD_CAPACITY: usize = 64;
+
+/// A [`CacheStore`] keeping up to a given number of values in memory.
+///
+/// When it is full, storing a value evicts the least recently used one of its
+/// shard. Values can additionally expire some time after they were stored, with
+/// [`ttl`](Self::ttl).
+///
+/// The values are spread across shards by the hash of their key, each with its
+/// own lock and share of the capacity, so that concurrent calls mostly touch
+/// different shards instead of all waiting for a single lock.
+pub struct InMemoryStore<K, V, T = DefaultTimer> {
+    capacity: usize,
+    ttl: Option<Duration>,
+    shards: Sharded<Lru<K, V>>,
+    timer: T,
+}
+
+impl<K: Hash + Eq + Clone, V> InMemoryStore<K, V> {
+    /// Creates a store keeping up to `capacity` values, which don't expire.
+    ///
+    /// The store has a shard for every 64 values, up to four per available thread.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `capacity` is zero.
+    pub fn new(capacity: usize) -> Self {
+        assert!(capacity > 0, "the capacity must not be zero");
+        let shards = (capacity / MIN_SHARD_CAPACITY).clamp(1, default_shards());
+        InMemoryStore {
+            capacity,
+            ttl: None,
+            shards: Sharded::new(shards, Lru::new),
+            timer: DefaultTimer::new(),
+        }
+    }
+}
+
+impl<K: Hash + Eq + Clone, V, T> InMemoryStore<K, V, T> {
+    /// Makes the values expire `ttl` after they were stored.
+    pub fn ttl(mut self, ttl: Duration) -> Self {
+        self.ttl = Some(ttl);
+        self
+    }
+
+    /// Splits the store in `shards` shards, each keeping up to the capacity divided
+    /// by `shards`, rounded up. The values stored so far are dropped.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `shards` is zero or greater than the capacity.
+    pub fn shards(mut self, shards: usize) -> Self {
+        assert!(
+            (1..=self.capacity).contains(&shards),
+            "the number of shards must be between one and the capacity"
+        );
+        self.shards = Sharded::new(shards, Lru::new);
+        self
+    }
+
+    /// Sets the timer expiring the values, [`DefaultTimer`] by default.
+    pub fn timer<U>(self, timer: U) -> InMemoryStore<K, V, U> {
+        InMemoryStore {
+            capacity: self.capacity,
+            ttl: self.ttl,
+            shards: self.shards,
+            timer,
+        }
+    }
+
+    /// Returns the number of values stored, expired or not.
+    pub fn len(&self) -> usize {
+        (0..self.shards.len())
+            .map(|i| self.shards.lock(i).len())
+            .sum()
+    }
+
+    /// Returns whether no value is stored.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+impl<K, V, T> CacheStore<K, V> for InMemoryStore<K, V, T>
+where
+    K: Hash + Eq + Clone + MaybeSend + MaybeSync,
+    V: Clone + MaybeSend,
+    T: Timer + MaybeSync,
+{
+    async fn get(&self, key: &K) -> Option<V> {
+        let mut shard = self.shards.shard(key);
+        let entry = shard.touch(key)?;
+        if entry
+            .expires
+            .is_some_and(|expires| expires <= self.timer.now())
+        {
+            shard.remove(key);
+            return None;
+        }
+        Some(entry.value.clone())
+    }
+
+    async fn put(&self, key: K, value: V) {
+        let now = self.ttl.map(|_| self.timer.now());
+        let expires = now.zip(self.ttl).map(|(now, ttl)| now + ttl);
+        let capacity = self.capacity.div_ceil(self.shards.len());
+        let mut shard = self.shards.shard(&key);
+        if let Some(now) = now {
+            shard.purge(now);
+        }
+        shard.insert(key, value, expires, capacity);
+    }
+
+    async fn invalidate(&self, key: &K) {
+        self.shards.shard(key).remove(key);
+    }
+}
+
+impl<K: Hash + Eq + Clone, V, T> fmt::Debug for InMemoryStore<K, V, T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("InMemoryStore")
+            .field("capacity", &self.capacity)
+            .field("ttl", &self.ttl)
+            .field("shards", &self.shards.len())
+            .field("len", &self.len())
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn evicts_the_least_recently_used_values() {
+        let store = InMemoryStore::new(2);
+        store.put(1, "one").await;
+        store.put(2, "two").await;
+        assert_eq!(store.get(&1).await, Some("one"));
+
+        store.put(3, "three").await;
+        assert_eq!(store.get(&2).await, None);
+        assert_eq!(store.get(&1).await, Some("one"));
+        assert_eq!(store.get(&3).await, Some("three"));
+
+        // Replacing a value doesn't evict another one.
+        store.put(3, "trois").await;
+        assert_eq!(store.get(&1).await, Some("one"));
+        assert_eq!(store.get(&3).await, Some("trois"));
+
+        store.invalidate(&1).await;
+        assert_eq!(store.get(&1).await, None);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn values_expire() {
+        let store = InMemoryStore::new(8).ttl(Duration::from_secs(10));
+        store.put("a", 1).await;
+        tokio::time::advance(Duration::from_secs(5)).await;
+        store.put("b", 2).await;
+        assert_eq!(store.get(&"a").await, Some(1));
+
+        tokio::time::advance(Duration::from_secs(5)).await;
+        assert_eq!(store.get(&"a").await, None);
+        assert_eq!(store.get(&"b").await, Some(2));
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn expired_values_are_dropped_before_evicting() {
+        let store = InMemoryStore::new(2).ttl(Duration::from_secs(10));
+        store.put("a", 1).await;
+        tokio::time::advance(Duration::from_secs(5)).await;
+        store.put("b", 2).await;
+        // `a` is used last, but expires first.
+        assert_eq!(store.get(&"a").await, Some(1));
+
+        tokio::time::advance(Duration::from_secs(5)).await;
+        store.put("c", 3).await;
+        assert_eq!(store.len(), 2);
+        assert_eq!(store.get(&"b").await, Some(2));
+        assert_eq!(store.get(&"c").await, Some(3));
+    }
+
+    #[tokio::test]
+    async fn shards_split_the_capacity() {
+        assert_eq!(InMemoryStore::<u32, u32>::new(16).shards.len(), 1);
+
+        let store = InMemoryStore::new(64).shards(4);
+        for i in 0..1000 {
+            store.put(i, i).await;
+        }
+        for i in 0..4 {
+            assert_eq!(store.shards.lock(i).len(), 16);
+        }
+        // The last value stored in a shard is kept.
+        assert_eq!(store.get(&999).await, Some(999));
+    }
+}
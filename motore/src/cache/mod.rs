@@ -0,0 +1,313 @@
+//! Caches the responses of a service.
+//!
+//! [`Cache`] looks up the response of a request in a [`CacheStore`] before calling
+//! the inner service, and stores the responses of the calls it makes, so a
+//! read-heavy service only computes each response once. Requests are identified
+//! by a key extracted from them, and the requests without a key bypass the cache.
+//!
+//! Only successful responses are cached, as a clone, so the response must be
+//! [`Clone`]. How long a response is kept, and when it is evicted, is up to the
+//! store: the [`CacheStore`] trait can be implemented on top of an external cache,
+//! like Redis, and [`InMemoryStore`] keeps the responses in memory.
+
+use std::{
+    fmt,
+    future::Future,
+    sync::Arc,
+    task::{Context, Poll},
+};
+
+use crate::{
+    layer::Layer,
+    load::{Load, Ready},
+    service::Service,
+    MaybeSend, MaybeSync,
+};
+
+mod memory;
+
+pub use self::memory::InMemoryStore;
+
+/// A store of cached values, used by [`Cache`].
+///
+/// A store unable to reach its backend should report it as a miss, or skip
+/// storing the value, rather than fail the request: [`Cache`] falls back to the
+/// inner service on a miss.
+pub trait CacheStore<K, V> {
+    /// Returns the value stored for `key`, if any.
+    #[cfg(feature = "service_send")]
+    fn get(&self, key: &K) -> impl Future<Output = Option<V>> + Send;
+    /// Returns the value stored for `key`, if any.
+    #[cfg(not(feature = "service_send"))]
+    fn get(&self, key: &K) -> impl Future<Output = Option<V>>;
+
+    /// Stores `value` for `key`, replacing the previous value.
+    #[cfg(feature = "service_send")]
+    fn put(&self, key: K, value: V) -> impl Future<Output = ()> + Send;
+    /// Stores `value` for `key`, replacing the previous value.
+    #[cfg(not(feature = "service_send"))]
+    fn put(&self, key: K, value: V) -> impl Future<Output = ()>;
+
+    /// Removes the value stored for `key`, if any.
+    #[cfg(feature = "service_send")]
+    fn invalidate(&self, key: &K) -> impl Future<Output = ()> + Send;
+    /// Removes the value stored for `key`, if any.
+    #[cfg(not(feature = "service_send"))]
+    fn invalidate(&self, key: &K) -> impl Future<Output = ()>;
+}
+
+/// Returns the responses cached in a [`CacheStore`] instead of calling the inner
+/// service, and caches the successful responses of the calls it makes.
+///
+/// The key of a request is given by a `Fn(&Req) -> Option<K>`, returning `None`
+/// for the requests that should not be cached. See the [module level docs](self)
+/// for details.
+pub struct Cache<S, St, F> {
+    inner: S,
+    store: Arc<St>,
+    key: F,
+}
+
+impl<S, St, F> Cache<S, St, F> {
+    /// Creates a cache storing the responses of `inner` in `store`, by the key
+    /// returned by `key`.
+    pub fn new(inner: S, store: St, key: F) -> Self {
+        Cache {
+            inner,
+            store: Arc::new(store),
+            key,
+        }
+    }
+
+    /// Returns the store of the cache, for instance to invalidate a key.
+    pub fn store(&self) -> &St {
+        &self.store
+    }
+}
+
+impl<Cx, Req, S, St, F, K> Service<Cx, Req> for Cache<S, St, F>
+where
+    Req: 'static + MaybeSend,
+    S: Service<Cx, Req> + 'static + MaybeSend + MaybeSync,
+    S::Response: Clone + MaybeSend,
+    St: CacheStore<K, S::Response> + 'static + MaybeSend + MaybeSync,
+    F: Fn(&Req) -> Option<K> + MaybeSend + MaybeSync,
+    K: MaybeSend,
+    Cx: 'static + MaybeSend,
+{
+    type Response = S::Response;
+
+    type Error = S::Error;
+
+    async fn call(&self, cx: &mut Cx, req: Req) -> Result<Self::Response, Self::Error> {
+        let Some(key) = (self.key)(&req) else {
+            return self.inner.call(cx, req).await;
+        };
+        if let Some(resp) = self.store.get(&key).await {
+            return Ok(resp);
+        }
+        let resp = self.inner.call(cx, req).await?;
+        self.store.put(key, resp.clone()).await;
+        Ok(resp)
+    }
+}
+
+impl<S, St, F> Ready for Cache<S, St, F>
+where
+    S: Ready,
+{
+    fn poll_ready(&self, cx: &mut Context<'_>) -> Poll<()> {
+        self.inner.poll_ready(cx)
+    }
+}
+
+impl<S, St, F> Load for Cache<S, St, F>
+where
+    S: Load,
+{
+    type Metric = S::Metric;
+
+    fn load(&self) -> Self::Metric {
+        self.inner.load()
+    }
+}
+
+impl<S: Clone, St, F: Clone> Clone for Cache<S, St, F> {
+    fn clone(&self) -> Self {
+        Cache {
+            inner: self.inner.clone(),
+            store: self.store.clone(),
+            key: self.key.clone(),
+        }
+    }
+}
+
+impl<S: fmt::Debug, St: fmt::Debug, F> fmt::Debug for Cache<S, St, F> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Cache")
+            .field("inner", &self.inner)
+            .field("store", &self.store)
+            .field("key", &format_args!("{}", std::any::type_name::<F>()))
+            .finish()
+    }
+}
+
+/// Applies a [`Cache`] to a service.
+///
+/// All the services made by the layer share the same store.
+///
+/// # Example
+///
+/// ```rust
+/// use std::{collections::HashMap, sync::Mutex};
+///
+/// use motore::{
+///     builder::ServiceBuilder,
+///     cache::{CacheLayer, CacheStore},
+///     service::service_fn,
+///     BoxError, Service,
+/// };
+///
+/// #[derive(Default)]
+/// struct Store(Mutex<HashMap<String, String>>);
+///
+/// impl CacheStore<String, String> for Store {
+///     async fn get(&self, key: &String) -> Option<String> {
+///         self.0.lock().unwrap().get(key).cloned()
+///     }
+///
+///     async fn put(&self, key: String, value: String) {
+///         self.0.lock().unwrap().insert(key, value);
+///     }
+///
+///     async fn invalidate(&self, key: &String) {
+///         self.0.lock().unwrap().remove(key);
+///     }
+/// }
+///
+/// # #[tokio::main(flavor = "current_thread")]
+/// # async fn main() {
+/// async fn render(_cx: &mut (), page: String) -> Result<String, BoxError> {
+///     Ok(format!("<h1>{page}</h1>"))
+/// }
+///
+/// let svc = ServiceBuilder::new()
+///     .layer(CacheLayer::new(Store::default(), |page: &String| {
+///         Some(page.clone())
+///     }))
+///     .service(service_fn(render));
+///
+/// assert_eq!(svc.call(&mut (), "home".into()).await.unwrap(), "<h1>home</h1>");
+/// assert!(svc.store().get(&"home".to_string()).await.is_some());
+/// # }
+/// ```
+pub struct CacheLayer<St, F> {
+    store: Arc<St>,
+    key: F,
+}
+
+impl<St, F> CacheLayer<St, F> {
+    /// Creates a layer storing the responses in `store`, by the key returned by
+    /// `key`.
+    pub fn new(store: St, key: F) -> Self {
+        CacheLayer {
+            store: Arc::new(store),
+            key,
+        }
+    }
+}
+
+impl<St, F: Clone> Clone for CacheLayer<St, F> {
+    fn clone(&self) -> Self {
+        CacheLayer {
+            store: self.store.clone(),
+            key: self.key.clone(),
+        }
+    }
+}
+
+impl<St: fmt::Debug, F> fmt::Debug for CacheLayer<St, F> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("CacheLayer")
+            .field("store", &self.store)
+            .field("key", &format_args!("{}", std::any::type_name::<F>()))
+            .finish()
+    }
+}
+
+impl<S, St, F> Layer<S> for CacheLayer<St, F> {
+    type Service = Cache<S, St, F>;
+
+    fn layer(self, inner: S) -> Self::Service {
+        Cache {
+            inner,
+            store: self.store,
+            key: self.key,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        collections::HashMap,
+        sync::{
+            atomic::{AtomicUsize, Ordering},
+            Mutex,
+        },
+    };
+
+    use super::*;
+    use crate::service::service_fn;
+
+    #[derive(Default)]
+    struct Store(Mutex<HashMap<u32, u32>>);
+
+    impl CacheStore<u32, u32> for Store {
+        async fn get(&self, key: &u32) -> Option<u32> {
+            self.0.lock().unwrap().get(key).copied()
+        }
+
+        async fn put(&self, key: u32, value: u32) {
+            self.0.lock().unwrap().insert(key, value);
+        }
+
+        async fn invalidate(&self, key: &u32) {
+            self.0.lock().unwrap().remove(key);
+        }
+    }
+
+    #[tokio::test]
+    async fn caches_successful_responses() {
+        let calls = Arc::new(AtomicUsize::new(0));
+        let svc = CacheLayer::new(Store::default(), |req: &u32| (*req < 100).then_some(*req))
+            .layer(service_fn({
+                let calls = calls.clone();
+                move |_cx: &mut (), req: u32| {
+                    calls.fetch_add(1, Ordering::SeqCst);
+                    async move {
+                        if req % 2 == 1 {
+                            return Err("odd");
+                        }
+                        Ok(req / 2)
+                    }
+                }
+            }));
+
+        for _ in 0..3 {
+            assert_eq!(svc.call(&mut (), 10).await, Ok(5));
+        }
+        assert_eq!(calls.load(Ordering::SeqCst), 1);
+
+        // Errors and requests without a key are not cached.
+        for _ in 0..3 {
+            assert_eq!(svc.call(&mut (), 11).await, Err("odd"));
+            assert_eq!(svc.call(&mut (), 200).await, Ok(100));
+        }
+        assert_eq!(calls.load(Ordering::SeqCst), 7);
+
+        svc.store().invalidate(&10).await;
+        assert_eq!(svc.call(&mut (), 10).await, Ok(5));
+        assert_eq!(calls.load(Ordering::SeqCst), 8);
+    }
+}
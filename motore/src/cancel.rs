@@ -0,0 +1,319 @@
+//! Aborts the calls of a service once a cancellation signal fires.
+//!
+//! [`Cancellation`] races every call against a [`CancelSignal`], and fails it
+//! with a [`Cancelled`] error as soon as the signal fires, dropping the call of
+//! the inner service. The signal is usually a [`CancellationToken`], cancelled by
+//! the server when the connection of the caller closes, so the work of the
+//! requests nobody waits for anymore stops right away.
+//!
+//! Any future can be used as a signal, through a closure returning a new one for
+//! every call, like `|| rx.clone()` for a
+//! [`Shared`](futures::future::Shared) future.
+
+use std::{
+    fmt,
+    future::Future,
+    sync::Arc,
+    task::{Context, Poll},
+};
+
+use tokio::sync::watch;
+
+use crate::{
+    layer::Layer,
+    load::{Load, Ready},
+    service::Service,
+    BoxError, MaybeSend, MaybeSync,
+};
+
+/// The error returned by [`Cancellation`] when its signal fires before the call
+/// completed, dropping the call.
+///
+/// Callers telling a cancellation apart from a failure of the service can check
+/// the [`BoxError`] with `err.is::<Cancelled>()`.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Cancelled;
+
+impl fmt::Display for Cancelled {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("service call cancelled")
+    }
+}
+
+impl std::error::Error for Cancelled {}
+
+/// A signal cancelling the calls of a [`Cancellation`].
+///
+/// This is implemented by [`CancellationToken`], and by the closures returning a
+/// future completing once the calls should be cancelled,
+/// `Fn() -> impl Future<Output = ()>`.
+pub trait CancelSignal {
+    /// Returns a future completing once the signal fires.
+    #[cfg(feature = "service_send")]
+    fn cancelled(&self) -> impl Future<Output = ()> + Send;
+
+    /// Returns a future completing once the signal fires.
+    #[cfg(not(feature = "service_send"))]
+    fn cancelled(&self) -> impl Future<Output = ()>;
+}
+
+#[cfg(feature = "service_send")]
+impl<F, Fut> CancelSignal for F
+where
+    F: Fn() -> Fut,
+    Fut: Future<Output = ()> + Send,
+{
+    fn cancelled(&self) -> impl Future<Output = ()> + Send {
+        self()
+    }
+}
+
+#[cfg(not(feature = "service_send"))]
+impl<F, Fut> CancelSignal for F
+where
+    F: Fn() -> Fut,
+    Fut: Future<Output = ()>,
+{
+    fn cancelled(&self) -> impl Future<Output = ()> {
+        self()
+    }
+}
+
+/// A token cancelling the calls made with it once [`cancel`](Self::cancel) is
+/// called.
+///
+/// Clones share the same state, so cancelling one cancels them all.
+#[derive(Clone)]
+pub struct CancellationToken {
+    cancelled: Arc<watch::Sender<bool>>,
+}
+
+impl CancellationToken {
+    /// Creates a token that isn't cancelled.
+    pub fn new() -> Self {
+        CancellationToken {
+            cancelled: Arc::new(watch::Sender::new(false)),
+        }
+    }
+
+    /// Cancels the token. Calling it again does nothing.
+    pub fn cancel(&self) {
+        self.cancelled
+            .send_if_modified(|cancelled| !std::mem::replace(cancelled, true));
+    }
+
+    /// Returns whether the token was cancelled.
+    pub fn is_cancelled(&self) -> bool {
+        *self.cancelled.borrow()
+    }
+
+    /// Resolves once the token is cancelled.
+    pub async fn cancelled(&self) {
+        let mut rx = self.cancelled.subscribe();
+        // The sender is kept alive by `self`, so this never fails.
+        let _ = rx.wait_for(|cancelled| *cancelled).await;
+    }
+}
+
+impl Default for CancellationToken {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl fmt::Debug for CancellationToken {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("CancellationToken")
+            .field("cancelled", &self.is_cancelled())
+            .finish()
+    }
+}
+
+impl CancelSignal for CancellationToken {
+    #[cfg(feature = "service_send")]
+    fn cancelled(&self) -> impl Future<Output = ()> + Send {
+        CancellationToken::cancelled(self)
+    }
+
+    #[cfg(not(feature = "service_send"))]
+    fn cancelled(&self) -> impl Future<Output = ()> {
+        CancellationToken::cancelled(self)
+    }
+}
+
+/// Fails the calls to the inner service with [`Cancelled`] once a
+/// [`CancelSignal`] fires.
+///
+/// The calls started after the signal fired fail right away. See the [module
+/// level docs](self) for details.
+#[derive(Clone)]
+pub struct Cancellation<S, C> {
+    inner: S,
+    signal: C,
+}
+
+impl<S, C> Cancellation<S, C> {
+    /// Creates a `Cancellation` cancelling the calls once `signal` fires.
+    pub const fn new(inner: S, signal: C) -> Self {
+        Cancellation { inner, signal }
+    }
+}
+
+impl<Cx, Req, S, C> Service<Cx, Req> for Cancellation<S, C>
+where
+    Req: 'static + MaybeSend,
+    S: Service<Cx, Req> + 'static + MaybeSend + MaybeSync,
+    C: CancelSignal + MaybeSync,
+    Cx: 'static + MaybeSend,
+    S::Error: MaybeSend + MaybeSync + Into<BoxError>,
+{
+    type Response = S::Response;
+
+    type Error = BoxError;
+
+    async fn call(&self, cx: &mut Cx, req: Req) -> Result<Self::Response, Self::Error> {
+        let cancelled = self.signal.cancelled();
+        tokio::select! {
+            biased;
+            _ = cancelled => Err(Cancelled.into()),
+            r = self.inner.call(cx, req) => r.map_err(Into::into),
+        }
+    }
+}
+
+impl<S, C> Ready for Cancellation<S, C>
+where
+    S: Ready,
+{
+    fn poll_ready(&self, cx: &mut Context<'_>) -> Poll<()> {
+        self.inner.poll_ready(cx)
+    }
+}
+
+impl<S, C> Load for Cancellation<S, C>
+where
+    S: Load,
+{
+    type Metric = S::Metric;
+
+    fn load(&self) -> Self::Metric {
+        self.inner.load()
+    }
+}
+
+impl<S: fmt::Debug, C> fmt::Debug for Cancellation<S, C> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Cancellation")
+            .field("inner", &self.inner)
+            .field("signal", &format_args!("{}", std::any::type_name::<C>()))
+            .finish()
+    }
+}
+
+/// Applies a [`Cancellation`] to a service.
+///
+/// # Example
+///
+/// ```rust
+/// use std::time::Duration;
+///
+/// use motore::{
+///     builder::ServiceBuilder,
+///     cancel::{CancellationLayer, CancellationToken, Cancelled},
+///     service::service_fn,
+///     BoxError, Service,
+/// };
+///
+/// # #[tokio::main(flavor = "current_thread")]
+/// # async fn main() {
+/// async fn slow(_cx: &mut (), req: String) -> Result<String, BoxError> {
+///     tokio::time::sleep(Duration::from_secs(60)).await;
+///     Ok(req)
+/// }
+///
+/// // Cancelled once the connection closes.
+/// let token = CancellationToken::new();
+/// let svc = ServiceBuilder::new()
+///     .layer(CancellationLayer::new(token.clone()))
+///     .service(service_fn(slow));
+///
+/// token.cancel();
+/// let err = svc.call(&mut (), "ping".into()).await.unwrap_err();
+/// assert!(err.is::<Cancelled>());
+/// # }
+/// ```
+#[derive(Clone)]
+pub struct CancellationLayer<C> {
+    signal: C,
+}
+
+impl<C> CancellationLayer<C> {
+    /// Creates a layer cancelling the calls once `signal` fires.
+    pub const fn new(signal: C) -> Self {
+        CancellationLayer { signal }
+    }
+}
+
+impl<C> fmt::Debug for CancellationLayer<C> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("CancellationLayer")
+            .field("signal", &format_args!("{}", std::any::type_name::<C>()))
+            .finish()
+    }
+}
+
+impl<S, C> Layer<S> for CancellationLayer<C> {
+    type Service = Cancellation<S, C>;
+
+    fn layer(self, inner: S) -> Self::Service {
+        Cancellation::new(inner, self.signal)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{convert::Infallible, time::Duration};
+
+    use futures::FutureExt;
+
+    use super::*;
+    use crate::{service::service_fn, ServiceExt};
+
+    fn sleeper() -> impl Service<(), u64, Response = u64, Error = Infallible> + Clone {
+        service_fn(|_cx: &mut (), millis: u64| async move {
+            tokio::time::sleep(Duration::from_millis(millis)).await;
+            Ok::<_, Infallible>(millis)
+        })
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn cancels_the_calls_in_flight() {
+        let token = CancellationToken::new();
+        let svc = sleeper().with_cancellation(token.clone());
+
+        assert_eq!(svc.call(&mut (), 10).await.unwrap(), 10);
+
+        let cancel = async {
+            tokio::time::sleep(Duration::from_millis(20)).await;
+            token.cancel();
+        };
+        let start = tokio::time::Instant::now();
+        let (res, ()) = tokio::join!(async { svc.call(&mut (), 1000).await }, cancel);
+        assert!(res.unwrap_err().is::<Cancelled>());
+        assert_eq!(start.elapsed(), Duration::from_millis(20));
+
+        // Already cancelled.
+        assert!(svc.call(&mut (), 0).await.unwrap_err().is::<Cancelled>());
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn cancels_with_a_future() {
+        let (tx, rx) = tokio::sync::oneshot::channel::<()>();
+        let rx = rx.map(|_| ()).shared();
+        let svc = CancellationLayer::new(move || rx.clone()).layer(sleeper());
+
+        assert_eq!(svc.call(&mut (), 10).await.unwrap(), 10);
+        drop(tx);
+        assert!(svc.call(&mut (), 10).await.unwrap_err().is::<Cancelled>());
+    }
+}
@@ -0,0 +1,224 @@
+//! Turns the panics of a service into errors.
+//!
+//! A handler panicking unwinds through everything driving it, so a single bad
+//! request can bring down the task accepting connections along with every request
+//! it serves. [`CatchPanic`] catches the panics of the inner service, both when it
+//! is called and while its future is polled, and fails the call with a
+//! [`PanicError`] instead.
+//!
+//! Unlike [`Spawn`](crate::spawn::Spawn), the call stays on the caller's task.
+//! Nothing can be caught when panics abort the process.
+
+use std::{
+    any::Any,
+    fmt,
+    panic::{self, AssertUnwindSafe},
+    task::{Context, Poll},
+};
+
+use futures::FutureExt;
+
+use crate::{
+    layer::Layer,
+    load::{Load, Ready},
+    service::Service,
+    BoxError, MaybeSend, MaybeSync,
+};
+
+/// The error returned by [`CatchPanic`] when the inner service panicked.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct PanicError {
+    /// The message of the panic, when its payload is a string.
+    pub message: String,
+}
+
+impl fmt::Display for PanicError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "service panicked: {}", self.message)
+    }
+}
+
+impl std::error::Error for PanicError {}
+
+pub(crate) fn panic_message(payload: Box<dyn Any + Send>) -> String {
+    match payload.downcast::<String>() {
+        Ok(msg) => *msg,
+        Err(payload) => match payload.downcast::<&'static str>() {
+            Ok(msg) => msg.to_string(),
+            Err(_) => "Box<dyn Any>".to_string(),
+        },
+    }
+}
+
+/// Catches the panics of the inner service, failing the call with a [`PanicError`].
+///
+/// The inner service, the context and the request are assumed to be
+/// [unwind safe](std::panic::UnwindSafe): a service whose state can be left
+/// inconsistent by a panic must not rely on it after the panic was caught.
+#[derive(Clone, Debug)]
+pub struct CatchPanic<S> {
+    inner: S,
+}
+
+impl<S> CatchPanic<S> {
+    pub const fn new(inner: S) -> Self {
+        CatchPanic { inner }
+    }
+}
+
+impl<Cx, Req, S> Service<Cx, Req> for CatchPanic<S>
+where
+    Req: 'static + MaybeSend,
+    S: Service<Cx, Req> + 'static + MaybeSend + MaybeSync,
+    Cx: 'static + MaybeSend,
+    S::Error: MaybeSend + MaybeSync + Into<BoxError>,
+{
+    type Response = S::Response;
+
+    type Error = BoxError;
+
+    async fn call(&self, cx: &mut Cx, req: Req) -> Result<Self::Response, Self::Error> {
+        let res = match panic::catch_unwind(AssertUnwindSafe(move || self.inner.call(cx, req))) {
+            Ok(fut) => AssertUnwindSafe(fut).catch_unwind().await,
+            Err(payload) => Err(payload),
+        };
+        match res {
+            Ok(res) => res.map_err(Into::into),
+            Err(payload) => Err(PanicError {
+                message: panic_message(payload),
+            }
+            .into()),
+        }
+    }
+}
+
+impl<S> Ready for CatchPanic<S>
+where
+    S: Ready,
+{
+    fn poll_ready(&self, cx: &mut Context<'_>) -> Poll<()> {
+        self.inner.poll_ready(cx)
+    }
+}
+
+impl<S> Load for CatchPanic<S>
+where
+    S: Load,
+{
+    type Metric = S::Metric;
+
+    fn load(&self) -> Self::Metric {
+        self.inner.load()
+    }
+}
+
+/// Applies a [`CatchPanic`] to a service.
+///
+/// # Example
+///
+/// ```rust
+/// use motore::{
+///     builder::ServiceBuilder,
+///     catch_panic::{CatchPanicLayer, PanicError},
+///     service::service_fn,
+///     BoxError, Service,
+/// };
+///
+/// # #[tokio::main(flavor = "current_thread")]
+/// # async fn main() {
+/// async fn handle(_cx: &mut (), req: Vec<u8>) -> Result<u8, BoxError> {
+///     Ok(req[0])
+/// }
+///
+/// let svc = ServiceBuilder::new()
+///     .layer(CatchPanicLayer::new())
+///     .service(service_fn(handle));
+///
+/// assert_eq!(svc.call(&mut (), vec![1]).await.unwrap(), 1);
+/// let err = svc.call(&mut (), vec![]).await.unwrap_err();
+/// assert!(err.is::<PanicError>());
+/// # }
+/// ```
+#[derive(Clone, Copy, Debug, Default)]
+pub struct CatchPanicLayer;
+
+impl CatchPanicLayer {
+    pub const fn new() -> Self {
+        CatchPanicLayer
+    }
+}
+
+impl<S> Layer<S> for CatchPanicLayer {
+    type Service = CatchPanic<S>;
+
+    fn layer(self, inner: S) -> Self::Service {
+        CatchPanic::new(inner)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::service::service_fn;
+
+    struct PanicOnCall;
+
+    impl Service<(), ()> for PanicOnCall {
+        type Response = ();
+
+        type Error = BoxError;
+
+        #[allow(clippy::manual_async_fn)]
+        #[cfg(feature = "service_send")]
+        fn call(
+            &self,
+            _cx: &mut (),
+            _req: (),
+        ) -> impl std::future::Future<Output = Result<(), BoxError>> + Send {
+            panic!("not even a future");
+            #[allow(unreachable_code)]
+            async {
+                Ok(())
+            }
+        }
+
+        #[allow(clippy::manual_async_fn)]
+        #[cfg(not(feature = "service_send"))]
+        fn call(
+            &self,
+            _cx: &mut (),
+            _req: (),
+        ) -> impl std::future::Future<Output = Result<(), BoxError>> {
+            panic!("not even a future");
+            #[allow(unreachable_code)]
+            async {
+                Ok(())
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn catches_panics() {
+        let svc = CatchPanicLayer::new().layer(service_fn(|_cx: &mut (), req: u32| async move {
+            if req == 0 {
+                panic!("request {req} is invalid");
+            }
+            Ok::<_, BoxError>(req)
+        }));
+
+        assert_eq!(svc.call(&mut (), 1).await.unwrap(), 1);
+        let err = svc.call(&mut (), 0).await.unwrap_err();
+        assert_eq!(
+            err.downcast_ref::<PanicError>(),
+            Some(&PanicError {
+                message: "request 0 is invalid".to_string()
+            })
+        );
+        // The service still works after a panic.
+        assert_eq!(svc.call(&mut (), 2).await.unwrap(), 2);
+
+        let svc = CatchPanic::new(PanicOnCall);
+        let err = svc.call(&mut (), ()).await.unwrap_err();
+        assert_eq!(err.to_string(), "service panicked: not even a future");
+    }
+}
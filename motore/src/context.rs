@@ -0,0 +1,227 @@
+//! Tools for the contexts the services are called with.
+//!
+//! Motore lets every application pick its own context type, so a middleware can't
+//! know where to keep the data it attaches to a request. A context implementing
+//! [`ExtensionsMut`] carries an [`Extensions`] map, holding one value of each
+//! type: a middleware stores its data there under a type of its own, and the
+//! layers below and the handler read it back by type, whatever the context.
+
+use std::{
+    any::{Any, TypeId},
+    collections::HashMap,
+    fmt,
+};
+
+use crate::{MaybeSend, MaybeSync};
+
+#[cfg(feature = "service_send")]
+type AnyMap = HashMap<TypeId, Box<dyn Any + Send + Sync>>;
+#[cfg(not(feature = "service_send"))]
+type AnyMap = HashMap<TypeId, Box<dyn Any>>;
+
+/// A map holding one value of each type, to attach data to a request.
+///
+/// The values are usually of types private to the middleware storing them, or
+/// newtypes, so the middlewares don't overwrite each other's values.
+/// With the `service_send` feature, the values need to be [`Send`] and [`Sync`].
+///
+/// # Example
+///
+/// ```rust
+/// use motore::context::Extensions;
+///
+/// #[derive(Debug, PartialEq)]
+/// struct TenantId(u32);
+///
+/// let mut extensions = Extensions::new();
+/// assert!(extensions.insert(TenantId(1)).is_none());
+/// assert_eq!(extensions.get::<TenantId>(), Some(&TenantId(1)));
+///
+/// extensions.get_mut::<TenantId>().unwrap().0 += 1;
+/// assert_eq!(extensions.remove::<TenantId>(), Some(TenantId(2)));
+/// assert!(extensions.is_empty());
+/// ```
+#[derive(Default)]
+pub struct Extensions {
+    // Boxed lazily, so that contexts without extensions stay small and cheap to
+    // create.
+    map: Option<Box<AnyMap>>,
+}
+
+impl Extensions {
+    /// Creates an empty map, without allocating.
+    pub const fn new() -> Self {
+        Extensions { map: None }
+    }
+
+    /// Inserts a value, returning the value of the same type it replaces.
+    pub fn insert<T: MaybeSend + MaybeSync + 'static>(&mut self, value: T) -> Option<T> {
+        self.map
+            .get_or_insert_with(Default::default)
+            .insert(TypeId::of::<T>(), Box::new(value))
+            .and_then(|prev| prev.downcast().ok().map(|prev| *prev))
+    }
+
+    /// Returns a reference to the value of type `T`.
+    pub fn get<T: MaybeSend + MaybeSync + 'static>(&self) -> Option<&T> {
+        self.map
+            .as_ref()?
+            .get(&TypeId::of::<T>())
+            .and_then(|value| value.downcast_ref())
+    }
+
+    /// Returns a mutable reference to the value of type `T`.
+    pub fn get_mut<T: MaybeSend + MaybeSync + 'static>(&mut self) -> Option<&mut T> {
+        self.map
+            .as_mut()?
+            .get_mut(&TypeId::of::<T>())
+            .and_then(|value| value.downcast_mut())
+    }
+
+    /// Returns a mutable reference to the value of type `T`, inserting the value
+    /// returned by `f` first if there is none.
+    pub fn get_or_insert_with<T, F>(&mut self, f: F) -> &mut T
+    where
+        T: MaybeSend + MaybeSync + 'static,
+        F: FnOnce() -> T,
+    {
+        self.map
+            .get_or_insert_with(Default::default)
+            .entry(TypeId::of::<T>())
+            .or_insert_with(|| Box::new(f()))
+            .downcast_mut()
+            .expect("the value is stored under its own type")
+    }
+
+    /// Removes the value of type `T` and returns it.
+    pub fn remove<T: MaybeSend + MaybeSync + 'static>(&mut self) -> Option<T> {
+        self.map
+            .as_mut()?
+            .remove(&TypeId::of::<T>())
+            .and_then(|value| value.downcast().ok().map(|value| *value))
+    }
+
+    /// Returns whether the map holds a value of type `T`.
+    pub fn contains<T: MaybeSend + MaybeSync + 'static>(&self) -> bool {
+        self.map
+            .as_ref()
+            .is_some_and(|map| map.contains_key(&TypeId::of::<T>()))
+    }
+
+    /// Returns the number of values in the map.
+    pub fn len(&self) -> usize {
+        self.map.as_ref().map_or(0, |map| map.len())
+    }
+
+    /// Returns whether the map is empty.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Removes all the values.
+    pub fn clear(&mut self) {
+        if let Some(map) = &mut self.map {
+            map.clear();
+        }
+    }
+
+    /// Moves all the values of `other` into this map, replacing the values of the
+    /// same types.
+    pub fn extend(&mut self, other: Extensions) {
+        if let Some(other) = other.map {
+            match &mut self.map {
+                Some(map) => map.extend(*other),
+                None => self.map = Some(other),
+            }
+        }
+    }
+}
+
+impl fmt::Debug for Extensions {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Extensions")
+            .field("len", &self.len())
+            .finish()
+    }
+}
+
+/// A context carrying [`Extensions`].
+///
+/// Implementing it lets the context be used with the middlewares storing their
+/// data in the extensions.
+///
+/// # Example
+///
+/// ```rust
+/// use motore::context::{Extensions, ExtensionsMut};
+///
+/// #[derive(Default)]
+/// struct Cx {
+///     method: &'static str,
+///     extensions: Extensions,
+/// }
+///
+/// impl ExtensionsMut for Cx {
+///     fn extensions(&self) -> &Extensions {
+///         &self.extensions
+///     }
+///
+///     fn extensions_mut(&mut self) -> &mut Extensions {
+///         &mut self.extensions
+///     }
+/// }
+/// ```
+pub trait ExtensionsMut {
+    /// Returns the extensions of the context.
+    fn extensions(&self) -> &Extensions;
+
+    /// Returns the extensions of the context, mutably.
+    fn extensions_mut(&mut self) -> &mut Extensions;
+}
+
+impl ExtensionsMut for Extensions {
+    fn extensions(&self) -> &Extensions {
+        self
+    }
+
+    fn extensions_mut(&mut self) -> &mut Extensions {
+        self
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[derive(Debug, PartialEq)]
+    struct A(u32);
+
+    #[derive(Debug, PartialEq)]
+    struct B(&'static str);
+
+    #[test]
+    fn holds_a_value_per_type() {
+        let mut extensions = Extensions::new();
+        assert!(extensions.get::<A>().is_none());
+        assert_eq!(extensions.insert(A(1)), None);
+        assert_eq!(extensions.insert(B("b")), None);
+        assert_eq!(extensions.insert(A(2)), Some(A(1)));
+        assert_eq!(extensions.len(), 2);
+        assert_eq!(extensions.get(), Some(&A(2)));
+        assert_eq!(extensions.get(), Some(&B("b")));
+
+        *extensions.get_or_insert_with(|| A(0)) = A(3);
+        assert_eq!(extensions.remove(), Some(A(3)));
+        assert!(!extensions.contains::<A>());
+        assert_eq!(extensions.get_or_insert_with(|| A(4)), &mut A(4));
+
+        let mut other = Extensions::new();
+        other.insert(B("c"));
+        extensions.extend(other);
+        assert_eq!(extensions.get(), Some(&B("c")));
+        assert_eq!(extensions.len(), 2);
+
+        extensions.clear();
+        assert!(extensions.is_empty());
+    }
+}
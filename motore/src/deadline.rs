@@ -0,0 +1,377 @@
+//! Propagates a deadline through the context.
+//!
+//! A [`Timeout`](crate::timeout::Timeout) bounds a single call, but a request
+//! usually goes through several services, each making its own calls, while its
+//! caller only waits for so long overall. [`DeadlineLayer`] records the point in
+//! time after which the caller no longer waits, a [`Deadline`], in the context, and
+//! every [`WithDeadline`] service the context goes through enforces it: a request
+//! whose deadline has passed fails with [`DeadlineExceeded`] instead of keeping
+//! services busy, and clients can send the remaining time along with the request.
+//!
+//! The deadline is a value of the [`Extensions`](crate::context::Extensions) of the
+//! contexts implementing [`ExtensionsMut`]. A context can also keep it elsewhere
+//! by implementing [`DeadlineContext`] itself.
+
+use std::{
+    fmt,
+    task::{Context, Poll},
+    time::Duration,
+};
+
+use crate::{
+    context::ExtensionsMut,
+    layer::Layer,
+    load::{Load, Ready},
+    service::Service,
+    timer::{DefaultTimer, Instant, Timer},
+    BoxError, MaybeSend, MaybeSync,
+};
+
+/// The point in time after which the caller of a request no longer waits for it.
+///
+/// The deadline is an [`Instant`] of the [`Timer`] measuring it, so
+/// [`remaining`](Deadline::remaining) and [`is_expired`](Deadline::is_expired)
+/// take the current time of that timer.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Deadline(Instant);
+
+impl Deadline {
+    /// Creates a deadline at `instant`.
+    pub const fn at(instant: Instant) -> Self {
+        Deadline(instant)
+    }
+
+    /// Returns the point in time of the deadline.
+    pub const fn instant(&self) -> Instant {
+        self.0
+    }
+
+    /// Returns the time left at `now`, zero once the deadline has passed.
+    pub fn remaining(&self, now: Instant) -> Duration {
+        self.0.saturating_duration_since(now)
+    }
+
+    /// Returns whether the deadline has passed at `now`.
+    pub fn is_expired(&self, now: Instant) -> bool {
+        self.0 <= now
+    }
+}
+
+/// A context carrying the [`Deadline`] of its request.
+///
+/// It is implemented for every context implementing [`ExtensionsMut`], which keeps
+/// the deadline in its [`Extensions`](crate::context::Extensions).
+pub trait DeadlineContext {
+    /// Returns the deadline of the request, if it has one.
+    fn deadline(&self) -> Option<Deadline>;
+
+    /// Sets the deadline of the request.
+    fn set_deadline(&mut self, deadline: Option<Deadline>);
+}
+
+impl<Cx: ExtensionsMut + ?Sized> DeadlineContext for Cx {
+    fn deadline(&self) -> Option<Deadline> {
+        self.extensions().get::<Deadline>().copied()
+    }
+
+    fn set_deadline(&mut self, deadline: Option<Deadline>) {
+        let extensions = self.extensions_mut();
+        match deadline {
+            Some(deadline) => extensions.insert(deadline),
+            None => extensions.remove::<Deadline>(),
+        };
+    }
+}
+
+/// Gives the context its previous deadline back when dropped, even if the call
+/// setting a tighter one is cancelled.
+pub(crate) struct DeadlineGuard<'a, Cx: DeadlineContext> {
+    pub(crate) cx: &'a mut Cx,
+    previous: Option<Deadline>,
+}
+
+impl<'a, Cx: DeadlineContext> DeadlineGuard<'a, Cx> {
+    /// Sets the deadline of `cx` until the guard is dropped.
+    pub(crate) fn set(cx: &'a mut Cx, deadline: Deadline) -> Self {
+        let previous = cx.deadline();
+        cx.set_deadline(Some(deadline));
+        DeadlineGuard { cx, previous }
+    }
+}
+
+impl<Cx: DeadlineContext> Drop for DeadlineGuard<'_, Cx> {
+    fn drop(&mut self) {
+        self.cx.set_deadline(self.previous);
+    }
+}
+
+/// The error returned by [`WithDeadline`] when the deadline of a request passed.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct DeadlineExceeded;
+
+impl fmt::Display for DeadlineExceeded {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("deadline exceeded")
+    }
+}
+
+impl std::error::Error for DeadlineExceeded {}
+
+/// Enforces the [`Deadline`] of the requests, optionally setting a tighter one.
+///
+/// The deadline set by a `WithDeadline` only applies to the inner service: once
+/// the call completed or was dropped, the context gets back its previous
+/// deadline. See the [module level docs](self) for details.
+#[derive(Clone, Debug)]
+pub struct WithDeadline<S, T = DefaultTimer> {
+    inner: S,
+    timeout: Option<Duration>,
+    timer: T,
+}
+
+impl<S> WithDeadline<S> {
+    /// Creates a `WithDeadline` enforcing the deadline of the context, and setting
+    /// it to `timeout` from now if that is sooner.
+    pub const fn new(inner: S, timeout: Option<Duration>) -> Self {
+        WithDeadline {
+            inner,
+            timeout,
+            timer: DefaultTimer::new(),
+        }
+    }
+}
+
+impl<S, T> WithDeadline<S, T> {
+    /// Sets the timer measuring the deadlines, [`DefaultTimer`] by default.
+    pub fn timer<U>(self, timer: U) -> WithDeadline<S, U> {
+        WithDeadline {
+            inner: self.inner,
+            timeout: self.timeout,
+            timer,
+        }
+    }
+}
+
+impl<Cx, Req, S, T> Service<Cx, Req> for WithDeadline<S, T>
+where
+    Req: 'static + MaybeSend,
+    S: Service<Cx, Req> + 'static + MaybeSend + MaybeSync,
+    Cx: DeadlineContext + 'static + MaybeSend,
+    S::Error: MaybeSend + MaybeSync + Into<BoxError>,
+    T: Timer + MaybeSync,
+{
+    type Response = S::Response;
+
+    type Error = BoxError;
+
+    async fn call(&self, cx: &mut Cx, req: Req) -> Result<Self::Response, Self::Error> {
+        let now = self.timer.now();
+        let deadline = match (cx.deadline(), self.timeout.map(|t| Deadline::at(now + t))) {
+            (Some(previous), Some(deadline)) => Some(previous.min(deadline)),
+            (previous, deadline) => previous.or(deadline),
+        };
+        let Some(deadline) = deadline else {
+            return self.inner.call(cx, req).await.map_err(Into::into);
+        };
+        if deadline.is_expired(now) {
+            return Err(DeadlineExceeded.into());
+        }
+
+        let guard = DeadlineGuard::set(cx, deadline);
+        tokio::select! {
+            res = self.inner.call(guard.cx, req) => res.map_err(Into::into),
+            _ = self.timer.sleep(deadline.remaining(now)) => Err(DeadlineExceeded.into()),
+        }
+    }
+}
+
+impl<S, T> Ready for WithDeadline<S, T>
+where
+    S: Ready,
+{
+    fn poll_ready(&self, cx: &mut Context<'_>) -> Poll<()> {
+        self.inner.poll_ready(cx)
+    }
+}
+
+impl<S, T> Load for WithDeadline<S, T>
+where
+    S: Load,
+{
+    type Metric = S::Metric;
+
+    fn load(&self) -> Self::Metric {
+        self.inner.load()
+    }
+}
+
+/// Applies a [`WithDeadline`] to a service.
+///
+/// # Example
+///
+/// ```rust
+/// use std::time::Duration;
+///
+/// use motore::{
+///     builder::ServiceBuilder,
+///     context::{Extensions, ExtensionsMut},
+///     deadline::{DeadlineContext, DeadlineLayer},
+///     service::service_fn,
+///     timer::{DefaultTimer, Timer},
+///     BoxError, Service,
+/// };
+///
+/// #[derive(Default)]
+/// struct Cx {
+///     extensions: Extensions,
+/// }
+///
+/// impl ExtensionsMut for Cx {
+///     fn extensions(&self) -> &Extensions {
+///         &self.extensions
+///     }
+///
+///     fn extensions_mut(&mut self) -> &mut Extensions {
+///         &mut self.extensions
+///     }
+/// }
+///
+/// async fn handle(cx: &mut Cx, req: String) -> Result<String, BoxError> {
+///     // A client would send the time left to the backend.
+///     let remaining = cx.deadline().unwrap().remaining(DefaultTimer::new().now());
+///     assert!(remaining <= Duration::from_secs(1));
+///     Ok(req)
+/// }
+///
+/// # #[tokio::main(flavor = "current_thread")]
+/// # async fn main() {
+/// let svc = ServiceBuilder::new()
+///     .layer(DeadlineLayer::new().timeout(Duration::from_secs(1)))
+///     .service(service_fn(handle));
+///
+/// let mut cx = Cx::default();
+/// assert_eq!(svc.call(&mut cx, "ping".into()).await.unwrap(), "ping");
+/// assert!(cx.deadline().is_none());
+/// # }
+/// ```
+#[derive(Clone, Copy, Debug, Default)]
+pub struct DeadlineLayer<T = DefaultTimer> {
+    timeout: Option<Duration>,
+    timer: T,
+}
+
+impl DeadlineLayer {
+    /// Creates a layer enforcing the deadline already in the context, if any.
+    pub const fn new() -> Self {
+        DeadlineLayer {
+            timeout: None,
+            timer: DefaultTimer::new(),
+        }
+    }
+}
+
+impl<T> DeadlineLayer<T> {
+    /// Sets the deadline to `timeout` from the start of each call, unless the
+    /// context already has a sooner one.
+    pub const fn timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = Some(timeout);
+        self
+    }
+
+    /// Sets the timer measuring the deadlines, [`DefaultTimer`] by default.
+    pub fn timer<U>(self, timer: U) -> DeadlineLayer<U> {
+        DeadlineLayer {
+            timeout: self.timeout,
+            timer,
+        }
+    }
+}
+
+impl<S, T> Layer<S> for DeadlineLayer<T> {
+    type Service = WithDeadline<S, T>;
+
+    fn layer(self, inner: S) -> Self::Service {
+        WithDeadline {
+            inner,
+            timeout: self.timeout,
+            timer: self.timer,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{builder::ServiceBuilder, service::service_fn};
+
+    #[derive(Default)]
+    struct Cx {
+        deadline: Option<Deadline>,
+        seen: Option<Duration>,
+    }
+
+    impl DeadlineContext for Cx {
+        fn deadline(&self) -> Option<Deadline> {
+            self.deadline
+        }
+
+        fn set_deadline(&mut self, deadline: Option<Deadline>) {
+            self.deadline = deadline;
+        }
+    }
+
+    async fn sleep(cx: &mut Cx, millis: u64) -> Result<(), BoxError> {
+        let now = DefaultTimer::new().now();
+        cx.seen = cx.deadline.map(|deadline| deadline.remaining(now));
+        tokio::time::sleep(Duration::from_millis(millis)).await;
+        Ok(())
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn nested_calls_keep_the_sooner_deadline() {
+        let svc = ServiceBuilder::new()
+            .layer(DeadlineLayer::new().timeout(Duration::from_millis(100)))
+            .layer(DeadlineLayer::new().timeout(Duration::from_millis(500)))
+            .service(service_fn(sleep));
+
+        let mut cx = Cx::default();
+        svc.call(&mut cx, 50).await.unwrap();
+        assert_eq!(cx.seen, Some(Duration::from_millis(100)));
+        assert_eq!(cx.deadline, None);
+
+        let err = svc.call(&mut cx, 150).await.unwrap_err();
+        assert!(err.downcast_ref::<DeadlineExceeded>().is_some());
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn expired_requests_are_not_called() {
+        let svc = DeadlineLayer::new().layer(service_fn(sleep));
+
+        let mut cx = Cx {
+            deadline: Some(Deadline::at(
+                DefaultTimer::new().now() + Duration::from_millis(10),
+            )),
+            seen: None,
+        };
+        tokio::time::advance(Duration::from_millis(10)).await;
+        let err = svc.call(&mut cx, 0).await.unwrap_err();
+        assert!(err.downcast_ref::<DeadlineExceeded>().is_some());
+        assert_eq!(cx.seen, None);
+
+        // Without a deadline, nothing is enforced.
+        cx.deadline = None;
+        svc.call(&mut cx, 1000).await.unwrap();
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn dropped_calls_restore_the_deadline() {
+        let svc = DeadlineLayer::new()
+            .timeout(Duration::from_millis(100))
+            .layer(service_fn(sleep));
+
+        let mut cx = Cx::default();
+        let call = tokio::time::timeout(Duration::from_millis(10), svc.call(&mut cx, 50));
+        assert!(call.await.is_err());
+        assert_eq!(cx.deadline, None);
+    }
+}
@@ -0,0 +1,90 @@
+use std::{
+    convert::Infallible,
+    iter::Enumerate,
+    pin::Pin,
+    task::{Context, Poll},
+    vec,
+};
+
+use futures::Stream;
+
+use super::Change;
+
+/// A [`Discover`](super::Discover) of a fixed list of services, keyed by their
+/// index in the list.
+///
+/// The services are inserted one after the other, then the stream stays pending
+/// rather than ending, like a discovery nothing changes. This is useful in tests
+/// and for deployments with a fixed set of hosts.
+///
+/// # Example
+///
+/// ```rust
+/// use motore::discover::{Change, Discover, ServiceList};
+///
+/// # #[tokio::main(flavor = "current_thread")]
+/// # async fn main() {
+/// let mut discover = ServiceList::new(["a", "b"]);
+/// assert_eq!(discover.next_change().await, Some(Ok(Change::Insert(0, "a"))));
+/// assert_eq!(discover.next_change().await, Some(Ok(Change::Insert(1, "b"))));
+/// # }
+/// ```
+#[derive(Clone, Debug)]
+pub struct ServiceList<S> {
+    services: Enumerate<vec::IntoIter<S>>,
+}
+
+impl<S> ServiceList<S> {
+    /// Creates a discovery of `services`.
+    pub fn new(services: impl IntoIterator<Item = S>) -> Self {
+        ServiceList {
+            services: services
+                .into_iter()
+                .collect::<Vec<_>>()
+                .into_iter()
+                .enumerate(),
+        }
+    }
+}
+
+impl<S> FromIterator<S> for ServiceList<S> {
+    fn from_iter<I: IntoIterator<Item = S>>(iter: I) -> Self {
+        Self::new(iter)
+    }
+}
+
+impl<S> Stream for ServiceList<S> {
+    type Item = Result<Change<usize, S>, Infallible>;
+
+    fn poll_next(mut self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        match self.services.next() {
+            Some((key, service)) => Poll::Ready(Some(Ok(Change::Insert(key, service)))),
+            None => Poll::Pending,
+        }
+    }
+}
+
+// `Enumerate<vec::IntoIter<S>>` is `Unpin` for any `S`, but doesn't say so.
+impl<S> Unpin for ServiceList<S> {}
+
+#[cfg(test)]
+mod tests {
+    use futures::{task::noop_waker_ref, StreamExt};
+
+    use super::*;
+
+    #[test]
+    fn inserts_the_services_then_stays_pending() {
+        let mut discover: ServiceList<_> = ["a", "b"].into_iter().collect();
+        let mut cx = Context::from_waker(noop_waker_ref());
+        assert_eq!(
+            discover.poll_next_unpin(&mut cx),
+            Poll::Ready(Some(Ok(Change::Insert(0, "a"))))
+        );
+        assert_eq!(
+            discover.poll_next_unpin(&mut cx),
+            Poll::Ready(Some(Ok(Change::Insert(1, "b"))))
+        );
+        assert_eq!(discover.poll_next_unpin(&mut cx), Poll::Pending);
+    }
+}
@@ -0,0 +1,140 @@
+//! Service discovery, the endpoints a load balancer spreads the requests across.
+//!
+//! A [`Discover`] is a stream of [`Change`]s to a set of services keyed by an ID,
+//! like the address of each endpoint: a service is inserted when an endpoint
+//! appears, replaced when it is inserted again under the same key, and removed
+//! when the endpoint goes away. Any [`TryStream`] of [`Change`]s is a
+//! [`Discover`], so a registry client only needs to produce such a stream to
+//! feed the balancers.
+//!
+//! [`ServiceList`] discovers a fixed list of services, and [`WatchDiscover`] the
+//! endpoints in a stream of snapshots, like a `watch` channel updated from a
+//! config file or a registry.
+
+use std::{
+    fmt,
+    future::Future,
+    pin::Pin,
+    task::{Context, Poll},
+};
+
+use futures::TryStream;
+
+use crate::sealed::Sealed;
+
+mod list;
+mod watch;
+pub use self::{list::ServiceList, watch::WatchDiscover};
+
+/// A change to the set of services of a [`Discover`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Change<K, V> {
+    /// Inserts the service under the key, replacing the one already there.
+    Insert(K, V),
+    /// Removes the service under the key.
+    Remove(K),
+}
+
+impl<K, V> Change<K, V> {
+    /// Returns the key of the changed service.
+    pub fn key(&self) -> &K {
+        match self {
+            Change::Insert(key, _) | Change::Remove(key) => key,
+        }
+    }
+}
+
+/// A stream of [`Change`]s to a set of services keyed by [`Key`](Discover::Key).
+///
+/// This is implemented by every [`TryStream`] of [`Change`]s, and sealed. The
+/// stream ends when no more change will come, and the balancers then keep the
+/// services discovered so far.
+///
+/// # Example
+///
+/// ```rust
+/// use futures::stream;
+/// use motore::discover::{Change, Discover};
+///
+/// # #[tokio::main(flavor = "current_thread")]
+/// # async fn main() {
+/// let mut discover = stream::iter([
+///     Ok::<_, std::convert::Infallible>(Change::Insert("10.0.0.1:80", "a")),
+///     Ok(Change::Insert("10.0.0.2:80", "b")),
+///     Ok(Change::Remove("10.0.0.1:80")),
+/// ]);
+///
+/// let mut keys = Vec::new();
+/// while let Some(change) = discover.next_change().await {
+///     keys.push(*change.unwrap().key());
+/// }
+/// assert_eq!(keys, ["10.0.0.1:80", "10.0.0.2:80", "10.0.0.1:80"]);
+/// # }
+/// ```
+pub trait Discover: Sealed<Change<(), ()>> {
+    /// The keys of the services.
+    type Key: Eq;
+    /// The discovered services.
+    type Service;
+    /// The errors of the discovery.
+    type Error;
+
+    /// Polls the next change, `None` once the stream ended.
+    #[allow(clippy::type_complexity)]
+    fn poll_discover(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Option<Result<Change<Self::Key, Self::Service>, Self::Error>>>;
+
+    /// Returns the next change, `None` once the stream ended.
+    fn next_change(&mut self) -> NextChange<'_, Self>
+    where
+        Self: Unpin,
+    {
+        NextChange { discover: self }
+    }
+}
+
+impl<K, S, E, D> Sealed<Change<(), ()>> for D
+where
+    D: TryStream<Ok = Change<K, S>, Error = E> + ?Sized,
+    K: Eq,
+{
+}
+
+impl<K, S, E, D> Discover for D
+where
+    D: TryStream<Ok = Change<K, S>, Error = E> + ?Sized,
+    K: Eq,
+{
+    type Key = K;
+    type Service = S;
+    type Error = E;
+
+    fn poll_discover(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Option<Result<Change<K, S>, E>>> {
+        TryStream::try_poll_next(self, cx)
+    }
+}
+
+/// Future returned by [`Discover::next_change`].
+#[must_use = "futures do nothing unless you `.await` or poll them"]
+pub struct NextChange<'a, D: ?Sized> {
+    discover: &'a mut D,
+}
+
+impl<D: Discover + Unpin + ?Sized> Future for NextChange<'_, D> {
+    type Output = Option<Result<Change<D::Key, D::Service>, D::Error>>;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        Pin::new(&mut *self.discover).poll_discover(cx)
+    }
+}
+
+impl<D: ?Sized> fmt::Debug for NextChange<'_, D> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("NextChange").finish()
+    }
+}
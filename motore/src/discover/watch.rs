@@ -0,0 +1,215 @@
+use std::{
+    collections::{HashSet, VecDeque},
+    convert::Infallible,
+    fmt,
+    hash::Hash,
+    pin::Pin,
+    task::{Context, Poll},
+};
+
+#[cfg(feature = "service_send")]
+use futures::stream::BoxStream;
+#[cfg(not(feature = "service_send"))]
+use futures::stream::LocalBoxStream as BoxStream;
+use futures::{stream, Stream, StreamExt};
+use pin_project::pin_project;
+use tokio::sync::watch;
+
+use super::Change;
+
+/// A [`Discover`](super::Discover) of the endpoints in the snapshots of a stream,
+/// like the contents of a config file or of a registry entry.
+///
+/// Every snapshot is the whole set of endpoints. It is diffed against the previous
+/// one: the new endpoints are inserted, with a service made by a closure taking
+/// the endpoint, `Fn(&K) -> S`, and the endpoints no longer there are removed. The
+/// endpoints are the keys of the services.
+///
+/// # Example
+///
+/// ```rust
+/// use motore::discover::{Change, Discover, WatchDiscover};
+///
+/// # #[tokio::main(flavor = "current_thread")]
+/// # async fn main() {
+/// let (tx, rx) = tokio::sync::watch::channel(vec!["10.0.0.1:80"]);
+/// let mut discover = WatchDiscover::from_watch(rx, |addr: &&str| addr.len());
+/// assert_eq!(
+///     discover.next_change().await,
+///     Some(Ok(Change::Insert("10.0.0.1:80", 11)))
+/// );
+///
+/// tx.send(vec!["10.0.0.2:80"]).unwrap();
+/// assert_eq!(
+///     discover.next_change().await,
+///     Some(Ok(Change::Remove("10.0.0.1:80")))
+/// );
+/// assert_eq!(
+///     discover.next_change().await,
+///     Some(Ok(Change::Insert("10.0.0.2:80", 11)))
+/// );
+/// # }
+/// ```
+#[pin_project]
+pub struct WatchDiscover<St, K, S, F> {
+    #[pin]
+    snapshots: St,
+    make_service: F,
+    endpoints: HashSet<K>,
+    pending: VecDeque<Change<K, S>>,
+}
+
+impl<St, K, S, F> WatchDiscover<St, K, S, F> {
+    /// Creates a discovery of the endpoints in the snapshots of `snapshots`, making
+    /// their services with `make_service`.
+    pub fn new(snapshots: St, make_service: F) -> Self {
+        WatchDiscover {
+            snapshots,
+            make_service,
+            endpoints: HashSet::new(),
+            pending: VecDeque::new(),
+        }
+    }
+}
+
+#[cfg(feature = "service_send")]
+impl<K, S, F> WatchDiscover<BoxStream<'static, Vec<K>>, K, S, F>
+where
+    K: Clone + Send + Sync + 'static,
+{
+    /// Creates a discovery of the endpoints in `rx`, starting with its current
+    /// value. The discovery ends once the sender is dropped.
+    pub fn from_watch(rx: watch::Receiver<Vec<K>>, make_service: F) -> Self {
+        Self::new(watch_snapshots(rx), make_service)
+    }
+}
+
+#[cfg(not(feature = "service_send"))]
+impl<K, S, F> WatchDiscover<BoxStream<'static, Vec<K>>, K, S, F>
+where
+    K: Clone + 'static,
+{
+    /// Creates a discovery of the endpoints in `rx`, starting with its current
+    /// value. The discovery ends once the sender is dropped.
+    pub fn from_watch(rx: watch::Receiver<Vec<K>>, make_service: F) -> Self {
+        Self::new(watch_snapshots(rx), make_service)
+    }
+}
+
+#[cfg(feature = "service_send")]
+fn watch_snapshots<K>(rx: watch::Receiver<Vec<K>>) -> BoxStream<'static, Vec<K>>
+where
+    K: Clone + Send + Sync + 'static,
+{
+    stream::unfold((rx, true), next_snapshot).boxed()
+}
+
+#[cfg(not(feature = "service_send"))]
+fn watch_snapshots<K>(rx: watch::Receiver<Vec<K>>) -> BoxStream<'static, Vec<K>>
+where
+    K: Clone + 'static,
+{
+    stream::unfold((rx, true), next_snapshot).boxed_local()
+}
+
+async fn next_snapshot<K: Clone>(
+    (mut rx, first): (watch::Receiver<Vec<K>>, bool),
+) -> Option<(Vec<K>, (watch::Receiver<Vec<K>>, bool))> {
+    if !first {
+        rx.changed().await.ok()?;
+    }
+    let snapshot = rx.borrow_and_update().clone();
+    Some((snapshot, (rx, false)))
+}
+
+impl<St, K, S, F, I> Stream for WatchDiscover<St, K, S, F>
+where
+    St: Stream<Item = I>,
+    I: IntoIterator<Item = K>,
+    K: Hash + Eq + Clone,
+    F: Fn(&K) -> S,
+{
+    type Item = Result<Change<K, S>, Infallible>;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let mut this = self.project();
+        loop {
+            if let Some(change) = this.pending.pop_front() {
+                return Poll::Ready(Some(Ok(change)));
+            }
+            let Some(snapshot) = futures::ready!(this.snapshots.as_mut().poll_next(cx)) else {
+                return Poll::Ready(None);
+            };
+
+            let mut endpoints = HashSet::new();
+            let mut inserted = Vec::new();
+            for endpoint in snapshot {
+                if endpoints.insert(endpoint.clone()) && !this.endpoints.contains(&endpoint) {
+                    inserted.push(endpoint);
+                }
+            }
+            this.pending.extend(
+                this.endpoints
+                    .iter()
+                    .filter(|endpoint| !endpoints.contains(*endpoint))
+                    .map(|endpoint| Change::Remove(endpoint.clone())),
+            );
+            this.pending.extend(inserted.into_iter().map(|endpoint| {
+                let service = (this.make_service)(&endpoint);
+                Change::Insert(endpoint, service)
+            }));
+            *this.endpoints = endpoints;
+        }
+    }
+}
+
+impl<St, K: fmt::Debug, S, F> fmt::Debug for WatchDiscover<St, K, S, F> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("WatchDiscover")
+            .field("endpoints", &self.endpoints)
+            .field("pending", &self.pending.len())
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::discover::Discover;
+
+    #[tokio::test]
+    async fn diffs_the_snapshots() {
+        let snapshots = stream::iter([vec![1, 2, 2], vec![2, 3, 4], vec![], vec![5]]);
+        let discover = WatchDiscover::new(snapshots, |n: &u32| n * 10);
+        let mut changes: Vec<_> = discover.map(Result::unwrap).collect().await;
+
+        // The removals of a snapshot come in no particular order.
+        changes[5..8].sort_by_key(|change| *change.key());
+        assert_eq!(
+            changes,
+            [
+                Change::Insert(1, 10),
+                Change::Insert(2, 20),
+                Change::Remove(1),
+                Change::Insert(3, 30),
+                Change::Insert(4, 40),
+                Change::Remove(2),
+                Change::Remove(3),
+                Change::Remove(4),
+                Change::Insert(5, 50),
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn ends_with_the_watch_channel() {
+        let (tx, rx) = watch::channel(vec![1]);
+        let mut discover = WatchDiscover::from_watch(rx, |n: &u32| *n);
+        assert_eq!(discover.next_change().await, Some(Ok(Change::Insert(1, 1))));
+
+        tx.send(vec![1, 2]).unwrap();
+        assert_eq!(discover.next_change().await, Some(Ok(Change::Insert(2, 2))));
+        drop(tx);
+        assert_eq!(discover.next_change().await, None);
+    }
+}
@@ -0,0 +1,344 @@
+//! Shuts a server down gracefully, letting the calls in flight complete.
+//!
+//! [`channel`] returns a [`Signal`] and a [`Watcher`]. The services of the server
+//! are wrapped in a [`Drain`], made by the [`DrainLayer`] of the watcher, which
+//! counts their calls in flight. To shut down, the server triggers the signal,
+//! stops accepting new connections once [`Watcher::signaled`] resolves, then waits
+//! on [`Watcher::drained`], or [`Watcher::drained_within`] to bound how long it
+//! waits, for the calls in flight to complete.
+//!
+//! A `Drain` doesn't reject the calls started while draining: the server is
+//! expected to stop sending new requests to its services, and such calls are
+//! waited for like the others until the watcher resolves.
+
+use std::{
+    fmt,
+    sync::Arc,
+    task::{Context, Poll},
+    time::Duration,
+};
+
+use tokio::sync::watch;
+
+use crate::{
+    layer::Layer,
+    load::{Load, Ready},
+    service::Service,
+    timer::{DefaultTimer, Timer},
+    MaybeSend, MaybeSync,
+};
+
+#[derive(Clone, Copy, Debug, Default)]
+struct State {
+    draining: bool,
+    in_flight: usize,
+}
+
+/// Returns a [`Signal`] starting the shutdown, and a [`Watcher`] tracking the calls
+/// in flight.
+pub fn channel() -> (Signal, Watcher) {
+    let state = Arc::new(watch::Sender::new(State::default()));
+    (
+        Signal {
+            state: state.clone(),
+        },
+        Watcher { state },
+    )
+}
+
+/// Starts the shutdown of the services watched by a [`Watcher`].
+#[derive(Clone)]
+pub struct Signal {
+    state: Arc<watch::Sender<State>>,
+}
+
+impl Signal {
+    /// Starts draining. Calling it again does nothing.
+    pub fn drain(&self) {
+        self.state.send_if_modified(|state| {
+            let modified = !state.draining;
+            state.draining = true;
+            modified
+        });
+    }
+}
+
+impl fmt::Debug for Signal {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Signal")
+            .field("draining", &self.state.borrow().draining)
+            .finish()
+    }
+}
+
+/// Tracks the calls in flight through the [`Drain`]s it made, and waits for them
+/// once the [`Signal`] is triggered.
+///
+/// Clones track the same calls.
+#[derive(Clone)]
+pub struct Watcher {
+    state: Arc<watch::Sender<State>>,
+}
+
+impl Watcher {
+    /// Returns whether the signal was triggered.
+    pub fn is_draining(&self) -> bool {
+        self.state.borrow().draining
+    }
+
+    /// Returns the number of calls in flight.
+    pub fn in_flight(&self) -> usize {
+        self.state.borrow().in_flight
+    }
+
+    /// Returns a layer counting the calls in flight with this watcher.
+    pub fn layer(&self) -> DrainLayer {
+        DrainLayer::new(self.clone())
+    }
+
+    async fn wait_for(&self, f: impl FnMut(&State) -> bool) {
+        let mut rx = self.state.subscribe();
+        // The sender is kept alive by `self`, so this never fails.
+        let _ = rx.wait_for(f).await;
+    }
+
+    /// Resolves once the signal is triggered, when the server should stop accepting
+    /// new connections.
+    pub async fn signaled(&self) {
+        self.wait_for(|state| state.draining).await
+    }
+
+    /// Resolves once the signal is triggered and no call is in flight.
+    pub async fn drained(&self) {
+        self.wait_for(|state| state.draining && state.in_flight == 0)
+            .await
+    }
+
+    /// Like [`drained`](Self::drained), but gives up waiting for the calls in flight
+    /// after `timeout`, counted from when the signal is triggered.
+    pub async fn drained_within(&self, timeout: Duration) -> Result<(), DrainTimeout> {
+        self.signaled().await;
+        let timer = DefaultTimer::new();
+        tokio::select! {
+            _ = self.drained() => Ok(()),
+            _ = timer.sleep(timeout) => Err(DrainTimeout {
+                elapsed: timeout,
+                in_flight: self.in_flight(),
+            }),
+        }
+    }
+
+    fn enter(&self) -> Guard<'_> {
+        self.state.send_modify(|state| state.in_flight += 1);
+        Guard { watcher: self }
+    }
+}
+
+impl fmt::Debug for Watcher {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let state = *self.state.borrow();
+        f.debug_struct("Watcher")
+            .field("draining", &state.draining)
+            .field("in_flight", &state.in_flight)
+            .finish()
+    }
+}
+
+/// Decrements the calls in flight once a call completed or was cancelled.
+struct Guard<'a> {
+    watcher: &'a Watcher,
+}
+
+impl Drop for Guard<'_> {
+    fn drop(&mut self) {
+        self.watcher.state.send_modify(|state| state.in_flight -= 1);
+    }
+}
+
+/// The error returned by [`Watcher::drained_within`] when calls are still in
+/// flight after the timeout.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct DrainTimeout {
+    /// How long the watcher waited for the calls in flight.
+    pub elapsed: Duration,
+    /// The number of calls still in flight.
+    pub in_flight: usize,
+}
+
+impl fmt::Display for DrainTimeout {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} calls still in flight after draining for {:?}",
+            self.in_flight, self.elapsed
+        )
+    }
+}
+
+impl std::error::Error for DrainTimeout {}
+
+/// Counts the calls to the inner service in flight with a [`Watcher`].
+///
+/// See the [module level docs](self) for details.
+#[derive(Clone, Debug)]
+pub struct Drain<S> {
+    inner: S,
+    watcher: Watcher,
+}
+
+impl<S> Drain<S> {
+    /// Creates a `Drain` counting the calls with `watcher`.
+    pub const fn new(inner: S, watcher: Watcher) -> Self {
+        Drain { inner, watcher }
+    }
+}
+
+impl<Cx, Req, S> Service<Cx, Req> for Drain<S>
+where
+    Req: 'static + MaybeSend,
+    S: Service<Cx, Req> + 'static + MaybeSend + MaybeSync,
+    Cx: 'static + MaybeSend,
+{
+    type Response = S::Response;
+
+    type Error = S::Error;
+
+    async fn call(&self, cx: &mut Cx, req: Req) -> Result<Self::Response, Self::Error> {
+        let _guard = self.watcher.enter();
+        self.inner.call(cx, req).await
+    }
+}
+
+impl<S> Ready for Drain<S>
+where
+    S: Ready,
+{
+    fn poll_ready(&self, cx: &mut Context<'_>) -> Poll<()> {
+        self.inner.poll_ready(cx)
+    }
+}
+
+impl<S> Load for Drain<S>
+where
+    S: Load,
+{
+    type Metric = S::Metric;
+
+    fn load(&self) -> Self::Metric {
+        self.inner.load()
+    }
+}
+
+/// Applies a [`Drain`] to a service.
+///
+/// # Example
+///
+/// ```rust
+/// use std::time::Duration;
+///
+/// use motore::{builder::ServiceBuilder, drain, service::service_fn, BoxError, Service};
+///
+/// # #[tokio::main(flavor = "current_thread")]
+/// # async fn main() {
+/// async fn echo(_cx: &mut (), req: String) -> Result<String, BoxError> {
+///     Ok(req)
+/// }
+///
+/// let (signal, watcher) = drain::channel();
+/// let svc = ServiceBuilder::new()
+///     .layer(watcher.layer())
+///     .service(service_fn(echo));
+///
+/// svc.call(&mut (), "ping".into()).await.unwrap();
+///
+/// // On shutdown.
+/// signal.drain();
+/// watcher
+///     .drained_within(Duration::from_secs(30))
+///     .await
+///     .unwrap();
+/// # }
+/// ```
+#[derive(Clone, Debug)]
+pub struct DrainLayer {
+    watcher: Watcher,
+}
+
+impl DrainLayer {
+    /// Creates a layer counting the calls with `watcher`.
+    pub const fn new(watcher: Watcher) -> Self {
+        DrainLayer { watcher }
+    }
+}
+
+impl<S> Layer<S> for DrainLayer {
+    type Service = Drain<S>;
+
+    fn layer(self, inner: S) -> Self::Service {
+        Drain::new(inner, self.watcher)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::convert::Infallible;
+
+    use super::*;
+    use crate::service::service_fn;
+
+    fn sleeper() -> impl Service<(), u64, Response = (), Error = Infallible> {
+        service_fn(|_cx: &mut (), millis: u64| async move {
+            tokio::time::sleep(Duration::from_millis(millis)).await;
+            Ok::<_, Infallible>(())
+        })
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn waits_for_the_calls_in_flight() {
+        let (signal, watcher) = channel();
+        let svc = watcher.layer().layer(sleeper());
+
+        let calls = async {
+            tokio::join!(async { svc.call(&mut (), 10).await }, async {
+                svc.call(&mut (), 30).await
+            })
+        };
+        let shutdown = async {
+            tokio::time::sleep(Duration::from_millis(5)).await;
+            assert_eq!(watcher.in_flight(), 2);
+            signal.drain();
+            watcher.signaled().await;
+            let start = tokio::time::Instant::now();
+            watcher.drained().await;
+            start.elapsed()
+        };
+        let (_, waited) = tokio::join!(calls, shutdown);
+        assert_eq!(waited, Duration::from_millis(25));
+        assert!(watcher.is_draining());
+        assert_eq!(watcher.in_flight(), 0);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn gives_up_after_the_timeout() {
+        let (signal, watcher) = channel();
+        let svc = watcher.layer().layer(sleeper());
+
+        let call = async { svc.call(&mut (), 1000).await };
+        let shutdown = async {
+            tokio::time::sleep(Duration::from_millis(5)).await;
+            signal.drain();
+            watcher.drained_within(Duration::from_millis(100)).await
+        };
+        let res = tokio::select! {
+            _ = call => unreachable!(),
+            res = shutdown => res,
+        };
+        assert_eq!(
+            res,
+            Err(DrainTimeout {
+                elapsed: Duration::from_millis(100),
+                in_flight: 1,
+            })
+        );
+    }
+}
@@ -0,0 +1,312 @@
+//! Delays the calls of a service with an injected latency.
+
+use std::{
+    fmt,
+    sync::{Arc, Mutex},
+    task::{Context, Poll},
+    time::Duration,
+};
+
+use super::{AllRequests, RequestFilter};
+use crate::{
+    layer::Layer,
+    load::{Load, Ready},
+    service::Service,
+    timer::{DefaultTimer, Timer},
+    utils::rng::Rng,
+    MaybeSend, MaybeSync,
+};
+
+/// The distribution of the delays injected by a [`Delay`], and of the latencies
+/// simulated by the `LatencyService` of the `sim` module.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Latency {
+    /// Every call takes the same time.
+    Constant(Duration),
+    /// Latencies are uniformly distributed between `min` and `max`.
+    Uniform { min: Duration, max: Duration },
+    /// Latencies follow a log-normal distribution with the given median, and
+    /// `sigma` as the standard deviation of the underlying normal distribution.
+    ///
+    /// This is usually a good model of the latency of real services: most calls
+    /// are close to the median while a few of them form a long tail.
+    LogNormal { median: Duration, sigma: f64 },
+}
+
+impl Latency {
+    pub(crate) fn sample(&self, rng: &mut Rng) -> Duration {
+        match *self {
+            Latency::Constant(d) => d,
+            Latency::Uniform { min, max } => min + max.saturating_sub(min).mul_f64(rng.next_f64()),
+            Latency::LogNormal { median, sigma } => {
+                // Box-Muller transform
+                let u1 = 1.0 - rng.next_f64();
+                let u2 = rng.next_f64();
+                let z = (-2.0 * u1.ln()).sqrt() * (2.0 * std::f64::consts::PI * u2).cos();
+                median.mul_f64((sigma * z).exp())
+            }
+        }
+    }
+}
+
+/// Waits for a delay drawn from a [`Latency`] before calling the inner service, or
+/// before returning its response.
+///
+/// The clones of a `Delay` share the same random decisions. See the [module level
+/// docs](super) for details.
+#[derive(Clone)]
+pub struct Delay<S, P = AllRequests, T = DefaultTimer> {
+    inner: S,
+    latency: Latency,
+    after: bool,
+    filter: P,
+    timer: T,
+    rng: Arc<Mutex<Rng>>,
+}
+
+impl<S> Delay<S> {
+    /// Creates a middleware delaying every call with a delay drawn from `latency`.
+    pub fn new(inner: S, latency: Latency) -> Self {
+        DelayLayer::new(latency).layer(inner)
+    }
+}
+
+impl<S, P, T> Delay<S, P, T> {
+    fn sample(&self) -> Duration {
+        match self.latency {
+            Latency::Constant(d) => d,
+            latency => latency.sample(&mut self.rng.lock().unwrap_or_else(|e| e.into_inner())),
+        }
+    }
+}
+
+impl<Cx, Req, S, P, T> Service<Cx, Req> for Delay<S, P, T>
+where
+    Req: 'static + MaybeSend,
+    S: Service<Cx, Req> + 'static + MaybeSend + MaybeSync,
+    P: RequestFilter<Req> + MaybeSync,
+    T: Timer + MaybeSend + MaybeSync,
+    Cx: 'static + MaybeSend,
+    S::Response: MaybeSend,
+    S::Error: MaybeSend,
+{
+    type Response = S::Response;
+
+    type Error = S::Error;
+
+    async fn call(&self, cx: &mut Cx, req: Req) -> Result<Self::Response, Self::Error> {
+        if !self.filter.matches(&req) {
+            return self.inner.call(cx, req).await;
+        }
+        let delay = self.sample();
+        if self.after {
+            let res = self.inner.call(cx, req).await;
+            self.timer.sleep(delay).await;
+            res
+        } else {
+            self.timer.sleep(delay).await;
+            self.inner.call(cx, req).await
+        }
+    }
+}
+
+impl<S, P, T> Ready for Delay<S, P, T>
+where
+    S: Ready,
+{
+    fn poll_ready(&self, cx: &mut Context<'_>) -> Poll<()> {
+        self.inner.poll_ready(cx)
+    }
+}
+
+impl<S, P, T> Load for Delay<S, P, T>
+where
+    S: Load,
+{
+    type Metric = S::Metric;
+
+    fn load(&self) -> Self::Metric {
+        self.inner.load()
+    }
+}
+
+impl<S: fmt::Debug, P, T> fmt::Debug for Delay<S, P, T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Delay")
+            .field("inner", &self.inner)
+            .field("latency", &self.latency)
+            .field("after", &self.after)
+            .field("filter", &format_args!("{}", std::any::type_name::<P>()))
+            .finish()
+    }
+}
+
+/// Applies a [`Delay`] to a service.
+///
+/// # Example
+///
+/// ```rust
+/// use std::time::Duration;
+///
+/// use motore::{
+///     builder::ServiceBuilder,
+///     fault::{DelayLayer, Latency},
+///     service::service_fn,
+///     timeout::TimeoutLayer,
+///     BoxError, Service,
+/// };
+///
+/// # #[tokio::main(flavor = "current_thread")]
+/// # async fn main() {
+/// async fn echo(_cx: &mut (), req: String) -> Result<String, BoxError> {
+///     Ok(req)
+/// }
+///
+/// // Checks the timeout is short enough for a backend slowing down to 100ms.
+/// let svc = ServiceBuilder::new()
+///     .layer(TimeoutLayer::new(Some(Duration::from_millis(50))))
+///     .layer(DelayLayer::new(Latency::Uniform {
+///         min: Duration::from_millis(100),
+///         max: Duration::from_millis(200),
+///     }))
+///     .service(service_fn(echo));
+///
+/// assert!(svc.call(&mut (), "ping".into()).await.is_err());
+/// # }
+/// ```
+#[derive(Clone)]
+pub struct DelayLayer<P = AllRequests, T = DefaultTimer> {
+    latency: Latency,
+    after: bool,
+    filter: P,
+    timer: T,
+}
+
+impl DelayLayer {
+    /// Creates a layer delaying every call with a delay drawn from `latency`,
+    /// before calling the inner service.
+    pub const fn new(latency: Latency) -> Self {
+        DelayLayer {
+            latency,
+            after: false,
+            filter: AllRequests,
+            timer: DefaultTimer::new(),
+        }
+    }
+}
+
+impl<P, T> DelayLayer<P, T> {
+    /// Waits after the inner service responded instead of before calling it, so
+    /// the call itself happens without delay.
+    pub const fn after(mut self) -> Self {
+        self.after = true;
+        self
+    }
+
+    /// Only delays the requests selected by `filter`, all of them by default.
+    pub fn when<Q>(self, filter: Q) -> DelayLayer<Q, T> {
+        DelayLayer {
+            latency: self.latency,
+            after: self.after,
+            filter,
+            timer: self.timer,
+        }
+    }
+
+    /// Sets the timer waiting for the delays, [`DefaultTimer`] by default.
+    pub fn timer<U>(self, timer: U) -> DelayLayer<P, U> {
+        DelayLayer {
+            latency: self.latency,
+            after: self.after,
+            filter: self.filter,
+            timer,
+        }
+    }
+}
+
+impl<P, T> fmt::Debug for DelayLayer<P, T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("DelayLayer")
+            .field("latency", &self.latency)
+            .field("after", &self.after)
+            .field("filter", &format_args!("{}", std::any::type_name::<P>()))
+            .finish()
+    }
+}
+
+impl<S, P, T> Layer<S> for DelayLayer<P, T> {
+    type Service = Delay<S, P, T>;
+
+    fn layer(self, inner: S) -> Self::Service {
+        Delay {
+            inner,
+            latency: self.latency,
+            after: self.after,
+            filter: self.filter,
+            timer: self.timer,
+            rng: Arc::new(Mutex::new(Rng::new())),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use tokio::time::Instant;
+
+    use super::*;
+    use crate::service::service_fn;
+
+    #[tokio::test(start_paused = true)]
+    async fn delays_the_selected_calls() {
+        let called = Arc::new(Mutex::new(None));
+        let inner = service_fn({
+            let called = called.clone();
+            move |_cx: &mut (), req: u32| {
+                *called.lock().unwrap() = Some(Instant::now());
+                async move { Ok::<_, String>(req) }
+            }
+        });
+        let delay = Latency::Constant(Duration::from_millis(100));
+
+        let before = DelayLayer::new(delay)
+            .when(|req: &u32| *req > 0)
+            .layer(inner.clone());
+        let start = Instant::now();
+        assert_eq!(before.call(&mut (), 0).await, Ok(0));
+        assert_eq!(start.elapsed(), Duration::ZERO);
+        assert_eq!(before.call(&mut (), 1).await, Ok(1));
+        assert_eq!(start.elapsed(), Duration::from_millis(100));
+        assert_eq!(
+            called.lock().unwrap().unwrap() - start,
+            Duration::from_millis(100)
+        );
+
+        let after = DelayLayer::new(delay).after().layer(inner);
+        let start = Instant::now();
+        assert_eq!(after.call(&mut (), 2).await, Ok(2));
+        assert_eq!(start.elapsed(), Duration::from_millis(100));
+        assert_eq!(called.lock().unwrap().unwrap(), start);
+    }
+
+    #[test]
+    fn distributions_stay_in_bounds() {
+        let mut rng = Rng::with_seed(7);
+        let uniform = Latency::Uniform {
+            min: Duration::from_millis(5),
+            max: Duration::from_millis(10),
+        };
+        let log_normal = Latency::LogNormal {
+            median: Duration::from_millis(20),
+            sigma: 0.5,
+        };
+        let mut samples = Vec::new();
+        for _ in 0..1000 {
+            let d = uniform.sample(&mut rng);
+            assert!(d >= Duration::from_millis(5) && d <= Duration::from_millis(10));
+            samples.push(log_normal.sample(&mut rng));
+        }
+        samples.sort();
+        let median = samples[samples.len() / 2];
+        assert!(median > Duration::from_millis(17) && median < Duration::from_millis(23));
+    }
+}
@@ -0,0 +1,234 @@
+//! Fails a share of the requests with an injected error.
+
+use std::{
+    fmt,
+    sync::{Arc, Mutex},
+    task::{Context, Poll},
+};
+
+use super::{AllRequests, RequestFilter};
+use crate::{
+    layer::Layer,
+    load::{Load, Ready},
+    service::Service,
+    utils::rng::Rng,
+    MaybeSend, MaybeSync,
+};
+
+/// Fails a fraction of the requests with an error made by a factory, without
+/// calling the inner service.
+///
+/// The clones of an `ErrorInject` share the same random decisions. See the
+/// [module level docs](super) for details.
+#[derive(Clone)]
+pub struct ErrorInject<S, F, P = AllRequests> {
+    inner: S,
+    ratio: f64,
+    error: F,
+    filter: P,
+    rng: Arc<Mutex<Rng>>,
+}
+
+impl<S, F> ErrorInject<S, F> {
+    /// Creates a middleware failing `ratio`, between 0 and 1, of the requests with
+    /// the errors made by `error`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `ratio` is not between 0 and 1.
+    pub fn new(inner: S, ratio: f64, error: F) -> Self {
+        ErrorInjectLayer::new(ratio, error).layer(inner)
+    }
+}
+
+impl<S, F, P> ErrorInject<S, F, P> {
+    fn sample(&self) -> bool {
+        self.ratio > 0.0
+            && self
+                .rng
+                .lock()
+                .unwrap_or_else(|e| e.into_inner())
+                .chance(self.ratio)
+    }
+}
+
+impl<Cx, Req, S, F, P, E> Service<Cx, Req> for ErrorInject<S, F, P>
+where
+    Req: 'static + MaybeSend,
+    S: Service<Cx, Req> + 'static + MaybeSend + MaybeSync,
+    F: Fn() -> E + MaybeSync,
+    E: Into<S::Error>,
+    P: RequestFilter<Req> + MaybeSync,
+    Cx: 'static + MaybeSend,
+{
+    type Response = S::Response;
+
+    type Error = S::Error;
+
+    async fn call(&self, cx: &mut Cx, req: Req) -> Result<Self::Response, Self::Error> {
+        if self.filter.matches(&req) && self.sample() {
+            return Err((self.error)().into());
+        }
+        self.inner.call(cx, req).await
+    }
+}
+
+impl<S, F, P> Ready for ErrorInject<S, F, P>
+where
+    S: Ready,
+{
+    fn poll_ready(&self, cx: &mut Context<'_>) -> Poll<()> {
+        self.inner.poll_ready(cx)
+    }
+}
+
+impl<S, F, P> Load for ErrorInject<S, F, P>
+where
+    S: Load,
+{
+    type Metric = S::Metric;
+
+    fn load(&self) -> Self::Metric {
+        self.inner.load()
+    }
+}
+
+impl<S: fmt::Debug, F, P> fmt::Debug for ErrorInject<S, F, P> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ErrorInject")
+            .field("inner", &self.inner)
+            .field("ratio", &self.ratio)
+            .field("error", &format_args!("{}", std::any::type_name::<F>()))
+            .field("filter", &format_args!("{}", std::any::type_name::<P>()))
+            .finish()
+    }
+}
+
+/// Applies an [`ErrorInject`] to a service.
+///
+/// # Example
+///
+/// ```rust
+/// use motore::{
+///     builder::ServiceBuilder, fault::ErrorInjectLayer, service::service_fn, BoxError, Service,
+/// };
+///
+/// # #[tokio::main(flavor = "current_thread")]
+/// # async fn main() {
+/// async fn echo(_cx: &mut (), req: String) -> Result<String, BoxError> {
+///     Ok(req)
+/// }
+///
+/// // Fails a fifth of the requests of the test user.
+/// let svc = ServiceBuilder::new()
+///     .layer(
+///         ErrorInjectLayer::new(0.2, || "injected fault")
+///             .when(|req: &String| req.starts_with("test")),
+///     )
+///     .service(service_fn(echo));
+///
+/// assert_eq!(svc.call(&mut (), "ping".into()).await.unwrap(), "ping");
+/// # }
+/// ```
+#[derive(Clone)]
+pub struct ErrorInjectLayer<F, P = AllRequests> {
+    ratio: f64,
+    error: F,
+    filter: P,
+}
+
+impl<F> ErrorInjectLayer<F> {
+    /// Creates a layer failing `ratio`, between 0 and 1, of the requests with the
+    /// errors made by `error`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `ratio` is not between 0 and 1.
+    pub fn new(ratio: f64, error: F) -> Self {
+        assert!(
+            (0.0..=1.0).contains(&ratio),
+            "the ratio must be between 0 and 1"
+        );
+        ErrorInjectLayer {
+            ratio,
+            error,
+            filter: AllRequests,
+        }
+    }
+}
+
+impl<F, P> ErrorInjectLayer<F, P> {
+    /// Only injects errors into the requests selected by `filter`, all of them by
+    /// default.
+    pub fn when<Q>(self, filter: Q) -> ErrorInjectLayer<F, Q> {
+        ErrorInjectLayer {
+            ratio: self.ratio,
+            error: self.error,
+            filter,
+        }
+    }
+}
+
+impl<F, P> fmt::Debug for ErrorInjectLayer<F, P> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ErrorInjectLayer")
+            .field("ratio", &self.ratio)
+            .field("error", &format_args!("{}", std::any::type_name::<F>()))
+            .field("filter", &format_args!("{}", std::any::type_name::<P>()))
+            .finish()
+    }
+}
+
+impl<S, F, P> Layer<S> for ErrorInjectLayer<F, P> {
+    type Service = ErrorInject<S, F, P>;
+
+    fn layer(self, inner: S) -> Self::Service {
+        ErrorInject {
+            inner,
+            ratio: self.ratio,
+            error: self.error,
+            filter: self.filter,
+            rng: Arc::new(Mutex::new(Rng::new())),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    use super::*;
+    use crate::service::service_fn;
+
+    #[tokio::test]
+    async fn fails_the_selected_requests() {
+        let calls = Arc::new(AtomicUsize::new(0));
+        let inner = service_fn({
+            let calls = calls.clone();
+            move |_cx: &mut (), req: u32| {
+                calls.fetch_add(1, Ordering::SeqCst);
+                async move { Ok::<_, String>(req) }
+            }
+        });
+
+        let svc = ErrorInjectLayer::new(1.0, || "injected")
+            .when(|req: &u32| req % 2 == 0)
+            .layer(inner);
+        for i in 0..10 {
+            let res = svc.call(&mut (), i).await;
+            if i % 2 == 0 {
+                assert_eq!(res, Err("injected".to_string()));
+            } else {
+                assert_eq!(res, Ok(i));
+            }
+        }
+        assert_eq!(calls.load(Ordering::SeqCst), 5);
+
+        let mut none = svc.clone();
+        none.ratio = 0.0;
+        for i in 0..10 {
+            assert_eq!(none.call(&mut (), i).await, Ok(i));
+        }
+        assert_eq!(calls.load(Ordering::SeqCst), 15);
+    }
+}
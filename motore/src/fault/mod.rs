@@ -0,0 +1,45 @@
+//! Injects faults into the calls of a service, for chaos testing.
+//!
+//! Retries, circuit breakers, timeouts and hedging only matter once a backend
+//! misbehaves, which is rare enough that their configuration mostly goes untested.
+//! The middlewares of this module make a healthy backend look unhealthy to the
+//! services in front of it: [`ErrorInject`] fails a share of the requests, and
+//! [`Delay`] slows the calls down with a latency drawn from a distribution.
+//!
+//! The faults can be limited to some of the requests with a [`RequestFilter`],
+//! for instance to the requests of a test tenant in a staging environment.
+
+pub mod delay;
+pub mod error;
+
+pub use self::{
+    delay::{Delay, DelayLayer, Latency},
+    error::{ErrorInject, ErrorInjectLayer},
+};
+
+/// Selects the requests a fault is injected into.
+///
+/// This is implemented for closures taking the request, and by [`AllRequests`].
+pub trait RequestFilter<Req> {
+    /// Returns whether a fault may be injected into `req`.
+    fn matches(&self, req: &Req) -> bool;
+}
+
+impl<F, Req> RequestFilter<Req> for F
+where
+    F: Fn(&Req) -> bool,
+{
+    fn matches(&self, req: &Req) -> bool {
+        self(req)
+    }
+}
+
+/// The [`RequestFilter`] selecting every request, used when none is given.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct AllRequests;
+
+impl<Req> RequestFilter<Req> for AllRequests {
+    fn matches(&self, _req: &Req) -> bool {
+        true
+    }
+}
@@ -0,0 +1,369 @@
+//! Rejects requests with a synchronous predicate before they reach a service.
+//!
+//! A [`Predicate`] sees the context, which it may update, and the request, and
+//! either lets the request through or rejects it. Rejected requests never reach the
+//! inner service, and the rejection is returned as [`FilterError::Rejected`].
+//!
+//! Checks that need to wait on something, like a token check against a remote
+//! service, are written as an [`AsyncPredicate`] and applied with [`AsyncFilter`].
+//! An [`AsyncPredicate`] takes the request by value, so it can also rewrite it
+//! before it is forwarded.
+
+use std::{
+    error::Error,
+    fmt,
+    future::Future,
+    task::{Context, Poll},
+};
+
+use crate::{
+    layer::Layer,
+    load::{Load, Ready},
+    service::Service,
+    MaybeSend, MaybeSync,
+};
+
+/// Decides synchronously whether a request may reach a service.
+///
+/// This is implemented for closures taking the context and the request.
+pub trait Predicate<Cx, Req> {
+    /// The reason a request was rejected.
+    type Rejection;
+
+    /// Checks the request, returning an error to reject it.
+    fn check(&self, cx: &mut Cx, req: &Req) -> Result<(), Self::Rejection>;
+}
+
+impl<F, Cx, Req, R> Predicate<Cx, Req> for F
+where
+    F: Fn(&mut Cx, &Req) -> Result<(), R>,
+{
+    type Rejection = R;
+
+    fn check(&self, cx: &mut Cx, req: &Req) -> Result<(), R> {
+        self(cx, req)
+    }
+}
+
+/// Decides asynchronously whether a request may reach a service, possibly
+/// rewriting it.
+///
+/// This is implemented for closures taking the request and returning a future.
+///
+/// # Example
+///
+/// ```rust
+/// use motore::filter::AsyncPredicate;
+///
+/// struct Cx {
+///     user: Option<String>,
+/// }
+///
+/// struct ResolveUser;
+///
+/// impl AsyncPredicate<Cx, String> for ResolveUser {
+///     type Request = (String, String);
+///     type Rejection = &'static str;
+///
+///     async fn check(&self, cx: &mut Cx, req: String) -> Result<Self::Request, Self::Rejection> {
+///         // A remote token check would be awaited here.
+///         match cx.user.take() {
+///             Some(user) => Ok((user, req)),
+///             None => Err("unauthenticated"),
+///         }
+///     }
+/// }
+/// ```
+pub trait AsyncPredicate<Cx, Req> {
+    /// The request forwarded to the inner service.
+    type Request;
+    /// The reason a request was rejected.
+    type Rejection;
+
+    /// Checks the request, returning the request to forward, or an error to reject
+    /// it.
+    #[cfg(feature = "service_send")]
+    fn check(
+        &self,
+        cx: &mut Cx,
+        req: Req,
+    ) -> impl Future<Output = Result<Self::Request, Self::Rejection>> + Send;
+
+    /// Checks the request, returning the request to forward, or an error to reject
+    /// it.
+    #[cfg(not(feature = "service_send"))]
+    fn check(
+        &self,
+        cx: &mut Cx,
+        req: Req,
+    ) -> impl Future<Output = Result<Self::Request, Self::Rejection>>;
+}
+
+#[cfg(feature = "service_send")]
+impl<F, Fut, Cx, Req, T, R> AsyncPredicate<Cx, Req> for F
+where
+    F: Fn(Req) -> Fut,
+    Fut: Future<Output = Result<T, R>> + Send,
+{
+    type Request = T;
+    type Rejection = R;
+
+    fn check(&self, _cx: &mut Cx, req: Req) -> impl Future<Output = Result<T, R>> + Send {
+        self(req)
+    }
+}
+
+#[cfg(not(feature = "service_send"))]
+impl<F, Fut, Cx, Req, T, R> AsyncPredicate<Cx, Req> for F
+where
+    F: Fn(Req) -> Fut,
+    Fut: Future<Output = Result<T, R>>,
+{
+    type Request = T;
+    type Rejection = R;
+
+    fn check(&self, _cx: &mut Cx, req: Req) -> impl Future<Output = Result<T, R>> {
+        self(req)
+    }
+}
+
+/// The error returned by [`Filter`] and [`AsyncFilter`].
+#[derive(Debug, PartialEq, Eq)]
+pub enum FilterError<R, E> {
+    /// The predicate rejected the request.
+    Rejected(R),
+    /// The inner service failed.
+    Service(E),
+}
+
+impl<R: fmt::Display, E: fmt::Display> fmt::Display for FilterError<R, E> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            FilterError::Rejected(r) => write!(f, "request rejected: {r}"),
+            FilterError::Service(e) => fmt::Display::fmt(e, f),
+        }
+    }
+}
+
+impl<R, E> Error for FilterError<R, E>
+where
+    R: fmt::Debug + fmt::Display,
+    E: Error + 'static,
+{
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            FilterError::Rejected(_) => None,
+            FilterError::Service(e) => Some(e),
+        }
+    }
+}
+
+/// Checks every request with a [`Predicate`] before calling the inner service.
+#[derive(Clone)]
+pub struct Filter<S, P> {
+    inner: S,
+    predicate: P,
+}
+
+impl<S, P> Filter<S, P> {
+    pub const fn new(inner: S, predicate: P) -> Self {
+        Self { inner, predicate }
+    }
+}
+
+impl<Cx, Req, S, P> Service<Cx, Req> for Filter<S, P>
+where
+    Req: 'static + MaybeSend,
+    S: Service<Cx, Req> + 'static + MaybeSend + MaybeSync,
+    P: Predicate<Cx, Req> + MaybeSync,
+    Cx: 'static + MaybeSend,
+{
+    type Response = S::Response;
+
+    type Error = FilterError<P::Rejection, S::Error>;
+
+    async fn call(&self, cx: &mut Cx, req: Req) -> Result<Self::Response, Self::Error> {
+        self.predicate
+            .check(cx, &req)
+            .map_err(FilterError::Rejected)?;
+        self.inner.call(cx, req).await.map_err(FilterError::Service)
+    }
+}
+
+impl<S, P> Ready for Filter<S, P>
+where
+    S: Ready,
+{
+    fn poll_ready(&self, cx: &mut Context<'_>) -> Poll<()> {
+        self.inner.poll_ready(cx)
+    }
+}
+
+impl<S, P> Load for Filter<S, P>
+where
+    S: Load,
+{
+    type Metric = S::Metric;
+
+    fn load(&self) -> Self::Metric {
+        self.inner.load()
+    }
+}
+
+/// Applies a [`Filter`] middleware with the given predicate.
+#[derive(Clone)]
+pub struct FilterLayer<P> {
+    predicate: P,
+}
+
+impl<P> FilterLayer<P> {
+    pub const fn new(predicate: P) -> Self {
+        FilterLayer { predicate }
+    }
+}
+
+impl<S, P> Layer<S> for FilterLayer<P> {
+    type Service = Filter<S, P>;
+
+    fn layer(self, inner: S) -> Self::Service {
+        Filter {
+            inner,
+            predicate: self.predicate,
+        }
+    }
+}
+
+/// Checks every request with an [`AsyncPredicate`] before calling the inner
+/// service with the request it returns.
+#[derive(Clone)]
+pub struct AsyncFilter<S, P> {
+    inner: S,
+    predicate: P,
+}
+
+impl<S, P> AsyncFilter<S, P> {
+    pub const fn new(inner: S, predicate: P) -> Self {
+        Self { inner, predicate }
+    }
+}
+
+impl<Cx, Req, S, P> Service<Cx, Req> for AsyncFilter<S, P>
+where
+    Req: 'static + MaybeSend,
+    S: Service<Cx, P::Request> + 'static + MaybeSend + MaybeSync,
+    P: AsyncPredicate<Cx, Req> + MaybeSync,
+    P::Request: MaybeSend,
+    Cx: 'static + MaybeSend,
+{
+    type Response = S::Response;
+
+    type Error = FilterError<P::Rejection, S::Error>;
+
+    async fn call(&self, cx: &mut Cx, req: Req) -> Result<Self::Response, Self::Error> {
+        let req = self
+            .predicate
+            .check(cx, req)
+            .await
+            .map_err(FilterError::Rejected)?;
+        self.inner.call(cx, req).await.map_err(FilterError::Service)
+    }
+}
+
+impl<S, P> Ready for AsyncFilter<S, P>
+where
+    S: Ready,
+{
+    fn poll_ready(&self, cx: &mut Context<'_>) -> Poll<()> {
+        self.inner.poll_ready(cx)
+    }
+}
+
+impl<S, P> Load for AsyncFilter<S, P>
+where
+    S: Load,
+{
+    type Metric = S::Metric;
+
+    fn load(&self) -> Self::Metric {
+        self.inner.load()
+    }
+}
+
+/// Applies an [`AsyncFilter`] middleware with the given predicate.
+#[derive(Clone)]
+pub struct AsyncFilterLayer<P> {
+    predicate: P,
+}
+
+impl<P> AsyncFilterLayer<P> {
+    pub const fn new(predicate: P) -> Self {
+        AsyncFilterLayer { predicate }
+    }
+}
+
+impl<S, P> Layer<S> for AsyncFilterLayer<P> {
+    type Service = AsyncFilter<S, P>;
+
+    fn layer(self, inner: S) -> Self::Service {
+        AsyncFilter {
+            inner,
+            predicate: self.predicate,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::service::service_fn;
+
+    async fn echo(checked: &mut u32, req: String) -> Result<String, &'static str> {
+        Ok(format!("{req} after {checked} checks"))
+    }
+
+    #[tokio::test]
+    async fn rejects_before_calling_the_service() {
+        let svc = FilterLayer::new(|checked: &mut u32, req: &String| {
+            *checked += 1;
+            if req.is_empty() {
+                Err("empty request")
+            } else {
+                Ok(())
+            }
+        })
+        .layer(service_fn(echo));
+
+        let mut checked = 0;
+        assert_eq!(
+            svc.call(&mut checked, "ping".to_string()).await,
+            Ok("ping after 1 checks".to_string())
+        );
+        assert_eq!(
+            svc.call(&mut checked, String::new()).await,
+            Err(FilterError::Rejected("empty request"))
+        );
+        assert_eq!(checked, 2);
+    }
+
+    #[tokio::test]
+    async fn async_predicates_rewrite_requests() {
+        async fn len(_cx: &mut u32, req: usize) -> Result<usize, &'static str> {
+            Ok(req)
+        }
+
+        let svc = AsyncFilterLayer::new(|req: String| async move {
+            tokio::task::yield_now().await;
+            match req.trim() {
+                "" => Err("blank request"),
+                trimmed => Ok(trimmed.len()),
+            }
+        })
+        .layer(service_fn(len));
+
+        assert_eq!(svc.call(&mut 0, " ping ".to_string()).await, Ok(4));
+        assert_eq!(
+            svc.call(&mut 0, "  ".to_string()).await,
+            Err(FilterError::Rejected("blank request"))
+        );
+    }
+}
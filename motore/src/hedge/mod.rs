@@ -0,0 +1,345 @@
+//! Sends a second request when the first one is slower than usual.
+//!
+//! Most requests to a backend complete quickly, but a few hit a slow instance, a
+//! garbage collection pause or a lost packet and take much longer. [`Hedge`]
+//! tracks the latency of the calls it makes, and when a call has taken longer than
+//! a given percentile of them, it sends a copy of the request and returns the
+//! response that comes first, dropping the other call.
+//!
+//! Hedging sends more requests than the caller does, at most one extra per request
+//! slower than the percentile, so it should only be used for idempotent requests.
+//!
+//! Latencies are recorded over a period, 10 seconds by default, and the percentile
+//! is computed from the previous period, so no request is hedged during the first
+//! period or after a period with too few calls.
+
+use std::{
+    fmt, mem,
+    sync::{Arc, Mutex, MutexGuard},
+    task::{Context, Poll},
+    time::Duration,
+};
+
+use crate::{
+    layer::Layer,
+    load::{Load, Ready},
+    service::Service,
+    timer::{DefaultTimer, Instant, Timer},
+    utils::histogram::Histogram,
+    MaybeSend, MaybeSync,
+};
+
+/// The latencies recorded over the current and the previous period.
+#[derive(Debug)]
+struct Latencies {
+    current: Histogram,
+    previous: Histogram,
+    rotated: Instant,
+}
+
+#[derive(Debug)]
+struct Stats {
+    percentile: f64,
+    min_data_points: u64,
+    period: Duration,
+    latencies: Mutex<Latencies>,
+}
+
+impl Stats {
+    fn latencies(&self, now: Instant) -> MutexGuard<'_, Latencies> {
+        let mut latencies = self.latencies.lock().unwrap_or_else(|e| e.into_inner());
+        let elapsed = now.saturating_duration_since(latencies.rotated);
+        if elapsed >= self.period {
+            let latencies = &mut *latencies;
+            mem::swap(&mut latencies.current, &mut latencies.previous);
+            if elapsed >= self.period * 2 {
+                // Nothing was recorded during the previous period.
+                latencies.previous.clear();
+            }
+            latencies.current.clear();
+            latencies.rotated += self.period * (elapsed.as_nanos() / self.period.as_nanos()) as u32;
+        }
+        latencies
+    }
+
+    /// Returns how long to wait before hedging, or `None` to not hedge.
+    fn delay(&self, now: Instant) -> Option<Duration> {
+        let latencies = self.latencies(now);
+        if latencies.previous.count() < self.min_data_points {
+            return None;
+        }
+        latencies.previous.percentile(self.percentile)
+    }
+
+    fn record(&self, now: Instant, latency: Duration) {
+        self.latencies(now).current.record(latency);
+    }
+}
+
+/// Sends a copy of the requests taking longer than a percentile of the latency of
+/// the inner service, and returns the first response.
+///
+/// Both the request and the context are cloned for the second call. When the
+/// second call completes first, the caller's context is replaced with its context.
+///
+/// The clones of a `Hedge` share the same latency statistics. See the [module level
+/// docs](self) for details.
+#[derive(Clone)]
+pub struct Hedge<S, T = DefaultTimer> {
+    inner: S,
+    stats: Arc<Stats>,
+    timer: T,
+}
+
+impl<S, T: Timer> Hedge<S, T> {
+    /// Records the latency of a call started at `start`.
+    fn record(&self, start: Instant) {
+        let now = self.timer.now();
+        self.stats.record(now, now.saturating_duration_since(start));
+    }
+}
+
+impl<Cx, Req, S, T> Service<Cx, Req> for Hedge<S, T>
+where
+    Req: Clone + 'static + MaybeSend,
+    S: Service<Cx, Req> + 'static + MaybeSend + MaybeSync,
+    S::Response: MaybeSend,
+    S::Error: MaybeSend,
+    Cx: Clone + 'static + MaybeSend,
+    T: Timer + MaybeSync,
+{
+    type Response = S::Response;
+
+    type Error = S::Error;
+
+    async fn call(&self, cx: &mut Cx, req: Req) -> Result<Self::Response, Self::Error> {
+        let start = self.timer.now();
+        let Some(delay) = self.stats.delay(start) else {
+            let res = self.inner.call(cx, req).await;
+            self.record(start);
+            return res;
+        };
+
+        let mut hedge_cx = cx.clone();
+        let hedge_req = req.clone();
+        let (res, hedged) = {
+            let first = self.inner.call(cx, req);
+            tokio::pin!(first);
+            let res = tokio::select! {
+                res = &mut first => Some(res),
+                _ = self.timer.sleep(delay) => None,
+            };
+            if let Some(res) = res {
+                self.record(start);
+                return res;
+            }
+
+            let hedge_start = self.timer.now();
+            let second = self.inner.call(&mut hedge_cx, hedge_req);
+            tokio::pin!(second);
+            tokio::select! {
+                res = &mut first => {
+                    self.record(start);
+                    (res, false)
+                }
+                res = &mut second => {
+                    self.record(hedge_start);
+                    (res, true)
+                }
+            }
+        };
+        if hedged {
+            *cx = hedge_cx;
+        }
+        res
+    }
+}
+
+impl<S, T> Ready for Hedge<S, T>
+where
+    S: Ready,
+{
+    fn poll_ready(&self, cx: &mut Context<'_>) -> Poll<()> {
+        self.inner.poll_ready(cx)
+    }
+}
+
+impl<S, T> Load for Hedge<S, T>
+where
+    S: Load,
+{
+    type Metric = S::Metric;
+
+    fn load(&self) -> Self::Metric {
+        self.inner.load()
+    }
+}
+
+impl<S: fmt::Debug, T: Timer> fmt::Debug for Hedge<S, T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Hedge")
+            .field("inner", &self.inner)
+            .field("percentile", &self.stats.percentile)
+            .field("delay", &self.stats.delay(self.timer.now()))
+            .finish()
+    }
+}
+
+/// Applies a [`Hedge`] to a service.
+///
+/// Every service made by the layer has its own latency statistics, shared only by
+/// its clones.
+///
+/// # Example
+///
+/// ```rust
+/// use motore::{
+///     builder::ServiceBuilder, hedge::HedgeLayer, service::service_fn, BoxError, Service,
+/// };
+///
+/// # #[tokio::main(flavor = "current_thread")]
+/// # async fn main() {
+/// async fn get(_cx: &mut (), key: String) -> Result<String, BoxError> {
+///     Ok(key)
+/// }
+///
+/// // Hedges the requests slower than 95% of the requests.
+/// let svc = ServiceBuilder::new()
+///     .layer(HedgeLayer::new(0.95))
+///     .service(service_fn(get));
+///
+/// assert_eq!(svc.call(&mut (), "key".into()).await.unwrap(), "key");
+/// # }
+/// ```
+#[derive(Clone, Copy, Debug)]
+pub struct HedgeLayer<T = DefaultTimer> {
+    percentile: f64,
+    min_data_points: u64,
+    period: Duration,
+    timer: T,
+}
+
+impl HedgeLayer {
+    /// Creates a layer hedging the requests slower than `percentile` of the
+    /// requests, between 0 and 1.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `percentile` is not between 0 and 1.
+    pub fn new(percentile: f64) -> Self {
+        assert!(
+            (0.0..=1.0).contains(&percentile),
+            "the percentile must be between 0 and 1"
+        );
+        HedgeLayer {
+            percentile,
+            min_data_points: 10,
+            period: Duration::from_secs(10),
+            timer: DefaultTimer::new(),
+        }
+    }
+}
+
+impl<T> HedgeLayer<T> {
+    /// Sets how many calls a period needs for the next one to hedge requests,
+    /// 10 by default.
+    pub const fn min_data_points(mut self, min: u64) -> Self {
+        self.min_data_points = min;
+        self
+    }
+
+    /// Sets the period over which latencies are recorded, 10 seconds by default.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `period` is zero.
+    pub fn period(mut self, period: Duration) -> Self {
+        assert!(period > Duration::ZERO, "the period must not be zero");
+        self.period = period;
+        self
+    }
+
+    /// Sets the timer measuring the latencies and the delays, [`DefaultTimer`] by
+    /// default.
+    pub fn timer<U>(self, timer: U) -> HedgeLayer<U> {
+        HedgeLayer {
+            percentile: self.percentile,
+            min_data_points: self.min_data_points,
+            period: self.period,
+            timer,
+        }
+    }
+}
+
+impl<S, T: Timer> Layer<S> for HedgeLayer<T> {
+    type Service = Hedge<S, T>;
+
+    fn layer(self, inner: S) -> Self::Service {
+        let now = self.timer.now();
+        Hedge {
+            inner,
+            stats: Arc::new(Stats {
+                percentile: self.percentile,
+                min_data_points: self.min_data_points,
+                period: self.period,
+                latencies: Mutex::new(Latencies {
+                    current: Histogram::new(),
+                    previous: Histogram::new(),
+                    rotated: now,
+                }),
+            }),
+            timer: self.timer,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    use tokio::time::Instant;
+
+    use super::*;
+    use crate::service::service_fn;
+
+    #[tokio::test(start_paused = true)]
+    async fn hedges_slow_requests() {
+        // Every 10th call takes a second, the others 10ms.
+        let calls = Arc::new(AtomicUsize::new(0));
+        let svc = HedgeLayer::new(0.8).layer(service_fn({
+            let calls = calls.clone();
+            move |cx: &mut usize, req: u32| {
+                let n = calls.fetch_add(1, Ordering::SeqCst);
+                *cx = n;
+                async move {
+                    let latency = if n % 10 == 9 { 1000 } else { 10 };
+                    tokio::time::sleep(Duration::from_millis(latency)).await;
+                    Ok::<_, &'static str>(req)
+                }
+            }
+        }));
+
+        // Nothing is hedged before the statistics are known.
+        for i in 0..10 {
+            let start = Instant::now();
+            let mut cx = 0;
+            assert_eq!(svc.call(&mut cx, i).await, Ok(i));
+            assert_eq!(cx, i as usize);
+            let expected = if i == 9 { 1000 } else { 10 };
+            assert_eq!(start.elapsed(), Duration::from_millis(expected));
+        }
+        tokio::time::advance(Duration::from_secs(10)).await;
+
+        for _ in 0..9 {
+            svc.call(&mut 0, 0).await.unwrap();
+        }
+        // The 20th call is slow, so a second call is made after the 80th percentile,
+        // and the context is the second call's.
+        let start = Instant::now();
+        let mut cx = 0;
+        assert_eq!(svc.call(&mut cx, 7).await, Ok(7));
+        assert_eq!(cx, 20);
+        assert!(start.elapsed() < Duration::from_millis(30));
+        assert_eq!(calls.load(Ordering::SeqCst), 21);
+    }
+}
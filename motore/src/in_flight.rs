@@ -0,0 +1,212 @@
+//! Counts the calls of a service in flight.
+//!
+//! [`CountInFlight`] increments a counter when a call starts and decrements it
+//! once the call completes or is cancelled. The counter is read through an
+//! [`InFlight`] handle, which an admin endpoint or a metrics exporter can keep to
+//! report the current concurrency of the service.
+
+use std::{
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    },
+    task::{Context, Poll},
+};
+
+use crate::{
+    layer::Layer,
+    load::{Load, Ready},
+    service::Service,
+    MaybeSend, MaybeSync,
+};
+
+/// A handle to the number of calls in flight through a [`CountInFlight`].
+///
+/// Clones share the same counter.
+#[derive(Clone, Debug, Default)]
+pub struct InFlight {
+    count: Arc<AtomicUsize>,
+}
+
+impl InFlight {
+    /// Creates a counter with no call in flight.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Returns the number of calls in flight.
+    pub fn get(&self) -> usize {
+        self.count.load(Ordering::Relaxed)
+    }
+
+    fn enter(&self) -> Guard<'_> {
+        self.count.fetch_add(1, Ordering::Relaxed);
+        Guard { in_flight: self }
+    }
+}
+
+/// Decrements the counter once a call completed or was cancelled.
+struct Guard<'a> {
+    in_flight: &'a InFlight,
+}
+
+impl Drop for Guard<'_> {
+    fn drop(&mut self) {
+        self.in_flight.count.fetch_sub(1, Ordering::Relaxed);
+    }
+}
+
+/// Counts the calls to the inner service in flight.
+///
+/// The clones of a `CountInFlight` share the same counter. See the [module level
+/// docs](self) for details.
+#[derive(Clone, Debug)]
+pub struct CountInFlight<S> {
+    inner: S,
+    in_flight: InFlight,
+}
+
+impl<S> CountInFlight<S> {
+    /// Creates a `CountInFlight` counting the calls with `in_flight`, which may be
+    /// shared with other services.
+    pub const fn new(inner: S, in_flight: InFlight) -> Self {
+        CountInFlight { inner, in_flight }
+    }
+
+    /// Returns the handle to the counter.
+    pub fn in_flight(&self) -> &InFlight {
+        &self.in_flight
+    }
+}
+
+impl<Cx, Req, S> Service<Cx, Req> for CountInFlight<S>
+where
+    Req: 'static + MaybeSend,
+    S: Service<Cx, Req> + 'static + MaybeSend + MaybeSync,
+    Cx: 'static + MaybeSend,
+{
+    type Response = S::Response;
+
+    type Error = S::Error;
+
+    async fn call(&self, cx: &mut Cx, req: Req) -> Result<Self::Response, Self::Error> {
+        let _guard = self.in_flight.enter();
+        self.inner.call(cx, req).await
+    }
+}
+
+impl<S> Ready for CountInFlight<S>
+where
+    S: Ready,
+{
+    fn poll_ready(&self, cx: &mut Context<'_>) -> Poll<()> {
+        self.inner.poll_ready(cx)
+    }
+}
+
+impl<S> Load for CountInFlight<S>
+where
+    S: Load,
+{
+    type Metric = S::Metric;
+
+    fn load(&self) -> Self::Metric {
+        self.inner.load()
+    }
+}
+
+/// Applies a [`CountInFlight`] to a service.
+///
+/// All the services made by a layer count their calls with the same counter.
+///
+/// # Example
+///
+/// ```rust
+/// use motore::{
+///     builder::ServiceBuilder, in_flight::InFlightLayer, service::service_fn, BoxError, Service,
+/// };
+///
+/// # #[tokio::main(flavor = "current_thread")]
+/// # async fn main() {
+/// async fn echo(_cx: &mut (), req: String) -> Result<String, BoxError> {
+///     Ok(req)
+/// }
+///
+/// let layer = InFlightLayer::new();
+/// // Kept by the admin endpoint.
+/// let in_flight = layer.in_flight().clone();
+///
+/// let svc = ServiceBuilder::new().layer(layer).service(service_fn(echo));
+/// svc.call(&mut (), "ping".into()).await.unwrap();
+/// assert_eq!(in_flight.get(), 0);
+/// # }
+/// ```
+#[derive(Clone, Debug, Default)]
+pub struct InFlightLayer {
+    in_flight: InFlight,
+}
+
+impl InFlightLayer {
+    /// Creates a layer counting the calls with a new counter.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Creates a layer counting the calls with `in_flight`.
+    pub const fn with_in_flight(in_flight: InFlight) -> Self {
+        InFlightLayer { in_flight }
+    }
+
+    /// Returns the handle to the counter.
+    pub fn in_flight(&self) -> &InFlight {
+        &self.in_flight
+    }
+}
+
+impl<S> Layer<S> for InFlightLayer {
+    type Service = CountInFlight<S>;
+
+    fn layer(self, inner: S) -> Self::Service {
+        CountInFlight::new(inner, self.in_flight)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{convert::Infallible, time::Duration};
+
+    use super::*;
+    use crate::service::service_fn;
+
+    #[tokio::test(start_paused = true)]
+    async fn counts_the_calls_in_flight() {
+        let layer = InFlightLayer::new();
+        let in_flight = layer.in_flight().clone();
+        let svc = layer.layer(service_fn(|_cx: &mut (), millis: u64| async move {
+            tokio::time::sleep(Duration::from_millis(millis)).await;
+            Ok::<_, Infallible>(())
+        }));
+
+        let calls = async {
+            tokio::join!(
+                async { svc.call(&mut (), 10).await },
+                async { svc.call(&mut (), 20).await },
+                // Cancelled while in flight.
+                tokio::time::timeout(Duration::from_millis(15), async {
+                    svc.call(&mut (), 1000).await
+                }),
+            )
+        };
+        let observe = async {
+            tokio::time::sleep(Duration::from_millis(5)).await;
+            let start = in_flight.get();
+            tokio::time::sleep(Duration::from_millis(7)).await;
+            let after_first = in_flight.get();
+            tokio::time::sleep(Duration::from_millis(5)).await;
+            (start, after_first, in_flight.get())
+        };
+        let (_, counts) = tokio::join!(calls, observe);
+        assert_eq!(counts, (3, 2, 1));
+        assert_eq!(in_flight.get(), 0);
+    }
+}
@@ -0,0 +1,283 @@
+//! Records the latency distribution of the calls of a service.
+//!
+//! [`RecordLatency`] records how long every call takes in a histogram, read
+//! through a [`LatencyHistogram`] handle, to track the percentiles of the latency
+//! of a backend against its objectives, or to tune timeouts and hedging.
+//!
+//! The histogram splits each power of two of microseconds in 8 buckets, so the
+//! percentiles are rounded up by at most an eighth of their value, while the
+//! histogram keeps a fixed size however many calls are recorded. A call
+//! cancelled before completing is not recorded.
+
+use std::{
+    fmt,
+    sync::{Arc, Mutex, MutexGuard},
+    task::{Context, Poll},
+    time::Duration,
+};
+
+use crate::{
+    layer::Layer,
+    load::{Load, Ready},
+    service::Service,
+    timer::{DefaultTimer, Timer},
+    utils::histogram::Histogram,
+    MaybeSend, MaybeSync,
+};
+
+/// A handle to the latencies recorded by a [`RecordLatency`].
+///
+/// Clones share the same histogram.
+#[derive(Clone)]
+pub struct LatencyHistogram {
+    histogram: Arc<Mutex<Histogram>>,
+}
+
+impl LatencyHistogram {
+    /// Creates an empty histogram.
+    pub fn new() -> Self {
+        LatencyHistogram {
+            histogram: Arc::new(Mutex::new(Histogram::new())),
+        }
+    }
+
+    fn lock(&self) -> MutexGuard<'_, Histogram> {
+        self.histogram.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// Records a latency.
+    pub fn record(&self, latency: Duration) {
+        self.lock().record(latency);
+    }
+
+    /// Returns a copy of the latencies recorded so far.
+    pub fn snapshot(&self) -> LatencySnapshot {
+        LatencySnapshot {
+            histogram: self.lock().clone(),
+        }
+    }
+
+    /// Forgets the latencies recorded so far, for instance at the start of each
+    /// reporting period.
+    pub fn reset(&self) {
+        self.lock().clear();
+    }
+}
+
+impl Default for LatencyHistogram {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl fmt::Debug for LatencyHistogram {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("LatencyHistogram")
+            .field("count", &self.lock().count())
+            .finish()
+    }
+}
+
+/// The latencies recorded by a [`LatencyHistogram`] at some point.
+#[derive(Clone)]
+pub struct LatencySnapshot {
+    histogram: Histogram,
+}
+
+impl LatencySnapshot {
+    /// Returns the number of latencies recorded.
+    pub fn count(&self) -> u64 {
+        self.histogram.count()
+    }
+
+    /// Returns the latency under which `percentile`, between 0 and 1, of the
+    /// latencies fall, or `None` if nothing was recorded.
+    pub fn percentile(&self, percentile: f64) -> Option<Duration> {
+        self.histogram.percentile(percentile)
+    }
+
+    /// Returns the median latency.
+    pub fn p50(&self) -> Option<Duration> {
+        self.percentile(0.5)
+    }
+
+    /// Returns the 95th percentile of the latencies.
+    pub fn p95(&self) -> Option<Duration> {
+        self.percentile(0.95)
+    }
+
+    /// Returns the 99th percentile of the latencies.
+    pub fn p99(&self) -> Option<Duration> {
+        self.percentile(0.99)
+    }
+}
+
+impl fmt::Debug for LatencySnapshot {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("LatencySnapshot")
+            .field("count", &self.count())
+            .field("p50", &self.p50())
+            .field("p95", &self.p95())
+            .field("p99", &self.p99())
+            .finish()
+    }
+}
+
+/// Records the latency of the calls to the inner service.
+///
+/// The clones of a `RecordLatency` record in the same histogram. See the [module
+/// level docs](self) for details.
+#[derive(Clone, Debug)]
+pub struct RecordLatency<S> {
+    inner: S,
+    histogram: LatencyHistogram,
+}
+
+impl<S> RecordLatency<S> {
+    /// Creates a `RecordLatency` recording the latencies in `histogram`, which may
+    /// be shared with other services.
+    pub const fn new(inner: S, histogram: LatencyHistogram) -> Self {
+        RecordLatency { inner, histogram }
+    }
+
+    /// Returns the handle to the histogram.
+    pub fn histogram(&self) -> &LatencyHistogram {
+        &self.histogram
+    }
+}
+
+impl<Cx, Req, S> Service<Cx, Req> for RecordLatency<S>
+where
+    Req: 'static + MaybeSend,
+    S: Service<Cx, Req> + 'static + MaybeSend + MaybeSync,
+    Cx: 'static + MaybeSend,
+{
+    type Response = S::Response;
+
+    type Error = S::Error;
+
+    async fn call(&self, cx: &mut Cx, req: Req) -> Result<Self::Response, Self::Error> {
+        let timer = DefaultTimer::new();
+        let start = timer.now();
+        let res = self.inner.call(cx, req).await;
+        self.histogram
+            .record(timer.now().saturating_duration_since(start));
+        res
+    }
+}
+
+impl<S> Ready for RecordLatency<S>
+where
+    S: Ready,
+{
+    fn poll_ready(&self, cx: &mut Context<'_>) -> Poll<()> {
+        self.inner.poll_ready(cx)
+    }
+}
+
+impl<S> Load for RecordLatency<S>
+where
+    S: Load,
+{
+    type Metric = S::Metric;
+
+    fn load(&self) -> Self::Metric {
+        self.inner.load()
+    }
+}
+
+/// Applies a [`RecordLatency`] to a service.
+///
+/// All the services made by a layer record in the same histogram.
+///
+/// # Example
+///
+/// ```rust
+/// use motore::{
+///     builder::ServiceBuilder, latency::RecordLatencyLayer, service::service_fn, BoxError,
+///     Service,
+/// };
+///
+/// # #[tokio::main(flavor = "current_thread")]
+/// # async fn main() {
+/// async fn echo(_cx: &mut (), req: String) -> Result<String, BoxError> {
+///     Ok(req)
+/// }
+///
+/// let layer = RecordLatencyLayer::new();
+/// let histogram = layer.histogram().clone();
+///
+/// let svc = ServiceBuilder::new().layer(layer).service(service_fn(echo));
+/// svc.call(&mut (), "ping".into()).await.unwrap();
+///
+/// let snapshot = histogram.snapshot();
+/// assert_eq!(snapshot.count(), 1);
+/// println!("p99: {:?}", snapshot.p99().unwrap());
+/// # }
+/// ```
+#[derive(Clone, Debug, Default)]
+pub struct RecordLatencyLayer {
+    histogram: LatencyHistogram,
+}
+
+impl RecordLatencyLayer {
+    /// Creates a layer recording the latencies in a new histogram.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Creates a layer recording the latencies in `histogram`.
+    pub const fn with_histogram(histogram: LatencyHistogram) -> Self {
+        RecordLatencyLayer { histogram }
+    }
+
+    /// Returns the handle to the histogram.
+    pub fn histogram(&self) -> &LatencyHistogram {
+        &self.histogram
+    }
+}
+
+impl<S> Layer<S> for RecordLatencyLayer {
+    type Service = RecordLatency<S>;
+
+    fn layer(self, inner: S) -> Self::Service {
+        RecordLatency::new(inner, self.histogram)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::convert::Infallible;
+
+    use super::*;
+    use crate::service::service_fn;
+
+    #[tokio::test(start_paused = true)]
+    async fn records_the_latencies() {
+        let svc = RecordLatencyLayer::new().layer(service_fn(|_cx: &mut (), ms: u64| async move {
+            tokio::time::sleep(Duration::from_millis(ms)).await;
+            Ok::<_, Infallible>(())
+        }));
+        let histogram = svc.histogram().clone();
+        assert_eq!(histogram.snapshot().p50(), None);
+
+        for ms in 1..=100 {
+            svc.call(&mut (), ms).await.unwrap();
+        }
+        let snapshot = histogram.snapshot();
+        assert_eq!(snapshot.count(), 100);
+        for (p, ms) in [
+            (snapshot.p50(), 50),
+            (snapshot.p95(), 95),
+            (snapshot.p99(), 99),
+        ] {
+            let expected = Duration::from_millis(ms);
+            let p = p.unwrap();
+            assert!(expected <= p && p <= expected * 9 / 8, "{p:?} for {ms}ms");
+        }
+
+        histogram.reset();
+        assert_eq!(histogram.snapshot().count(), 0);
+        assert_eq!(snapshot.count(), 100);
+    }
+}
@@ -0,0 +1,142 @@
+use std::{fmt, marker::PhantomData};
+
+use super::Layer;
+use crate::service::{BoxCloneService, Service};
+
+/// A [`Layer`] that erases the type of the service it wraps.
+///
+/// Every layer in a [`ServiceBuilder`] nests the type of the service below it,
+/// so large stacks instantiated for many request types can result in long
+/// compile times and big binaries. Putting an `EraseLayer` in the middle of a
+/// stack turns everything below it into a [`BoxCloneService`], trading a
+/// dynamic dispatch and a boxed future per call for a flat type that the
+/// outer layers are instantiated against.
+///
+/// The request, response and error types stay generic, only the concrete service type is
+/// erased.
+///
+/// # Example
+///
+/// ```rust
+/// use motore::{builder::ServiceBuilder, layer::EraseLayer, service::service_fn, BoxCloneService};
+///
+/// # struct Cx;
+/// async fn handle(_cx: &mut Cx, req: String) -> Result<String, std::convert::Infallible> {
+///     Ok(req)
+/// }
+///
+/// let svc: BoxCloneService<Cx, String, String, std::convert::Infallible> = ServiceBuilder::new()
+///     .layer(EraseLayer::new())
+///     .service_fn(handle);
+/// ```
+///
+/// [`ServiceBuilder`]: crate::builder::ServiceBuilder
+pub struct EraseLayer<Cx, Req> {
+    _phantom: PhantomData<fn(Cx, Req)>,
+}
+
+impl<Cx, Req> EraseLayer<Cx, Req> {
+    /// Create a new [`EraseLayer`].
+    pub const fn new() -> Self {
+        EraseLayer {
+            _phantom: PhantomData,
+        }
+    }
+}
+
+impl<Cx, Req> Default for EraseLayer<Cx, Req> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<Cx, Req> Clone for EraseLayer<Cx, Req> {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+impl<Cx, Req> Copy for EraseLayer<Cx, Req> {}
+
+impl<Cx, Req> fmt::Debug for EraseLayer<Cx, Req> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("EraseLayer").finish()
+    }
+}
+
+#[cfg(feature = "service_send")]
+impl<S, Cx, Req> Layer<S> for EraseLayer<Cx, Req>
+where
+    S: Service<Cx, Req> + Clone + Send + Sync + 'static,
+    Req: 'static,
+{
+    type Service = BoxCloneService<Cx, Req, S::Response, S::Error>;
+
+    fn layer(self, inner: S) -> Self::Service {
+        BoxCloneService::new(inner)
+    }
+}
+
+#[cfg(not(feature = "service_send"))]
+impl<S, Cx, Req> Layer<S> for EraseLayer<Cx, Req>
+where
+    S: Service<Cx, Req> + Clone + 'static,
+    Req: 'static,
+{
+    type Service = BoxCloneService<Cx, Req, S::Response, S::Error>;
+
+    fn layer(self, inner: S) -> Self::Service {
+        BoxCloneService::new(inner)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    };
+
+    use super::*;
+    use crate::{
+        builder::ServiceBuilder,
+        service::{service_fn, BoxService},
+    };
+
+    #[tokio::test]
+    async fn erases_the_layers_below() {
+        let calls = Arc::new(AtomicUsize::new(0));
+        let handler = {
+            let calls = calls.clone();
+            move |_cx: &mut (), req: u32| {
+                calls.fetch_add(1, Ordering::Relaxed);
+                async move {
+                    match req {
+                        0 => Err("zero"),
+                        req => Ok(100 / req),
+                    }
+                }
+            }
+        };
+        let svc: BoxCloneService<(), u32, u32, String> = ServiceBuilder::new()
+            .erase_layer()
+            .map_err(|e: usize| format!("{e} bytes"))
+            .map_err(str::len)
+            .service(service_fn(handler));
+
+        assert_eq!(svc.call(&mut (), 4).await, Ok(25));
+        assert_eq!(svc.call(&mut (), 0).await, Err("4 bytes".to_string()));
+
+        // The clones and the boxed service share the erased stack.
+        let clone = svc.clone();
+        assert_eq!(clone.call(&mut (), 5).await, Ok(20));
+        let boxed = BoxService::new(svc);
+        assert_eq!(boxed.call(&mut (), 10).await, Ok(10));
+        assert_eq!(boxed.call(&mut (), 0).await, Err("4 bytes".to_string()));
+        assert_eq!(calls.load(Ordering::Relaxed), 5);
+
+        drop(clone);
+        drop(boxed);
+        assert_eq!(Arc::strong_count(&calls), 1);
+    }
+}
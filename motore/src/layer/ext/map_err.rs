@@ -1,5 +1,6 @@
 use crate::{layer::Layer, service::MapErr};
 
+#[derive(Clone)]
 pub struct MapErrLayer<F> {
     pub(crate) f: F,
 }
@@ -1,6 +1,51 @@
 use super::{Identity, Layer, Stack};
 use crate::utils::Either;
 
+/// An ordered list of layers, from the innermost to the outermost.
+///
+/// The first layer added is the one closest to the service. [`push`](Layers::push)
+/// and [`insert_outer`](Layers::insert_outer) wrap everything added so far, while
+/// [`insert_inner`](Layers::insert_inner) goes under everything added so far. The
+/// relative order of the layers already in the list never changes.
+///
+/// Since the list is statically typed, layers can't be inserted at an arbitrary
+/// index. Frameworks that want to offer extension points around their own layers
+/// can keep a separate `Layers` for each slot, and nest them, as `Layers` is itself a
+/// [`Layer`]:
+///
+/// ```rust
+/// use motore::layer::{Identity, Layer, Layers};
+///
+/// struct Client<Inner, Outer> {
+///     // Applied between the framework's layers and the transport.
+///     inner: Layers<Inner>,
+///     // Applied around the framework's layers.
+///     outer: Layers<Outer>,
+/// }
+///
+/// impl Client<Identity, Identity> {
+///     fn new() -> Self {
+///         Client {
+///             inner: Layers::default(),
+///             outer: Layers::default(),
+///         }
+///     }
+/// }
+///
+/// impl<Inner, Outer> Client<Inner, Outer> {
+///     fn build<S, Framework>(self, framework: Framework, transport: S) -> Outer::Service
+///     where
+///         Inner: Layer<S>,
+///         Framework: Layer<Inner::Service>,
+///         Outer: Layer<Framework::Service>,
+///     {
+///         self.inner
+///             .push(framework)
+///             .push(self.outer)
+///             .layer(transport)
+///     }
+/// }
+/// ```
 #[derive(Clone, Debug)]
 pub struct Layers<L>(pub L);
 
@@ -15,10 +60,12 @@ impl<L> Layers<L> {
         Layers(layer)
     }
 
+    /// Adds a layer outside all the layers added so far.
     pub fn push<O>(self, outer: O) -> Layers<Stack<L, O>> {
         Layers(Stack::new(self.0, outer))
     }
 
+    /// Optionally adds a layer outside all the layers added so far.
     pub fn push_optional<O>(self, outer: Option<O>) -> Layers<Stack<L, Either<O, Identity>>> {
         self.push(if let Some(o) = outer {
             Either::A(o)
@@ -26,6 +73,19 @@ impl<L> Layers<L> {
             Either::B(Identity::new())
         })
     }
+
+    /// Adds a layer outside all the layers added so far, making it the outermost one.
+    ///
+    /// This is the same as [`push`](Layers::push).
+    pub fn insert_outer<O>(self, outer: O) -> Layers<Stack<L, O>> {
+        self.push(outer)
+    }
+
+    /// Adds a layer under all the layers added so far, making it the innermost one,
+    /// directly wrapping the service.
+    pub fn insert_inner<I>(self, inner: I) -> Layers<Stack<I, L>> {
+        Layers(Stack::new(inner, self.0))
+    }
 }
 
 impl<M, L: Layer<M>> Layer<M> for Layers<L> {
@@ -35,3 +95,26 @@ impl<M, L: Layer<M>> Layer<M> for Layers<L> {
         self.0.layer(inner)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::layer::layer_fn;
+
+    #[derive(Debug, PartialEq)]
+    struct Wrapped<S>(&'static str, S);
+
+    #[test]
+    fn insertion_order() {
+        let svc = Layers::new(layer_fn(|s| Wrapped("b", s)))
+            .insert_outer(layer_fn(|s| Wrapped("c", s)))
+            .insert_inner(layer_fn(|s| Wrapped("a", s)))
+            .push(layer_fn(|s| Wrapped("d", s)))
+            .layer(0u8);
+
+        assert_eq!(
+            svc,
+            Wrapped("d", Wrapped("c", Wrapped("b", Wrapped("a", 0u8))))
+        );
+    }
+}
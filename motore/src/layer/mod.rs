@@ -5,8 +5,24 @@
 //!
 //! A middleware implements the [`Layer`] and [`Service`] trait.
 //!
+//! Every layer shipped with motore is [`Clone`], and so are the services of the
+//! middlewares wrapping an inner service, as long as the inner service is. Cloning
+//! them is cheap: configuration is copied or shared, and the inner service is only
+//! cloned when the stack itself is. Cloning the whole stack per connection or task
+//! is the expected way to share it.
+//!
+//! The services owning state of their own rather than wrapping a service, like the
+//! balancers of [`balance`](crate::balance), or the [`Reconnect`] and [`Pooled`]
+//! connections, are not [`Clone`]. They are shared behind an [`Arc`], or a
+//! [`Buffer`] to share them across tasks without requiring them to be [`Sync`].
+//!
 //! [`Service`]: crate::Service
+//! [`Reconnect`]: crate::make::Reconnect
+//! [`Pooled`]: crate::make::Pooled
+//! [`Arc`]: std::sync::Arc
+//! [`Buffer`]: crate::buffer::Buffer
 
+mod erase;
 mod ext;
 mod identity;
 mod layer_fn;
@@ -18,6 +34,7 @@ mod tower_adapter;
 #[cfg(feature = "tower")]
 pub use self::tower_adapter::*;
 pub use self::{
+    erase::EraseLayer,
     ext::{LayerExt, MapErrLayer},
     identity::Identity,
     layer_fn::{layer_fn, LayerFn},
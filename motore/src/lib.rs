@@ -37,14 +37,57 @@
 //! [`Layer`]: crate::layer::Layer
 //! [`ServiceBuilder`]: crate::builder::ServiceBuilder
 
+pub mod access_log;
+pub mod auth;
+pub mod backoff;
+pub mod balance;
+pub mod buffer;
 pub mod builder;
+pub mod cache;
+pub mod cancel;
+pub mod catch_panic;
+pub mod context;
+pub mod deadline;
+pub mod discover;
+pub mod drain;
+pub mod fault;
+pub mod filter;
+pub mod hedge;
+pub mod in_flight;
+pub mod latency;
 pub mod layer;
+pub mod limit;
+pub mod load;
+pub mod load_shed;
 pub mod make;
+mod maybe_send;
+pub mod metrics;
+pub mod mirror;
+#[cfg(feature = "test-util")]
+#[cfg_attr(docsrs, doc(cfg(feature = "test-util")))]
+pub mod mock;
+pub mod prelude;
+pub mod request_id;
+pub mod retry;
+pub mod serve;
 pub mod service;
+#[cfg(feature = "test-util")]
+#[cfg_attr(docsrs, doc(cfg(feature = "test-util")))]
+pub mod sim;
+pub mod singleflight;
+pub mod spawn;
+pub mod steer;
+pub mod stream;
 pub mod timeout;
+pub mod timer;
+#[cfg(feature = "tracing")]
+#[cfg_attr(docsrs, doc(cfg(feature = "tracing")))]
+pub mod trace;
 pub mod utils;
+pub mod validate;
+pub use maybe_send::{MaybeSend, MaybeSync};
 pub use motore_macros::service;
-pub use service::{BoxCloneService, Service, ServiceExt, UnaryService};
+pub use service::{ArcService, BoxCloneService, BoxService, Service, ServiceExt, UnaryService};
 
 /// Alias for a type-erased error type.
 pub type BoxError = Box<dyn std::error::Error + Send + Sync>;
@@ -54,7 +97,9 @@ mod sealed {
     pub trait Sealed<T> {}
 }
 
+// The service of `test_service_macro` is never built, only checked to compile.
 #[cfg(test)]
+#[allow(dead_code)]
 mod tests {
 
     #[test]
@@ -73,4 +118,26 @@ mod tests {
             }
         }
     }
+
+    #[tokio::test]
+    pub async fn test_service_macro_forwards_calls() {
+        pub struct Context;
+        pub struct Service<S>(S);
+
+        #[crate::service]
+        impl<S, Req> crate::Service<Context, Req> for Service<S>
+        where
+            Req: 'static + crate::MaybeSend,
+            S: crate::Service<Context, Req> + crate::MaybeSync,
+        {
+            async fn call(&self, cx: &mut Context, req: Req) -> Result<S::Response, S::Error> {
+                self.0.call(cx, req).await
+            }
+        }
+
+        let svc = Service(crate::service::service_fn(
+            |_cx: &mut Context, req: u32| async move { Ok::<_, std::convert::Infallible>(req + 1) },
+        ));
+        assert_eq!(crate::Service::call(&svc, &mut Context, 1).await, Ok(2));
+    }
 }
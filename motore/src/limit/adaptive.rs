@@ -0,0 +1,397 @@
+//! Bounds the number of in-flight requests with a limit adapting to the latency.
+//!
+//! A static [`ConcurrencyLimit`](super::ConcurrencyLimit) has to be tuned for every
+//! deployment: too low and it throttles a healthy backend, too high and it lets an
+//! overloaded one queue up work. [`AdaptiveConcurrency`] instead finds the limit
+//! with an AIMD (additive increase, multiplicative decrease) algorithm, like TCP
+//! congestion control does:
+//!
+//! - every call completing within the latency threshold while the limit is in use
+//!   raises the limit by one,
+//! - every call slower than the threshold multiplies the limit by a backoff ratio.
+//!
+//! The limit thus settles around the concurrency the backend sustains without its
+//! latency degrading.
+
+use std::{
+    fmt,
+    sync::{Arc, Mutex, MutexGuard},
+    task::{Context, Poll},
+    time::Duration,
+};
+
+use tokio::sync::{Semaphore, SemaphorePermit};
+
+use crate::{
+    layer::Layer,
+    load::{Load, Ready},
+    service::Service,
+    timer::{DefaultTimer, Timer},
+    MaybeSend, MaybeSync,
+};
+
+/// The configuration of the AIMD algorithm.
+#[derive(Clone, Copy, Debug)]
+struct Aimd {
+    threshold: Duration,
+    initial: usize,
+    min: usize,
+    max: usize,
+    backoff: f64,
+}
+
+/// The limit shared by the clones of an [`AdaptiveConcurrency`].
+#[derive(Debug)]
+struct Controller {
+    aimd: Aimd,
+    semaphore: Semaphore,
+    state: Mutex<State>,
+}
+
+#[derive(Debug)]
+struct State {
+    limit: usize,
+    /// The permits to forget when they are released, as the limit was lowered
+    /// while they were in use.
+    debt: usize,
+}
+
+impl Controller {
+    fn new(aimd: Aimd) -> Self {
+        Controller {
+            aimd,
+            semaphore: Semaphore::new(aimd.initial),
+            state: Mutex::new(State {
+                limit: aimd.initial,
+                debt: 0,
+            }),
+        }
+    }
+
+    fn state(&self) -> MutexGuard<'_, State> {
+        self.state.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// Adjusts the limit after a call took `latency`, or was cancelled if `None`,
+    /// and releases its permit.
+    fn release(&self, permit: SemaphorePermit<'_>, latency: Option<Duration>) {
+        let mut state = self.state();
+        match latency {
+            // Cancelled calls say nothing of the latency.
+            None => {}
+            Some(latency) if latency > self.aimd.threshold => {
+                let limit = ((state.limit as f64 * self.aimd.backoff) as usize).max(self.aimd.min);
+                let decrease = state.limit - limit;
+                state.limit = limit;
+                state.debt += decrease - self.semaphore.forget_permits(decrease);
+            }
+            Some(_) if state.limit < self.aimd.max => {
+                // Only raise the limit when it is actually used, so that an idle
+                // service doesn't accumulate a limit it never proved to sustain.
+                // The permits in use are both the ones under the limit and the
+                // ones to forget.
+                let in_flight =
+                    (state.limit + state.debt).saturating_sub(self.semaphore.available_permits());
+                if in_flight * 2 >= state.limit {
+                    state.limit += 1;
+                    if state.debt > 0 {
+                        state.debt -= 1;
+                    } else {
+                        self.semaphore.add_permits(1);
+                    }
+                }
+            }
+            Some(_) => {}
+        }
+
+        if state.debt > 0 {
+            state.debt -= 1;
+            permit.forget();
+        }
+    }
+}
+
+/// Bounds the number of in-flight calls to the inner service with a limit
+/// adjusted to its latency.
+///
+/// The clones of an `AdaptiveConcurrency` share the same limit. See the [module
+/// level docs](self) for how the limit is adjusted.
+#[derive(Clone)]
+pub struct AdaptiveConcurrency<S, T = DefaultTimer> {
+    inner: S,
+    controller: Arc<Controller>,
+    timer: T,
+}
+
+impl<S, T> AdaptiveConcurrency<S, T> {
+    /// Returns the current limit.
+    pub fn limit(&self) -> usize {
+        self.controller.state().limit
+    }
+}
+
+impl<Cx, Req, S, T> Service<Cx, Req> for AdaptiveConcurrency<S, T>
+where
+    Req: 'static + MaybeSend,
+    S: Service<Cx, Req> + 'static + MaybeSend + MaybeSync,
+    Cx: 'static + MaybeSend,
+    T: Timer + MaybeSync,
+{
+    type Response = S::Response;
+
+    type Error = S::Error;
+
+    async fn call(&self, cx: &mut Cx, req: Req) -> Result<Self::Response, Self::Error> {
+        // The semaphore is never closed.
+        let permit = self.controller.semaphore.acquire().await.unwrap();
+        let mut guard = Guard {
+            controller: &self.controller,
+            permit: Some(permit),
+        };
+        let start = self.timer.now();
+        let res = self.inner.call(cx, req).await;
+        if let Some(permit) = guard.permit.take() {
+            let latency = self.timer.now().saturating_duration_since(start);
+            self.controller.release(permit, Some(latency));
+        }
+        res
+    }
+}
+
+/// Releases the permit of a cancelled call.
+struct Guard<'a> {
+    controller: &'a Controller,
+    permit: Option<SemaphorePermit<'a>>,
+}
+
+impl Drop for Guard<'_> {
+    fn drop(&mut self) {
+        if let Some(permit) = self.permit.take() {
+            self.controller.release(permit, None);
+        }
+    }
+}
+
+impl<S, T> Ready for AdaptiveConcurrency<S, T>
+where
+    S: Ready,
+{
+    fn poll_ready(&self, cx: &mut Context<'_>) -> Poll<()> {
+        self.inner.poll_ready(cx)
+    }
+}
+
+impl<S, T> Load for AdaptiveConcurrency<S, T>
+where
+    S: Load,
+{
+    type Metric = S::Metric;
+
+    fn load(&self) -> Self::Metric {
+        self.inner.load()
+    }
+}
+
+impl<S: fmt::Debug, T> fmt::Debug for AdaptiveConcurrency<S, T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("AdaptiveConcurrency")
+            .field("inner", &self.inner)
+            .field("limit", &self.limit())
+            .finish()
+    }
+}
+
+/// Applies an [`AdaptiveConcurrency`] limit to a service.
+///
+/// Every service made by the layer has its own limit, shared only by its clones.
+///
+/// # Example
+///
+/// ```rust
+/// use std::time::Duration;
+///
+/// use motore::{
+///     builder::ServiceBuilder, limit::AdaptiveConcurrencyLayer, service::service_fn, BoxError,
+///     Service,
+/// };
+///
+/// # #[tokio::main(flavor = "current_thread")]
+/// # async fn main() {
+/// async fn echo(_cx: &mut (), req: String) -> Result<String, BoxError> {
+///     Ok(req)
+/// }
+///
+/// let svc = ServiceBuilder::new()
+///     .layer(AdaptiveConcurrencyLayer::new(Duration::from_millis(50)).max(200))
+///     .service(service_fn(echo));
+///
+/// assert_eq!(svc.call(&mut (), "ping".into()).await.unwrap(), "ping");
+/// # }
+/// ```
+#[derive(Clone, Copy, Debug)]
+pub struct AdaptiveConcurrencyLayer<T = DefaultTimer> {
+    aimd: Aimd,
+    timer: T,
+}
+
+impl AdaptiveConcurrencyLayer {
+    /// Creates a layer lowering the limit when calls take longer than `threshold`.
+    ///
+    /// The limit starts at 20 and stays between 1 and 1000, and is multiplied by
+    /// 0.9 on every slow call.
+    pub const fn new(threshold: Duration) -> Self {
+        AdaptiveConcurrencyLayer {
+            aimd: Aimd {
+                threshold,
+                initial: 20,
+                min: 1,
+                max: 1000,
+                backoff: 0.9,
+            },
+            timer: DefaultTimer::new(),
+        }
+    }
+}
+
+impl<T> AdaptiveConcurrencyLayer<T> {
+    /// Sets the limit before any call completed.
+    pub const fn initial(mut self, initial: usize) -> Self {
+        self.aimd.initial = initial;
+        self
+    }
+
+    /// Sets the lowest limit.
+    pub const fn min(mut self, min: usize) -> Self {
+        self.aimd.min = min;
+        self
+    }
+
+    /// Sets the highest limit.
+    pub const fn max(mut self, max: usize) -> Self {
+        self.aimd.max = max;
+        self
+    }
+
+    /// Sets the ratio the limit is multiplied by after a slow call.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `ratio` is not between 0.5 and 1.
+    pub fn backoff(mut self, ratio: f64) -> Self {
+        assert!(
+            (0.5..1.0).contains(&ratio),
+            "the backoff ratio must be between 0.5 and 1"
+        );
+        self.aimd.backoff = ratio;
+        self
+    }
+
+    /// Sets the timer measuring the latencies, [`DefaultTimer`] by default.
+    pub fn timer<U>(self, timer: U) -> AdaptiveConcurrencyLayer<U> {
+        AdaptiveConcurrencyLayer {
+            aimd: self.aimd,
+            timer,
+        }
+    }
+}
+
+impl<S, T> Layer<S> for AdaptiveConcurrencyLayer<T> {
+    type Service = AdaptiveConcurrency<S, T>;
+
+    fn layer(self, inner: S) -> Self::Service {
+        let mut aimd = self.aimd;
+        aimd.min = aimd.min.max(1);
+        aimd.max = aimd.max.max(aimd.min);
+        aimd.initial = aimd.initial.clamp(aimd.min, aimd.max);
+        AdaptiveConcurrency {
+            inner,
+            controller: Arc::new(Controller::new(aimd)),
+            timer: self.timer,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        convert::Infallible,
+        sync::atomic::{AtomicU64, Ordering},
+    };
+
+    use tokio::time::Instant;
+
+    use super::*;
+    use crate::service::service_fn;
+
+    #[tokio::test(start_paused = true)]
+    async fn limit_follows_the_latency() {
+        let latency = Arc::new(AtomicU64::new(10));
+        let svc = AdaptiveConcurrencyLayer::new(Duration::from_millis(50))
+            .initial(4)
+            .min(2)
+            .max(6)
+            .backoff(0.5)
+            .layer(service_fn({
+                let latency = latency.clone();
+                move |_cx: &mut (), req: u32| {
+                    let latency = latency.load(Ordering::SeqCst);
+                    async move {
+                        tokio::time::sleep(Duration::from_millis(latency)).await;
+                        Ok::<_, Infallible>(req)
+                    }
+                }
+            }));
+
+        // Sequential calls don't use the limit, so they don't raise it.
+        for i in 0..5 {
+            assert_eq!(svc.call(&mut (), i).await, Ok(i));
+        }
+        assert_eq!(svc.limit(), 4);
+
+        let burst = |n: u32| {
+            futures::future::join_all((0..n).map(|i| {
+                let svc = svc.clone();
+                async move { svc.call(&mut (), i).await }
+            }))
+        };
+        burst(12).await;
+        assert_eq!(svc.limit(), 6);
+
+        latency.store(100, Ordering::SeqCst);
+        burst(6).await;
+        assert_eq!(svc.limit(), 2);
+
+        // The permits of the calls in flight when the limit was lowered are gone.
+        let start = Instant::now();
+        burst(4).await;
+        assert_eq!(start.elapsed(), Duration::from_millis(200));
+        assert_eq!(svc.controller.semaphore.available_permits(), 2);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn limit_recovers_after_a_slow_burst() {
+        let svc = AdaptiveConcurrencyLayer::new(Duration::from_millis(50))
+            .initial(100)
+            .layer(service_fn(|_cx: &mut (), millis: u64| async move {
+                tokio::time::sleep(Duration::from_millis(millis)).await;
+                Ok::<_, Infallible>(())
+            }));
+        let burst = |millis| {
+            futures::future::join_all((0..50).map(|_| {
+                let svc = svc.clone();
+                async move { svc.call(&mut (), millis).await }
+            }))
+        };
+
+        // The slow calls lower the limit while all the permits are in use, so the
+        // permits of the fast calls are owed when they complete.
+        tokio::join!(burst(60), async {
+            tokio::time::sleep(Duration::from_millis(20)).await;
+            burst(45).await
+        });
+        assert!(svc.limit() > 1, "limit: {}", svc.limit());
+        let state = svc.controller.state();
+        assert_eq!(state.debt, 0);
+        assert_eq!(svc.controller.semaphore.available_permits(), state.limit);
+    }
+}
@@ -0,0 +1,288 @@
+//! Bounds the number of in-flight requests of each key.
+//!
+//! A single [`ConcurrencyLimit`](super::ConcurrencyLimit) in front of a service
+//! shared by several tenants, or calling several downstream clusters, lets the
+//! busiest of them take all the permits, and the others wait behind it. A
+//! [`Bulkhead`] splits the capacity into compartments: every request is given a
+//! key, like its tenant or method, and the requests of each key have a limit of
+//! their own, so a noisy key only waits for itself.
+//!
+//! Keys have a limit only while they have requests in flight or waiting, so the
+//! number of keys needn't be bounded.
+
+use std::{
+    collections::HashMap,
+    fmt,
+    hash::Hash,
+    marker::PhantomData,
+    sync::{Arc, Mutex},
+    task::{Context, Poll},
+};
+
+use tokio::sync::{OwnedSemaphorePermit, Semaphore};
+
+use crate::{
+    layer::Layer,
+    load::{Load, Ready},
+    service::Service,
+    MaybeSend, MaybeSync,
+};
+
+type Compartments<K> = Mutex<HashMap<K, Arc<Semaphore>>>;
+
+/// Holds a permit of the compartment of a key, and removes the compartment once
+/// it is no longer used.
+struct Compartment<'a, K: Hash + Eq> {
+    compartments: &'a Compartments<K>,
+    key: K,
+    semaphore: Arc<Semaphore>,
+    permit: Option<OwnedSemaphorePermit>,
+}
+
+impl<K: Hash + Eq> Drop for Compartment<'_, K> {
+    fn drop(&mut self) {
+        drop(self.permit.take());
+        let mut compartments = self.compartments.lock().unwrap_or_else(|e| e.into_inner());
+        // The semaphores are only cloned under the lock, so no other call uses it
+        // when only the map and this call hold it.
+        if Arc::strong_count(&self.semaphore) == 2 {
+            compartments.remove(&self.key);
+        }
+    }
+}
+
+/// Bounds the number of in-flight calls to the inner service for each key.
+///
+/// The key of a request is given by a closure, `Fn(&Req) -> K`, and `K` is
+/// inferred. The calls over the limit of their key wait for a permit. The clones of
+/// a `Bulkhead` share the same limits. See the [module level docs](self) for
+/// details.
+pub struct Bulkhead<S, F, K> {
+    inner: S,
+    key: F,
+    max: usize,
+    compartments: Arc<Compartments<K>>,
+}
+
+impl<S, F, K> Bulkhead<S, F, K> {
+    /// Creates a bulkhead allowing `max` in-flight calls for each of the keys
+    /// returned by `key`.
+    pub fn new(inner: S, max: usize, key: F) -> Self {
+        Bulkhead {
+            inner,
+            key,
+            max,
+            compartments: Arc::new(Mutex::new(HashMap::new())),
+        }
+    }
+}
+
+impl<S, F, K: Hash + Eq> Bulkhead<S, F, K> {
+    /// Returns the number of calls of `key` that can start without waiting.
+    pub fn available(&self, key: &K) -> usize {
+        self.compartments
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .get(key)
+            .map_or(self.max, |semaphore| semaphore.available_permits())
+    }
+}
+
+impl<Cx, Req, S, F, K> Service<Cx, Req> for Bulkhead<S, F, K>
+where
+    Req: 'static + MaybeSend,
+    S: Service<Cx, Req> + 'static + MaybeSend + MaybeSync,
+    F: Fn(&Req) -> K + MaybeSend + MaybeSync,
+    K: Hash + Eq + Clone + MaybeSend + MaybeSync,
+    Cx: 'static + MaybeSend,
+{
+    type Response = S::Response;
+
+    type Error = S::Error;
+
+    async fn call(&self, cx: &mut Cx, req: Req) -> Result<Self::Response, Self::Error> {
+        let key = (self.key)(&req);
+        let semaphore = self
+            .compartments
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .entry(key.clone())
+            .or_insert_with(|| Arc::new(Semaphore::new(self.max)))
+            .clone();
+        let mut compartment = Compartment {
+            compartments: &self.compartments,
+            key,
+            semaphore,
+            permit: None,
+        };
+        // The semaphores are never closed.
+        compartment.permit = compartment.semaphore.clone().acquire_owned().await.ok();
+        self.inner.call(cx, req).await
+    }
+}
+
+impl<S, F, K> Ready for Bulkhead<S, F, K>
+where
+    S: Ready,
+{
+    fn poll_ready(&self, cx: &mut Context<'_>) -> Poll<()> {
+        self.inner.poll_ready(cx)
+    }
+}
+
+impl<S, F, K> Load for Bulkhead<S, F, K>
+where
+    S: Load,
+{
+    type Metric = S::Metric;
+
+    fn load(&self) -> Self::Metric {
+        self.inner.load()
+    }
+}
+
+impl<S: Clone, F: Clone, K> Clone for Bulkhead<S, F, K> {
+    fn clone(&self) -> Self {
+        Bulkhead {
+            inner: self.inner.clone(),
+            key: self.key.clone(),
+            max: self.max,
+            compartments: self.compartments.clone(),
+        }
+    }
+}
+
+impl<S: fmt::Debug, F, K> fmt::Debug for Bulkhead<S, F, K> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Bulkhead")
+            .field("inner", &self.inner)
+            .field("max", &self.max)
+            .field("key", &format_args!("{}", std::any::type_name::<F>()))
+            .finish()
+    }
+}
+
+/// Applies a [`Bulkhead`] to a service.
+///
+/// Every service made by the layer has its own limits, shared only by its clones.
+///
+/// # Example
+///
+/// ```rust
+/// use motore::{
+///     builder::ServiceBuilder, limit::BulkheadLayer, service::service_fn, BoxError, Service,
+/// };
+///
+/// struct Request {
+///     tenant: String,
+///     body: String,
+/// }
+///
+/// # #[tokio::main(flavor = "current_thread")]
+/// # async fn main() {
+/// async fn handle(_cx: &mut (), req: Request) -> Result<String, BoxError> {
+///     Ok(req.body)
+/// }
+///
+/// // Each tenant has up to 8 requests in flight.
+/// let svc = ServiceBuilder::new()
+///     .layer(BulkheadLayer::new(8, |req: &Request| req.tenant.clone()))
+///     .service(service_fn(handle));
+///
+/// let req = Request {
+///     tenant: "acme".into(),
+///     body: "ping".into(),
+/// };
+/// assert_eq!(svc.call(&mut (), req).await.unwrap(), "ping");
+/// # }
+/// ```
+pub struct BulkheadLayer<F, K> {
+    max: usize,
+    key: F,
+    _phantom: PhantomData<fn() -> K>,
+}
+
+impl<F, K> BulkheadLayer<F, K> {
+    /// Creates a layer allowing `max` in-flight calls for each of the keys returned
+    /// by `key`.
+    pub const fn new(max: usize, key: F) -> Self {
+        BulkheadLayer {
+            max,
+            key,
+            _phantom: PhantomData,
+        }
+    }
+}
+
+impl<F: Clone, K> Clone for BulkheadLayer<F, K> {
+    fn clone(&self) -> Self {
+        BulkheadLayer {
+            max: self.max,
+            key: self.key.clone(),
+            _phantom: PhantomData,
+        }
+    }
+}
+
+impl<F, K> fmt::Debug for BulkheadLayer<F, K> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("BulkheadLayer")
+            .field("max", &self.max)
+            .field("key", &format_args!("{}", std::any::type_name::<F>()))
+            .finish()
+    }
+}
+
+impl<S, F, K> Layer<S> for BulkheadLayer<F, K> {
+    type Service = Bulkhead<S, F, K>;
+
+    fn layer(self, inner: S) -> Self::Service {
+        Bulkhead::new(inner, self.max, self.key)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{convert::Infallible, time::Duration};
+
+    use tokio::time::Instant;
+
+    use super::*;
+    use crate::service::service_fn;
+
+    #[tokio::test(start_paused = true)]
+    async fn isolates_the_keys() {
+        let svc = BulkheadLayer::new(1, |req: &(&'static str, u32)| req.0).layer(service_fn(
+            |_cx: &mut (), req: (&'static str, u32)| async move {
+                tokio::time::sleep(Duration::from_millis(100)).await;
+                Ok::<_, Infallible>((Instant::now(), req.1))
+            },
+        ));
+        let start = Instant::now();
+
+        let calls = [("noisy", 0), ("noisy", 1), ("noisy", 2), ("quiet", 3)].map(|req| {
+            let svc = svc.clone();
+            async move { svc.call(&mut (), req).await.unwrap() }
+        });
+        let done = futures::future::join_all(calls).await;
+        let elapsed: Vec<_> = done.iter().map(|(at, i)| (*at - start, *i)).collect();
+        assert_eq!(
+            elapsed,
+            [
+                (Duration::from_millis(100), 0),
+                (Duration::from_millis(200), 1),
+                (Duration::from_millis(300), 2),
+                (Duration::from_millis(100), 3),
+            ]
+        );
+
+        // The compartments are removed once idle, including after a cancellation.
+        assert!(svc.compartments.lock().unwrap().is_empty());
+        let call = async { svc.call(&mut (), ("noisy", 4)).await };
+        assert!(tokio::time::timeout(Duration::from_millis(10), call)
+            .await
+            .is_err());
+        assert!(svc.compartments.lock().unwrap().is_empty());
+        assert_eq!(svc.available(&"noisy"), 1);
+    }
+}
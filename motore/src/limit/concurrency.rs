@@ -0,0 +1,521 @@
+//! Bounds the number of requests a service handles at once.
+//!
+//! Every call takes a permit from a semaphore, waiting for one when all are in
+//! use, and holds it until the inner service returns. A service under overload
+//! thus makes its callers wait instead of accumulating in-flight work.
+//!
+//! A [`ConcurrencyLimitLayer`] gives each service it makes its own limit, while a
+//! [`GlobalConcurrencyLimitLayer`] applies a single limit to all of them, such as
+//! a cap on the in-flight requests of all the clients of a process.
+//!
+//! Under heavy contention, the semaphore becomes a bottleneck of its own, as every
+//! call updates it. [`ConcurrencyLimitLayer::sharded`] splits the permits of each
+//! limit across shards instead: the calls take the permits of the shard of their
+//! thread first, steal them from the other shards once it has none left, and
+//! otherwise wait for a permit of any shard.
+//!
+//! A limit with no permit left reports itself as not [`Ready`], so it can be
+//! wrapped in a [`LoadShed`](crate::load_shed::LoadShed) to fail the calls over the
+//! limit instead of making them wait.
+
+use std::{
+    fmt,
+    future::Future,
+    sync::Arc,
+    task::{ready, Context, Poll},
+};
+
+use crate::{
+    layer::Layer,
+    load::{Load, Ready},
+    service::Service,
+    utils::{
+        semaphore::ShardedSemaphore,
+        sync::{AcquireError, Mutex, OwnedSemaphorePermit, Semaphore, SemaphorePermit},
+        waiters::Waiters,
+    },
+    MaybeSend, MaybeSync,
+};
+use futures::{
+    future::BoxFuture,
+    task::{self, ArcWake},
+};
+
+/// Bounds the number of in-flight calls to the inner service.
+///
+/// The clones of a `ConcurrencyLimit` share the same limit.
+///
+/// The limit is [`Ready`] when it has a permit available. When it doesn't, the
+/// tasks polling it are all woken once a permit is released, whichever service
+/// sharing the semaphore released it.
+#[derive(Clone)]
+pub struct ConcurrencyLimit<S> {
+    inner: S,
+    permits: Permits,
+    readiness: Arc<Readiness>,
+}
+
+/// The permits of a [`ConcurrencyLimit`].
+#[derive(Clone)]
+enum Permits {
+    Single(Arc<Semaphore>),
+    Sharded(Arc<ShardedSemaphore>),
+}
+
+impl Permits {
+    fn sharded(max: usize, shards: usize) -> Self {
+        Permits::Sharded(Arc::new(ShardedSemaphore::new(max, shards)))
+    }
+
+    fn available(&self) -> usize {
+        match self {
+            Permits::Single(semaphore) => semaphore.available_permits(),
+            Permits::Sharded(semaphore) => semaphore.available_permits(),
+        }
+    }
+
+    /// Takes a permit, or returns `None` if the semaphore was closed.
+    async fn acquire(&self) -> Option<SemaphorePermit<'_>> {
+        match self {
+            Permits::Single(semaphore) => semaphore.acquire().await.ok(),
+            Permits::Sharded(semaphore) => semaphore.acquire().await.ok(),
+        }
+    }
+
+    /// Returns a future taking a permit, from any shard.
+    fn acquire_owned(&self) -> BoxFuture<'static, Result<OwnedSemaphorePermit, AcquireError>> {
+        match self {
+            Permits::Single(semaphore) => Box::pin(semaphore.clone().acquire_owned()),
+            Permits::Sharded(semaphore) => semaphore.acquire_owned(),
+        }
+    }
+}
+
+/// Waits for a permit on behalf of the tasks polling a [`ConcurrencyLimit`] for
+/// readiness.
+///
+/// A single acquisition waits in the queue of the semaphore. Once it is assigned a
+/// permit, its waker drops it, which gives the permit back to the semaphore, as
+/// readiness reserves nothing, and wakes all the tasks. The permit is thus never
+/// held on behalf of tasks which may have stopped polling.
+#[derive(Default)]
+struct Readiness {
+    acquire: Mutex<Option<BoxFuture<'static, Result<OwnedSemaphorePermit, AcquireError>>>>,
+    waiters: Waiters,
+}
+
+impl Readiness {
+    fn poll_permit(self: &Arc<Self>, permits: &Permits, cx: &mut Context<'_>) -> Poll<()> {
+        self.waiters.register(cx.waker());
+        let mut acquire = self.acquire.lock().unwrap_or_else(|e| e.into_inner());
+        let fut = acquire.get_or_insert_with(|| permits.acquire_owned());
+        let waker = task::waker(self.clone());
+        // The permit is released right away. A closed semaphore is ready too, as
+        // the calls don't wait for it.
+        drop(ready!(Future::poll(
+            fut.as_mut(),
+            &mut Context::from_waker(&waker)
+        )));
+        *acquire = None;
+        drop(acquire);
+        // The other tasks waited for the same permit.
+        self.waiters.wake_all();
+        Poll::Ready(())
+    }
+}
+
+impl ArcWake for Readiness {
+    fn wake_by_ref(arc_self: &Arc<Self>) {
+        let acquire = arc_self
+            .acquire
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .take();
+        drop(acquire);
+        arc_self.waiters.wake_all();
+    }
+}
+
+impl<S> ConcurrencyLimit<S> {
+    /// Creates a limit allowing `max` in-flight calls.
+    pub fn new(inner: S, max: usize) -> Self {
+        Self::with_semaphore(inner, Arc::new(Semaphore::new(max)))
+    }
+
+    /// Creates a limit taking its permits from `semaphore`, which may be shared with
+    /// other services.
+    pub fn with_semaphore(inner: S, semaphore: Arc<Semaphore>) -> Self {
+        ConcurrencyLimit {
+            inner,
+            permits: Permits::Single(semaphore),
+            readiness: Arc::default(),
+        }
+    }
+
+    /// Creates a limit allowing `max` in-flight calls, with its permits split
+    /// across `shards` shards. See the [module level docs](self) for details.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `shards` is zero.
+    pub fn sharded(inner: S, max: usize, shards: usize) -> Self {
+        assert!(shards > 0, "the number of shards must not be zero");
+        ConcurrencyLimit {
+            inner,
+            permits: Permits::sharded(max, shards),
+            readiness: Arc::default(),
+        }
+    }
+
+    /// Returns the number of calls that can start without waiting.
+    pub fn available(&self) -> usize {
+        self.permits.available()
+    }
+}
+
+impl<Cx, Req, S> Service<Cx, Req> for ConcurrencyLimit<S>
+where
+    Req: 'static + MaybeSend,
+    S: Service<Cx, Req> + 'static + MaybeSend + MaybeSync,
+    Cx: 'static + MaybeSend,
+{
+    type Response = S::Response;
+
+    type Error = S::Error;
+
+    async fn call(&self, cx: &mut Cx, req: Req) -> Result<Self::Response, Self::Error> {
+        // A closed semaphore lets the calls through, as there is nothing to wait for.
+        let _permit = self.permits.acquire().await;
+        self.inner.call(cx, req).await
+    }
+}
+
+impl<S> Ready for ConcurrencyLimit<S>
+where
+    S: Ready,
+{
+    fn poll_ready(&self, cx: &mut Context<'_>) -> Poll<()> {
+        if self.permits.available() == 0 {
+            ready!(self.readiness.poll_permit(&self.permits, cx));
+        }
+        self.inner.poll_ready(cx)
+    }
+}
+
+impl<S> Load for ConcurrencyLimit<S>
+where
+    S: Load,
+{
+    type Metric = S::Metric;
+
+    fn load(&self) -> Self::Metric {
+        self.inner.load()
+    }
+}
+
+impl<S: fmt::Debug> fmt::Debug for ConcurrencyLimit<S> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ConcurrencyLimit")
+            .field("inner", &self.inner)
+            .field("available", &self.available())
+            .finish()
+    }
+}
+
+/// Applies a [`ConcurrencyLimit`] to a service.
+///
+/// Every service made by the layer has its own limit, shared only by its clones.
+/// See [`GlobalConcurrencyLimitLayer`] to share a limit between services.
+///
+/// # Example
+///
+/// ```rust
+/// use motore::{
+///     builder::ServiceBuilder, limit::ConcurrencyLimitLayer, service::service_fn, BoxError,
+///     Service,
+/// };
+///
+/// # #[tokio::main(flavor = "current_thread")]
+/// # async fn main() {
+/// async fn echo(_cx: &mut (), req: String) -> Result<String, BoxError> {
+///     Ok(req)
+/// }
+///
+/// let svc = ServiceBuilder::new()
+///     .layer(ConcurrencyLimitLayer::new(16))
+///     .service(service_fn(echo));
+///
+/// assert_eq!(svc.call(&mut (), "ping".into()).await.unwrap(), "ping");
+/// # }
+/// ```
+#[derive(Clone, Copy, Debug)]
+pub struct ConcurrencyLimitLayer {
+    max: usize,
+    shards: usize,
+}
+
+impl ConcurrencyLimitLayer {
+    /// Creates a layer allowing `max` in-flight calls to each service.
+    pub const fn new(max: usize) -> Self {
+        ConcurrencyLimitLayer { max, shards: 1 }
+    }
+
+    /// Splits the permits of each limit across `shards` shards, usually the number
+    /// of threads of the runtime, to relieve the contention on the semaphore. See
+    /// the [module level docs](self) for details.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `shards` is zero.
+    pub const fn sharded(mut self, shards: usize) -> Self {
+        assert!(shards > 0, "the number of shards must not be zero");
+        self.shards = shards;
+        self
+    }
+}
+
+impl<S> Layer<S> for ConcurrencyLimitLayer {
+    type Service = ConcurrencyLimit<S>;
+
+    fn layer(self, inner: S) -> Self::Service {
+        if self.shards > 1 {
+            ConcurrencyLimit::sharded(inner, self.max, self.shards)
+        } else {
+            ConcurrencyLimit::new(inner, self.max)
+        }
+    }
+}
+
+/// Applies a [`ConcurrencyLimit`] shared by all the services made by the layer.
+///
+/// # Example
+///
+/// ```rust
+/// use std::sync::Arc;
+///
+/// use motore::{layer::Layer, limit::GlobalConcurrencyLimitLayer, service::service_fn, BoxError};
+/// use tokio::sync::Semaphore;
+///
+/// async fn echo(_cx: &mut (), req: String) -> Result<String, BoxError> {
+///     Ok(req)
+/// }
+///
+/// // The two clients never have more than 64 requests in flight together.
+/// let limit = GlobalConcurrencyLimitLayer::with_semaphore(Arc::new(Semaphore::new(64)));
+/// let users = limit.clone().layer(service_fn(echo));
+/// let orders = limit.layer(service_fn(echo));
+/// ```
+#[derive(Clone, Debug)]
+pub struct GlobalConcurrencyLimitLayer {
+    semaphore: Arc<Semaphore>,
+}
+
+impl GlobalConcurrencyLimitLayer {
+    /// Creates a layer allowing `max` in-flight calls to all its services.
+    pub fn new(max: usize) -> Self {
+        Self::with_semaphore(Arc::new(Semaphore::new(max)))
+    }
+
+    /// Creates a layer taking the permits of all its services from `semaphore`.
+    pub const fn with_semaphore(semaphore: Arc<Semaphore>) -> Self {
+        GlobalConcurrencyLimitLayer { semaphore }
+    }
+}
+
+impl<S> Layer<S> for GlobalConcurrencyLimitLayer {
+    type Service = ConcurrencyLimit<S>;
+
+    fn layer(self, inner: S) -> Self::Service {
+        ConcurrencyLimit::with_semaphore(inner, self.semaphore)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{convert::Infallible, time::Duration};
+
+    use tokio::time::Instant;
+
+    use super::*;
+    use crate::{load::AlwaysReady, service::service_fn};
+
+    async fn slow(_cx: &mut (), req: u32) -> Result<u32, Infallible> {
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        Ok(req)
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn bounds_in_flight_calls() {
+        let svc = ConcurrencyLimitLayer::new(2).layer(service_fn(slow));
+        let start = Instant::now();
+
+        let calls = (0..5).map(|i| {
+            let svc = svc.clone();
+            async move { svc.call(&mut (), i).await }
+        });
+        let responses = futures::future::join_all(calls).await;
+
+        assert_eq!(responses, (0..5).map(Ok).collect::<Vec<_>>());
+        assert_eq!(start.elapsed(), Duration::from_millis(300));
+        assert_eq!(svc.available(), 2);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn dropped_calls_release_their_permit() {
+        let svc = ConcurrencyLimitLayer::new(1).layer(service_fn(slow));
+
+        let res = tokio::time::timeout(Duration::from_millis(10), svc.call(&mut (), 1)).await;
+        assert!(res.is_err());
+        assert_eq!(svc.available(), 1);
+        assert_eq!(svc.call(&mut (), 2).await, Ok(2));
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn global_limit_is_shared_between_services() {
+        let layer = GlobalConcurrencyLimitLayer::new(1);
+        let a = layer.clone().layer(service_fn(slow));
+        let b = layer.layer(service_fn(slow));
+        let start = Instant::now();
+
+        let (ra, rb) = tokio::join!(async { a.call(&mut (), 1).await }, async {
+            b.call(&mut (), 2).await
+        });
+        assert_eq!((ra, rb), (Ok(1), Ok(2)));
+        assert_eq!(start.elapsed(), Duration::from_millis(200));
+
+        // Separate layers don't share their limit.
+        let a = ConcurrencyLimitLayer::new(1).layer(service_fn(slow));
+        let b = ConcurrencyLimitLayer::new(1).layer(service_fn(slow));
+        let start = Instant::now();
+        let _ = tokio::join!(async { a.call(&mut (), 1).await }, async {
+            b.call(&mut (), 2).await
+        });
+        assert_eq!(start.elapsed(), Duration::from_millis(100));
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn services_sharing_a_permit_wake_each_other() {
+        let layer = GlobalConcurrencyLimitLayer::new(1);
+        let a = layer.clone().layer(AlwaysReady::new(service_fn(slow)));
+        let b = layer.layer(AlwaysReady::new(service_fn(slow)));
+        let start = Instant::now();
+
+        // Both tasks waiting for `b` are woken when the call through `a` completes.
+        let ready = || async {
+            b.ready().await;
+            start.elapsed()
+        };
+        let (res, first, second) = tokio::time::timeout(Duration::from_secs(1), async {
+            tokio::join!(async { a.call(&mut (), 1).await }, ready(), ready())
+        })
+        .await
+        .unwrap();
+        assert_eq!(res, Ok(1));
+        assert_eq!(first, Duration::from_millis(100));
+        assert_eq!(second, Duration::from_millis(100));
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn sharded_limit_steals_and_waits_for_any_shard() {
+        // Sleeps for the number of milliseconds of the request.
+        async fn sleep(_cx: &mut (), millis: u64) -> Result<u64, Infallible> {
+            tokio::time::sleep(Duration::from_millis(millis)).await;
+            Ok(millis)
+        }
+
+        let svc = ConcurrencyLimitLayer::new(2)
+            .sharded(2)
+            .layer(service_fn(sleep));
+        let start = Instant::now();
+
+        // The second call steals the permit of the other shard, and the third gets
+        // the first permit released, whichever shard it belongs to.
+        let call = |millis| {
+            let svc = svc.clone();
+            async move {
+                svc.call(&mut (), millis).await.unwrap();
+                start.elapsed()
+            }
+        };
+        let elapsed = tokio::join!(call(100), call(50), call(100));
+        assert_eq!(
+            elapsed,
+            (
+                Duration::from_millis(100),
+                Duration::from_millis(50),
+                Duration::from_millis(150)
+            )
+        );
+        assert_eq!(svc.available(), 2);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn abandoned_readiness_keeps_no_permit() {
+        let svc = ConcurrencyLimitLayer::new(1).layer(AlwaysReady::new(service_fn(slow)));
+
+        // The wait for readiness is given up while the call holds the permit.
+        let (res, ready) = tokio::join!(async { svc.call(&mut (), 1).await }, async {
+            tokio::time::timeout(Duration::from_millis(10), svc.ready()).await
+        });
+        assert_eq!(res, Ok(1));
+        assert!(ready.is_err());
+
+        let res = tokio::time::timeout(Duration::from_secs(1), svc.call(&mut (), 2)).await;
+        assert_eq!(res, Ok(Ok(2)));
+        assert_eq!(svc.available(), 1);
+    }
+}
+
+#[cfg(all(test, loom))]
+mod loom_tests {
+    use std::convert::Infallible;
+
+    use loom::sync::atomic::{AtomicUsize, Ordering};
+
+    use super::*;
+
+    /// Fails if it is called again before a call returned.
+    #[derive(Clone)]
+    struct Exclusive(Arc<AtomicUsize>);
+
+    impl Service<(), ()> for Exclusive {
+        type Response = ();
+        type Error = Infallible;
+
+        async fn call(&self, _cx: &mut (), _req: ()) -> Result<(), Infallible> {
+            assert_eq!(self.0.fetch_add(1, Ordering::SeqCst), 0);
+            loom::thread::yield_now();
+            self.0.fetch_sub(1, Ordering::SeqCst);
+            Ok(())
+        }
+    }
+
+    impl Ready for Exclusive {}
+
+    #[test]
+    fn calls_never_exceed_the_limit() {
+        loom::model(|| {
+            let svc = ConcurrencyLimit::new(Exclusive(Arc::new(AtomicUsize::new(0))), 1);
+
+            let other = loom::thread::spawn({
+                let svc = svc.clone();
+                move || loom::future::block_on(svc.call(&mut (), ())).unwrap()
+            });
+            loom::future::block_on(svc.call(&mut (), ())).unwrap();
+            other.join().unwrap();
+        });
+    }
+
+    #[test]
+    fn readiness_is_signalled_once_a_permit_is_released() {
+        loom::model(|| {
+            let svc = ConcurrencyLimit::new(Exclusive(Arc::new(AtomicUsize::new(0))), 1);
+            let permit = svc.permits.acquire_owned();
+            let permit = loom::future::block_on(permit).unwrap();
+
+            let release = loom::thread::spawn(move || drop(permit));
+            loom::future::block_on(futures::future::poll_fn(|cx| svc.poll_ready(cx)));
+            release.join().unwrap();
+        });
+    }
+}
@@ -0,0 +1,347 @@
+//! Restricts the rate of requests of each key reaching a service.
+//!
+//! A [`KeyedRateLimit`] gives every key, like a tenant or a client address, a
+//! token bucket of its own, working as the one of a [`RateLimit`](super::RateLimit):
+//! each key may send bursts of up to `num` requests, and `num` requests per `per`
+//! over time. The key is given by a closure taking the context and the request.
+//!
+//! A key whose bucket has refilled completely is forgotten, as it would get a new,
+//! full bucket anyway. The buckets are swept at most once per `per`, so the keys
+//! idle for longer than `per` don't use memory.
+
+use std::{
+    collections::HashMap,
+    fmt,
+    hash::Hash,
+    sync::{Arc, Mutex},
+    task::{Context, Poll},
+    time::Duration,
+};
+
+use super::rate::RateLimitExceeded;
+use crate::{
+    layer::Layer,
+    load::{Load, Ready},
+    service::Service,
+    timer::{DefaultTimer, Instant, Timer},
+    utils::token_bucket::TokenBucket,
+    BoxError, MaybeSend, MaybeSync,
+};
+
+/// The buckets of the keys, shared by the clones of a [`KeyedRateLimit`].
+struct Buckets<K> {
+    num: u64,
+    per: Duration,
+    state: Mutex<State<K>>,
+}
+
+struct State<K> {
+    buckets: HashMap<K, Arc<TokenBucket>>,
+    /// When the full buckets were last removed.
+    swept: Option<Instant>,
+}
+
+impl<K: Hash + Eq> Buckets<K> {
+    fn new(num: u64, per: Duration) -> Self {
+        Buckets {
+            num,
+            per,
+            state: Mutex::new(State {
+                buckets: HashMap::new(),
+                swept: None,
+            }),
+        }
+    }
+
+    fn bucket(&self, key: K, now: Instant) -> Arc<TokenBucket> {
+        let mut state = self.state.lock().unwrap_or_else(|e| e.into_inner());
+        let swept = *state.swept.get_or_insert(now);
+        if now.saturating_duration_since(swept) >= self.per {
+            // A bucket still used by a waiting call is kept, so it can't be replaced
+            // by a new one while the call waits for its token.
+            state
+                .buckets
+                .retain(|_, bucket| Arc::strong_count(bucket) > 1 || !bucket.is_full(now));
+            state.swept = Some(now);
+        }
+        state
+            .buckets
+            .entry(key)
+            .or_insert_with(|| Arc::new(TokenBucket::new(self.num, self.per)))
+            .clone()
+    }
+
+    fn len(&self) -> usize {
+        self.state
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .buckets
+            .len()
+    }
+}
+
+/// Restricts each key to a number of requests per period.
+///
+/// The key of a request is given by a closure, `Fn(&Cx, &Req) -> K`. The clones
+/// of a `KeyedRateLimit` share the same limits. See the [module level docs](self)
+/// for details.
+pub struct KeyedRateLimit<S, F, K, T = DefaultTimer> {
+    inner: S,
+    key: F,
+    buckets: Arc<Buckets<K>>,
+    wait: bool,
+    timer: T,
+}
+
+impl<S, F, K: Hash + Eq> KeyedRateLimit<S, F, K> {
+    /// Creates a rate limit allowing `num` requests per `per` for each of the keys
+    /// returned by `key`, failing the requests exceeding it.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `num` or `per` is zero.
+    pub fn new(inner: S, num: u64, per: Duration, key: F) -> Self {
+        KeyedRateLimitLayer::new(num, per, key).layer(inner)
+    }
+}
+
+impl<S, F, K: Hash + Eq, T> KeyedRateLimit<S, F, K, T> {
+    /// Returns the number of keys with a bucket, which have been limited recently.
+    pub fn keys(&self) -> usize {
+        self.buckets.len()
+    }
+}
+
+impl<Cx, Req, S, F, K, T> Service<Cx, Req> for KeyedRateLimit<S, F, K, T>
+where
+    Req: 'static + MaybeSend,
+    S: Service<Cx, Req> + 'static + MaybeSend + MaybeSync,
+    F: Fn(&Cx, &Req) -> K + MaybeSync,
+    K: Hash + Eq + MaybeSend + MaybeSync,
+    T: Timer + MaybeSend + MaybeSync,
+    Cx: 'static + MaybeSend,
+    S::Error: MaybeSend + MaybeSync + Into<BoxError>,
+{
+    type Response = S::Response;
+
+    type Error = BoxError;
+
+    async fn call(&self, cx: &mut Cx, req: Req) -> Result<Self::Response, Self::Error> {
+        let bucket = self.buckets.bucket((self.key)(cx, &req), self.timer.now());
+        loop {
+            match bucket.acquire(self.timer.now()) {
+                Ok(()) => break,
+                Err(retry_after) if self.wait => self.timer.sleep(retry_after).await,
+                Err(retry_after) => return Err(RateLimitExceeded { retry_after }.into()),
+            }
+        }
+        drop(bucket);
+        self.inner.call(cx, req).await.map_err(Into::into)
+    }
+}
+
+impl<S, F, K, T> Ready for KeyedRateLimit<S, F, K, T>
+where
+    S: Ready,
+{
+    fn poll_ready(&self, cx: &mut Context<'_>) -> Poll<()> {
+        self.inner.poll_ready(cx)
+    }
+}
+
+impl<S, F, K, T> Load for KeyedRateLimit<S, F, K, T>
+where
+    S: Load,
+{
+    type Metric = S::Metric;
+
+    fn load(&self) -> Self::Metric {
+        self.inner.load()
+    }
+}
+
+impl<S: Clone, F: Clone, K, T: Clone> Clone for KeyedRateLimit<S, F, K, T> {
+    fn clone(&self) -> Self {
+        KeyedRateLimit {
+            inner: self.inner.clone(),
+            key: self.key.clone(),
+            buckets: self.buckets.clone(),
+            wait: self.wait,
+            timer: self.timer.clone(),
+        }
+    }
+}
+
+impl<S: fmt::Debug, F, K, T> fmt::Debug for KeyedRateLimit<S, F, K, T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("KeyedRateLimit")
+            .field("inner", &self.inner)
+            .field("num", &self.buckets.num)
+            .field("per", &self.buckets.per)
+            .field("wait", &self.wait)
+            .field("key", &format_args!("{}", std::any::type_name::<F>()))
+            .finish()
+    }
+}
+
+/// Applies a [`KeyedRateLimit`] to a service.
+///
+/// All the services made by a layer, and their clones, share the same limits.
+///
+/// # Example
+///
+/// ```rust
+/// use std::{net::IpAddr, time::Duration};
+///
+/// use motore::{
+///     builder::ServiceBuilder,
+///     limit::{KeyedRateLimitLayer, RateLimitExceeded},
+///     service::service_fn,
+///     BoxError, Service,
+/// };
+///
+/// struct Cx {
+///     peer: IpAddr,
+/// }
+///
+/// # #[tokio::main(flavor = "current_thread")]
+/// # async fn main() {
+/// async fn echo(_cx: &mut Cx, req: String) -> Result<String, BoxError> {
+///     Ok(req)
+/// }
+///
+/// // Each client may send 10 requests per second.
+/// let svc = ServiceBuilder::new()
+///     .layer(KeyedRateLimitLayer::new(
+///         10,
+///         Duration::from_secs(1),
+///         |cx: &Cx, _req: &String| cx.peer,
+///     ))
+///     .service(service_fn(echo));
+///
+/// let mut cx = Cx {
+///     peer: [127, 0, 0, 1].into(),
+/// };
+/// for _ in 0..10 {
+///     assert!(svc.call(&mut cx, "ping".into()).await.is_ok());
+/// }
+/// let err = svc.call(&mut cx, "ping".into()).await.unwrap_err();
+/// assert!(err.is::<RateLimitExceeded>());
+///
+/// cx.peer = [127, 0, 0, 2].into();
+/// assert!(svc.call(&mut cx, "ping".into()).await.is_ok());
+/// # }
+/// ```
+pub struct KeyedRateLimitLayer<F, K, T = DefaultTimer> {
+    key: F,
+    buckets: Arc<Buckets<K>>,
+    wait: bool,
+    timer: T,
+}
+
+impl<F, K: Hash + Eq> KeyedRateLimitLayer<F, K> {
+    /// Creates a layer allowing `num` requests per `per` for each of the keys
+    /// returned by `key`, failing the requests exceeding it.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `num` or `per` is zero.
+    pub fn new(num: u64, per: Duration, key: F) -> Self {
+        assert!(num > 0, "the number of requests must not be zero");
+        assert!(per > Duration::ZERO, "the period must not be zero");
+        KeyedRateLimitLayer {
+            key,
+            buckets: Arc::new(Buckets::new(num, per)),
+            wait: false,
+            timer: DefaultTimer::new(),
+        }
+    }
+}
+
+impl<F, K, T> KeyedRateLimitLayer<F, K, T> {
+    /// Waits for the rate limit of their key to allow requests exceeding it,
+    /// instead of failing them.
+    pub fn wait(mut self) -> Self {
+        self.wait = true;
+        self
+    }
+
+    /// Sets the timer measuring the rates, [`DefaultTimer`] by default.
+    pub fn timer<U>(self, timer: U) -> KeyedRateLimitLayer<F, K, U> {
+        KeyedRateLimitLayer {
+            key: self.key,
+            buckets: self.buckets,
+            wait: self.wait,
+            timer,
+        }
+    }
+}
+
+impl<F: Clone, K, T: Clone> Clone for KeyedRateLimitLayer<F, K, T> {
+    fn clone(&self) -> Self {
+        KeyedRateLimitLayer {
+            key: self.key.clone(),
+            buckets: self.buckets.clone(),
+            wait: self.wait,
+            timer: self.timer.clone(),
+        }
+    }
+}
+
+impl<F, K, T> fmt::Debug for KeyedRateLimitLayer<F, K, T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("KeyedRateLimitLayer")
+            .field("num", &self.buckets.num)
+            .field("per", &self.buckets.per)
+            .field("wait", &self.wait)
+            .field("key", &format_args!("{}", std::any::type_name::<F>()))
+            .finish()
+    }
+}
+
+impl<S, F, K, T> Layer<S> for KeyedRateLimitLayer<F, K, T> {
+    type Service = KeyedRateLimit<S, F, K, T>;
+
+    fn layer(self, inner: S) -> Self::Service {
+        KeyedRateLimit {
+            inner,
+            key: self.key,
+            buckets: self.buckets,
+            wait: self.wait,
+            timer: self.timer,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::service::service_fn;
+
+    async fn echo(_cx: &mut u32, req: u32) -> Result<u32, BoxError> {
+        Ok(req)
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn limits_each_key_and_forgets_idle_ones() {
+        let svc = KeyedRateLimitLayer::new(2, Duration::from_secs(1), |cx: &u32, _req: &u32| *cx)
+            .layer(service_fn(echo));
+
+        for mut key in [1, 2] {
+            assert!(svc.call(&mut key, 0).await.is_ok());
+            assert!(svc.call(&mut key, 1).await.is_ok());
+            let err = svc.call(&mut key, 2).await.unwrap_err();
+            assert!(err.is::<RateLimitExceeded>());
+        }
+        assert_eq!(svc.keys(), 2);
+
+        // Key 1 is used again before it refilled, key 2 is forgotten.
+        tokio::time::advance(Duration::from_millis(500)).await;
+        assert!(svc.call(&mut 1, 3).await.is_ok());
+        tokio::time::advance(Duration::from_millis(500)).await;
+        assert!(svc.call(&mut 3, 4).await.is_ok());
+        assert_eq!(svc.keys(), 2);
+        assert!(svc.call(&mut 1, 5).await.is_ok());
+        assert!(svc.call(&mut 1, 6).await.is_err());
+    }
+}
@@ -0,0 +1,21 @@
+//! Middlewares limiting what a service accepts.
+
+pub mod adaptive;
+pub mod bulkhead;
+pub mod concurrency;
+pub mod keyed_rate;
+pub mod priority;
+pub mod rate;
+pub mod size;
+pub mod throttle;
+
+pub use self::{
+    adaptive::{AdaptiveConcurrency, AdaptiveConcurrencyLayer},
+    bulkhead::{Bulkhead, BulkheadLayer},
+    concurrency::{ConcurrencyLimit, ConcurrencyLimitLayer, GlobalConcurrencyLimitLayer},
+    keyed_rate::{KeyedRateLimit, KeyedRateLimitLayer},
+    priority::{PriorityLimit, PriorityLimitLayer},
+    rate::{GlobalRateLimitLayer, RateLimit, RateLimitExceeded, RateLimitLayer},
+    size::{Measure, Payload, PayloadTooLarge, SizeLimit, SizeLimitLayer},
+    throttle::{Throttle, ThrottleLayer},
+};
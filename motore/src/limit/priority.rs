@@ -0,0 +1,350 @@
+//! Bounds the number of in-flight requests, admitting the most important first.
+//!
+//! A [`ConcurrencyLimit`](super::ConcurrencyLimit) serves its waiting calls in
+//! order, so a batch of background requests queued behind a service delays the
+//! interactive requests arriving after it. A [`PriorityLimit`] classifies each
+//! request into a priority level, and when a permit is released gives it to the
+//! waiting call of the highest level, the calls of a level being served in order.
+//!
+//! Low priority requests can still wait forever while higher ones keep the pool
+//! busy, so the limit is best combined with a timeout on the low priority traffic.
+
+use std::{
+    cmp::{Ordering, Reverse},
+    collections::BinaryHeap,
+    fmt,
+    sync::{Arc, Mutex},
+    task::{Context, Poll},
+};
+
+use tokio::sync::oneshot;
+
+use crate::{
+    layer::Layer,
+    load::{Load, Ready},
+    service::Service,
+    MaybeSend, MaybeSync,
+};
+
+/// A call waiting for a permit.
+struct Waiter {
+    priority: u8,
+    seq: Reverse<u64>,
+    tx: oneshot::Sender<()>,
+}
+
+impl PartialEq for Waiter {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for Waiter {}
+
+impl PartialOrd for Waiter {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Waiter {
+    fn cmp(&self, other: &Self) -> Ordering {
+        (self.priority, self.seq).cmp(&(other.priority, other.seq))
+    }
+}
+
+struct State {
+    available: usize,
+    waiters: BinaryHeap<Waiter>,
+    next_seq: u64,
+}
+
+/// The permits shared by the clones of a [`PriorityLimit`].
+struct Pool {
+    max: usize,
+    state: Mutex<State>,
+}
+
+impl Pool {
+    fn new(max: usize) -> Self {
+        Pool {
+            max,
+            state: Mutex::new(State {
+                available: max,
+                waiters: BinaryHeap::new(),
+                next_seq: 0,
+            }),
+        }
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, State> {
+        self.state.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    async fn acquire(&self, priority: u8) -> Permit<'_> {
+        let rx = {
+            let mut state = self.lock();
+            if state.available > 0 && state.waiters.is_empty() {
+                state.available -= 1;
+                return Permit { pool: self };
+            }
+            let (tx, rx) = oneshot::channel();
+            let seq = Reverse(state.next_seq);
+            state.next_seq += 1;
+            state.waiters.push(Waiter { priority, seq, tx });
+            rx
+        };
+
+        let mut waiting = Waiting {
+            pool: self,
+            rx,
+            done: false,
+        };
+        // The sender is only dropped along with the pool, which outlives the call.
+        let _ = (&mut waiting.rx).await;
+        waiting.done = true;
+        Permit { pool: self }
+    }
+
+    /// Gives a permit to the waiting call of the highest priority, or back to the
+    /// pool.
+    fn release(&self) {
+        let mut state = self.lock();
+        while let Some(waiter) = state.waiters.pop() {
+            // The call may have been cancelled while waiting.
+            if waiter.tx.send(()).is_ok() {
+                return;
+            }
+        }
+        state.available += 1;
+    }
+}
+
+/// Gives back the permit a cancelled call was handed while it was dropped.
+struct Waiting<'a> {
+    pool: &'a Pool,
+    rx: oneshot::Receiver<()>,
+    done: bool,
+}
+
+impl Drop for Waiting<'_> {
+    fn drop(&mut self) {
+        if self.done {
+            return;
+        }
+        self.rx.close();
+        if self.rx.try_recv().is_ok() {
+            self.pool.release();
+        }
+    }
+}
+
+/// Releases a permit, even when the call is cancelled.
+struct Permit<'a> {
+    pool: &'a Pool,
+}
+
+impl Drop for Permit<'_> {
+    fn drop(&mut self) {
+        self.pool.release();
+    }
+}
+
+/// Bounds the number of in-flight calls to the inner service, admitting the
+/// waiting calls by priority.
+///
+/// The priority of a call is given by a closure taking the context and the request,
+/// `Fn(&Cx, &Req) -> u8`, higher levels going first. The clones of a
+/// `PriorityLimit` share the same limit. See the [module level docs](self) for
+/// details.
+#[derive(Clone)]
+pub struct PriorityLimit<S, F> {
+    inner: S,
+    pool: Arc<Pool>,
+    priority: F,
+}
+
+impl<S, F> PriorityLimit<S, F> {
+    /// Creates a limit allowing `max` in-flight calls, whose priority is given by
+    /// `priority`.
+    pub fn new(inner: S, max: usize, priority: F) -> Self {
+        PriorityLimitLayer::new(max, priority).layer(inner)
+    }
+
+    /// Returns the number of calls that can start without waiting.
+    pub fn available(&self) -> usize {
+        self.pool.lock().available
+    }
+}
+
+impl<Cx, Req, S, F> Service<Cx, Req> for PriorityLimit<S, F>
+where
+    Req: 'static + MaybeSend,
+    S: Service<Cx, Req> + 'static + MaybeSend + MaybeSync,
+    F: Fn(&Cx, &Req) -> u8 + MaybeSync,
+    Cx: 'static + MaybeSend,
+{
+    type Response = S::Response;
+
+    type Error = S::Error;
+
+    async fn call(&self, cx: &mut Cx, req: Req) -> Result<Self::Response, Self::Error> {
+        let _permit = self.pool.acquire((self.priority)(cx, &req)).await;
+        self.inner.call(cx, req).await
+    }
+}
+
+impl<S, F> Ready for PriorityLimit<S, F>
+where
+    S: Ready,
+{
+    fn poll_ready(&self, cx: &mut Context<'_>) -> Poll<()> {
+        self.inner.poll_ready(cx)
+    }
+}
+
+impl<S, F> Load for PriorityLimit<S, F>
+where
+    S: Load,
+{
+    type Metric = S::Metric;
+
+    fn load(&self) -> Self::Metric {
+        self.inner.load()
+    }
+}
+
+impl<S: fmt::Debug, F> fmt::Debug for PriorityLimit<S, F> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("PriorityLimit")
+            .field("inner", &self.inner)
+            .field("max", &self.pool.max)
+            .field("available", &self.available())
+            .field("priority", &format_args!("{}", std::any::type_name::<F>()))
+            .finish()
+    }
+}
+
+/// Applies a [`PriorityLimit`] to a service.
+///
+/// All the services made by a layer, and their clones, share the same limit.
+///
+/// # Example
+///
+/// ```rust
+/// use motore::{
+///     builder::ServiceBuilder, limit::PriorityLimitLayer, service::service_fn, BoxError,
+///     Service,
+/// };
+///
+/// struct Cx {
+///     interactive: bool,
+/// }
+///
+/// # #[tokio::main(flavor = "current_thread")]
+/// # async fn main() {
+/// async fn echo(_cx: &mut Cx, req: String) -> Result<String, BoxError> {
+///     Ok(req)
+/// }
+///
+/// let svc = ServiceBuilder::new()
+///     .layer(PriorityLimitLayer::new(64, |cx: &Cx, _req: &String| {
+///         u8::from(cx.interactive)
+///     }))
+///     .service(service_fn(echo));
+///
+/// let mut cx = Cx { interactive: true };
+/// assert_eq!(svc.call(&mut cx, "ping".into()).await.unwrap(), "ping");
+/// # }
+/// ```
+#[derive(Clone)]
+pub struct PriorityLimitLayer<F> {
+    pool: Arc<Pool>,
+    priority: F,
+}
+
+impl<F> PriorityLimitLayer<F> {
+    /// Creates a layer allowing `max` in-flight calls to all its services, whose
+    /// priority is given by `priority`.
+    pub fn new(max: usize, priority: F) -> Self {
+        PriorityLimitLayer {
+            pool: Arc::new(Pool::new(max)),
+            priority,
+        }
+    }
+}
+
+impl<F> fmt::Debug for PriorityLimitLayer<F> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("PriorityLimitLayer")
+            .field("max", &self.pool.max)
+            .field("priority", &format_args!("{}", std::any::type_name::<F>()))
+            .finish()
+    }
+}
+
+impl<S, F> Layer<S> for PriorityLimitLayer<F> {
+    type Service = PriorityLimit<S, F>;
+
+    fn layer(self, inner: S) -> Self::Service {
+        PriorityLimit {
+            inner,
+            pool: self.pool,
+            priority: self.priority,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{convert::Infallible, time::Duration};
+
+    use super::*;
+    use crate::service::service_fn;
+
+    #[tokio::test(start_paused = true)]
+    async fn admits_higher_priorities_first() {
+        let started = Arc::new(Mutex::new(Vec::new()));
+        let svc =
+            PriorityLimitLayer::new(1, |_cx: &(), req: &(u8, &str)| req.0).layer(service_fn({
+                let started = started.clone();
+                move |_cx: &mut (), req: (u8, &'static str)| {
+                    started.lock().unwrap().push(req.1);
+                    async {
+                        tokio::time::sleep(Duration::from_millis(10)).await;
+                        Ok::<_, Infallible>(())
+                    }
+                }
+            }));
+
+        let calls = [(0, "a"), (0, "b"), (5, "c"), (1, "d"), (5, "e")].map(|req| {
+            let svc = svc.clone();
+            async move { svc.call(&mut (), req).await }
+        });
+        futures::future::join_all(calls).await;
+        assert_eq!(*started.lock().unwrap(), ["a", "c", "e", "d", "b"]);
+        assert_eq!(svc.available(), 1);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn cancelled_calls_give_their_turn_away() {
+        let svc = PriorityLimitLayer::new(1, |_cx: &(), req: &u8| *req).layer(service_fn(
+            |_cx: &mut (), req: u8| async move {
+                tokio::time::sleep(Duration::from_millis(10)).await;
+                Ok::<_, Infallible>(req)
+            },
+        ));
+
+        let first = async { svc.call(&mut (), 0).await };
+        let cancelled = async {
+            let call = async { svc.call(&mut (), 9).await };
+            tokio::time::timeout(Duration::from_millis(5), call).await
+        };
+        let last = async { svc.call(&mut (), 1).await };
+        let (first, cancelled, last) = tokio::join!(first, cancelled, last);
+        assert_eq!(first, Ok(0));
+        assert!(cancelled.is_err());
+        assert_eq!(last, Ok(1));
+        assert_eq!(svc.available(), 1);
+    }
+}
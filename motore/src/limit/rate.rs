@@ -0,0 +1,365 @@
+//! Restricts the rate of requests reaching a service.
+//!
+//! The rate is enforced by a token bucket holding up to `num` tokens, refilled
+//! at a rate of `num` tokens per `per`. Every request takes a token, so bursts of
+//! up to `num` requests go through at once, while sustained traffic is held to the
+//! configured rate.
+//!
+//! When the bucket is empty, requests fail with a [`RateLimitExceeded`] error, or,
+//! with [`RateLimitLayer::wait`], wait for the next token.
+//!
+//! A [`RateLimitLayer`] gives each service it makes its own bucket, while a
+//! [`GlobalRateLimitLayer`] makes all of them take their tokens from a single one,
+//! such as a cap on the requests all the clients of a process send to a backend.
+
+use std::{
+    fmt,
+    sync::Arc,
+    task::{Context, Poll},
+    time::Duration,
+};
+
+use crate::{
+    layer::Layer,
+    load::{Load, Ready},
+    service::Service,
+    timer::{DefaultTimer, Timer},
+    utils::token_bucket::TokenBucket,
+    BoxError, MaybeSend, MaybeSync,
+};
+
+/// The error returned by [`RateLimit`] when the rate limit is exceeded.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct RateLimitExceeded {
+    /// How long until the next request would be allowed.
+    pub retry_after: Duration,
+}
+
+impl fmt::Display for RateLimitExceeded {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "rate limit exceeded, retry after {:?}", self.retry_after)
+    }
+}
+
+impl std::error::Error for RateLimitExceeded {}
+
+/// Restricts the inner service to a number of requests per period.
+///
+/// The clones of a `RateLimit` share the same limit.
+#[derive(Clone)]
+pub struct RateLimit<S, T = DefaultTimer> {
+    inner: S,
+    bucket: Arc<TokenBucket>,
+    wait: bool,
+    timer: T,
+}
+
+impl<S> RateLimit<S> {
+    /// Creates a rate limit allowing `num` requests per `per`, failing the requests
+    /// exceeding it.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `num` or `per` is zero.
+    pub fn new(inner: S, num: u64, per: Duration) -> Self {
+        RateLimitLayer::new(num, per).layer(inner)
+    }
+}
+
+impl<Cx, Req, S, T> Service<Cx, Req> for RateLimit<S, T>
+where
+    Req: 'static + MaybeSend,
+    S: Service<Cx, Req> + 'static + MaybeSend + MaybeSync,
+    T: Timer + MaybeSend + MaybeSync,
+    Cx: 'static + MaybeSend,
+    S::Error: MaybeSend + MaybeSync + Into<BoxError>,
+{
+    type Response = S::Response;
+
+    type Error = BoxError;
+
+    async fn call(&self, cx: &mut Cx, req: Req) -> Result<Self::Response, Self::Error> {
+        loop {
+            match self.bucket.acquire(self.timer.now()) {
+                Ok(()) => break,
+                Err(retry_after) if self.wait => self.timer.sleep(retry_after).await,
+                Err(retry_after) => return Err(RateLimitExceeded { retry_after }.into()),
+            }
+        }
+        self.inner.call(cx, req).await.map_err(Into::into)
+    }
+}
+
+impl<S, T> Ready for RateLimit<S, T>
+where
+    S: Ready,
+{
+    fn poll_ready(&self, cx: &mut Context<'_>) -> Poll<()> {
+        self.inner.poll_ready(cx)
+    }
+}
+
+impl<S, T> Load for RateLimit<S, T>
+where
+    S: Load,
+{
+    type Metric = S::Metric;
+
+    fn load(&self) -> Self::Metric {
+        self.inner.load()
+    }
+}
+
+impl<S: fmt::Debug, T> fmt::Debug for RateLimit<S, T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("RateLimit")
+            .field("inner", &self.inner)
+            .field("num", &self.bucket.num)
+            .field("per", &self.bucket.per)
+            .field("wait", &self.wait)
+            .finish()
+    }
+}
+
+/// Applies a [`RateLimit`] to a service.
+///
+/// Every service made by the layer has its own limit, shared only by its clones.
+/// See [`GlobalRateLimitLayer`] to share a limit between services.
+///
+/// # Example
+///
+/// ```rust
+/// use std::time::Duration;
+///
+/// use motore::{
+///     builder::ServiceBuilder,
+///     limit::{RateLimitExceeded, RateLimitLayer},
+///     service::service_fn,
+///     BoxError, Service,
+/// };
+///
+/// # #[tokio::main(flavor = "current_thread")]
+/// # async fn main() {
+/// async fn echo(_cx: &mut (), req: String) -> Result<String, BoxError> {
+///     Ok(req)
+/// }
+///
+/// let svc = ServiceBuilder::new()
+///     .layer(RateLimitLayer::new(1, Duration::from_secs(1)))
+///     .service(service_fn(echo));
+///
+/// assert!(svc.call(&mut (), "ping".into()).await.is_ok());
+/// let err = svc.call(&mut (), "ping".into()).await.unwrap_err();
+/// assert!(err.is::<RateLimitExceeded>());
+/// # }
+/// ```
+#[derive(Clone, Copy, Debug)]
+pub struct RateLimitLayer<T = DefaultTimer> {
+    num: u64,
+    per: Duration,
+    wait: bool,
+    timer: T,
+}
+
+impl RateLimitLayer {
+    /// Creates a layer allowing `num` requests per `per` to each service, failing
+    /// the requests exceeding it.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `num` or `per` is zero.
+    pub fn new(num: u64, per: Duration) -> Self {
+        assert!(num > 0, "the number of requests must not be zero");
+        assert!(per > Duration::ZERO, "the period must not be zero");
+        RateLimitLayer {
+            num,
+            per,
+            wait: false,
+            timer: DefaultTimer::new(),
+        }
+    }
+}
+
+impl<T> RateLimitLayer<T> {
+    /// Waits for the rate limit to allow requests exceeding it, instead of failing
+    /// them.
+    pub fn wait(mut self) -> Self {
+        self.wait = true;
+        self
+    }
+
+    /// Sets the timer measuring the rate, [`DefaultTimer`] by default.
+    pub fn timer<U>(self, timer: U) -> RateLimitLayer<U> {
+        RateLimitLayer {
+            num: self.num,
+            per: self.per,
+            wait: self.wait,
+            timer,
+        }
+    }
+}
+
+impl<S, T> Layer<S> for RateLimitLayer<T> {
+    type Service = RateLimit<S, T>;
+
+    fn layer(self, inner: S) -> Self::Service {
+        RateLimit {
+            inner,
+            bucket: Arc::new(TokenBucket::new(self.num, self.per)),
+            wait: self.wait,
+            timer: self.timer,
+        }
+    }
+}
+
+/// Applies a [`RateLimit`] shared by all the services made by the layer.
+///
+/// # Example
+///
+/// ```rust
+/// use std::time::Duration;
+///
+/// use motore::{layer::Layer, limit::GlobalRateLimitLayer, service::service_fn, BoxError};
+///
+/// async fn echo(_cx: &mut (), req: String) -> Result<String, BoxError> {
+///     Ok(req)
+/// }
+///
+/// // The two clients never send more than 100 requests per second together.
+/// let limit = GlobalRateLimitLayer::new(100, Duration::from_secs(1));
+/// let users = limit.clone().layer(service_fn(echo));
+/// let orders = limit.layer(service_fn(echo));
+/// ```
+#[derive(Clone)]
+pub struct GlobalRateLimitLayer<T = DefaultTimer> {
+    bucket: Arc<TokenBucket>,
+    wait: bool,
+    timer: T,
+}
+
+impl GlobalRateLimitLayer {
+    /// Creates a layer allowing `num` requests per `per` to all its services,
+    /// failing the requests exceeding it.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `num` or `per` is zero.
+    pub fn new(num: u64, per: Duration) -> Self {
+        assert!(num > 0, "the number of requests must not be zero");
+        assert!(per > Duration::ZERO, "the period must not be zero");
+        GlobalRateLimitLayer {
+            bucket: Arc::new(TokenBucket::new(num, per)),
+            wait: false,
+            timer: DefaultTimer::new(),
+        }
+    }
+}
+
+impl<T> GlobalRateLimitLayer<T> {
+    /// Waits for the rate limit to allow requests exceeding it, instead of failing
+    /// them.
+    pub fn wait(mut self) -> Self {
+        self.wait = true;
+        self
+    }
+
+    /// Sets the timer measuring the rate, [`DefaultTimer`] by default.
+    pub fn timer<U>(self, timer: U) -> GlobalRateLimitLayer<U> {
+        GlobalRateLimitLayer {
+            bucket: self.bucket,
+            wait: self.wait,
+            timer,
+        }
+    }
+}
+
+impl<T> fmt::Debug for GlobalRateLimitLayer<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("GlobalRateLimitLayer")
+            .field("num", &self.bucket.num)
+            .field("per", &self.bucket.per)
+            .field("wait", &self.wait)
+            .finish()
+    }
+}
+
+impl<S, T> Layer<S> for GlobalRateLimitLayer<T> {
+    type Service = RateLimit<S, T>;
+
+    fn layer(self, inner: S) -> Self::Service {
+        RateLimit {
+            inner,
+            bucket: self.bucket,
+            wait: self.wait,
+            timer: self.timer,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use tokio::time::Instant;
+
+    use super::*;
+    use crate::service::service_fn;
+
+    async fn echo(_cx: &mut (), req: u32) -> Result<u32, BoxError> {
+        Ok(req)
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn rejects_requests_over_the_rate() {
+        let svc = RateLimitLayer::new(2, Duration::from_secs(1)).layer(service_fn(echo));
+        let clone = svc.clone();
+
+        assert_eq!(svc.call(&mut (), 1).await.unwrap(), 1);
+        assert_eq!(clone.call(&mut (), 2).await.unwrap(), 2);
+        let err = svc.call(&mut (), 3).await.unwrap_err();
+        assert_eq!(
+            *err.downcast::<RateLimitExceeded>().unwrap(),
+            RateLimitExceeded {
+                retry_after: Duration::from_millis(500)
+            }
+        );
+
+        tokio::time::advance(Duration::from_millis(500)).await;
+        assert_eq!(svc.call(&mut (), 4).await.unwrap(), 4);
+        assert!(svc.call(&mut (), 5).await.is_err());
+
+        // The bucket never holds more than `num` tokens.
+        tokio::time::advance(Duration::from_secs(10)).await;
+        assert!(svc.call(&mut (), 6).await.is_ok());
+        assert!(svc.call(&mut (), 7).await.is_ok());
+        assert!(svc.call(&mut (), 8).await.is_err());
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn services_have_their_own_limit_unless_global() {
+        let layer = RateLimitLayer::new(1, Duration::from_secs(1));
+        let (a, b) = (layer.layer(service_fn(echo)), layer.layer(service_fn(echo)));
+        assert!(a.call(&mut (), 1).await.is_ok());
+        assert!(b.call(&mut (), 2).await.is_ok());
+        assert!(a.call(&mut (), 3).await.is_err());
+
+        let layer = GlobalRateLimitLayer::new(1, Duration::from_secs(1));
+        let (a, b) = (
+            layer.clone().layer(service_fn(echo)),
+            layer.layer(service_fn(echo)),
+        );
+        assert!(a.call(&mut (), 1).await.is_ok());
+        assert!(b.call(&mut (), 2).await.is_err());
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn waits_for_a_token() {
+        let svc = RateLimitLayer::new(2, Duration::from_secs(1))
+            .wait()
+            .layer(service_fn(echo));
+
+        let start = Instant::now();
+        for i in 0..5 {
+            assert_eq!(svc.call(&mut (), i).await.unwrap(), i);
+        }
+        assert_eq!(start.elapsed(), Duration::from_millis(1500));
+    }
+}
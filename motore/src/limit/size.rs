@@ -0,0 +1,311 @@
+//! Rejects requests or responses exceeding a size limit.
+//!
+//! The size of a payload is reported by the [`Measure`] trait, which is implemented
+//! for the common buffer types, for [`Bytes`] with the `bytes` feature, and for
+//! [`http::Request`] and [`http::Response`] with the `http` feature.
+//!
+//! [`Bytes`]: https://docs.rs/bytes/latest/bytes/struct.Bytes.html
+//! [`http::Request`]: https://docs.rs/http/latest/http/request/struct.Request.html
+//! [`http::Response`]: https://docs.rs/http/latest/http/response/struct.Response.html
+
+use std::{
+    fmt,
+    task::{Context, Poll},
+};
+
+use crate::{
+    layer::Layer,
+    load::{Load, Ready},
+    service::Service,
+    BoxError, MaybeSend, MaybeSync,
+};
+
+/// A payload whose size in bytes can be measured.
+pub trait Measure {
+    /// Returns the size of the payload in bytes.
+    ///
+    /// Payloads whose size is not fully known yet, like streaming bodies, should
+    /// return the lower bound of their size.
+    fn size(&self) -> u64;
+}
+
+impl Measure for [u8] {
+    fn size(&self) -> u64 {
+        self.len() as u64
+    }
+}
+
+impl Measure for str {
+    fn size(&self) -> u64 {
+        self.len() as u64
+    }
+}
+
+impl Measure for Vec<u8> {
+    fn size(&self) -> u64 {
+        self.len() as u64
+    }
+}
+
+impl Measure for String {
+    fn size(&self) -> u64 {
+        self.len() as u64
+    }
+}
+
+impl<T: Measure + ?Sized> Measure for &T {
+    fn size(&self) -> u64 {
+        (**self).size()
+    }
+}
+
+impl<T: Measure + ?Sized> Measure for Box<T> {
+    fn size(&self) -> u64 {
+        (**self).size()
+    }
+}
+
+#[cfg(feature = "bytes")]
+#[cfg_attr(docsrs, doc(cfg(feature = "bytes")))]
+impl Measure for bytes::Bytes {
+    fn size(&self) -> u64 {
+        self.len() as u64
+    }
+}
+
+#[cfg(feature = "bytes")]
+#[cfg_attr(docsrs, doc(cfg(feature = "bytes")))]
+impl Measure for bytes::BytesMut {
+    fn size(&self) -> u64 {
+        self.len() as u64
+    }
+}
+
+/// Measures the body of the request, using the lower bound of its size hint.
+#[cfg(feature = "http")]
+#[cfg_attr(docsrs, doc(cfg(feature = "http")))]
+impl<B: http_body::Body> Measure for http::Request<B> {
+    fn size(&self) -> u64 {
+        self.body().size_hint().lower()
+    }
+}
+
+/// Measures the body of the response, using the lower bound of its size hint.
+#[cfg(feature = "http")]
+#[cfg_attr(docsrs, doc(cfg(feature = "http")))]
+impl<B: http_body::Body> Measure for http::Response<B> {
+    fn size(&self) -> u64 {
+        self.body().size_hint().lower()
+    }
+}
+
+/// Which side of a call exceeded its size limit.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Payload {
+    Request,
+    Response,
+}
+
+/// The error returned by [`SizeLimit`] when a payload exceeds its limit.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct PayloadTooLarge {
+    /// Which payload was too large.
+    pub payload: Payload,
+    /// The measured size of the payload.
+    pub size: u64,
+    /// The configured limit.
+    pub limit: u64,
+}
+
+impl fmt::Display for PayloadTooLarge {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let payload = match self.payload {
+            Payload::Request => "request",
+            Payload::Response => "response",
+        };
+        write!(
+            f,
+            "{payload} of {} bytes exceeds the limit of {} bytes",
+            self.size, self.limit
+        )
+    }
+}
+
+impl std::error::Error for PayloadTooLarge {}
+
+/// Rejects requests and responses exceeding their size limit with a
+/// [`PayloadTooLarge`] error.
+///
+/// Oversized requests are rejected before the inner service is called.
+#[derive(Clone)]
+pub struct SizeLimit<S> {
+    inner: S,
+    request: Option<u64>,
+    response: Option<u64>,
+}
+
+impl<S> SizeLimit<S> {
+    pub const fn new(inner: S, request: Option<u64>, response: Option<u64>) -> Self {
+        Self {
+            inner,
+            request,
+            response,
+        }
+    }
+}
+
+impl<Cx, Req, S> Service<Cx, Req> for SizeLimit<S>
+where
+    Req: Measure + 'static + MaybeSend,
+    S: Service<Cx, Req> + 'static + MaybeSend + MaybeSync,
+    S::Response: Measure,
+    Cx: 'static + MaybeSend,
+    S::Error: MaybeSend + MaybeSync + Into<BoxError>,
+{
+    type Response = S::Response;
+
+    type Error = BoxError;
+
+    async fn call(&self, cx: &mut Cx, req: Req) -> Result<Self::Response, Self::Error> {
+        check(Payload::Request, &req, self.request)?;
+        let resp = self.inner.call(cx, req).await.map_err(Into::into)?;
+        check(Payload::Response, &resp, self.response)?;
+        Ok(resp)
+    }
+}
+
+fn check<T: Measure>(payload: Payload, t: &T, limit: Option<u64>) -> Result<(), PayloadTooLarge> {
+    match limit {
+        Some(limit) => {
+            let size = t.size();
+            if size > limit {
+                return Err(PayloadTooLarge {
+                    payload,
+                    size,
+                    limit,
+                });
+            }
+            Ok(())
+        }
+        None => Ok(()),
+    }
+}
+
+impl<S> Ready for SizeLimit<S>
+where
+    S: Ready,
+{
+    fn poll_ready(&self, cx: &mut Context<'_>) -> Poll<()> {
+        self.inner.poll_ready(cx)
+    }
+}
+
+impl<S> Load for SizeLimit<S>
+where
+    S: Load,
+{
+    type Metric = S::Metric;
+
+    fn load(&self) -> Self::Metric {
+        self.inner.load()
+    }
+}
+
+/// Applies a [`SizeLimit`] to a service.
+///
+/// # Example
+///
+/// ```rust
+/// use motore::{
+///     builder::ServiceBuilder, limit::SizeLimitLayer, service::service_fn, BoxError, Service,
+/// };
+///
+/// # #[tokio::main(flavor = "current_thread")]
+/// # async fn main() {
+/// async fn echo(_cx: &mut (), req: Vec<u8>) -> Result<Vec<u8>, BoxError> {
+///     Ok(req)
+/// }
+///
+/// let svc = ServiceBuilder::new()
+///     .layer(SizeLimitLayer::new().request(4))
+///     .service(service_fn(echo));
+///
+/// assert!(svc.call(&mut (), vec![0; 4]).await.is_ok());
+/// assert!(svc.call(&mut (), vec![0; 5]).await.is_err());
+/// # }
+/// ```
+#[derive(Clone, Default)]
+pub struct SizeLimitLayer {
+    request: Option<u64>,
+    response: Option<u64>,
+}
+
+impl SizeLimitLayer {
+    /// Creates a layer without any limit.
+    pub const fn new() -> Self {
+        SizeLimitLayer {
+            request: None,
+            response: None,
+        }
+    }
+
+    /// Sets the maximum size of requests, in bytes.
+    pub const fn request(mut self, max: u64) -> Self {
+        self.request = Some(max);
+        self
+    }
+
+    /// Sets the maximum size of responses, in bytes.
+    pub const fn response(mut self, max: u64) -> Self {
+        self.response = Some(max);
+        self
+    }
+}
+
+impl<S> Layer<S> for SizeLimitLayer {
+    type Service = SizeLimit<S>;
+
+    fn layer(self, inner: S) -> Self::Service {
+        SizeLimit {
+            inner,
+            request: self.request,
+            response: self.response,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::service::service_fn;
+
+    async fn repeat(_cx: &mut (), req: String) -> Result<String, BoxError> {
+        Ok(req.repeat(2))
+    }
+
+    #[tokio::test]
+    async fn limits_requests_and_responses() {
+        let svc = SizeLimitLayer::new()
+            .request(4)
+            .response(6)
+            .layer(service_fn(repeat));
+
+        assert_eq!(svc.call(&mut (), "abc".into()).await.unwrap(), "abcabc");
+
+        let err = svc.call(&mut (), "abcde".into()).await.unwrap_err();
+        assert_eq!(
+            *err.downcast::<PayloadTooLarge>().unwrap(),
+            PayloadTooLarge {
+                payload: Payload::Request,
+                size: 5,
+                limit: 4,
+            }
+        );
+
+        let err = svc.call(&mut (), "abcd".into()).await.unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "response of 8 bytes exceeds the limit of 6 bytes"
+        );
+    }
+}
@@ -0,0 +1,227 @@
+//! Spaces out the calls to a service.
+//!
+//! Where a [`RateLimit`](super::RateLimit) lets bursts through and rejects the
+//! requests over the rate, a [`Throttle`] makes every call start at least a given
+//! interval after the previous one, waiting as long as needed. This suits the
+//! clients of third party APIs enforcing a strict rate, for which queueing the
+//! requests is better than failing them.
+
+use std::{
+    fmt,
+    sync::{Arc, Mutex},
+    task::{Context, Poll},
+    time::Duration,
+};
+
+use crate::{
+    layer::Layer,
+    load::{Load, Ready},
+    service::Service,
+    timer::{DefaultTimer, Instant, Timer},
+    MaybeSend, MaybeSync,
+};
+
+/// The start of the calls, shared by the clones of a [`Throttle`].
+#[derive(Debug)]
+struct Schedule {
+    interval: Duration,
+    /// When the next call may start, `None` until the first call.
+    next: Mutex<Option<Instant>>,
+}
+
+impl Schedule {
+    /// Reserves the next start, returning how long to wait for it.
+    fn reserve(&self, now: Instant) -> Duration {
+        let mut next = self.next.lock().unwrap_or_else(|e| e.into_inner());
+        let start = next.map_or(now, |next| next.max(now));
+        *next = Some(start + self.interval);
+        start - now
+    }
+}
+
+/// Starts each call to the inner service at least an interval after the previous
+/// one.
+///
+/// The calls wait for their turn in the order they were made. The clones of a
+/// `Throttle` share the same schedule. A call cancelled while waiting still uses
+/// its turn.
+#[derive(Clone)]
+pub struct Throttle<S, T = DefaultTimer> {
+    inner: S,
+    schedule: Arc<Schedule>,
+    timer: T,
+}
+
+impl<S> Throttle<S> {
+    /// Creates a throttle starting the calls at least `interval` apart.
+    pub fn new(inner: S, interval: Duration) -> Self {
+        ThrottleLayer::new(interval).layer(inner)
+    }
+}
+
+impl<Cx, Req, S, T> Service<Cx, Req> for Throttle<S, T>
+where
+    Req: 'static + MaybeSend,
+    S: Service<Cx, Req> + 'static + MaybeSend + MaybeSync,
+    T: Timer + MaybeSend + MaybeSync,
+    Cx: 'static + MaybeSend,
+{
+    type Response = S::Response;
+
+    type Error = S::Error;
+
+    async fn call(&self, cx: &mut Cx, req: Req) -> Result<Self::Response, Self::Error> {
+        let wait = self.schedule.reserve(self.timer.now());
+        if !wait.is_zero() {
+            self.timer.sleep(wait).await;
+        }
+        self.inner.call(cx, req).await
+    }
+}
+
+impl<S, T> Ready for Throttle<S, T>
+where
+    S: Ready,
+{
+    fn poll_ready(&self, cx: &mut Context<'_>) -> Poll<()> {
+        self.inner.poll_ready(cx)
+    }
+}
+
+impl<S, T> Load for Throttle<S, T>
+where
+    S: Load,
+{
+    type Metric = S::Metric;
+
+    fn load(&self) -> Self::Metric {
+        self.inner.load()
+    }
+}
+
+impl<S: fmt::Debug, T> fmt::Debug for Throttle<S, T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Throttle")
+            .field("inner", &self.inner)
+            .field("interval", &self.schedule.interval)
+            .finish()
+    }
+}
+
+/// Applies a [`Throttle`] to a service.
+///
+/// All the services made by a layer, and their clones, share the same schedule.
+///
+/// # Example
+///
+/// ```rust
+/// use std::time::Duration;
+///
+/// use motore::{
+///     builder::ServiceBuilder, limit::ThrottleLayer, service::service_fn, BoxError, Service,
+/// };
+///
+/// # #[tokio::main(flavor = "current_thread")]
+/// # async fn main() {
+/// async fn echo(_cx: &mut (), req: String) -> Result<String, BoxError> {
+///     Ok(req)
+/// }
+///
+/// // The API allows 20 requests per second.
+/// let svc = ServiceBuilder::new()
+///     .layer(ThrottleLayer::new(Duration::from_millis(50)))
+///     .service(service_fn(echo));
+///
+/// let start = std::time::Instant::now();
+/// for _ in 0..3 {
+///     svc.call(&mut (), "ping".into()).await.unwrap();
+/// }
+/// assert!(start.elapsed() >= Duration::from_millis(100));
+/// # }
+/// ```
+#[derive(Clone)]
+pub struct ThrottleLayer<T = DefaultTimer> {
+    schedule: Arc<Schedule>,
+    timer: T,
+}
+
+impl ThrottleLayer {
+    /// Creates a layer starting the calls at least `interval` apart.
+    pub fn new(interval: Duration) -> Self {
+        ThrottleLayer {
+            schedule: Arc::new(Schedule {
+                interval,
+                next: Mutex::new(None),
+            }),
+            timer: DefaultTimer::new(),
+        }
+    }
+}
+
+impl<T> ThrottleLayer<T> {
+    /// Sets the timer spacing the calls, [`DefaultTimer`] by default.
+    pub fn timer<U>(self, timer: U) -> ThrottleLayer<U> {
+        ThrottleLayer {
+            schedule: self.schedule,
+            timer,
+        }
+    }
+}
+
+impl<T> fmt::Debug for ThrottleLayer<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ThrottleLayer")
+            .field("interval", &self.schedule.interval)
+            .finish()
+    }
+}
+
+impl<S, T> Layer<S> for ThrottleLayer<T> {
+    type Service = Throttle<S, T>;
+
+    fn layer(self, inner: S) -> Self::Service {
+        Throttle {
+            inner,
+            schedule: self.schedule,
+            timer: self.timer,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use tokio::time::Instant;
+
+    use super::*;
+    use crate::service::service_fn;
+
+    #[tokio::test(start_paused = true)]
+    async fn spaces_out_the_calls() {
+        let starts = Arc::new(Mutex::new(Vec::new()));
+        let svc = ThrottleLayer::new(Duration::from_millis(100)).layer(service_fn({
+            let starts = starts.clone();
+            move |_cx: &mut (), req: u32| {
+                starts.lock().unwrap().push(Instant::now());
+                async move { Ok::<_, String>(req) }
+            }
+        }));
+        let clone = svc.clone();
+
+        let start = Instant::now();
+        let (a, b, c) = tokio::join!(
+            async { svc.call(&mut (), 1).await },
+            async { clone.call(&mut (), 2).await },
+            async { svc.call(&mut (), 3).await },
+        );
+        assert_eq!((a, b, c), (Ok(1), Ok(2), Ok(3)));
+        let offsets: Vec<_> = starts.lock().unwrap().iter().map(|s| *s - start).collect();
+        assert_eq!(offsets, [0, 100, 200].map(Duration::from_millis).to_vec());
+
+        // An idle service doesn't accumulate turns.
+        tokio::time::advance(Duration::from_secs(1)).await;
+        let start = Instant::now();
+        svc.call(&mut (), 4).await.unwrap();
+        svc.call(&mut (), 5).await.unwrap();
+        assert_eq!(start.elapsed(), Duration::from_millis(100));
+    }
+}
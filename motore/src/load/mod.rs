@@ -0,0 +1,294 @@
+//! Optional readiness and load reporting for services.
+//!
+//! [`Service`] has no `poll_ready`: a motore service is always expected to accept a
+//! call. Some middlewares, like load shedding and load balancing, still want to know
+//! whether an inner service can take more work right now, or how loaded it is
+//! compared to its peers. Services can opt in to report this by implementing
+//! [`Ready`] and [`Load`].
+//!
+//! [`Ready`] is "always ready" by default, so implementing it for a service that has
+//! no notion of capacity is a one-line `impl`. A service that doesn't implement it,
+//! like one from another crate, can be wrapped in an [`AlwaysReady`] instead. The
+//! middlewares shipped with motore forward both traits to the service they wrap.
+//!
+//! [`PendingRequests`] and [`PeakEwma`] measure the load of any service, by its
+//! calls in flight, or by its recent latency weighted by its calls in flight,
+//! for the balancers to pick the least loaded endpoints.
+//!
+//! [`Service`]: crate::Service
+
+use std::{
+    fmt,
+    future::Future,
+    pin::Pin,
+    sync::Arc,
+    task::{Context, Poll},
+};
+
+use crate::Service;
+
+mod peak_ewma;
+mod pending;
+pub use self::{
+    peak_ewma::{PeakEwma, PeakEwmaLayer},
+    pending::{PendingRequests, PendingRequestsLayer},
+};
+
+/// A service that can report whether it is ready to accept a request.
+///
+/// Unlike tower's `poll_ready`, readiness is advisory: calling a service that is not
+/// ready is still allowed, and no capacity is reserved by a successful poll.
+///
+/// # Example
+///
+/// ```rust
+/// use motore::load::Ready;
+///
+/// struct Backend;
+///
+/// // Always ready.
+/// impl Ready for Backend {}
+/// ```
+pub trait Ready {
+    /// Polls whether the service is ready to accept a request.
+    ///
+    /// When the service is not ready, the current task is woken once it may
+    /// have become ready.
+    fn poll_ready(&self, _cx: &mut Context<'_>) -> Poll<()> {
+        Poll::Ready(())
+    }
+
+    /// Waits until the service is ready to accept a request.
+    fn ready(&self) -> ReadyFuture<'_, Self> {
+        ReadyFuture { inner: self }
+    }
+}
+
+/// A service that can report how loaded it is.
+///
+/// Lower is less loaded; the metric is only meaningful when compared with the
+/// metric of services of the same type.
+pub trait Load {
+    /// A comparable load metric.
+    type Metric: PartialOrd;
+
+    /// Returns the current load of the service.
+    fn load(&self) -> Self::Metric;
+}
+
+/// Reports the inner service as always ready.
+///
+/// This lets the services that don't implement [`Ready`] be used where it is
+/// required, e.g. by the balancers, without an `impl` of their own.
+///
+/// # Example
+///
+/// ```rust
+/// use std::convert::Infallible;
+///
+/// use motore::{
+///     load::{AlwaysReady, Ready},
+///     Service,
+/// };
+///
+/// // Implements `Service`, but not `Ready`.
+/// struct Backend;
+///
+/// impl Service<(), u32> for Backend {
+///     type Response = u32;
+///     type Error = Infallible;
+///
+///     async fn call(&self, _cx: &mut (), req: u32) -> Result<u32, Infallible> {
+///         Ok(req)
+///     }
+/// }
+///
+/// # #[tokio::main(flavor = "current_thread")]
+/// # async fn main() {
+/// let svc = AlwaysReady::new(Backend);
+/// svc.ready().await;
+/// assert_eq!(svc.call(&mut (), 1).await, Ok(1));
+/// # }
+/// ```
+#[derive(Clone, Copy, Debug, Default)]
+pub struct AlwaysReady<S> {
+    inner: S,
+}
+
+impl<S> AlwaysReady<S> {
+    /// Creates an `AlwaysReady` around `inner`.
+    pub const fn new(inner: S) -> Self {
+        AlwaysReady { inner }
+    }
+
+    /// Returns a reference to the inner service.
+    pub fn get_ref(&self) -> &S {
+        &self.inner
+    }
+
+    /// Consumes `self`, returning the inner service.
+    pub fn into_inner(self) -> S {
+        self.inner
+    }
+}
+
+impl<Cx, Req, S> Service<Cx, Req> for AlwaysReady<S>
+where
+    S: Service<Cx, Req>,
+{
+    type Response = S::Response;
+
+    type Error = S::Error;
+
+    #[cfg(feature = "service_send")]
+    fn call(
+        &self,
+        cx: &mut Cx,
+        req: Req,
+    ) -> impl Future<Output = Result<Self::Response, Self::Error>> + Send {
+        self.inner.call(cx, req)
+    }
+    #[cfg(not(feature = "service_send"))]
+    fn call(
+        &self,
+        cx: &mut Cx,
+        req: Req,
+    ) -> impl Future<Output = Result<Self::Response, Self::Error>> {
+        self.inner.call(cx, req)
+    }
+}
+
+impl<S> Ready for AlwaysReady<S> {}
+
+impl<S: Load> Load for AlwaysReady<S> {
+    type Metric = S::Metric;
+
+    fn load(&self) -> Self::Metric {
+        self.inner.load()
+    }
+}
+
+/// Future returned by [`Ready::ready`].
+#[must_use = "futures do nothing unless you `.await` or poll them"]
+pub struct ReadyFuture<'a, T: ?Sized> {
+    inner: &'a T,
+}
+
+impl<T: Ready + ?Sized> Future for ReadyFuture<'_, T> {
+    type Output = ();
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<()> {
+        self.inner.poll_ready(cx)
+    }
+}
+
+impl<T: ?Sized> fmt::Debug for ReadyFuture<'_, T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ReadyFuture").finish()
+    }
+}
+
+macro_rules! impl_load_ref {
+    ($t: tt) => {
+        impl<T: Ready + ?Sized> Ready for $t<T> {
+            fn poll_ready(&self, cx: &mut Context<'_>) -> Poll<()> {
+                (**self).poll_ready(cx)
+            }
+        }
+
+        impl<T: Load + ?Sized> Load for $t<T> {
+            type Metric = T::Metric;
+
+            fn load(&self) -> Self::Metric {
+                (**self).load()
+            }
+        }
+    };
+}
+
+impl_load_ref!(Arc);
+impl_load_ref!(Box);
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        convert::Infallible,
+        sync::atomic::{AtomicUsize, Ordering},
+    };
+
+    use futures::task::{noop_waker_ref, AtomicWaker};
+
+    use super::*;
+    use crate::{timeout::Timeout, ServiceExt};
+
+    #[derive(Default)]
+    struct Pool {
+        idle: AtomicUsize,
+        waker: AtomicWaker,
+    }
+
+    impl Pool {
+        fn release(&self) {
+            self.idle.fetch_add(1, Ordering::SeqCst);
+            self.waker.wake();
+        }
+    }
+
+    impl Service<(), ()> for Pool {
+        type Response = ();
+        type Error = Infallible;
+
+        async fn call(&self, _cx: &mut (), _req: ()) -> Result<(), Infallible> {
+            Ok(())
+        }
+    }
+
+    impl Ready for Pool {
+        fn poll_ready(&self, cx: &mut Context<'_>) -> Poll<()> {
+            self.waker.register(cx.waker());
+            if self.idle.load(Ordering::SeqCst) > 0 {
+                Poll::Ready(())
+            } else {
+                Poll::Pending
+            }
+        }
+    }
+
+    impl Load for Pool {
+        type Metric = usize;
+
+        fn load(&self) -> usize {
+            usize::MAX - self.idle.load(Ordering::SeqCst)
+        }
+    }
+
+    #[test]
+    fn ready_by_default() {
+        struct Backend;
+        impl Ready for Backend {}
+
+        let mut cx = Context::from_waker(noop_waker_ref());
+        assert!(Backend.poll_ready(&mut cx).is_ready());
+    }
+
+    #[tokio::test]
+    async fn forwarded_through_middlewares() {
+        let pool = Arc::new(Pool::default());
+        let svc = Timeout::new(pool.clone(), None).map_err(|_| ());
+
+        let mut cx = Context::from_waker(noop_waker_ref());
+        assert!(svc.poll_ready(&mut cx).is_pending());
+        let before = svc.load();
+
+        let ready = tokio::spawn({
+            let pool = pool.clone();
+            async move { pool.ready().await }
+        });
+        tokio::task::yield_now().await;
+        pool.release();
+        ready.await.unwrap();
+
+        assert!(svc.poll_ready(&mut cx).is_ready());
+        assert!(svc.load() < before);
+    }
+}
@@ -0,0 +1,221 @@
+use std::{
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc, Mutex,
+    },
+    task::{Context, Poll},
+    time::Duration,
+};
+
+use super::{Load, Ready};
+use crate::{
+    layer::Layer,
+    service::Service,
+    timer::{DefaultTimer, Instant, Timer},
+    MaybeSend, MaybeSync,
+};
+
+/// Reports the latency of the inner service, weighted by its calls in flight, as
+/// its [`Load`].
+///
+/// The latency is a peak-sensitive exponentially weighted moving average: a call
+/// slower than the average replaces it right away, while faster calls only pull it
+/// down gradually, over about `decay`. The average also decays towards zero while
+/// no call completes, so an endpoint that was slow once gets tried again. The load
+/// is the average in nanoseconds, multiplied by the calls in flight plus one.
+///
+/// The clones of a `PeakEwma` share the same average.
+#[derive(Clone, Debug)]
+pub struct PeakEwma<S, T = DefaultTimer> {
+    inner: S,
+    estimate: Arc<Mutex<Estimate>>,
+    pending: Arc<AtomicUsize>,
+    decay: f64,
+    timer: T,
+}
+
+#[derive(Debug)]
+struct Estimate {
+    rtt: f64,
+    updated_at: Instant,
+}
+
+impl Estimate {
+    /// Averages the latency of a call completed at `now` into the estimate.
+    fn update(&mut self, now: Instant, rtt: f64, decay: f64) -> f64 {
+        if rtt > self.rtt {
+            self.rtt = rtt;
+        } else {
+            let elapsed = now.saturating_duration_since(self.updated_at).as_nanos() as f64;
+            let weight = (-elapsed / decay).exp();
+            self.rtt = self.rtt * weight + rtt * (1.0 - weight);
+        }
+        self.updated_at = self.updated_at.max(now);
+        self.rtt
+    }
+}
+
+impl<S> PeakEwma<S> {
+    /// Creates a `PeakEwma` starting from a latency of `default_rtt`, and averaging
+    /// the latencies over about `decay`.
+    pub fn new(inner: S, default_rtt: Duration, decay: Duration) -> Self {
+        Self::with_timer(inner, default_rtt, decay, DefaultTimer::new())
+    }
+}
+
+impl<S, T: Timer> PeakEwma<S, T> {
+    fn with_timer(inner: S, default_rtt: Duration, decay: Duration, timer: T) -> Self {
+        PeakEwma {
+            inner,
+            estimate: Arc::new(Mutex::new(Estimate {
+                rtt: default_rtt.as_nanos() as f64,
+                updated_at: timer.now(),
+            })),
+            pending: Arc::new(AtomicUsize::new(0)),
+            decay: decay.as_nanos().max(1) as f64,
+            timer,
+        }
+    }
+
+    fn update(&self, rtt: f64) -> f64 {
+        let now = self.timer.now();
+        self.estimate
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .update(now, rtt, self.decay)
+    }
+}
+
+/// Decrements the calls in flight once a call completed or was cancelled.
+struct Guard<'a> {
+    pending: &'a AtomicUsize,
+}
+
+impl Drop for Guard<'_> {
+    fn drop(&mut self) {
+        self.pending.fetch_sub(1, Ordering::Relaxed);
+    }
+}
+
+impl<Cx, Req, S, T> Service<Cx, Req> for PeakEwma<S, T>
+where
+    Req: 'static + MaybeSend,
+    S: Service<Cx, Req> + 'static + MaybeSend + MaybeSync,
+    T: Timer + MaybeSend + MaybeSync,
+    Cx: 'static + MaybeSend,
+{
+    type Response = S::Response;
+
+    type Error = S::Error;
+
+    async fn call(&self, cx: &mut Cx, req: Req) -> Result<Self::Response, Self::Error> {
+        self.pending.fetch_add(1, Ordering::Relaxed);
+        let _guard = Guard {
+            pending: &self.pending,
+        };
+        let start = self.timer.now();
+        let res = self.inner.call(cx, req).await;
+        let rtt = self.timer.now().saturating_duration_since(start);
+        self.update(rtt.as_nanos() as f64);
+        res
+    }
+}
+
+impl<S, T> Ready for PeakEwma<S, T>
+where
+    S: Ready,
+{
+    fn poll_ready(&self, cx: &mut Context<'_>) -> Poll<()> {
+        self.inner.poll_ready(cx)
+    }
+}
+
+impl<S, T: Timer> Load for PeakEwma<S, T> {
+    type Metric = f64;
+
+    fn load(&self) -> f64 {
+        let pending = self.pending.load(Ordering::Relaxed);
+        // Decays the estimate up to now.
+        let rtt = self.update(0.0);
+        rtt * (pending + 1) as f64
+    }
+}
+
+/// Applies a [`PeakEwma`] to a service.
+#[derive(Clone, Copy, Debug)]
+pub struct PeakEwmaLayer<T = DefaultTimer> {
+    default_rtt: Duration,
+    decay: Duration,
+    timer: T,
+}
+
+impl PeakEwmaLayer {
+    /// Creates a layer starting from a latency of `default_rtt`, and averaging the
+    /// latencies over about `decay`.
+    pub const fn new(default_rtt: Duration, decay: Duration) -> Self {
+        PeakEwmaLayer {
+            default_rtt,
+            decay,
+            timer: DefaultTimer::new(),
+        }
+    }
+}
+
+impl<T> PeakEwmaLayer<T> {
+    /// Sets the timer measuring the latencies, [`DefaultTimer`] by default.
+    pub fn timer<U>(self, timer: U) -> PeakEwmaLayer<U> {
+        PeakEwmaLayer {
+            default_rtt: self.default_rtt,
+            decay: self.decay,
+            timer,
+        }
+    }
+}
+
+impl<S, T: Timer> Layer<S> for PeakEwmaLayer<T> {
+    type Service = PeakEwma<S, T>;
+
+    fn layer(self, inner: S) -> Self::Service {
+        PeakEwma::with_timer(inner, self.default_rtt, self.decay, self.timer)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::convert::Infallible;
+
+    use super::*;
+    use crate::service::service_fn;
+
+    fn millis(load: f64) -> f64 {
+        (load / 1e6).round()
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn follows_the_peaks_and_decays() {
+        let svc = PeakEwmaLayer::new(Duration::from_millis(10), Duration::from_millis(100)).layer(
+            service_fn(|_cx: &mut (), millis: u64| async move {
+                tokio::time::sleep(Duration::from_millis(millis)).await;
+                Ok::<_, Infallible>(())
+            }),
+        );
+        assert_eq!(millis(svc.load()), 10.0);
+
+        // A slower call is taken right away.
+        svc.call(&mut (), 50).await.unwrap();
+        assert_eq!(millis(svc.load()), 50.0);
+
+        // Weighted by the calls in flight.
+        let observe = async {
+            tokio::time::sleep(Duration::from_millis(1)).await;
+            svc.load()
+        };
+        let (_, load) = tokio::join!(async { svc.call(&mut (), 1).await }, observe);
+        assert!(millis(load) > 90.0);
+
+        // Decays without calls.
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        let load = millis(svc.load());
+        assert!(10.0 < load && load < 25.0, "{load}");
+    }
+}
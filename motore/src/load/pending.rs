@@ -0,0 +1,127 @@
+use std::{
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    },
+    task::{Context, Poll},
+};
+
+use super::{Load, Ready};
+use crate::{layer::Layer, service::Service, MaybeSend, MaybeSync};
+
+/// Reports the number of calls in flight as the [`Load`] of the inner service.
+///
+/// The clones of a `PendingRequests` count their calls together, so a balancer
+/// may hand out clones of the same endpoint.
+#[derive(Clone, Debug)]
+pub struct PendingRequests<S> {
+    inner: S,
+    pending: Arc<AtomicUsize>,
+}
+
+impl<S> PendingRequests<S> {
+    /// Creates a `PendingRequests` with no call in flight.
+    pub fn new(inner: S) -> Self {
+        PendingRequests {
+            inner,
+            pending: Arc::new(AtomicUsize::new(0)),
+        }
+    }
+}
+
+/// Decrements the calls in flight once a call completed or was cancelled.
+struct Guard<'a> {
+    pending: &'a AtomicUsize,
+}
+
+impl Drop for Guard<'_> {
+    fn drop(&mut self) {
+        self.pending.fetch_sub(1, Ordering::Relaxed);
+    }
+}
+
+impl<Cx, Req, S> Service<Cx, Req> for PendingRequests<S>
+where
+    Req: 'static + MaybeSend,
+    S: Service<Cx, Req> + 'static + MaybeSend + MaybeSync,
+    Cx: 'static + MaybeSend,
+{
+    type Response = S::Response;
+
+    type Error = S::Error;
+
+    async fn call(&self, cx: &mut Cx, req: Req) -> Result<Self::Response, Self::Error> {
+        self.pending.fetch_add(1, Ordering::Relaxed);
+        let _guard = Guard {
+            pending: &self.pending,
+        };
+        self.inner.call(cx, req).await
+    }
+}
+
+impl<S> Ready for PendingRequests<S>
+where
+    S: Ready,
+{
+    fn poll_ready(&self, cx: &mut Context<'_>) -> Poll<()> {
+        self.inner.poll_ready(cx)
+    }
+}
+
+impl<S> Load for PendingRequests<S> {
+    type Metric = usize;
+
+    fn load(&self) -> usize {
+        self.pending.load(Ordering::Relaxed)
+    }
+}
+
+/// Applies a [`PendingRequests`] to a service.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct PendingRequestsLayer;
+
+impl PendingRequestsLayer {
+    pub const fn new() -> Self {
+        PendingRequestsLayer
+    }
+}
+
+impl<S> Layer<S> for PendingRequestsLayer {
+    type Service = PendingRequests<S>;
+
+    fn layer(self, inner: S) -> Self::Service {
+        PendingRequests::new(inner)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{convert::Infallible, time::Duration};
+
+    use super::*;
+    use crate::service::service_fn;
+
+    #[tokio::test(start_paused = true)]
+    async fn counts_the_calls_in_flight() {
+        let svc = PendingRequests::new(service_fn(|_cx: &mut (), millis: u64| async move {
+            tokio::time::sleep(Duration::from_millis(millis)).await;
+            Ok::<_, Infallible>(())
+        }));
+        let clone = svc.clone();
+
+        let calls = async {
+            tokio::join!(async { svc.call(&mut (), 10).await }, async {
+                clone.call(&mut (), 20).await
+            })
+        };
+        let observe = async {
+            tokio::time::sleep(Duration::from_millis(5)).await;
+            let both = svc.load();
+            tokio::time::sleep(Duration::from_millis(10)).await;
+            (both, svc.load())
+        };
+        let (_, loads) = tokio::join!(calls, observe);
+        assert_eq!(loads, (2, 1));
+        assert_eq!(svc.load(), 0);
+    }
+}
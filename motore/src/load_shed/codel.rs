@@ -0,0 +1,311 @@
+//! Sheds load based on how long requests wait to be admitted.
+//!
+//! [`CoDel`] admits a bounded number of in-flight requests, like a
+//! [`ConcurrencyLimit`](crate::limit::ConcurrencyLimit), and measures how long each
+//! request waits for its turn. The approach comes from the CoDel queue management
+//! algorithm: a queue that briefly fills up absorbs a burst, which is fine, while a
+//! queue that never drains below some delay only adds latency.
+//!
+//! - While the shortest wait within an interval stays under the target delay,
+//!   requests may wait up to a full interval before being shed.
+//! - Once even the shortest wait of an interval exceeds the target, the service is
+//!   overloaded, and requests not admitted within the target delay are shed, until
+//!   an interval sees a shorter wait again.
+
+use std::{
+    fmt,
+    sync::{Arc, Mutex},
+    task::{Context, Poll},
+    time::Duration,
+};
+
+use tokio::sync::Semaphore;
+
+use super::Overloaded;
+use crate::{
+    layer::Layer,
+    load::{Load, Ready},
+    service::Service,
+    timer::{DefaultTimer, Instant, Timer},
+    BoxError, MaybeSend, MaybeSync,
+};
+
+/// The queue shared by the clones of a [`CoDel`].
+#[derive(Debug)]
+struct Queue {
+    semaphore: Semaphore,
+    target: Duration,
+    interval: Duration,
+    state: Mutex<State>,
+}
+
+#[derive(Debug)]
+struct State {
+    interval_start: Instant,
+    min_wait: Option<Duration>,
+    overloaded: bool,
+}
+
+impl Queue {
+    fn new(max: usize, target: Duration, interval: Duration, now: Instant) -> Self {
+        Queue {
+            semaphore: Semaphore::new(max),
+            target,
+            interval,
+            state: Mutex::new(State {
+                interval_start: now,
+                min_wait: None,
+                overloaded: false,
+            }),
+        }
+    }
+
+    fn is_overloaded(&self) -> bool {
+        self.state
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .overloaded
+    }
+
+    /// Returns how long a request may wait to be admitted.
+    fn timeout(&self) -> Duration {
+        if self.is_overloaded() {
+            self.target
+        } else {
+            self.interval
+        }
+    }
+
+    /// Records that a request waited `wait` until `now`, whether it was admitted
+    /// or shed.
+    fn record(&self, now: Instant, wait: Duration) {
+        let mut state = self.state.lock().unwrap_or_else(|e| e.into_inner());
+        state.min_wait = Some(state.min_wait.map_or(wait, |min| min.min(wait)));
+
+        if now.saturating_duration_since(state.interval_start) >= self.interval {
+            state.overloaded = state.min_wait.is_some_and(|min| min > self.target);
+            state.min_wait = None;
+            state.interval_start = now;
+        }
+    }
+}
+
+/// Bounds the number of in-flight calls to the inner service, shedding the
+/// requests that wait too long to be admitted with an [`Overloaded`] error.
+///
+/// The clones of a `CoDel` share the same queue. See the [module level
+/// docs](self) for when requests are shed.
+#[derive(Clone)]
+pub struct CoDel<S, T = DefaultTimer> {
+    inner: S,
+    queue: Arc<Queue>,
+    timer: T,
+}
+
+impl<S, T> CoDel<S, T> {
+    /// Returns whether the queue is currently considered overloaded.
+    pub fn is_overloaded(&self) -> bool {
+        self.queue.is_overloaded()
+    }
+}
+
+impl<Cx, Req, S, T> Service<Cx, Req> for CoDel<S, T>
+where
+    Req: 'static + MaybeSend,
+    S: Service<Cx, Req> + 'static + MaybeSend + MaybeSync,
+    Cx: 'static + MaybeSend,
+    S::Error: MaybeSend + MaybeSync + Into<BoxError>,
+    T: Timer + MaybeSync,
+{
+    type Response = S::Response;
+
+    type Error = BoxError;
+
+    async fn call(&self, cx: &mut Cx, req: Req) -> Result<Self::Response, Self::Error> {
+        let start = self.timer.now();
+        let timeout = self.queue.timeout();
+        let permit = tokio::select! {
+            permit = self.queue.semaphore.acquire() => Some(permit),
+            _ = self.timer.sleep(timeout) => None,
+        };
+        let now = self.timer.now();
+        self.queue.record(now, now.saturating_duration_since(start));
+        // The semaphore is never closed.
+        let Some(_permit) = permit else {
+            return Err(Overloaded.into());
+        };
+        self.inner.call(cx, req).await.map_err(Into::into)
+    }
+}
+
+impl<S, T> Ready for CoDel<S, T>
+where
+    S: Ready,
+{
+    fn poll_ready(&self, cx: &mut Context<'_>) -> Poll<()> {
+        self.inner.poll_ready(cx)
+    }
+}
+
+impl<S, T> Load for CoDel<S, T>
+where
+    S: Load,
+{
+    type Metric = S::Metric;
+
+    fn load(&self) -> Self::Metric {
+        self.inner.load()
+    }
+}
+
+impl<S: fmt::Debug, T> fmt::Debug for CoDel<S, T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("CoDel")
+            .field("inner", &self.inner)
+            .field("target", &self.queue.target)
+            .field("interval", &self.queue.interval)
+            .field("overloaded", &self.is_overloaded())
+            .finish()
+    }
+}
+
+/// Applies a [`CoDel`] to a service.
+///
+/// Every service made by the layer has its own queue, shared only by its clones.
+///
+/// # Example
+///
+/// ```rust
+/// use std::time::Duration;
+///
+/// use motore::{
+///     builder::ServiceBuilder, load_shed::codel::CoDelLayer, service::service_fn, BoxError,
+///     Service,
+/// };
+///
+/// # #[tokio::main(flavor = "current_thread")]
+/// # async fn main() {
+/// async fn echo(_cx: &mut (), req: String) -> Result<String, BoxError> {
+///     Ok(req)
+/// }
+///
+/// // Up to 64 requests in flight, shedding when requests keep waiting over 5ms.
+/// let svc = ServiceBuilder::new()
+///     .layer(CoDelLayer::new(64, Duration::from_millis(5)))
+///     .service(service_fn(echo));
+///
+/// assert_eq!(svc.call(&mut (), "ping".into()).await.unwrap(), "ping");
+/// # }
+/// ```
+#[derive(Clone, Copy, Debug)]
+pub struct CoDelLayer<T = DefaultTimer> {
+    max: usize,
+    target: Duration,
+    interval: Duration,
+    timer: T,
+}
+
+impl CoDelLayer {
+    /// Creates a layer allowing `max` in-flight calls, and shedding when requests
+    /// keep waiting longer than `target` to be admitted.
+    ///
+    /// The interval over which waits are measured defaults to 100ms.
+    pub const fn new(max: usize, target: Duration) -> Self {
+        CoDelLayer {
+            max,
+            target,
+            interval: Duration::from_millis(100),
+            timer: DefaultTimer::new(),
+        }
+    }
+}
+
+impl<T> CoDelLayer<T> {
+    /// Sets the interval over which waits are measured, which is also the longest
+    /// a request waits when the service is not overloaded.
+    ///
+    /// It should be a few times the usual latency of the service.
+    pub const fn interval(mut self, interval: Duration) -> Self {
+        self.interval = interval;
+        self
+    }
+
+    /// Sets the timer measuring the waits, [`DefaultTimer`] by default.
+    pub fn timer<U>(self, timer: U) -> CoDelLayer<U> {
+        CoDelLayer {
+            max: self.max,
+            target: self.target,
+            interval: self.interval,
+            timer,
+        }
+    }
+}
+
+impl<S, T: Timer> Layer<S> for CoDelLayer<T> {
+    type Service = CoDel<S, T>;
+
+    fn layer(self, inner: S) -> Self::Service {
+        let now = self.timer.now();
+        CoDel {
+            inner,
+            queue: Arc::new(Queue::new(self.max, self.target, self.interval, now)),
+            timer: self.timer,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use tokio::time::Instant;
+
+    use super::*;
+    use crate::service::service_fn;
+
+    async fn slow(_cx: &mut (), req: u32) -> Result<u32, BoxError> {
+        tokio::time::sleep(Duration::from_millis(60)).await;
+        Ok(req)
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn sheds_faster_under_sustained_overload() {
+        let svc = CoDelLayer::new(1, Duration::from_millis(10)).layer(service_fn(slow));
+
+        // A request every 20ms, for a service handling one every 60ms.
+        let calls = (0..50).map(|i| {
+            let svc = svc.clone();
+            async move {
+                tokio::time::sleep(Duration::from_millis(20 * i)).await;
+                let start = Instant::now();
+                let res = svc.call(&mut (), i as u32).await;
+                (res.is_ok(), start.elapsed())
+            }
+        });
+        let results = futures::future::join_all(calls).await;
+
+        let shed = |wait| {
+            results
+                .iter()
+                .filter(|&&(ok, elapsed)| !ok && elapsed == wait)
+                .count()
+        };
+        assert!(shed(Duration::from_millis(100)) > 0);
+        assert!(shed(Duration::from_millis(10)) > 0);
+        assert_eq!(
+            shed(Duration::from_millis(100)) + shed(Duration::from_millis(10)),
+            results.iter().filter(|(ok, _)| !ok).count()
+        );
+        assert!(svc.is_overloaded());
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn bursts_are_absorbed() {
+        let svc = CoDelLayer::new(1, Duration::from_millis(10)).layer(service_fn(slow));
+
+        let burst = futures::future::join_all((0..2).map(|i| {
+            let svc = svc.clone();
+            async move { svc.call(&mut (), i).await.is_ok() }
+        }));
+        assert_eq!(burst.await, [true, true]);
+        assert!(!svc.is_overloaded());
+    }
+}
@@ -0,0 +1,170 @@
+//! Fails requests immediately when the inner service is not ready.
+//!
+//! Services report their capacity through [`Ready`]: for instance a
+//! [`ConcurrencyLimit`](crate::limit::ConcurrencyLimit) is not ready when all its
+//! permits are in use. Calling such a service makes the request wait for capacity,
+//! and under sustained overload the waiting requests pile up. [`LoadShed`] instead
+//! fails them right away with an [`Overloaded`] error, so a server can shed the
+//! load it can't handle and its clients can try elsewhere.
+//!
+//! Shedding only what exceeds a fixed capacity works poorly with bursty traffic:
+//! a burst the service would absorb in a few milliseconds gets shed all the same.
+//! [`CoDel`](codel::CoDel) sheds based on how long requests wait for capacity
+//! instead, and only once waiting has become the norm.
+
+use std::{
+    fmt,
+    task::{Context, Poll},
+};
+
+use futures::task::noop_waker_ref;
+
+use crate::{
+    layer::Layer,
+    load::{Load, Ready},
+    service::Service,
+    BoxError, MaybeSend, MaybeSync,
+};
+
+pub mod codel;
+
+/// The error returned by [`LoadShed`] and [`CoDel`](codel::CoDel) when a request
+/// is shed.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Overloaded;
+
+impl fmt::Display for Overloaded {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("service overloaded")
+    }
+}
+
+impl std::error::Error for Overloaded {}
+
+/// Fails the requests with an [`Overloaded`] error when the inner service is not
+/// [`Ready`], instead of calling it.
+///
+/// A `LoadShed` is always ready itself, as it never makes a request wait.
+#[derive(Clone, Debug)]
+pub struct LoadShed<S> {
+    inner: S,
+}
+
+impl<S> LoadShed<S> {
+    pub const fn new(inner: S) -> Self {
+        LoadShed { inner }
+    }
+}
+
+impl<Cx, Req, S> Service<Cx, Req> for LoadShed<S>
+where
+    Req: 'static + MaybeSend,
+    S: Service<Cx, Req> + Ready + 'static + MaybeSend + MaybeSync,
+    Cx: 'static + MaybeSend,
+    S::Error: MaybeSend + MaybeSync + Into<BoxError>,
+{
+    type Response = S::Response;
+
+    type Error = BoxError;
+
+    async fn call(&self, cx: &mut Cx, req: Req) -> Result<Self::Response, Self::Error> {
+        // Only the current state matters, nothing waits to be woken.
+        let ready = self
+            .inner
+            .poll_ready(&mut Context::from_waker(noop_waker_ref()));
+        if ready.is_pending() {
+            return Err(Overloaded.into());
+        }
+        self.inner.call(cx, req).await.map_err(Into::into)
+    }
+}
+
+impl<S> Ready for LoadShed<S> {
+    fn poll_ready(&self, _cx: &mut Context<'_>) -> Poll<()> {
+        Poll::Ready(())
+    }
+}
+
+impl<S> Load for LoadShed<S>
+where
+    S: Load,
+{
+    type Metric = S::Metric;
+
+    fn load(&self) -> Self::Metric {
+        self.inner.load()
+    }
+}
+
+/// Applies a [`LoadShed`] to a service.
+///
+/// # Example
+///
+/// ```rust
+/// use motore::{
+///     builder::ServiceBuilder,
+///     limit::ConcurrencyLimitLayer,
+///     load_shed::{LoadShedLayer, Overloaded},
+///     service::service_fn,
+///     BoxError, Service,
+/// };
+///
+/// # #[tokio::main(flavor = "current_thread")]
+/// # async fn main() {
+/// async fn echo(_cx: &mut (), req: String) -> Result<String, BoxError> {
+///     Ok(req)
+/// }
+///
+/// // Fails the requests exceeding 64 in flight.
+/// let svc = ServiceBuilder::new()
+///     .layer(LoadShedLayer::new())
+///     .layer(ConcurrencyLimitLayer::new(64))
+///     .service(service_fn(echo));
+///
+/// assert_eq!(svc.call(&mut (), "ping".into()).await.unwrap(), "ping");
+/// # }
+/// ```
+#[derive(Clone, Copy, Debug, Default)]
+pub struct LoadShedLayer;
+
+impl LoadShedLayer {
+    pub const fn new() -> Self {
+        LoadShedLayer
+    }
+}
+
+impl<S> Layer<S> for LoadShedLayer {
+    type Service = LoadShed<S>;
+
+    fn layer(self, inner: S) -> Self::Service {
+        LoadShed { inner }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::*;
+    use crate::{limit::ConcurrencyLimitLayer, service::service_fn};
+
+    async fn slow(_cx: &mut (), req: u32) -> Result<u32, BoxError> {
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        Ok(req)
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn sheds_calls_over_the_limit() {
+        let svc = LoadShedLayer::new().layer(ConcurrencyLimitLayer::new(1).layer(service_fn(slow)));
+
+        let (first, second) = tokio::join!(async { svc.call(&mut (), 1).await }, async {
+            tokio::task::yield_now().await;
+            svc.call(&mut (), 2).await
+        });
+        assert_eq!(first.unwrap(), 1);
+        assert!(second.unwrap_err().is::<Overloaded>());
+
+        // Capacity is back once the first call completed.
+        assert_eq!(svc.call(&mut (), 3).await.unwrap(), 3);
+    }
+}
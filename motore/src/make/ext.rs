@@ -0,0 +1,346 @@
+use std::{fmt, future::Future, time::Duration};
+
+use crate::{
+    make::MakeConnection,
+    retry::Policy,
+    timeout::TimeoutError,
+    timer::{DefaultTimer, Timer},
+    BoxError, MaybeSend, MaybeSync, UnaryService,
+};
+
+/// An extension trait for [`MakeConnection`]s bounding and retrying the attempts to
+/// connect.
+///
+/// The adapters apply to the connection attempts only: once connected, the
+/// connection is used without them. They are [`MakeConnection`]s themselves, so
+/// they can be chained, the timeout of each attempt before the retries of the
+/// failed ones.
+///
+/// # Example
+///
+/// ```rust
+/// use std::{future::Future, io::Cursor, time::Duration};
+///
+/// use motore::{
+///     backoff::{BackoffExt, Exponential},
+///     make::{MakeConnection, MakeConnectionExt},
+///     retry::BackoffPolicy,
+///     BoxError, UnaryService,
+/// };
+///
+/// // Connects to an in-memory buffer.
+/// struct Connector;
+///
+/// impl UnaryService<&'static str> for Connector {
+///     type Response = Cursor<Vec<u8>>;
+///     type Error = BoxError;
+///
+///     # #[cfg(feature = "service_send")]
+///     fn call(
+///         &self,
+///         _addr: &'static str,
+///     ) -> impl Future<Output = Result<Cursor<Vec<u8>>, BoxError>> + Send {
+///         async { Ok(Cursor::new(Vec::new())) }
+///     }
+///     # #[cfg(not(feature = "service_send"))]
+///     # fn call(
+///     #     &self,
+///     #     _addr: &'static str,
+///     # ) -> impl Future<Output = Result<Cursor<Vec<u8>>, BoxError>> {
+///     #     async { Ok(Cursor::new(Vec::new())) }
+///     # }
+/// }
+///
+/// # #[tokio::main(flavor = "current_thread")]
+/// # async fn main() {
+/// let backoff = Exponential::new(Duration::from_millis(100), Duration::from_secs(1))
+///     .full_jitter()
+///     .max_attempts(3);
+/// let connector = Connector
+///     .timeout(Duration::from_secs(3))
+///     .retry(BackoffPolicy::new(backoff, |res: &Result<_, BoxError>| {
+///         res.is_err()
+///     }));
+///
+/// let conn = connector.make_connection("127.0.0.1:8080").await.unwrap();
+/// assert!(conn.get_ref().is_empty());
+/// # }
+/// ```
+pub trait MakeConnectionExt<Address>: MakeConnection<Address> + Sized {
+    /// Fails the attempts to connect that don't complete within `duration` with a
+    /// [`TimeoutError`].
+    fn timeout(self, duration: Duration) -> ConnectTimeout<Self> {
+        ConnectTimeout::new(self, duration)
+    }
+
+    /// Retries the failed attempts to connect as decided by `policy`.
+    ///
+    /// The policy is called with `()` as the context, and the address as the
+    /// request.
+    fn retry<P>(self, policy: P) -> ConnectRetry<Self, P> {
+        ConnectRetry::new(self, policy)
+    }
+}
+
+impl<M: MakeConnection<Address>, Address> MakeConnectionExt<Address> for M {}
+
+/// Fails the attempts to connect of the inner [`MakeConnection`] that don't
+/// complete in time.
+///
+/// See [`MakeConnectionExt::timeout`].
+#[derive(Clone)]
+pub struct ConnectTimeout<M, T = DefaultTimer> {
+    inner: M,
+    duration: Duration,
+    timer: T,
+}
+
+impl<M> ConnectTimeout<M> {
+    /// Creates a `ConnectTimeout` giving up on the attempts after `duration`.
+    pub const fn new(inner: M, duration: Duration) -> Self {
+        ConnectTimeout {
+            inner,
+            duration,
+            timer: DefaultTimer::new(),
+        }
+    }
+}
+
+impl<M, T> ConnectTimeout<M, T> {
+    /// Sets the timer measuring the timeouts, [`DefaultTimer`] by default.
+    pub fn timer<U>(self, timer: U) -> ConnectTimeout<M, U> {
+        ConnectTimeout {
+            inner: self.inner,
+            duration: self.duration,
+            timer,
+        }
+    }
+
+    async fn connect<Address>(&self, addr: Address) -> Result<M::Connection, BoxError>
+    where
+        M: MakeConnection<Address>,
+        M::Error: Into<BoxError>,
+        T: Timer,
+    {
+        let sleep = self.timer.sleep(self.duration);
+        tokio::select! {
+            r = self.inner.make_connection(addr) => r.map_err(Into::into),
+            _ = sleep => Err(TimeoutError { elapsed: self.duration }.into()),
+        }
+    }
+}
+
+impl<M, T, Address> UnaryService<Address> for ConnectTimeout<M, T>
+where
+    M: MakeConnection<Address> + MaybeSync,
+    M::Error: Into<BoxError>,
+    T: Timer + MaybeSync,
+    Address: MaybeSend,
+{
+    type Response = M::Connection;
+
+    type Error = BoxError;
+
+    #[cfg(feature = "service_send")]
+    fn call(
+        &self,
+        addr: Address,
+    ) -> impl Future<Output = Result<Self::Response, Self::Error>> + Send {
+        self.connect(addr)
+    }
+
+    #[cfg(not(feature = "service_send"))]
+    fn call(&self, addr: Address) -> impl Future<Output = Result<Self::Response, Self::Error>> {
+        self.connect(addr)
+    }
+}
+
+impl<M: fmt::Debug, T> fmt::Debug for ConnectTimeout<M, T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ConnectTimeout")
+            .field("inner", &self.inner)
+            .field("duration", &self.duration)
+            .finish()
+    }
+}
+
+/// Retries the failed attempts to connect of the inner [`MakeConnection`] as
+/// decided by a [`Policy`].
+///
+/// See [`MakeConnectionExt::retry`].
+#[derive(Clone)]
+pub struct ConnectRetry<M, P, T = DefaultTimer> {
+    inner: M,
+    policy: P,
+    timer: T,
+}
+
+impl<M, P> ConnectRetry<M, P> {
+    /// Creates a `ConnectRetry` retrying the attempts as decided by `policy`.
+    pub const fn new(inner: M, policy: P) -> Self {
+        ConnectRetry {
+            inner,
+            policy,
+            timer: DefaultTimer::new(),
+        }
+    }
+}
+
+impl<M, P, T> ConnectRetry<M, P, T> {
+    /// Sets the timer waiting between the attempts, [`DefaultTimer`] by default.
+    pub fn timer<U>(self, timer: U) -> ConnectRetry<M, P, U> {
+        ConnectRetry {
+            inner: self.inner,
+            policy: self.policy,
+            timer,
+        }
+    }
+
+    async fn connect<Address>(&self, mut addr: Address) -> Result<M::Connection, M::Error>
+    where
+        M: MakeConnection<Address>,
+        P: Policy<(), Address, M::Connection, M::Error> + Clone,
+        T: Timer,
+    {
+        let mut policy = self.policy.clone();
+        loop {
+            let next = policy.clone_request(&(), &addr);
+            let res = self.inner.make_connection(addr).await;
+            let Some(next) = next else {
+                return res;
+            };
+            match policy.retry(&mut (), &next, &res) {
+                Some(backoff) => {
+                    if !backoff.is_zero() {
+                        self.timer.sleep(backoff).await;
+                    }
+                    addr = next;
+                }
+                None => return res,
+            }
+        }
+    }
+}
+
+impl<M, P, T, Address> UnaryService<Address> for ConnectRetry<M, P, T>
+where
+    M: MakeConnection<Address> + MaybeSync,
+    M::Error: MaybeSend,
+    P: Policy<(), Address, M::Connection, M::Error> + Clone + MaybeSend + MaybeSync,
+    T: Timer + MaybeSync,
+    Address: MaybeSend,
+{
+    type Response = M::Connection;
+
+    type Error = M::Error;
+
+    #[cfg(feature = "service_send")]
+    fn call(
+        &self,
+        addr: Address,
+    ) -> impl Future<Output = Result<Self::Response, Self::Error>> + Send {
+        self.connect(addr)
+    }
+
+    #[cfg(not(feature = "service_send"))]
+    fn call(&self, addr: Address) -> impl Future<Output = Result<Self::Response, Self::Error>> {
+        self.connect(addr)
+    }
+}
+
+impl<M: fmt::Debug, P: fmt::Debug, T> fmt::Debug for ConnectRetry<M, P, T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ConnectRetry")
+            .field("inner", &self.inner)
+            .field("policy", &self.policy)
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        io::{self, Cursor},
+        sync::atomic::{AtomicUsize, Ordering},
+    };
+
+    use super::*;
+    use crate::{
+        backoff::{BackoffExt, Exponential},
+        retry::BackoffPolicy,
+    };
+
+    /// Connects after `delays[n]` on the attempt `n`, and right away after the
+    /// last one.
+    struct Connector {
+        delays: Vec<Duration>,
+        attempts: AtomicUsize,
+    }
+
+    impl Connector {
+        fn new(delays: impl Into<Vec<Duration>>) -> Self {
+            Connector {
+                delays: delays.into(),
+                attempts: AtomicUsize::new(0),
+            }
+        }
+
+        async fn connect(&self) -> io::Result<Cursor<Vec<u8>>> {
+            let attempt = self.attempts.fetch_add(1, Ordering::Relaxed);
+            if let Some(delay) = self.delays.get(attempt) {
+                tokio::time::sleep(*delay).await;
+            }
+            Ok(Cursor::new(vec![attempt as u8]))
+        }
+    }
+
+    impl UnaryService<()> for Connector {
+        type Response = Cursor<Vec<u8>>;
+
+        type Error = io::Error;
+
+        #[cfg(feature = "service_send")]
+        fn call(&self, _addr: ()) -> impl Future<Output = io::Result<Cursor<Vec<u8>>>> + Send {
+            self.connect()
+        }
+
+        #[cfg(not(feature = "service_send"))]
+        fn call(&self, _addr: ()) -> impl Future<Output = io::Result<Cursor<Vec<u8>>>> {
+            self.connect()
+        }
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn times_out_the_attempts() {
+        let connector = Connector::new([Duration::from_secs(10)]).timeout(Duration::from_secs(1));
+
+        let start = tokio::time::Instant::now();
+        let err = connector.make_connection(()).await.unwrap_err();
+        assert_eq!(
+            err.downcast_ref::<TimeoutError>(),
+            Some(&TimeoutError {
+                elapsed: Duration::from_secs(1)
+            })
+        );
+        assert_eq!(start.elapsed(), Duration::from_secs(1));
+
+        let conn = connector.make_connection(()).await.unwrap();
+        assert_eq!(conn.get_ref(), &[1]);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn retries_the_attempts_timed_out() {
+        let backoff =
+            Exponential::new(Duration::from_millis(100), Duration::from_secs(1)).max_attempts(3);
+        let connector = Connector::new([Duration::from_secs(10); 2])
+            .timeout(Duration::from_secs(1))
+            .retry(BackoffPolicy::new(backoff, |res: &Result<_, BoxError>| {
+                res.is_err()
+            }));
+
+        let start = tokio::time::Instant::now();
+        let conn = connector.make_connection(()).await.unwrap();
+        assert_eq!(conn.get_ref(), &[2]);
+        assert_eq!(start.elapsed(), Duration::from_millis(2300));
+    }
+}
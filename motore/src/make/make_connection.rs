@@ -1,7 +1,7 @@
 use futures::Future;
 use tokio::io::{AsyncRead, AsyncWrite};
 
-use crate::{sealed::Sealed, UnaryService};
+use crate::{sealed::Sealed, MaybeSend, UnaryService};
 
 /// This trait is used to create a connection.
 ///
@@ -9,7 +9,7 @@ use crate::{sealed::Sealed, UnaryService};
 /// which means that we only ask for something that is `AsyncRead + AsyncWrite`.
 /// A typical example of a virtual connection is a HTTP/2 stream.
 pub trait MakeConnection<Address>: Sealed<(Address,)> {
-    type Connection: AsyncRead + AsyncWrite + Unpin + Send;
+    type Connection: AsyncRead + AsyncWrite + Unpin + MaybeSend;
     type Error;
 
     #[cfg(feature = "service_send")]
@@ -29,7 +29,7 @@ impl<S, Address> Sealed<(Address,)> for S where S: UnaryService<Address> {}
 impl<S, Address> MakeConnection<Address> for S
 where
     S: UnaryService<Address>,
-    S::Response: AsyncRead + AsyncWrite + Unpin + Send,
+    S::Response: AsyncRead + AsyncWrite + Unpin + MaybeSend,
 {
     type Connection = S::Response;
     type Error = S::Error;
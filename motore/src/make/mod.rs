@@ -1,5 +1,13 @@
 //! Pre-defined Service traits that may be useful for specified use cases.
 
+mod ext;
 mod make_connection;
+mod pool;
+mod reconnect;
 
-pub use self::make_connection::MakeConnection;
+pub use self::{
+    ext::{ConnectRetry, ConnectTimeout, MakeConnectionExt},
+    make_connection::MakeConnection,
+    pool::{Pooled, PooledConnection},
+    reconnect::{ConnectError, Reconnect},
+};
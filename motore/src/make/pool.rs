@@ -0,0 +1,594 @@
+use std::{
+    collections::{HashMap, VecDeque},
+    fmt,
+    future::Future,
+    hash::Hash,
+    io,
+    pin::Pin,
+    sync::{Arc, Weak},
+    task::{Context, Poll},
+    time::Duration,
+};
+
+use tokio::io::{AsyncRead, AsyncWrite, ReadBuf};
+
+use crate::{
+    make::MakeConnection,
+    timer::{DefaultTimer, Instant, Timer},
+    utils::sync::{Mutex, MutexGuard},
+    MaybeSend, MaybeSync, UnaryService,
+};
+
+#[derive(Clone, Copy, Debug)]
+struct Config {
+    max_idle: usize,
+    idle_timeout: Duration,
+    max_lifetime: Option<Duration>,
+}
+
+impl Config {
+    fn outlived(&self, created: Instant, now: Instant) -> bool {
+        self.max_lifetime
+            .is_some_and(|lifetime| now.saturating_duration_since(created) >= lifetime)
+    }
+
+    fn expired<C>(&self, idle: &Idle<C>, now: Instant) -> bool {
+        self.outlived(idle.created, now)
+            || now.saturating_duration_since(idle.since) >= self.idle_timeout
+    }
+
+    /// Drops the idle connections expired at `now`, to every address.
+    fn purge<A: Eq + Hash, C>(
+        &self,
+        connections: &mut HashMap<A, VecDeque<Idle<C>>>,
+        now: Instant,
+    ) {
+        connections.retain(|_, idle| {
+            idle.retain(|idle| !self.expired(idle, now));
+            !idle.is_empty()
+        });
+    }
+}
+
+struct Idle<C> {
+    conn: C,
+    created: Instant,
+    since: Instant,
+}
+
+type IdleConnections<A, C> = Mutex<HashMap<A, VecDeque<Idle<C>>>>;
+
+/// Keeps the connections made by a [`MakeConnection`] once dropped, and hands them
+/// out again instead of connecting.
+///
+/// Every call checks out an idle connection to the address if there is one, the
+/// most recently used first, or makes a new one. The [`PooledConnection`] returns
+/// to the idle connections of its address once dropped, unless it failed or
+/// outlived its [`max_lifetime`](Self::max_lifetime). At most
+/// [`max_idle`](Self::max_idle) connections are kept per address, and the ones
+/// idle for longer than [`idle_timeout`](Self::idle_timeout) are dropped. The
+/// expired connections, to any address, are dropped whenever a connection is
+/// checked out or returns to the pool, so a pool left unused keeps them open
+/// until its next call or its drop.
+///
+/// `Pooled` is a [`UnaryService`] returning the connections, so it is a
+/// [`MakeConnection`] itself.
+///
+/// # Example
+///
+/// ```rust
+/// use std::{future::Future, io::Cursor};
+///
+/// use motore::{make::Pooled, BoxError, UnaryService};
+///
+/// // Connects to an in-memory buffer.
+/// struct Connector;
+///
+/// impl UnaryService<&'static str> for Connector {
+///     type Response = Cursor<Vec<u8>>;
+///     type Error = BoxError;
+///
+///     # #[cfg(feature = "service_send")]
+///     fn call(
+///         &self,
+///         _addr: &'static str,
+///     ) -> impl Future<Output = Result<Cursor<Vec<u8>>, BoxError>> + Send {
+///         async { Ok(Cursor::new(Vec::new())) }
+///     }
+///     # #[cfg(not(feature = "service_send"))]
+///     # fn call(
+///     #     &self,
+///     #     _addr: &'static str,
+///     # ) -> impl Future<Output = Result<Cursor<Vec<u8>>, BoxError>> {
+///     #     async { Ok(Cursor::new(Vec::new())) }
+///     # }
+/// }
+///
+/// # #[tokio::main(flavor = "current_thread")]
+/// # async fn main() {
+/// let pool = Pooled::new(Connector).max_idle(8);
+///
+/// let conn = pool.call("127.0.0.1:8080").await.unwrap();
+/// assert!(!conn.is_reused());
+/// drop(conn);
+///
+/// let conn = pool.call("127.0.0.1:8080").await.unwrap();
+/// assert!(conn.is_reused());
+/// # }
+/// ```
+pub struct Pooled<M: MakeConnection<A>, A, T = DefaultTimer> {
+    make: M,
+    config: Config,
+    idle: Arc<IdleConnections<A, M::Connection>>,
+    timer: T,
+}
+
+impl<M: MakeConnection<A>, A> Pooled<M, A> {
+    /// Creates a pool of the connections made by `make`.
+    ///
+    /// It keeps any number of idle connections per address, for at most 90s, and
+    /// the connections aren't limited in age.
+    pub fn new(make: M) -> Self {
+        Pooled {
+            make,
+            config: Config {
+                max_idle: usize::MAX,
+                idle_timeout: Duration::from_secs(90),
+                max_lifetime: None,
+            },
+            idle: Default::default(),
+            timer: DefaultTimer::new(),
+        }
+    }
+}
+
+impl<M: MakeConnection<A>, A, T> Pooled<M, A, T> {
+    /// Sets the number of idle connections kept per address, unbounded by default.
+    ///
+    /// The connections used the least recently are dropped first.
+    pub fn max_idle(mut self, max: usize) -> Self {
+        self.config.max_idle = max;
+        self
+    }
+
+    /// Sets how long a connection stays idle before it is dropped, 90s by default.
+    pub fn idle_timeout(mut self, timeout: Duration) -> Self {
+        self.config.idle_timeout = timeout;
+        self
+    }
+
+    /// Sets how long a connection is used before it is dropped, counted from when
+    /// it was made, unlimited by default.
+    ///
+    /// The connections checked out are used until dropped, even when they outlive
+    /// it.
+    pub fn max_lifetime(mut self, lifetime: Duration) -> Self {
+        self.config.max_lifetime = Some(lifetime);
+        self
+    }
+
+    /// Sets the timer telling the age of the connections, [`DefaultTimer`] by
+    /// default.
+    pub fn timer<U>(self, timer: U) -> Pooled<M, A, U> {
+        Pooled {
+            make: self.make,
+            config: self.config,
+            idle: self.idle,
+            timer,
+        }
+    }
+
+    fn lock(&self) -> MutexGuard<'_, HashMap<A, VecDeque<Idle<M::Connection>>>> {
+        self.idle.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+impl<M, A, T> Pooled<M, A, T>
+where
+    M: MakeConnection<A>,
+    A: Eq + Hash + Clone,
+    T: Timer + Clone,
+{
+    /// Returns the number of idle connections to `addr`, expired or not.
+    pub fn idle(&self, addr: &A) -> usize {
+        self.lock().get(addr).map_or(0, VecDeque::len)
+    }
+
+    /// Takes the most recently used idle connection to `addr` that didn't expire,
+    /// dropping the expired ones to every address.
+    fn checkout(&self, addr: &A) -> Option<PooledConnection<M::Connection, A, T>> {
+        let mut connections = self.lock();
+        self.config.purge(&mut connections, self.timer.now());
+        let idle = connections.get_mut(addr)?;
+        let checked_out = idle.pop_back();
+        if idle.is_empty() {
+            connections.remove(addr);
+        }
+        checked_out.map(|idle| self.wrap(addr.clone(), idle.conn, idle.created, true))
+    }
+
+    fn wrap(
+        &self,
+        addr: A,
+        conn: M::Connection,
+        created: Instant,
+        reused: bool,
+    ) -> PooledConnection<M::Connection, A, T> {
+        PooledConnection {
+            conn: Some(conn),
+            addr,
+            created,
+            reused,
+            broken: false,
+            config: self.config,
+            pool: Arc::downgrade(&self.idle),
+            timer: self.timer.clone(),
+        }
+    }
+
+    async fn get(&self, addr: A) -> Result<PooledConnection<M::Connection, A, T>, M::Error> {
+        if let Some(conn) = self.checkout(&addr) {
+            return Ok(conn);
+        }
+        let conn = self.make.make_connection(addr.clone()).await?;
+        Ok(self.wrap(addr, conn, self.timer.now(), false))
+    }
+}
+
+impl<M, A, T> UnaryService<A> for Pooled<M, A, T>
+where
+    M: MakeConnection<A> + MaybeSync,
+    A: Eq + Hash + Clone + MaybeSend + MaybeSync,
+    T: Timer + Clone + MaybeSend + MaybeSync,
+{
+    type Response = PooledConnection<M::Connection, A, T>;
+
+    type Error = M::Error;
+
+    #[cfg(feature = "service_send")]
+    fn call(&self, addr: A) -> impl Future<Output = Result<Self::Response, Self::Error>> + Send {
+        self.get(addr)
+    }
+
+    #[cfg(not(feature = "service_send"))]
+    fn call(&self, addr: A) -> impl Future<Output = Result<Self::Response, Self::Error>> {
+        self.get(addr)
+    }
+}
+
+impl<M, A, T> fmt::Debug for Pooled<M, A, T>
+where
+    M: MakeConnection<A>,
+    A: fmt::Debug,
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let idle = self.lock();
+        f.debug_struct("Pooled")
+            .field("config", &self.config)
+            .field(
+                "idle",
+                &idle
+                    .iter()
+                    .map(|(addr, idle)| (addr, idle.len()))
+                    .collect::<Vec<_>>(),
+            )
+            .finish()
+    }
+}
+
+/// A connection checked out of a [`Pooled`], returning to its idle connections once
+/// dropped.
+///
+/// A connection that failed to read or write, or was shut down, is dropped instead.
+/// [`into_inner`](Self::into_inner) takes the connection out of the pool for good.
+pub struct PooledConnection<C, A: Eq + Hash + Clone, T: Timer> {
+    // `None` once taken out of the pool.
+    conn: Option<C>,
+    addr: A,
+    created: Instant,
+    reused: bool,
+    broken: bool,
+    config: Config,
+    pool: Weak<IdleConnections<A, C>>,
+    timer: T,
+}
+
+impl<C, A: Eq + Hash + Clone, T: Timer> PooledConnection<C, A, T> {
+    /// Returns whether the connection was used before, and may have been closed by
+    /// the peer meanwhile.
+    pub fn is_reused(&self) -> bool {
+        self.reused
+    }
+
+    /// Returns the connection.
+    pub fn get_ref(&self) -> &C {
+        self.conn
+            .as_ref()
+            .expect("the connection is only taken on drop")
+    }
+
+    /// Returns the connection, mutably.
+    pub fn get_mut(&mut self) -> &mut C {
+        self.conn
+            .as_mut()
+            .expect("the connection is only taken on drop")
+    }
+
+    /// Returns the connection, which won't return to the pool.
+    pub fn into_inner(mut self) -> C {
+        self.conn
+            .take()
+            .expect("the connection is only taken on drop")
+    }
+
+    /// Marks the connection broken if an operation failed.
+    fn track<R>(&mut self, res: Poll<io::Result<R>>) -> Poll<io::Result<R>> {
+        if let Poll::Ready(Err(_)) = &res {
+            self.broken = true;
+        }
+        res
+    }
+}
+
+// The connection is never pinned in place.
+impl<C, A: Eq + Hash + Clone, T: Timer> Unpin for PooledConnection<C, A, T> {}
+
+impl<C, A: Eq + Hash + Clone, T: Timer> Drop for PooledConnection<C, A, T> {
+    fn drop(&mut self) {
+        let (Some(conn), Some(pool)) = (self.conn.take(), self.pool.upgrade()) else {
+            return;
+        };
+        let now = self.timer.now();
+        if self.broken || self.config.max_idle == 0 || self.config.outlived(self.created, now) {
+            return;
+        }
+        let mut connections = pool.lock().unwrap_or_else(|e| e.into_inner());
+        self.config.purge(&mut connections, now);
+        let idle = connections.entry(self.addr.clone()).or_default();
+        if idle.len() >= self.config.max_idle {
+            idle.pop_front();
+        }
+        idle.push_back(Idle {
+            conn,
+            created: self.created,
+            since: now,
+        });
+    }
+}
+
+impl<C, A, T> AsyncRead for PooledConnection<C, A, T>
+where
+    C: AsyncRead + Unpin,
+    A: Eq + Hash + Clone,
+    T: Timer,
+{
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        let this = self.get_mut();
+        let res = Pin::new(this.get_mut()).poll_read(cx, buf);
+        this.track(res)
+    }
+}
+
+impl<C, A, T> AsyncWrite for PooledConnection<C, A, T>
+where
+    C: AsyncWrite + Unpin,
+    A: Eq + Hash + Clone,
+    T: Timer,
+{
+    fn poll_write(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        let this = self.get_mut();
+        let res = Pin::new(this.get_mut()).poll_write(cx, buf);
+        this.track(res)
+    }
+
+    fn poll_write_vectored(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        bufs: &[io::IoSlice<'_>],
+    ) -> Poll<io::Result<usize>> {
+        let this = self.get_mut();
+        let res = Pin::new(this.get_mut()).poll_write_vectored(cx, bufs);
+        this.track(res)
+    }
+
+    fn is_write_vectored(&self) -> bool {
+        self.get_ref().is_write_vectored()
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        let this = self.get_mut();
+        let res = Pin::new(this.get_mut()).poll_flush(cx);
+        this.track(res)
+    }
+
+    fn poll_shutdown(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        let this = self.get_mut();
+        // A connection shut down can't be reused.
+        this.broken = true;
+        Pin::new(this.get_mut()).poll_shutdown(cx)
+    }
+}
+
+impl<C: fmt::Debug, A, T> fmt::Debug for PooledConnection<C, A, T>
+where
+    A: Eq + Hash + Clone + fmt::Debug,
+    T: Timer,
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("PooledConnection")
+            .field("conn", &self.conn)
+            .field("addr", &self.addr)
+            .field("reused", &self.reused)
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        io::Cursor,
+        sync::atomic::{AtomicU8, Ordering},
+    };
+
+    use super::*;
+
+    /// Connects to a buffer holding the number of the connection.
+    #[derive(Default)]
+    struct Connector {
+        made: AtomicU8,
+    }
+
+    impl Connector {
+        fn connect(&self) -> std::future::Ready<io::Result<Cursor<Vec<u8>>>> {
+            let id = self.made.fetch_add(1, Ordering::Relaxed);
+            std::future::ready(Ok(Cursor::new(vec![id])))
+        }
+    }
+
+    impl UnaryService<&'static str> for Connector {
+        type Response = Cursor<Vec<u8>>;
+
+        type Error = io::Error;
+
+        #[cfg(feature = "service_send")]
+        fn call(
+            &self,
+            _addr: &'static str,
+        ) -> impl Future<Output = io::Result<Cursor<Vec<u8>>>> + Send {
+            self.connect()
+        }
+
+        #[cfg(not(feature = "service_send"))]
+        fn call(&self, _addr: &'static str) -> impl Future<Output = io::Result<Cursor<Vec<u8>>>> {
+            self.connect()
+        }
+    }
+
+    /// Returns the number of a connection to `addr`, recycling it.
+    async fn id(pool: &Pooled<Connector, &'static str>, addr: &'static str) -> u8 {
+        let conn = pool.call(addr).await.unwrap();
+        conn.get_ref().get_ref()[0]
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn reuses_the_idle_connections() {
+        let pool = Pooled::new(Connector::default()).max_idle(1);
+
+        // Recycled once dropped.
+        assert_eq!(id(&pool, "a").await, 0);
+        assert_eq!(id(&pool, "a").await, 0);
+        assert_eq!(id(&pool, "b").await, 1);
+
+        let (c0, c2) = (pool.call("a").await.unwrap(), pool.call("a").await.unwrap());
+        assert!(c0.is_reused());
+        assert!(!c2.is_reused());
+        drop(c0);
+        drop(c2);
+        // Only the connection used the last is kept.
+        assert_eq!(pool.idle(&"a"), 1);
+        assert_eq!(id(&pool, "a").await, 2);
+
+        // Failed connections aren't kept.
+        let mut conn = pool.call("a").await.unwrap();
+        conn.broken = true;
+        drop(conn);
+        assert_eq!(pool.idle(&"a"), 0);
+        assert_eq!(id(&pool, "a").await, 3);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn drops_the_expired_connections() {
+        let pool = Pooled::new(Connector::default())
+            .idle_timeout(Duration::from_secs(10))
+            .max_lifetime(Duration::from_secs(25));
+
+        assert_eq!(id(&pool, "a").await, 0);
+        tokio::time::sleep(Duration::from_secs(5)).await;
+        assert_eq!(id(&pool, "a").await, 0);
+        tokio::time::sleep(Duration::from_secs(10)).await;
+        assert_eq!(id(&pool, "a").await, 1);
+
+        // Not recycled once too old.
+        let conn = pool.call("a").await.unwrap();
+        tokio::time::sleep(Duration::from_secs(25)).await;
+        drop(conn);
+        assert_eq!(pool.idle(&"a"), 0);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn drops_the_expired_connections_to_every_address() {
+        let pool = Pooled::new(Connector::default()).idle_timeout(Duration::from_secs(10));
+
+        assert_eq!(id(&pool, "a").await, 0);
+        tokio::time::sleep(Duration::from_secs(10)).await;
+        // Checking out a connection to another address drops the one to `a`.
+        let conn = pool.call("b").await.unwrap();
+        assert_eq!(pool.idle(&"a"), 0);
+
+        // And so does returning one.
+        assert_eq!(id(&pool, "c").await, 2);
+        tokio::time::sleep(Duration::from_secs(10)).await;
+        drop(conn);
+        assert_eq!(pool.idle(&"c"), 0);
+        assert_eq!(pool.idle(&"b"), 1);
+    }
+}
+
+#[cfg(all(test, loom))]
+mod loom_tests {
+    use std::io::Cursor;
+
+    use loom::sync::atomic::{AtomicU8, Ordering};
+
+    use super::*;
+
+    /// Connects to a buffer holding the number of the connection.
+    #[derive(Default)]
+    struct Connector(AtomicU8);
+
+    impl UnaryService<()> for Connector {
+        type Response = Cursor<Vec<u8>>;
+
+        type Error = io::Error;
+
+        #[cfg(feature = "service_send")]
+        fn call(&self, _addr: ()) -> impl Future<Output = io::Result<Cursor<Vec<u8>>>> + Send {
+            std::future::ready(Ok(Cursor::new(vec![self.0.fetch_add(1, Ordering::SeqCst)])))
+        }
+
+        #[cfg(not(feature = "service_send"))]
+        fn call(&self, _addr: ()) -> impl Future<Output = io::Result<Cursor<Vec<u8>>>> {
+            std::future::ready(Ok(Cursor::new(vec![self.0.fetch_add(1, Ordering::SeqCst)])))
+        }
+    }
+
+    #[test]
+    fn an_idle_connection_is_checked_out_once() {
+        loom::model(|| {
+            let pool = Arc::new(Pooled::new(Connector::default()));
+            drop(loom::future::block_on(pool.call(())).unwrap());
+
+            let other = loom::thread::spawn({
+                let pool = pool.clone();
+                move || loom::future::block_on(pool.call(())).unwrap()
+            });
+            let mine = loom::future::block_on(pool.call(())).unwrap();
+            let theirs = other.join().unwrap();
+
+            // One of the threads got the idle connection, and the other a new one.
+            let mut ids = [mine.get_ref().get_ref()[0], theirs.get_ref().get_ref()[0]];
+            ids.sort_unstable();
+            assert_eq!(ids, [0, 1]);
+            drop((mine, theirs));
+            assert_eq!(pool.idle(&()), 2);
+        });
+    }
+}
@@ -0,0 +1,406 @@
+use std::{
+    error::Error,
+    fmt,
+    sync::{Arc, Mutex, MutexGuard},
+    time::Duration,
+};
+
+use crate::{
+    backoff::{Backoff, BackoffExt, Exponential, MaxAttempts},
+    make::MakeConnection,
+    service::Service,
+    timer::{DefaultTimer, Timer},
+    utils::wait_map::{Entry, WaitMap},
+    BoxError, MaybeSend, MaybeSync,
+};
+
+/// The error returned by [`Reconnect`] when it couldn't connect.
+///
+/// It is boxed into a [`BoxError`], and can be recognized with
+/// `err.downcast_ref::<ConnectError>()`. Its [`source`](Error::source) is the error
+/// of the last attempt to connect, shared by all the calls that waited for it.
+#[derive(Clone, Debug)]
+pub struct ConnectError(Arc<BoxError>);
+
+impl fmt::Display for ConnectError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "failed to connect: {}", self.0)
+    }
+}
+
+impl Error for ConnectError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        Some(&**self.0)
+    }
+}
+
+/// A long-lived service over a connection, connecting again once the connection
+/// fails.
+///
+/// The connection is made by a [`MakeConnection`] on the first call, and turned
+/// into a service, like the client of a protocol, by `make_service`. The calls
+/// share that service until one of them fails: the service is then dropped, and
+/// the next call connects again. Failed attempts to connect are retried after the
+/// delays of a [`Backoff`], three attempts 100ms then 200ms apart by default.
+///
+/// Only one connection is made at a time: the calls arriving meanwhile wait for
+/// it, and get its outcome. Once the backoff gives up, the call connecting and all
+/// the waiting ones fail with the same [`ConnectError`], and the next call starts
+/// over.
+///
+/// # Example
+///
+/// ```rust
+/// use std::{future::Future, io::Cursor};
+///
+/// use motore::{make::Reconnect, service::service_fn, BoxError, Service, UnaryService};
+///
+/// // Connects to an in-memory buffer.
+/// struct Connector;
+///
+/// impl UnaryService<&'static str> for Connector {
+///     type Response = Cursor<Vec<u8>>;
+///     type Error = BoxError;
+///
+///     # #[cfg(feature = "service_send")]
+///     fn call(
+///         &self,
+///         _addr: &'static str,
+///     ) -> impl Future<Output = Result<Cursor<Vec<u8>>, BoxError>> + Send {
+///         async { Ok(Cursor::new(Vec::new())) }
+///     }
+///     # #[cfg(not(feature = "service_send"))]
+///     # fn call(
+///     #     &self,
+///     #     _addr: &'static str,
+///     # ) -> impl Future<Output = Result<Cursor<Vec<u8>>, BoxError>> {
+///     #     async { Ok(Cursor::new(Vec::new())) }
+///     # }
+/// }
+///
+/// # #[tokio::main(flavor = "current_thread")]
+/// # async fn main() {
+/// // A client over the connection.
+/// let client = Reconnect::new(Connector, "127.0.0.1:8080", |conn: Cursor<Vec<u8>>| {
+///     let len = conn.get_ref().len();
+///     service_fn(move |_cx: &mut (), req: String| async move {
+///         Ok::<_, BoxError>(format!("{req} over a connection of {len} bytes"))
+///     })
+/// });
+///
+/// let res = client.call(&mut (), "ping".into()).await.unwrap();
+/// assert_eq!(res, "ping over a connection of 0 bytes");
+/// assert!(client.is_connected());
+/// # }
+/// ```
+pub struct Reconnect<M, A, F, S, B = MaxAttempts<Exponential>, T = DefaultTimer> {
+    make: M,
+    addr: A,
+    make_service: F,
+    service: Mutex<Option<Arc<S>>>,
+    /// The attempt to connect in flight, whose outcome is shared by the calls
+    /// waiting for it.
+    connecting: WaitMap<(), Result<Arc<S>, ConnectError>>,
+    backoff: Mutex<B>,
+    timer: T,
+}
+
+impl<M, A, F, S> Reconnect<M, A, F, S>
+where
+    M: MakeConnection<A>,
+    F: Fn(M::Connection) -> S,
+{
+    /// Creates a `Reconnect` turning the connections made by `make` to `addr` into
+    /// services with `make_service`.
+    pub fn new(make: M, addr: A, make_service: F) -> Self {
+        Reconnect {
+            make,
+            addr,
+            make_service,
+            service: Mutex::new(None),
+            connecting: WaitMap::with_shards(1),
+            backoff: Mutex::new(
+                Exponential::new(Duration::from_millis(100), Duration::from_secs(5))
+                    .max_attempts(2),
+            ),
+            timer: DefaultTimer::new(),
+        }
+    }
+}
+
+impl<M, A, F, S, B, T> Reconnect<M, A, F, S, B, T> {
+    /// Sets the delays between the attempts to connect.
+    ///
+    /// Once the backoff gives up, the calls waiting for the connection fail with
+    /// the error of the last attempt, and the next call starts over.
+    pub fn backoff<U: Backoff>(self, backoff: U) -> Reconnect<M, A, F, S, U, T> {
+        Reconnect {
+            make: self.make,
+            addr: self.addr,
+            make_service: self.make_service,
+            service: self.service,
+            connecting: self.connecting,
+            backoff: Mutex::new(backoff),
+            timer: self.timer,
+        }
+    }
+
+    /// Sets the timer of the delays between the attempts to connect,
+    /// [`DefaultTimer`] by default.
+    pub fn timer<U>(self, timer: U) -> Reconnect<M, A, F, S, B, U> {
+        Reconnect {
+            make: self.make,
+            addr: self.addr,
+            make_service: self.make_service,
+            service: self.service,
+            connecting: self.connecting,
+            backoff: self.backoff,
+            timer,
+        }
+    }
+
+    /// Returns whether a connection is established.
+    pub fn is_connected(&self) -> bool {
+        self.service
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .is_some()
+    }
+
+    fn current(&self) -> Option<Arc<S>> {
+        self.service
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .clone()
+    }
+
+    fn backoff_state(&self) -> MutexGuard<'_, B> {
+        self.backoff.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// Drops the service after a failed call, unless it was replaced already.
+    fn disconnect(&self, svc: &Arc<S>) {
+        let mut service = self.service.lock().unwrap_or_else(|e| e.into_inner());
+        if service
+            .as_ref()
+            .is_some_and(|current| Arc::ptr_eq(current, svc))
+        {
+            *service = None;
+        }
+    }
+}
+
+impl<M, A, F, S, B, T> Reconnect<M, A, F, S, B, T>
+where
+    M: MakeConnection<A>,
+    M::Error: Into<BoxError>,
+    A: Clone,
+    F: Fn(M::Connection) -> S,
+    B: Backoff,
+    T: Timer,
+{
+    /// Returns the service of the current connection, connecting first if there is
+    /// none, or waiting for the call connecting already.
+    async fn connect(&self) -> Result<Arc<S>, ConnectError> {
+        loop {
+            if let Some(svc) = self.current() {
+                return Ok(svc);
+            }
+            match self.connecting.enter(&()) {
+                Entry::Wait(waiter) => {
+                    // Entered again if the call connecting was cancelled.
+                    if let Some(res) = waiter.wait().await {
+                        return res;
+                    }
+                }
+                Entry::Lead(flight) => {
+                    // Connected by another call since the service was checked.
+                    if let Some(svc) = self.current() {
+                        return Ok(svc);
+                    }
+                    let res = self.make_service().await;
+                    flight.complete(res.clone());
+                    return res;
+                }
+            }
+        }
+    }
+
+    async fn make_service(&self) -> Result<Arc<S>, ConnectError> {
+        self.backoff_state().reset();
+        loop {
+            let delay = match self.make.make_connection(self.addr.clone()).await {
+                Ok(conn) => {
+                    let svc = Arc::new((self.make_service)(conn));
+                    *self.service.lock().unwrap_or_else(|e| e.into_inner()) = Some(svc.clone());
+                    return Ok(svc);
+                }
+                Err(e) => match self.backoff_state().next_backoff() {
+                    Some(delay) => delay,
+                    None => return Err(ConnectError(Arc::new(e.into()))),
+                },
+            };
+            self.timer.sleep(delay).await;
+        }
+    }
+}
+
+impl<Cx, Req, M, A, F, S, B, T> Service<Cx, Req> for Reconnect<M, A, F, S, B, T>
+where
+    Req: 'static + MaybeSend,
+    M: MakeConnection<A> + MaybeSync,
+    M::Error: Into<BoxError>,
+    A: Clone + MaybeSend + MaybeSync,
+    F: Fn(M::Connection) -> S + MaybeSync,
+    S: Service<Cx, Req> + 'static + MaybeSend + MaybeSync,
+    S::Error: Into<BoxError>,
+    B: Backoff + MaybeSend,
+    T: Timer + MaybeSync,
+    Cx: 'static + MaybeSend,
+{
+    type Response = S::Response;
+
+    type Error = BoxError;
+
+    async fn call(&self, cx: &mut Cx, req: Req) -> Result<Self::Response, Self::Error> {
+        let svc = self.connect().await?;
+        let res = svc.call(cx, req).await;
+        if res.is_err() {
+            self.disconnect(&svc);
+        }
+        res.map_err(Into::into)
+    }
+}
+
+impl<M, A: fmt::Debug, F, S, B, T> fmt::Debug for Reconnect<M, A, F, S, B, T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Reconnect")
+            .field("addr", &self.addr)
+            .field("connected", &self.is_connected())
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        future::Future,
+        io::{self, Cursor},
+        sync::atomic::{AtomicBool, AtomicUsize, Ordering},
+    };
+
+    use super::*;
+    use crate::{service::service_fn, UnaryService};
+
+    /// Connects once `attempts` attempts failed, counting the attempts.
+    struct Connector {
+        attempts: usize,
+        made: AtomicUsize,
+    }
+
+    impl Connector {
+        fn new(attempts: usize) -> Self {
+            Connector {
+                attempts,
+                made: AtomicUsize::new(0),
+            }
+        }
+
+        fn connect(&self) -> std::future::Ready<io::Result<Cursor<Vec<u8>>>> {
+            let attempt = self.made.fetch_add(1, Ordering::Relaxed);
+            std::future::ready(if attempt < self.attempts {
+                Err(io::ErrorKind::ConnectionRefused.into())
+            } else {
+                // The connection carries the number of the attempt.
+                Ok(Cursor::new(vec![attempt as u8]))
+            })
+        }
+    }
+
+    impl UnaryService<()> for Connector {
+        type Response = Cursor<Vec<u8>>;
+
+        type Error = io::Error;
+
+        #[cfg(feature = "service_send")]
+        fn call(&self, _addr: ()) -> impl Future<Output = io::Result<Cursor<Vec<u8>>>> + Send {
+            self.connect()
+        }
+
+        #[cfg(not(feature = "service_send"))]
+        fn call(&self, _addr: ()) -> impl Future<Output = io::Result<Cursor<Vec<u8>>>> {
+            self.connect()
+        }
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn reconnects_after_a_failure() {
+        let broken = Arc::new(AtomicBool::new(false));
+        let client = Reconnect::new(Connector::new(2), (), |conn: Cursor<Vec<u8>>| {
+            let broken = broken.clone();
+            service_fn(move |_cx: &mut (), _req: ()| {
+                let res = if broken.swap(false, Ordering::Relaxed) {
+                    Err("broken pipe")
+                } else {
+                    Ok(conn.get_ref()[0])
+                };
+                async move { res }
+            })
+        })
+        .backoff(Exponential::new(
+            Duration::from_secs(1),
+            Duration::from_secs(10),
+        ));
+        assert!(!client.is_connected());
+
+        let start = tokio::time::Instant::now();
+        assert_eq!(client.call(&mut (), ()).await.unwrap(), 2);
+        assert_eq!(start.elapsed(), Duration::from_secs(3));
+        assert_eq!(client.call(&mut (), ()).await.unwrap(), 2);
+
+        broken.store(true, Ordering::Relaxed);
+        assert!(client.call(&mut (), ()).await.is_err());
+        assert!(!client.is_connected());
+        assert_eq!(client.call(&mut (), ()).await.unwrap(), 3);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn gives_up_connecting() {
+        let client = Reconnect::new(Connector::new(usize::MAX), (), |_conn: Cursor<Vec<u8>>| {
+            service_fn(|_cx: &mut (), _req: ()| async { Ok::<_, io::Error>(()) })
+        });
+
+        let start = tokio::time::Instant::now();
+        let err = client.call(&mut (), ()).await.unwrap_err();
+        let err = err
+            .downcast_ref::<ConnectError>()
+            .unwrap()
+            .source()
+            .unwrap();
+        assert_eq!(
+            err.downcast_ref::<io::Error>().unwrap().kind(),
+            io::ErrorKind::ConnectionRefused
+        );
+        assert_eq!(start.elapsed(), Duration::from_millis(300));
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn concurrent_calls_share_the_attempt_to_connect() {
+        let client = Reconnect::new(Connector::new(usize::MAX), (), |_conn: Cursor<Vec<u8>>| {
+            service_fn(|_cx: &mut (), _req: ()| async { Ok::<_, io::Error>(()) })
+        });
+
+        let start = tokio::time::Instant::now();
+        let calls = (0..3).map(|_| async { client.call(&mut (), ()).await });
+        let results = futures::future::join_all(calls).await;
+        assert_eq!(start.elapsed(), Duration::from_millis(300));
+        assert_eq!(client.make.made.load(Ordering::Relaxed), 3);
+        for res in results {
+            assert!(res.unwrap_err().is::<ConnectError>());
+        }
+
+        // The next call starts over.
+        assert!(client.call(&mut (), ()).await.is_err());
+        assert_eq!(client.make.made.load(Ordering::Relaxed), 6);
+    }
+}
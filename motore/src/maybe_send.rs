@@ -0,0 +1,105 @@
+//! Bounds following the `service_send` feature.
+//!
+//! The middlewares need their services, contexts and requests to be [`Send`] and
+//! [`Sync`] for the futures they return to be [`Send`], but only when the
+//! `service_send` feature is enabled. [`MaybeSend`] and [`MaybeSync`] are
+//! [`Send`] and [`Sync`] with the feature, and implemented by every type without
+//! it, so a single implementation serves both modes, including on thread-per-core
+//! runtimes where nothing is `Send`.
+
+/// [`Send`] when the `service_send` feature is enabled, implemented by every type
+/// otherwise.
+#[cfg(feature = "service_send")]
+pub trait MaybeSend: Send {}
+
+#[cfg(feature = "service_send")]
+impl<T: Send + ?Sized> MaybeSend for T {}
+
+/// [`Send`] when the `service_send` feature is enabled, implemented by every type
+/// otherwise.
+#[cfg(not(feature = "service_send"))]
+pub trait MaybeSend {}
+
+#[cfg(not(feature = "service_send"))]
+impl<T: ?Sized> MaybeSend for T {}
+
+/// [`Sync`] when the `service_send` feature is enabled, implemented by every type
+/// otherwise.
+#[cfg(feature = "service_send")]
+pub trait MaybeSync: Sync {}
+
+#[cfg(feature = "service_send")]
+impl<T: Sync + ?Sized> MaybeSync for T {}
+
+/// [`Sync`] when the `service_send` feature is enabled, implemented by every type
+/// otherwise.
+#[cfg(not(feature = "service_send"))]
+pub trait MaybeSync {}
+
+#[cfg(not(feature = "service_send"))]
+impl<T: ?Sized> MaybeSync for T {}
+
+#[cfg(all(test, not(feature = "service_send")))]
+mod tests {
+    use std::{cell::Cell, rc::Rc, time::Duration};
+
+    use crate::{
+        builder::ServiceBuilder, context::Extensions, limit::ConcurrencyLimitLayer, BoxError,
+        BoxService, Service, ServiceExt,
+    };
+
+    /// Counts its calls in an `Rc`, so it is neither `Send` nor `Sync`.
+    struct Counter(Rc<Cell<u32>>);
+
+    impl Service<(), ()> for Counter {
+        type Response = u32;
+        type Error = BoxError;
+
+        async fn call(&self, _cx: &mut (), _req: ()) -> Result<u32, BoxError> {
+            tokio::task::yield_now().await;
+            self.0.set(self.0.get() + 1);
+            Ok(self.0.get())
+        }
+    }
+
+    #[tokio::test]
+    async fn middlewares_accept_services_neither_send_nor_sync() {
+        let count = Rc::new(Cell::new(0));
+        let errors = Rc::new(Cell::new(0));
+        let svc = ServiceBuilder::new()
+            .timeout(Some(Duration::from_secs(1)))
+            .layer(ConcurrencyLimitLayer::new(1))
+            .map_err({
+                let errors = errors.clone();
+                move |e: BoxError| {
+                    errors.set(errors.get() + 1);
+                    e
+                }
+            })
+            .service(Counter(count.clone()));
+        let seen = Rc::new(Cell::new(0));
+        let svc = svc
+            .inspect_ok({
+                let seen = seen.clone();
+                move |n: &u32| seen.set(*n)
+            })
+            .map_response({
+                let count = count.clone();
+                move |n| n + count.get()
+            })
+            .map_both(move |n| n, move |e: BoxError| (e, errors.get()));
+        let svc = BoxService::new(svc);
+
+        assert_eq!(svc.call(&mut (), ()).await.unwrap(), 2);
+        assert_eq!(svc.call(&mut (), ()).await.unwrap(), 4);
+        assert_eq!(count.get(), 2);
+        assert_eq!(seen.get(), 2);
+    }
+
+    #[test]
+    fn extensions_hold_values_neither_send_nor_sync() {
+        let mut extensions = Extensions::new();
+        extensions.insert(Rc::new(1));
+        assert_eq!(extensions.get::<Rc<i32>>().map(|n| **n), Some(1));
+    }
+}
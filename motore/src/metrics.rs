@@ -0,0 +1,322 @@
+//! Reports the number, the failures and the latency of the calls of a service.
+//!
+//! [`Metrics`] reports every call to a [`MetricsSink`], which forwards it to a
+//! metrics backend, like Prometheus, `metrics` or OpenTelemetry, by implementing
+//! the trait. The calls are reported with labels made from their context, like the
+//! method or the peer, so the backend can break the metrics down by label.
+//!
+//! A call is counted as a request when it starts. Once it completes, its latency
+//! is recorded and, when it failed, it is counted as an error. A call cancelled
+//! before completing is only counted as a request.
+
+use std::{
+    fmt,
+    sync::Arc,
+    task::{Context, Poll},
+    time::Duration,
+};
+
+use crate::{
+    layer::Layer,
+    load::{Load, Ready},
+    service::Service,
+    timer::{DefaultTimer, Timer},
+    MaybeSend, MaybeSync,
+};
+
+/// Receives the metrics of the calls made through a [`Metrics`], labeled with `L`.
+///
+/// The methods are called on the path of every request, so they should only update
+/// some counters in memory, rather than wait on the backend.
+///
+/// # Example
+///
+/// ```rust
+/// use std::{
+///     collections::HashMap,
+///     sync::Mutex,
+///     time::Duration,
+/// };
+///
+/// use motore::metrics::MetricsSink;
+///
+/// #[derive(Default)]
+/// struct Counts {
+///     /// The requests and the errors of each method.
+///     methods: Mutex<HashMap<&'static str, (u64, u64)>>,
+/// }
+///
+/// impl MetricsSink<&'static str> for Counts {
+///     fn increment_requests(&self, method: &&'static str) {
+///         self.methods.lock().unwrap().entry(method).or_default().0 += 1;
+///     }
+///
+///     fn increment_errors(&self, method: &&'static str) {
+///         self.methods.lock().unwrap().entry(method).or_default().1 += 1;
+///     }
+///
+///     fn record_latency(&self, _method: &&'static str, _latency: Duration) {}
+/// }
+/// ```
+pub trait MetricsSink<L> {
+    /// Counts a call starting.
+    fn increment_requests(&self, labels: &L);
+
+    /// Counts a call failing.
+    fn increment_errors(&self, labels: &L);
+
+    /// Records the latency of a completed call, whether it succeeded or failed.
+    fn record_latency(&self, labels: &L, latency: Duration);
+}
+
+impl<L, T> MetricsSink<L> for Arc<T>
+where
+    T: MetricsSink<L> + ?Sized,
+{
+    fn increment_requests(&self, labels: &L) {
+        (**self).increment_requests(labels)
+    }
+
+    fn increment_errors(&self, labels: &L) {
+        (**self).increment_errors(labels)
+    }
+
+    fn record_latency(&self, labels: &L, latency: Duration) {
+        (**self).record_latency(labels, latency)
+    }
+}
+
+/// Reports the calls to the inner service to a [`MetricsSink`].
+///
+/// The labels of a call are given by a closure taking its context,
+/// `Fn(&Cx) -> L`, called before the call. See the [module level docs](self) for
+/// details.
+#[derive(Clone)]
+pub struct Metrics<S, Si, F> {
+    inner: S,
+    sink: Si,
+    labels: F,
+}
+
+impl<S, Si, F> Metrics<S, Si, F> {
+    /// Creates a `Metrics` reporting the calls to `sink`, labeled by `labels`.
+    pub const fn new(inner: S, sink: Si, labels: F) -> Self {
+        Metrics {
+            inner,
+            sink,
+            labels,
+        }
+    }
+
+    /// Returns the sink the calls are reported to.
+    pub fn sink(&self) -> &Si {
+        &self.sink
+    }
+}
+
+impl<Cx, Req, S, Si, F, L> Service<Cx, Req> for Metrics<S, Si, F>
+where
+    Req: 'static + MaybeSend,
+    S: Service<Cx, Req> + 'static + MaybeSend + MaybeSync,
+    Si: MetricsSink<L> + MaybeSync,
+    F: Fn(&Cx) -> L + MaybeSync,
+    L: MaybeSend,
+    Cx: 'static + MaybeSend,
+{
+    type Response = S::Response;
+
+    type Error = S::Error;
+
+    async fn call(&self, cx: &mut Cx, req: Req) -> Result<Self::Response, Self::Error> {
+        let labels = (self.labels)(cx);
+        self.sink.increment_requests(&labels);
+        let timer = DefaultTimer::new();
+        let start = timer.now();
+        let res = self.inner.call(cx, req).await;
+        self.sink
+            .record_latency(&labels, timer.now().saturating_duration_since(start));
+        if res.is_err() {
+            self.sink.increment_errors(&labels);
+        }
+        res
+    }
+}
+
+impl<S, Si, F> Ready for Metrics<S, Si, F>
+where
+    S: Ready,
+{
+    fn poll_ready(&self, cx: &mut Context<'_>) -> Poll<()> {
+        self.inner.poll_ready(cx)
+    }
+}
+
+impl<S, Si, F> Load for Metrics<S, Si, F>
+where
+    S: Load,
+{
+    type Metric = S::Metric;
+
+    fn load(&self) -> Self::Metric {
+        self.inner.load()
+    }
+}
+
+impl<S: fmt::Debug, Si: fmt::Debug, F> fmt::Debug for Metrics<S, Si, F> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Metrics")
+            .field("inner", &self.inner)
+            .field("sink", &self.sink)
+            .field("labels", &format_args!("{}", std::any::type_name::<F>()))
+            .finish()
+    }
+}
+
+/// Applies a [`Metrics`] to a service.
+///
+/// The sink is cloned for every service made by the layer, so a sink shared by
+/// several services is usually given as an [`Arc`].
+///
+/// # Example
+///
+/// ```rust
+/// use std::{
+///     sync::{
+///         atomic::{AtomicU64, Ordering},
+///         Arc,
+///     },
+///     time::Duration,
+/// };
+///
+/// use motore::{
+///     builder::ServiceBuilder,
+///     metrics::{MetricsLayer, MetricsSink},
+///     service::service_fn,
+///     BoxError, Service,
+/// };
+///
+/// #[derive(Default)]
+/// struct Errors(AtomicU64);
+///
+/// impl<L> MetricsSink<L> for Errors {
+///     fn increment_requests(&self, _labels: &L) {}
+///
+///     fn increment_errors(&self, _labels: &L) {
+///         self.0.fetch_add(1, Ordering::Relaxed);
+///     }
+///
+///     fn record_latency(&self, _labels: &L, _latency: Duration) {}
+/// }
+///
+/// # #[tokio::main(flavor = "current_thread")]
+/// # async fn main() {
+/// async fn parse(_cx: &mut (), req: String) -> Result<u32, BoxError> {
+///     Ok(req.parse()?)
+/// }
+///
+/// let errors = Arc::new(Errors::default());
+/// let svc = ServiceBuilder::new()
+///     .layer(MetricsLayer::new(errors.clone(), |_cx: &()| ()))
+///     .service(service_fn(parse));
+///
+/// assert!(svc.call(&mut (), "x".into()).await.is_err());
+/// assert_eq!(errors.0.load(Ordering::Relaxed), 1);
+/// # }
+/// ```
+#[derive(Clone)]
+pub struct MetricsLayer<Si, F> {
+    sink: Si,
+    labels: F,
+}
+
+impl<Si, F> MetricsLayer<Si, F> {
+    /// Creates a layer reporting the calls to `sink`, labeled by `labels`.
+    pub const fn new(sink: Si, labels: F) -> Self {
+        MetricsLayer { sink, labels }
+    }
+}
+
+impl<Si: fmt::Debug, F> fmt::Debug for MetricsLayer<Si, F> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("MetricsLayer")
+            .field("sink", &self.sink)
+            .field("labels", &format_args!("{}", std::any::type_name::<F>()))
+            .finish()
+    }
+}
+
+impl<S, Si, F> Layer<S> for MetricsLayer<Si, F> {
+    type Service = Metrics<S, Si, F>;
+
+    fn layer(self, inner: S) -> Self::Service {
+        Metrics::new(inner, self.sink, self.labels)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{collections::HashMap, sync::Mutex};
+
+    use super::*;
+    use crate::service::service_fn;
+
+    #[derive(Debug, Default, PartialEq)]
+    struct Counts {
+        requests: u64,
+        errors: u64,
+        latency: Duration,
+    }
+
+    #[derive(Default)]
+    struct Recorder(Mutex<HashMap<&'static str, Counts>>);
+
+    impl MetricsSink<&'static str> for Recorder {
+        fn increment_requests(&self, labels: &&'static str) {
+            self.0.lock().unwrap().entry(labels).or_default().requests += 1;
+        }
+
+        fn increment_errors(&self, labels: &&'static str) {
+            self.0.lock().unwrap().entry(labels).or_default().errors += 1;
+        }
+
+        fn record_latency(&self, labels: &&'static str, latency: Duration) {
+            self.0.lock().unwrap().entry(labels).or_default().latency += latency;
+        }
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn reports_the_calls_by_label() {
+        let svc = MetricsLayer::new(Recorder::default(), |cx: &&'static str| *cx).layer(
+            service_fn(|_cx: &mut &'static str, millis: u64| async move {
+                tokio::time::sleep(Duration::from_millis(millis)).await;
+                if millis == 0 {
+                    return Err("instant failure");
+                }
+                Ok(millis)
+            }),
+        );
+
+        assert_eq!(svc.call(&mut "get", 10).await, Ok(10));
+        assert_eq!(svc.call(&mut "get", 20).await, Ok(20));
+        assert!(svc.call(&mut "get", 0).await.is_err());
+        assert_eq!(svc.call(&mut "put", 5).await, Ok(5));
+
+        let counts = svc.sink().0.lock().unwrap();
+        assert_eq!(
+            counts["get"],
+            Counts {
+                requests: 3,
+                errors: 1,
+                latency: Duration::from_millis(30),
+            }
+        );
+        assert_eq!(
+            counts["put"],
+            Counts {
+                requests: 1,
+                errors: 0,
+                latency: Duration::from_millis(5),
+            }
+        );
+    }
+}
@@ -0,0 +1,252 @@
+//! Copies a share of the traffic to a shadow service.
+//!
+//! [`Mirror`] calls the primary service as usual, and additionally sends a copy of
+//! a configurable fraction of the requests to a shadow service, on a task of its
+//! own. The shadow's responses and errors are discarded, and the caller never waits
+//! for it, so a new backend can be tried with production traffic without the
+//! callers noticing anything.
+
+use std::{
+    fmt,
+    sync::{Arc, Mutex},
+    task::{Context, Poll},
+};
+
+use crate::{
+    layer::Layer,
+    load::{Load, Ready},
+    service::Service,
+    utils::rng::Rng,
+    MaybeSend, MaybeSync,
+};
+
+/// Sends a copy of a fraction of the requests to a shadow service, discarding its
+/// responses.
+///
+/// Both the request and the context are cloned for the shadow call. See the
+/// [module level docs](self) for details.
+pub struct Mirror<S, M> {
+    inner: S,
+    shadow: Arc<M>,
+    ratio: f64,
+    rng: Arc<Mutex<Rng>>,
+}
+
+impl<S, M> Mirror<S, M> {
+    /// Creates a mirror sending a copy of `ratio`, between 0 and 1, of the requests
+    /// to `shadow`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `ratio` is not between 0 and 1.
+    pub fn new(inner: S, shadow: M, ratio: f64) -> Self {
+        MirrorLayer::new(shadow, ratio).layer(inner)
+    }
+
+    fn sample(&self) -> bool {
+        self.ratio > 0.0
+            && self
+                .rng
+                .lock()
+                .unwrap_or_else(|e| e.into_inner())
+                .chance(self.ratio)
+    }
+}
+
+impl<Cx, Req, S, M> Service<Cx, Req> for Mirror<S, M>
+where
+    Req: Clone + 'static + MaybeSend,
+    S: Service<Cx, Req> + 'static + MaybeSend + MaybeSync,
+    M: Service<Cx, Req> + 'static + MaybeSend + MaybeSync,
+    Cx: Clone + 'static + MaybeSend,
+{
+    type Response = S::Response;
+
+    type Error = S::Error;
+
+    async fn call(&self, cx: &mut Cx, req: Req) -> Result<Self::Response, Self::Error> {
+        if self.sample() {
+            let shadow = self.shadow.clone();
+            let mut shadow_cx = cx.clone();
+            let shadow_req = req.clone();
+            let task = async move {
+                let _ = shadow.call(&mut shadow_cx, shadow_req).await;
+            };
+            #[cfg(feature = "service_send")]
+            tokio::spawn(task);
+            #[cfg(not(feature = "service_send"))]
+            tokio::task::spawn_local(task);
+        }
+        self.inner.call(cx, req).await
+    }
+}
+
+impl<S, M> Ready for Mirror<S, M>
+where
+    S: Ready,
+{
+    fn poll_ready(&self, cx: &mut Context<'_>) -> Poll<()> {
+        self.inner.poll_ready(cx)
+    }
+}
+
+impl<S, M> Load for Mirror<S, M>
+where
+    S: Load,
+{
+    type Metric = S::Metric;
+
+    fn load(&self) -> Self::Metric {
+        self.inner.load()
+    }
+}
+
+impl<S: Clone, M> Clone for Mirror<S, M> {
+    fn clone(&self) -> Self {
+        Mirror {
+            inner: self.inner.clone(),
+            shadow: self.shadow.clone(),
+            ratio: self.ratio,
+            rng: self.rng.clone(),
+        }
+    }
+}
+
+impl<S: fmt::Debug, M: fmt::Debug> fmt::Debug for Mirror<S, M> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Mirror")
+            .field("inner", &self.inner)
+            .field("shadow", &self.shadow)
+            .field("ratio", &self.ratio)
+            .finish()
+    }
+}
+
+/// Applies a [`Mirror`] to a service.
+///
+/// All the services made by the layer send their copies to the same shadow.
+///
+/// # Example
+///
+/// ```rust
+/// use motore::{
+///     builder::ServiceBuilder, mirror::MirrorLayer, service::service_fn, BoxError, Service,
+/// };
+///
+/// # #[tokio::main(flavor = "current_thread")]
+/// # async fn main() {
+/// # tokio::task::LocalSet::new().run_until(async {
+/// async fn current(_cx: &mut (), req: String) -> Result<String, BoxError> {
+///     Ok(req)
+/// }
+///
+/// async fn candidate(_cx: &mut (), req: String) -> Result<String, BoxError> {
+///     Ok(req.to_uppercase())
+/// }
+///
+/// // Tries the candidate backend with 10% of the traffic.
+/// let svc = ServiceBuilder::new()
+///     .layer(MirrorLayer::new(service_fn(candidate), 0.1))
+///     .service(service_fn(current));
+///
+/// assert_eq!(svc.call(&mut (), "ping".into()).await.unwrap(), "ping");
+/// # }).await;
+/// # }
+/// ```
+pub struct MirrorLayer<M> {
+    shadow: Arc<M>,
+    ratio: f64,
+}
+
+impl<M> MirrorLayer<M> {
+    /// Creates a layer sending a copy of `ratio`, between 0 and 1, of the requests
+    /// to `shadow`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `ratio` is not between 0 and 1.
+    pub fn new(shadow: M, ratio: f64) -> Self {
+        assert!(
+            (0.0..=1.0).contains(&ratio),
+            "the ratio must be between 0 and 1"
+        );
+        MirrorLayer {
+            shadow: Arc::new(shadow),
+            ratio,
+        }
+    }
+}
+
+impl<M> Clone for MirrorLayer<M> {
+    fn clone(&self) -> Self {
+        MirrorLayer {
+            shadow: self.shadow.clone(),
+            ratio: self.ratio,
+        }
+    }
+}
+
+impl<M: fmt::Debug> fmt::Debug for MirrorLayer<M> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("MirrorLayer")
+            .field("shadow", &self.shadow)
+            .field("ratio", &self.ratio)
+            .finish()
+    }
+}
+
+impl<S, M> Layer<S> for MirrorLayer<M> {
+    type Service = Mirror<S, M>;
+
+    fn layer(self, inner: S) -> Self::Service {
+        Mirror {
+            inner,
+            shadow: self.shadow,
+            ratio: self.ratio,
+            rng: Arc::new(Mutex::new(Rng::new())),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    use super::*;
+    use crate::service::service_fn;
+
+    async fn echo(_cx: &mut (), req: u32) -> Result<u32, &'static str> {
+        Ok(req)
+    }
+
+    #[tokio::test]
+    async fn copies_a_share_of_the_requests() {
+        tokio::task::LocalSet::new()
+            .run_until(async {
+                let mirrored = Arc::new(AtomicUsize::new(0));
+                let shadow = service_fn({
+                    let mirrored = mirrored.clone();
+                    move |_cx: &mut (), _req: u32| {
+                        mirrored.fetch_add(1, Ordering::SeqCst);
+                        async { Err::<u32, _>("the shadow's errors are discarded") }
+                    }
+                });
+
+                let all = MirrorLayer::new(shadow, 1.0).layer(service_fn(echo));
+                for i in 0..10 {
+                    assert_eq!(all.call(&mut (), i).await, Ok(i));
+                }
+                tokio::task::yield_now().await;
+                assert_eq!(mirrored.load(Ordering::SeqCst), 10);
+
+                let mut none = all.clone();
+                none.ratio = 0.0;
+                for i in 0..10 {
+                    assert_eq!(none.call(&mut (), i).await, Ok(i));
+                }
+                tokio::task::yield_now().await;
+                assert_eq!(mirrored.load(Ordering::SeqCst), 10);
+            })
+            .await;
+    }
+}
@@ -0,0 +1,221 @@
+//! Reusable contract checks for middlewares.
+//!
+//! The [`Service`] trait comes with expectations that the type system cannot
+//! enforce. The functions of this module build a middleware around a
+//! [`Mock`] inner service through the given constructor and check that:
+//!
+//! - successful responses of the inner service reach the caller;
+//! - errors of the inner service are passed through, i.e. a [`MockError`] can be
+//!   downcast from the error returned to the caller;
+//! - polling a pending call again does not issue the inner request again;
+//! - dropping a call drops the inner call, and the middleware keeps working afterwards
+//!   (no permit or slot is leaked);
+//! - with [`check_clone`], clones of the middleware can be called and cancelled
+//!   independently of each other.
+//!
+//! Every check panics with a description of the violated property.
+//!
+//! # Example
+//!
+//! ```rust
+//! use std::time::Duration;
+//!
+//! use motore::{mock::conformance, timeout::Timeout};
+//!
+//! # #[tokio::main(flavor = "current_thread")]
+//! # async fn main() {
+//! conformance::check_clone::<(), _, _>(|inner| Timeout::new(inner, Some(Duration::from_secs(1)))).await;
+//! # }
+//! ```
+
+use std::{future::Future, pin::Pin, task::Poll};
+
+use super::{pair, poll_once, Handle, Mock, MockError, SendResponse};
+use crate::{BoxError, Service};
+
+type MockService<Cx> = Mock<Cx, u64, u64, MockError>;
+
+/// Checks the contract of the middleware built by `make`.
+pub async fn check<Cx, S, F>(make: F)
+where
+    Cx: Default,
+    F: Fn(MockService<Cx>) -> S,
+    S: Service<Cx, u64>,
+    S::Error: Into<BoxError>,
+{
+    let (inner, mut handle) = pair();
+    let svc = make(inner);
+
+    check_ok(&svc, &mut handle).await;
+    check_err(&svc, &mut handle).await;
+    check_repoll(&svc, &mut handle).await;
+    check_cancel(&svc, &mut handle).await;
+    check_ok(&svc, &mut handle).await;
+}
+
+/// Checks the contract of the middleware built by `make`, including that its
+/// clones are independent of each other.
+pub async fn check_clone<Cx, S, F>(make: F)
+where
+    Cx: Default,
+    F: Fn(MockService<Cx>) -> S,
+    S: Service<Cx, u64> + Clone,
+    S::Error: Into<BoxError>,
+{
+    check(&make).await;
+
+    let (inner, mut handle) = pair();
+    let svc = make(inner);
+    let clone = svc.clone();
+
+    // both clones can have calls in-flight at the same time
+    let mut a = call(&svc, 1);
+    let mut b = call(&clone, 2);
+    let tx_a = forwarded(&mut a, &mut handle, "the original").await;
+    let tx_b = forwarded(&mut b, &mut handle, "the clone").await;
+
+    // cancelling a call through the clone does not affect the original
+    drop(b);
+    assert!(
+        tx_b.is_canceled(),
+        "dropping a call on a clone of the middleware did not drop the inner call"
+    );
+    assert!(
+        !tx_a.is_canceled(),
+        "dropping a call on a clone of the middleware dropped a call of the original"
+    );
+    tx_a.send_response(1);
+    let res = complete(&mut a).await;
+    assert!(
+        res.is_ok(),
+        "the original middleware failed after a call on its clone was dropped"
+    );
+
+    // dropping the original does not break the clone
+    drop(a);
+    drop(svc);
+    check_ok(&clone, &mut handle).await;
+}
+
+type Call<'a, T, E> = Pin<Box<dyn Future<Output = Result<T, E>> + 'a>>;
+
+fn call<Cx, S>(svc: &S, req: u64) -> Call<'_, S::Response, S::Error>
+where
+    Cx: Default,
+    S: Service<Cx, u64>,
+{
+    Box::pin(async move {
+        let mut cx = Cx::default();
+        svc.call(&mut cx, req).await
+    })
+}
+
+/// Polls `call` until its inner request reaches the mock.
+async fn forwarded<T, E>(
+    call: &mut Call<'_, T, E>,
+    handle: &mut Handle<u64, u64, MockError>,
+    what: &str,
+) -> SendResponse<u64, MockError> {
+    for _ in 0..64 {
+        assert!(
+            poll_once(call.as_mut()).is_pending(),
+            "{what} middleware completed a call without waiting for the inner service"
+        );
+        if let Some((_, tx)) = handle.try_next_request() {
+            return tx;
+        }
+        tokio::task::yield_now().await;
+    }
+    panic!("{what} middleware never called the inner service");
+}
+
+async fn complete<T, E>(call: &mut Call<'_, T, E>) -> Result<T, E> {
+    for _ in 0..64 {
+        if let Poll::Ready(res) = poll_once(call.as_mut()) {
+            return res;
+        }
+        tokio::task::yield_now().await;
+    }
+    panic!("the middleware never completed a call whose inner call has completed");
+}
+
+async fn check_ok<Cx, S>(svc: &S, handle: &mut Handle<u64, u64, MockError>)
+where
+    Cx: Default,
+    S: Service<Cx, u64>,
+{
+    let mut c = call(svc, 1);
+    forwarded(&mut c, handle, "the").await.send_response(1);
+    assert!(
+        complete(&mut c).await.is_ok(),
+        "the middleware failed a call whose inner call succeeded"
+    );
+}
+
+async fn check_err<Cx, S>(svc: &S, handle: &mut Handle<u64, u64, MockError>)
+where
+    Cx: Default,
+    S: Service<Cx, u64>,
+    S::Error: Into<BoxError>,
+{
+    let mut c = call(svc, 2);
+    forwarded(&mut c, handle, "the").await.send_error(MockError);
+    match complete(&mut c).await {
+        Ok(_) => panic!("the middleware succeeded a call whose inner call failed"),
+        Err(err) => assert!(
+            err.into().downcast_ref::<MockError>().is_some(),
+            "the middleware did not pass the error of the inner service through"
+        ),
+    }
+}
+
+async fn check_repoll<Cx, S>(svc: &S, handle: &mut Handle<u64, u64, MockError>)
+where
+    Cx: Default,
+    S: Service<Cx, u64>,
+{
+    let mut c = call(svc, 3);
+    let tx = forwarded(&mut c, handle, "the").await;
+    for _ in 0..8 {
+        assert!(poll_once(c.as_mut()).is_pending());
+    }
+    assert!(
+        handle.try_next_request().is_none(),
+        "polling a pending call again issued the inner request again"
+    );
+    tx.send_response(3);
+    assert!(complete(&mut c).await.is_ok());
+}
+
+async fn check_cancel<Cx, S>(svc: &S, handle: &mut Handle<u64, u64, MockError>)
+where
+    Cx: Default,
+    S: Service<Cx, u64>,
+{
+    let mut c = call(svc, 4);
+    let tx = forwarded(&mut c, handle, "the").await;
+    drop(c);
+    assert!(
+        tx.is_canceled(),
+        "dropping a call did not drop the call to the inner service"
+    );
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{layer::Layer, service::ServiceExt};
+
+    #[tokio::test]
+    async fn passthrough_conforms() {
+        check_clone::<(), _, _>(|inner| inner).await;
+        check_clone::<(), _, _>(|inner| crate::layer::Identity::new().layer(inner)).await;
+        check::<(), _, _>(|inner| inner.map_response(|res| res + 1)).await;
+    }
+
+    #[tokio::test]
+    #[should_panic(expected = "did not pass the error")]
+    async fn detects_swallowed_errors() {
+        check::<(), _, _>(|inner| inner.map_err(|_| std::io::Error::other("swallowed"))).await;
+    }
+}
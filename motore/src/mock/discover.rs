@@ -0,0 +1,356 @@
+use std::{
+    collections::HashMap,
+    fmt,
+    future::Future,
+    hash::Hash,
+    pin::Pin,
+    sync::{
+        atomic::{AtomicBool, AtomicUsize, Ordering},
+        Arc, Mutex,
+    },
+    task::{Context, Poll},
+};
+
+use futures::Stream;
+use tokio::sync::mpsc;
+
+use super::MockError;
+use crate::{
+    discover::Change,
+    load::{Load, Ready},
+    utils::waiters::Waiters,
+    MaybeSend, Service,
+};
+
+/// Creates a new mock [`Discover`] and the [`DiscoverHandle`] pushing its changes.
+///
+/// # Example
+///
+/// ```rust
+/// use std::convert::Infallible;
+///
+/// use motore::{balance::round_robin::Balance, mock, Service};
+///
+/// # #[tokio::main(flavor = "current_thread")]
+/// # async fn main() {
+/// let (discover, handle) = mock::discover::<_, _, Infallible>();
+/// let endpoints = mock::ScriptedEndpoints::new();
+/// handle.insert("a", endpoints.endpoint(&"a"));
+/// handle.insert("b", endpoints.endpoint(&"b"));
+/// let svc = Balance::new(discover);
+///
+/// // `b` stops taking requests.
+/// endpoints.set_ready(&"b", false);
+/// for _ in 0..4 {
+///     assert_eq!(svc.call(&mut (), ()).await.unwrap(), "a");
+/// }
+///
+/// handle.remove("a");
+/// endpoints.set_ready(&"b", true);
+/// assert_eq!(svc.call(&mut (), ()).await.unwrap(), "b");
+/// assert_eq!(endpoints.call_count(&"a"), 4);
+/// # }
+/// ```
+pub fn discover<K, S, E>() -> (Discover<K, S, E>, DiscoverHandle<K, S, E>) {
+    let (tx, rx) = mpsc::unbounded_channel();
+    (Discover { rx }, DiscoverHandle { tx })
+}
+
+/// A [`Discover`](crate::discover::Discover) whose changes are pushed by the
+/// paired [`DiscoverHandle`].
+///
+/// The discovery ends once the handle and all its clones are dropped, and every
+/// change was polled.
+pub struct Discover<K, S, E> {
+    rx: mpsc::UnboundedReceiver<Result<Change<K, S>, E>>,
+}
+
+impl<K, S, E> Stream for Discover<K, S, E> {
+    type Item = Result<Change<K, S>, E>;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        self.rx.poll_recv(cx)
+    }
+}
+
+impl<K, S, E> fmt::Debug for Discover<K, S, E> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Discover")
+            .field("pending", &self.rx.len())
+            .finish()
+    }
+}
+
+/// Handle to the paired mock [`Discover`].
+///
+/// The changes pushed once the discover is dropped are ignored.
+pub struct DiscoverHandle<K, S, E> {
+    tx: mpsc::UnboundedSender<Result<Change<K, S>, E>>,
+}
+
+impl<K, S, E> DiscoverHandle<K, S, E> {
+    /// Inserts `service` under `key`, replacing the service already there.
+    pub fn insert(&self, key: K, service: S) {
+        let _ = self.tx.send(Ok(Change::Insert(key, service)));
+    }
+
+    /// Removes the service under `key`.
+    pub fn remove(&self, key: K) {
+        let _ = self.tx.send(Ok(Change::Remove(key)));
+    }
+
+    /// Fails the discovery with `err`.
+    pub fn send_error(&self, err: E) {
+        let _ = self.tx.send(Err(err));
+    }
+
+    /// Returns `true` if the discover was dropped, e.g. along with its balancer.
+    pub fn is_closed(&self) -> bool {
+        self.tx.is_closed()
+    }
+}
+
+impl<K, S, E> Clone for DiscoverHandle<K, S, E> {
+    fn clone(&self) -> Self {
+        DiscoverHandle {
+            tx: self.tx.clone(),
+        }
+    }
+}
+
+impl<K, S, E> fmt::Debug for DiscoverHandle<K, S, E> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("DiscoverHandle")
+            .field("closed", &self.is_closed())
+            .finish()
+    }
+}
+
+/// What the endpoints with a given key do, set from the test body.
+#[derive(Debug, Default)]
+struct Script {
+    unready: AtomicBool,
+    failing: AtomicBool,
+    load: AtomicUsize,
+    calls: AtomicUsize,
+    waiters: Waiters,
+}
+
+/// Makes endpoint services whose readiness, load and failures are scripted by
+/// key from the test body.
+///
+/// The endpoints made for a key share its script, so an endpoint replaced by
+/// another with the same key keeps behaving the same. An endpoint is ready, not
+/// loaded and answers every call with its key until told otherwise. The clones
+/// of a `ScriptedEndpoints` share the scripts, so one can make the services of a
+/// [`WatchDiscover`](crate::discover::WatchDiscover) while the test body keeps
+/// another.
+pub struct ScriptedEndpoints<K> {
+    scripts: Arc<Mutex<HashMap<K, Arc<Script>>>>,
+}
+
+impl<K: Hash + Eq + Clone> ScriptedEndpoints<K> {
+    /// Creates a factory with no endpoint scripted yet.
+    pub fn new() -> Self {
+        ScriptedEndpoints {
+            scripts: Arc::new(Mutex::new(HashMap::new())),
+        }
+    }
+
+    fn script(&self, key: &K) -> Arc<Script> {
+        let mut scripts = self.scripts.lock().unwrap_or_else(|e| e.into_inner());
+        if let Some(script) = scripts.get(key) {
+            return script.clone();
+        }
+        let script = Arc::new(Script::default());
+        scripts.insert(key.clone(), script.clone());
+        script
+    }
+
+    /// Makes an endpoint service for `key`.
+    pub fn endpoint(&self, key: &K) -> ScriptedEndpoint<K> {
+        ScriptedEndpoint {
+            key: key.clone(),
+            script: self.script(key),
+        }
+    }
+
+    /// Sets whether the endpoints of `key` are [`Ready`], waking the tasks
+    /// waiting for them once they are.
+    pub fn set_ready(&self, key: &K, ready: bool) {
+        let script = self.script(key);
+        script.unready.store(!ready, Ordering::Release);
+        if ready {
+            script.waiters.wake_all();
+        }
+    }
+
+    /// Sets whether the endpoints of `key` fail every call with a [`MockError`].
+    pub fn set_failing(&self, key: &K, failing: bool) {
+        self.script(key).failing.store(failing, Ordering::Release);
+    }
+
+    /// Sets the [`Load`] reported by the endpoints of `key`.
+    pub fn set_load(&self, key: &K, load: usize) {
+        self.script(key).load.store(load, Ordering::Release);
+    }
+
+    /// Returns how many times the endpoints of `key` have been called.
+    pub fn call_count(&self, key: &K) -> usize {
+        self.script(key).calls.load(Ordering::Acquire)
+    }
+}
+
+impl<K: Hash + Eq + Clone> Default for ScriptedEndpoints<K> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<K> Clone for ScriptedEndpoints<K> {
+    fn clone(&self) -> Self {
+        ScriptedEndpoints {
+            scripts: self.scripts.clone(),
+        }
+    }
+}
+
+impl<K> fmt::Debug for ScriptedEndpoints<K> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let scripts = self.scripts.lock().unwrap_or_else(|e| e.into_inner());
+        f.debug_struct("ScriptedEndpoints")
+            .field("endpoints", &scripts.len())
+            .finish()
+    }
+}
+
+/// An endpoint service made by [`ScriptedEndpoints`], answering with its key.
+pub struct ScriptedEndpoint<K> {
+    key: K,
+    script: Arc<Script>,
+}
+
+impl<K> ScriptedEndpoint<K> {
+    fn respond(&self) -> Result<K, MockError>
+    where
+        K: Clone,
+    {
+        self.script.calls.fetch_add(1, Ordering::AcqRel);
+        if self.script.failing.load(Ordering::Acquire) {
+            Err(MockError)
+        } else {
+            Ok(self.key.clone())
+        }
+    }
+}
+
+impl<Cx, Req, K> Service<Cx, Req> for ScriptedEndpoint<K>
+where
+    K: Clone + MaybeSend,
+{
+    type Response = K;
+    type Error = MockError;
+
+    #[cfg(feature = "service_send")]
+    fn call(
+        &self,
+        _cx: &mut Cx,
+        _req: Req,
+    ) -> impl Future<Output = Result<Self::Response, Self::Error>> + Send {
+        std::future::ready(self.respond())
+    }
+
+    #[cfg(not(feature = "service_send"))]
+    fn call(
+        &self,
+        _cx: &mut Cx,
+        _req: Req,
+    ) -> impl Future<Output = Result<Self::Response, Self::Error>> {
+        std::future::ready(self.respond())
+    }
+}
+
+impl<K> Ready for ScriptedEndpoint<K> {
+    fn poll_ready(&self, cx: &mut Context<'_>) -> Poll<()> {
+        self.script.waiters.register(cx.waker());
+        if self.script.unready.load(Ordering::Acquire) {
+            return Poll::Pending;
+        }
+        Poll::Ready(())
+    }
+}
+
+impl<K> Load for ScriptedEndpoint<K> {
+    type Metric = usize;
+
+    fn load(&self) -> usize {
+        self.script.load.load(Ordering::Acquire)
+    }
+}
+
+impl<K: fmt::Debug> fmt::Debug for ScriptedEndpoint<K> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ScriptedEndpoint")
+            .field("key", &self.key)
+            .field("script", &self.script)
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{convert::Infallible, time::Duration};
+
+    use super::*;
+    use crate::{balance::p2c::Balance, discover::Discover as _};
+
+    #[tokio::test]
+    async fn pushes_the_changes() {
+        let (mut discover, handle) = discover::<u32, &str, &str>();
+        handle.insert(1, "one");
+        handle.send_error("boom");
+        handle.remove(1);
+        assert_eq!(
+            discover.next_change().await,
+            Some(Ok(Change::Insert(1, "one")))
+        );
+        assert_eq!(discover.next_change().await, Some(Err("boom")));
+        assert_eq!(discover.next_change().await, Some(Ok(Change::Remove(1))));
+
+        drop(handle);
+        assert_eq!(discover.next_change().await, None);
+    }
+
+    #[tokio::test]
+    async fn scripts_the_endpoints() {
+        let (discover, handle) = discover::<_, _, Infallible>();
+        let endpoints = ScriptedEndpoints::new();
+        for key in [1, 2] {
+            handle.insert(key, endpoints.endpoint(&key));
+        }
+        let svc = Balance::new(discover);
+
+        // Picks the least loaded endpoint.
+        endpoints.set_load(&1, 10);
+        for _ in 0..4 {
+            assert_eq!(svc.call(&mut (), ()).await.unwrap(), 2);
+        }
+        endpoints.set_failing(&2, true);
+        assert!(svc.call(&mut (), ()).await.is_err());
+        assert_eq!(endpoints.call_count(&2), 5);
+
+        // Waits for an endpoint to be ready again.
+        endpoints.set_ready(&1, false);
+        endpoints.set_ready(&2, false);
+        let call = async { svc.call(&mut (), ()).await };
+        let ready = async {
+            tokio::time::sleep(Duration::from_millis(10)).await;
+            endpoints.set_ready(&1, true);
+        };
+        let (res, ()) = tokio::join!(call, ready);
+        assert_eq!(res.unwrap(), 1);
+
+        drop(svc);
+        assert!(handle.is_closed());
+    }
+}
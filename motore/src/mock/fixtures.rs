@@ -0,0 +1,219 @@
+use std::{convert::Infallible, fmt, future::Future, marker::PhantomData, time::Duration};
+
+use crate::{MaybeSend, MaybeSync, Service};
+
+/// A [`Service`] that responds with the request itself.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Identity;
+
+impl<Cx, Req> Service<Cx, Req> for Identity
+where
+    Req: MaybeSend,
+{
+    type Response = Req;
+    type Error = Infallible;
+
+    #[cfg(feature = "service_send")]
+    fn call(
+        &self,
+        _cx: &mut Cx,
+        req: Req,
+    ) -> impl Future<Output = Result<Self::Response, Self::Error>> + Send {
+        std::future::ready(Ok(req))
+    }
+
+    #[cfg(not(feature = "service_send"))]
+    fn call(
+        &self,
+        _cx: &mut Cx,
+        req: Req,
+    ) -> impl Future<Output = Result<Self::Response, Self::Error>> {
+        std::future::ready(Ok(req))
+    }
+}
+
+/// A [`Service`] that echoes the request back, converted into the response type `Res`.
+///
+/// # Example
+///
+/// ```rust
+/// use motore::{mock::Echo, Service};
+///
+/// # #[tokio::main(flavor = "current_thread")]
+/// # async fn main() {
+/// let echo = Echo::<String>::new();
+/// assert_eq!(echo.call(&mut (), "hello").await.unwrap(), "hello");
+/// # }
+/// ```
+pub struct Echo<Res> {
+    _phantom: PhantomData<fn() -> Res>,
+}
+
+impl<Res> Echo<Res> {
+    /// Create a new `Echo` service.
+    pub const fn new() -> Self {
+        Echo {
+            _phantom: PhantomData,
+        }
+    }
+}
+
+impl<Res> Default for Echo<Res> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<Res> Clone for Echo<Res> {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+impl<Res> Copy for Echo<Res> {}
+
+impl<Res> fmt::Debug for Echo<Res> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Echo").finish()
+    }
+}
+
+impl<Cx, Req, Res> Service<Cx, Req> for Echo<Res>
+where
+    Req: Into<Res>,
+    Res: MaybeSend,
+{
+    type Response = Res;
+    type Error = Infallible;
+
+    #[cfg(feature = "service_send")]
+    fn call(
+        &self,
+        _cx: &mut Cx,
+        req: Req,
+    ) -> impl Future<Output = Result<Self::Response, Self::Error>> + Send {
+        std::future::ready(Ok(req.into()))
+    }
+
+    #[cfg(not(feature = "service_send"))]
+    fn call(
+        &self,
+        _cx: &mut Cx,
+        req: Req,
+    ) -> impl Future<Output = Result<Self::Response, Self::Error>> {
+        std::future::ready(Ok(req.into()))
+    }
+}
+
+/// A [`Service`] that fails every request with an error built by `F`.
+#[derive(Clone, Copy)]
+pub struct AlwaysFail<F> {
+    f: F,
+}
+
+impl<F> AlwaysFail<F> {
+    /// Create a new `AlwaysFail` service building its errors with `f`.
+    pub const fn new(f: F) -> Self {
+        AlwaysFail { f }
+    }
+}
+
+impl<F> fmt::Debug for AlwaysFail<F> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("AlwaysFail")
+            .field("f", &format_args!("{}", std::any::type_name::<F>()))
+            .finish()
+    }
+}
+
+impl<Cx, Req, F, E> Service<Cx, Req> for AlwaysFail<F>
+where
+    F: Fn() -> E,
+    E: MaybeSend,
+{
+    type Response = Infallible;
+    type Error = E;
+
+    #[cfg(feature = "service_send")]
+    fn call(
+        &self,
+        _cx: &mut Cx,
+        _req: Req,
+    ) -> impl Future<Output = Result<Self::Response, Self::Error>> + Send {
+        std::future::ready(Err((self.f)()))
+    }
+
+    #[cfg(not(feature = "service_send"))]
+    fn call(
+        &self,
+        _cx: &mut Cx,
+        _req: Req,
+    ) -> impl Future<Output = Result<Self::Response, Self::Error>> {
+        std::future::ready(Err((self.f)()))
+    }
+}
+
+/// A [`Service`] that waits for a fixed duration before calling the inner service.
+///
+/// With the default inner service, [`Identity`], it responds with the request
+/// after the delay.
+#[derive(Clone, Debug)]
+pub struct Delay<S = Identity> {
+    inner: S,
+    delay: Duration,
+}
+
+impl Delay {
+    /// Create a new `Delay` responding with the request after `delay`.
+    pub const fn new(delay: Duration) -> Self {
+        Delay {
+            inner: Identity,
+            delay,
+        }
+    }
+}
+
+impl<S> Delay<S> {
+    /// Create a new `Delay` calling `inner` after `delay`.
+    pub const fn with_inner(delay: Duration, inner: S) -> Self {
+        Delay { inner, delay }
+    }
+}
+
+impl<Cx, Req, S> Service<Cx, Req> for Delay<S>
+where
+    Cx: MaybeSend,
+    Req: MaybeSend,
+    S: Service<Cx, Req> + MaybeSync,
+{
+    type Response = S::Response;
+    type Error = S::Error;
+
+    async fn call(&self, cx: &mut Cx, req: Req) -> Result<Self::Response, Self::Error> {
+        tokio::time::sleep(self.delay).await;
+        self.inner.call(cx, req).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test(start_paused = true)]
+    async fn fixtures() {
+        assert_eq!(Identity.call(&mut (), 1).await, Ok(1));
+        assert_eq!(
+            Echo::<Vec<u8>>::new().call(&mut (), "ab").await,
+            Ok(b"ab".to_vec())
+        );
+        assert_eq!(
+            AlwaysFail::new(|| "boom").call(&mut (), 1).await,
+            Err("boom")
+        );
+
+        let start = tokio::time::Instant::now();
+        let svc = Delay::with_inner(Duration::from_secs(1), AlwaysFail::new(|| "late"));
+        assert_eq!(svc.call(&mut (), ()).await, Err("late"));
+        assert_eq!(start.elapsed(), Duration::from_secs(1));
+    }
+}
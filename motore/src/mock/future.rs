@@ -0,0 +1,269 @@
+use std::{
+    fmt,
+    future::Future,
+    pin::Pin,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    task::{Context, Poll},
+};
+
+use futures::task::noop_waker_ref;
+use pin_project::{pin_project, pinned_drop};
+use tokio::sync::oneshot;
+
+/// Creates a future that stays pending until it is released by the paired [`Release`].
+///
+/// This is useful to control precisely when an inner service responds, e.g. to
+/// check how a middleware behaves while a call is in-flight, without relying on
+/// sleeps.
+pub fn controlled<T>() -> (Controlled<T>, Release<T>) {
+    let (tx, rx) = oneshot::channel();
+    let dropped = DropFlag::default();
+
+    (
+        Controlled {
+            rx,
+            dropped: dropped.clone(),
+        },
+        Release {
+            tx: Some(tx),
+            dropped,
+        },
+    )
+}
+
+/// A future returned by [`controlled`].
+///
+/// # Panics
+///
+/// Polling it panics if the paired [`Release`] is dropped without releasing it.
+pub struct Controlled<T> {
+    rx: oneshot::Receiver<T>,
+    dropped: DropFlag,
+}
+
+impl<T> Future for Controlled<T> {
+    type Output = T;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        match Pin::new(&mut self.rx).poll(cx) {
+            Poll::Ready(Ok(value)) => Poll::Ready(value),
+            Poll::Ready(Err(_)) => panic!("the `Release` of a controlled future was dropped"),
+            Poll::Pending => Poll::Pending,
+        }
+    }
+}
+
+impl<T> Drop for Controlled<T> {
+    fn drop(&mut self) {
+        self.dropped.set();
+    }
+}
+
+impl<T> fmt::Debug for Controlled<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Controlled").finish()
+    }
+}
+
+/// Releases the paired [`Controlled`] future.
+pub struct Release<T> {
+    tx: Option<oneshot::Sender<T>>,
+    dropped: DropFlag,
+}
+
+impl<T> Release<T> {
+    /// Completes the paired future with `value`.
+    ///
+    /// Returns the value back if the future has already been dropped.
+    pub fn release(mut self, value: T) -> Result<(), T> {
+        self.tx.take().expect("released twice").send(value)
+    }
+
+    /// Returns `true` if the paired future has been dropped.
+    pub fn is_dropped(&self) -> bool {
+        self.dropped.get()
+    }
+
+    /// Asserts that the paired future has been dropped, i.e. that cancellation
+    /// has propagated down to it.
+    #[track_caller]
+    pub fn assert_dropped(&self) {
+        assert!(
+            self.is_dropped(),
+            "expected the controlled future to be dropped"
+        );
+    }
+}
+
+impl<T> fmt::Debug for Release<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Release")
+            .field("dropped", &self.is_dropped())
+            .finish()
+    }
+}
+
+/// Wraps `fut` so that the returned [`DropTracker`] can tell whether it has been
+/// dropped.
+pub fn track<F>(fut: F) -> (Tracked<F>, DropTracker) {
+    let dropped = DropFlag::default();
+    (
+        Tracked {
+            inner: fut,
+            dropped: dropped.clone(),
+        },
+        DropTracker { dropped },
+    )
+}
+
+/// A future returned by [`track`].
+#[pin_project(PinnedDrop)]
+pub struct Tracked<F> {
+    #[pin]
+    inner: F,
+    dropped: DropFlag,
+}
+
+impl<F: Future> Future for Tracked<F> {
+    type Output = F::Output;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        self.project().inner.poll(cx)
+    }
+}
+
+#[pinned_drop]
+impl<F> PinnedDrop for Tracked<F> {
+    fn drop(self: Pin<&mut Self>) {
+        self.dropped.set();
+    }
+}
+
+impl<F> fmt::Debug for Tracked<F> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Tracked")
+            .field("inner", &format_args!("{}", std::any::type_name::<F>()))
+            .finish()
+    }
+}
+
+/// Tells whether a future wrapped by [`track`] has been dropped.
+#[derive(Clone, Debug)]
+pub struct DropTracker {
+    dropped: DropFlag,
+}
+
+impl DropTracker {
+    /// Returns `true` if the tracked future has been dropped.
+    pub fn is_dropped(&self) -> bool {
+        self.dropped.get()
+    }
+
+    /// Asserts that the tracked future has been dropped.
+    #[track_caller]
+    pub fn assert_dropped(&self) {
+        assert!(
+            self.is_dropped(),
+            "expected the tracked future to be dropped"
+        );
+    }
+
+    /// Asserts that the tracked future is still alive.
+    #[track_caller]
+    pub fn assert_alive(&self) {
+        assert!(
+            !self.is_dropped(),
+            "expected the tracked future to be alive"
+        );
+    }
+}
+
+/// Polls `fut` once with a no-op waker.
+pub fn poll_once<F>(fut: Pin<&mut F>) -> Poll<F::Output>
+where
+    F: Future + ?Sized,
+{
+    fut.poll(&mut Context::from_waker(noop_waker_ref()))
+}
+
+/// Polls `fut` once and asserts that it is pending.
+#[track_caller]
+pub fn assert_pending<F>(fut: Pin<&mut F>)
+where
+    F: Future + ?Sized,
+{
+    assert!(
+        poll_once(fut).is_pending(),
+        "expected the future to be pending"
+    );
+}
+
+/// Polls `fut` once and asserts that it is ready, returning its output.
+#[track_caller]
+pub fn assert_ready<F>(fut: Pin<&mut F>) -> F::Output
+where
+    F: Future + ?Sized,
+{
+    match poll_once(fut) {
+        Poll::Ready(output) => output,
+        Poll::Pending => panic!("expected the future to be ready"),
+    }
+}
+
+#[derive(Clone, Debug, Default)]
+struct DropFlag(Arc<AtomicBool>);
+
+impl DropFlag {
+    fn set(&self) {
+        self.0.store(true, Ordering::Release);
+    }
+
+    fn get(&self) -> bool {
+        self.0.load(Ordering::Acquire)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::*;
+    use crate::{service::service_fn, timeout::Timeout, Service};
+
+    #[test]
+    fn controlled_future() {
+        let (fut, release) = controlled();
+        let mut fut = Box::pin(fut);
+        assert_pending(fut.as_mut());
+        release.release(1).unwrap();
+        assert_eq!(assert_ready(fut.as_mut()), 1);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn timeout_drops_inner_future() {
+        let (fut, release) = controlled::<()>();
+        let fut = std::sync::Mutex::new(Some(fut));
+        let svc = Timeout::new(
+            service_fn(move |_cx: &mut (), _req: ()| {
+                let fut = fut.lock().unwrap().take().unwrap();
+                async move {
+                    fut.await;
+                    Ok::<_, crate::BoxError>(())
+                }
+            }),
+            Some(Duration::from_secs(1)),
+        );
+
+        let mut cx = ();
+        let mut call = Box::pin(svc.call(&mut cx, ()));
+        assert_pending(call.as_mut());
+        assert!(!release.is_dropped());
+
+        tokio::time::advance(Duration::from_secs(1)).await;
+        assert!(call.await.is_err());
+        release.assert_dropped();
+    }
+}
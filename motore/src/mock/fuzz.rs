@@ -0,0 +1,371 @@
+use std::{collections::BTreeMap, fmt, future::Future, pin::Pin, task::Poll, time::Duration};
+
+use super::{poll_once, Handle, SendResponse};
+use crate::{utils::rng::Rng, Service};
+
+/// The error answered by the mock inner service of a [`Fuzz`] run.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct MockError;
+
+impl fmt::Display for MockError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("mock error")
+    }
+}
+
+impl std::error::Error for MockError {}
+
+type Calls<'a, T, E> = BTreeMap<u64, Pin<Box<dyn Future<Output = Result<T, E>> + 'a>>>;
+
+/// Something that happened while running a [`Fuzz`] schedule.
+///
+/// Every event is passed to the invariant given to [`Fuzz::run`].
+#[derive(Debug)]
+pub enum Event<'a, Res, Err> {
+    /// A call with the given id was issued to the stack.
+    Called(u64),
+    /// The mock inner service received the request with the given id.
+    Forwarded(u64),
+    /// The mock inner service answered the request with the given id.
+    Responded(u64, &'a Result<u64, MockError>),
+    /// The call with the given id completed.
+    Completed(u64, &'a Result<Res, Err>),
+    /// The call with the given id was dropped before completing.
+    Cancelled(u64),
+    /// The (paused) clock was advanced.
+    Advanced(Duration),
+}
+
+/// Randomized schedules of calls, responses, errors and cancellations against a
+/// middleware stack.
+///
+/// The stack under test wraps a [`Mock`](super::Mock) created by
+/// [`pair`](super::pair), with `u64` requests and responses and [`MockError`]
+/// errors. Each request carries a unique id, which the mock answers with either
+/// the same id or an error.
+///
+/// A schedule is fully determined by its seed, so a failing seed can be replayed.
+/// While the schedule runs, the invariant given to [`Fuzz::run`] is called after
+/// every [`Event`] and can inspect the shared state of the middlewares (e.g.
+/// that permits are always released or that a budget never becomes negative).
+/// Besides, the runner itself checks that:
+///
+/// - dropping a call drops the inner calls it issued, i.e. no inner request is still
+///   waiting for a response after its caller has gone, unless disabled with
+///   [`Fuzz::check_cancellation`];
+/// - once the inner service has answered all the requests, every call completes.
+///
+/// # Example
+///
+/// ```rust
+/// use motore::{
+///     layer::{Layer, MapErrLayer},
+///     mock::{self, Fuzz},
+/// };
+///
+/// # #[tokio::main(flavor = "current_thread")]
+/// # async fn main() {
+/// for seed in 0..16 {
+///     let (svc, mut handle) = mock::pair::<(), u64, u64, mock::MockError>();
+///     let svc = MapErrLayer::new(|e| e).layer(svc);
+///
+///     Fuzz::new(seed)
+///         .run(&svc, &mut handle, |_event| {
+///             // check the invariants of the stack here
+///         })
+///         .await;
+/// }
+/// # }
+/// ```
+#[derive(Clone, Debug)]
+pub struct Fuzz {
+    seed: u64,
+    steps: usize,
+    max_in_flight: usize,
+    error_ratio: f64,
+    cancel_ratio: f64,
+    advance: Option<Duration>,
+    check_cancellation: bool,
+}
+
+impl Fuzz {
+    /// Create a new schedule generator with the given seed.
+    pub const fn new(seed: u64) -> Self {
+        Fuzz {
+            seed,
+            steps: 256,
+            max_in_flight: 16,
+            error_ratio: 0.2,
+            cancel_ratio: 0.1,
+            advance: None,
+            check_cancellation: true,
+        }
+    }
+
+    /// Sets the number of steps of the schedule, 256 by default.
+    pub const fn steps(mut self, steps: usize) -> Self {
+        self.steps = steps;
+        self
+    }
+
+    /// Sets the maximum number of calls in-flight at the same time, 16 by default.
+    pub const fn max_in_flight(mut self, max_in_flight: usize) -> Self {
+        self.max_in_flight = max_in_flight;
+        self
+    }
+
+    /// Sets the ratio of inner requests answered with an error, 0.2 by default.
+    pub const fn error_ratio(mut self, ratio: f64) -> Self {
+        self.error_ratio = ratio;
+        self
+    }
+
+    /// Sets the ratio of steps cancelling an in-flight call, 0.1 by default.
+    pub const fn cancel_ratio(mut self, ratio: f64) -> Self {
+        self.cancel_ratio = ratio;
+        self
+    }
+
+    /// Lets the schedule advance the clock by up to `max` at once.
+    ///
+    /// This requires the tokio clock to be paused, see [`tokio::time::pause`].
+    pub const fn advance_time(mut self, max: Duration) -> Self {
+        self.advance = Some(max);
+        self
+    }
+
+    /// Enables or disables checking that cancellation propagates to the inner
+    /// service, enabled by default.
+    ///
+    /// This should be disabled for middlewares that intentionally keep inner
+    /// calls running after their caller is gone.
+    pub const fn check_cancellation(mut self, enabled: bool) -> Self {
+        self.check_cancellation = enabled;
+        self
+    }
+
+    /// Runs the schedule against `svc`, calling `invariant` after every event.
+    ///
+    /// # Panics
+    ///
+    /// Panics, mentioning the seed, if one of the checks of the runner fails. The
+    /// invariant is expected to panic as well when it is violated.
+    pub async fn run<Cx, S, I>(
+        &self,
+        svc: &S,
+        handle: &mut Handle<u64, u64, MockError>,
+        mut invariant: I,
+    ) -> Report
+    where
+        Cx: Default,
+        S: Service<Cx, u64>,
+        I: FnMut(&Event<'_, S::Response, S::Error>),
+    {
+        let mut rng = Rng::with_seed(self.seed);
+        let mut report = Report::default();
+        let mut calls: Calls<'_, S::Response, S::Error> = BTreeMap::new();
+        let mut inner: Vec<(u64, SendResponse<u64, MockError>)> = Vec::new();
+        let mut next_id = 0;
+
+        for _ in 0..self.steps {
+            match rng.below(8) {
+                0..=2 if calls.len() < self.max_in_flight => {
+                    let id = next_id;
+                    next_id += 1;
+                    calls.insert(
+                        id,
+                        Box::pin(async move {
+                            let mut cx = Cx::default();
+                            svc.call(&mut cx, id).await
+                        }),
+                    );
+                    report.calls += 1;
+                    invariant(&Event::Called(id));
+                }
+                3 if !inner.is_empty() => {
+                    let (id, tx) = inner.swap_remove(rng.below(inner.len() as u64) as usize);
+                    let res = if rng.chance(self.error_ratio) {
+                        Err(MockError)
+                    } else {
+                        Ok(id)
+                    };
+                    invariant(&Event::Responded(id, &res));
+                    match res {
+                        Ok(res) => tx.send_response(res),
+                        Err(err) => tx.send_error(err),
+                    }
+                }
+                4 if !calls.is_empty() && rng.chance(self.cancel_ratio * 4.0) => {
+                    let idx = rng.below(calls.len() as u64) as usize;
+                    let id = *calls.keys().nth(idx).unwrap();
+                    drop(calls.remove(&id));
+                    report.cancelled += 1;
+                    invariant(&Event::Cancelled(id));
+                }
+                5 => {
+                    if let Some(max) = self.advance {
+                        let by = max.mul_f64(rng.next_f64());
+                        tokio::time::advance(by).await;
+                        invariant(&Event::Advanced(by));
+                    }
+                }
+                _ => {}
+            }
+
+            self.poll_calls(&mut calls, &mut report, &mut invariant);
+            self.receive(handle, &mut inner, &mut invariant);
+            if self.check_cancellation {
+                self.check_cancelled(&calls, &inner);
+            }
+        }
+
+        // answer everything until all the calls have completed
+        for _ in 0..1024 {
+            if calls.is_empty() {
+                break;
+            }
+            for (id, tx) in inner.drain(..) {
+                invariant(&Event::Responded(id, &Ok(id)));
+                tx.send_response(id);
+            }
+            tokio::task::yield_now().await;
+            if let Some(max) = self.advance {
+                tokio::time::advance(max).await;
+            }
+            self.poll_calls(&mut calls, &mut report, &mut invariant);
+            self.receive(handle, &mut inner, &mut invariant);
+        }
+        assert!(
+            calls.is_empty(),
+            "seed {}: calls {:?} never completed although the inner service answered every request",
+            self.seed,
+            calls.keys().collect::<Vec<_>>(),
+        );
+
+        report
+    }
+
+    fn poll_calls<'a, T, E, I>(
+        &self,
+        calls: &mut Calls<'a, T, E>,
+        report: &mut Report,
+        invariant: &mut I,
+    ) where
+        I: FnMut(&Event<'_, T, E>),
+    {
+        calls.retain(|id, call| match poll_once(call.as_mut()) {
+            Poll::Ready(res) => {
+                if res.is_ok() {
+                    report.succeeded += 1;
+                } else {
+                    report.failed += 1;
+                }
+                invariant(&Event::Completed(*id, &res));
+                false
+            }
+            Poll::Pending => true,
+        });
+    }
+
+    fn receive<T, E, I>(
+        &self,
+        handle: &mut Handle<u64, u64, MockError>,
+        inner: &mut Vec<(u64, SendResponse<u64, MockError>)>,
+        invariant: &mut I,
+    ) where
+        I: FnMut(&Event<'_, T, E>),
+    {
+        while let Some((id, tx)) = handle.try_next_request() {
+            invariant(&Event::Forwarded(id));
+            inner.push((id, tx));
+        }
+    }
+
+    fn check_cancelled<T>(
+        &self,
+        calls: &BTreeMap<u64, T>,
+        inner: &[(u64, SendResponse<u64, MockError>)],
+    ) {
+        for (id, tx) in inner {
+            assert!(
+                calls.contains_key(id) || tx.is_canceled(),
+                "seed {}: the inner request of call {id} is still in-flight after the call was \
+                 dropped",
+                self.seed,
+            );
+        }
+    }
+}
+
+/// Statistics about a [`Fuzz`] run.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct Report {
+    /// The number of calls issued.
+    pub calls: usize,
+    /// The number of calls that completed successfully.
+    pub succeeded: usize,
+    /// The number of calls that completed with an error.
+    pub failed: usize,
+    /// The number of calls dropped before completing.
+    pub cancelled: usize,
+}
+
+impl fmt::Display for Report {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} calls: {} succeeded, {} failed, {} cancelled",
+            self.calls, self.succeeded, self.failed, self.cancelled
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{collections::HashSet, time::Duration};
+
+    use super::*;
+    use crate::{mock::pair, timeout::Timeout};
+
+    #[tokio::test]
+    async fn passthrough_is_consistent() {
+        for seed in 0..32 {
+            let (svc, mut handle) = pair::<(), u64, u64, MockError>();
+            let mut in_flight = HashSet::new();
+
+            let report = Fuzz::new(seed)
+                .run(&svc, &mut handle, |event| match event {
+                    Event::Called(id) => assert!(in_flight.insert(*id)),
+                    Event::Completed(id, res) => {
+                        assert!(in_flight.remove(id));
+                        if let Ok(res) = res {
+                            assert_eq!(res, id);
+                        }
+                    }
+                    Event::Cancelled(id) => assert!(in_flight.remove(id)),
+                    _ => {}
+                })
+                .await;
+
+            assert!(in_flight.is_empty());
+            assert_eq!(
+                report.calls,
+                report.succeeded + report.failed + report.cancelled
+            );
+        }
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn timeout_with_paused_clock() {
+        for seed in 0..8 {
+            let (svc, mut handle) = pair::<(), u64, u64, MockError>();
+            let svc = Timeout::new(svc, Some(Duration::from_millis(100)));
+
+            Fuzz::new(seed)
+                .advance_time(Duration::from_millis(50))
+                .run(&svc, &mut handle, |_| {})
+                .await;
+        }
+    }
+}
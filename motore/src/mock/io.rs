@@ -0,0 +1,425 @@
+use std::{
+    collections::VecDeque,
+    fmt,
+    future::Future,
+    io,
+    pin::Pin,
+    task::{Context, Poll, Waker},
+    time::Duration,
+};
+
+use tokio::{
+    io::{AsyncRead, AsyncWrite, DuplexStream, ReadBuf},
+    sync::{mpsc, Mutex},
+    time::Sleep,
+};
+
+use crate::UnaryService;
+
+/// Creates an in-memory connector and the listener accepting its connections.
+///
+/// Every connection made by the [`DuplexConnector`] is one end of a
+/// [`tokio::io::duplex`] pair with `max_buf_size` bytes of buffer, whose other
+/// end is handed to the [`DuplexListener`] along with the address that was
+/// dialed. Since the connector is a [`UnaryService`] returning an
+/// `AsyncRead + AsyncWrite` stream, it can be used anywhere a
+/// [`MakeConnection`] is expected.
+///
+/// [`MakeConnection`]: crate::make::MakeConnection
+pub fn duplex<A>(max_buf_size: usize) -> (DuplexConnector<A>, DuplexListener<A>) {
+    let (tx, rx) = mpsc::unbounded_channel();
+    (
+        DuplexConnector { tx, max_buf_size },
+        DuplexListener { rx: Mutex::new(rx) },
+    )
+}
+
+/// The connecting side of [`duplex`].
+pub struct DuplexConnector<A> {
+    tx: mpsc::UnboundedSender<(A, DuplexStream)>,
+    max_buf_size: usize,
+}
+
+impl<A> DuplexConnector<A> {
+    fn connect(&self, addr: A) -> io::Result<DuplexStream> {
+        let (client, server) = tokio::io::duplex(self.max_buf_size);
+        self.tx.send((addr, server)).map_err(|_| {
+            io::Error::new(
+                io::ErrorKind::ConnectionRefused,
+                "the duplex listener has been dropped",
+            )
+        })?;
+        Ok(client)
+    }
+}
+
+impl<A> UnaryService<A> for DuplexConnector<A> {
+    type Response = DuplexStream;
+    type Error = io::Error;
+
+    #[cfg(feature = "service_send")]
+    fn call(&self, addr: A) -> impl Future<Output = Result<Self::Response, Self::Error>> + Send {
+        std::future::ready(self.connect(addr))
+    }
+
+    #[cfg(not(feature = "service_send"))]
+    fn call(&self, addr: A) -> impl Future<Output = Result<Self::Response, Self::Error>> {
+        std::future::ready(self.connect(addr))
+    }
+}
+
+impl<A> Clone for DuplexConnector<A> {
+    fn clone(&self) -> Self {
+        Self {
+            tx: self.tx.clone(),
+            max_buf_size: self.max_buf_size,
+        }
+    }
+}
+
+impl<A> fmt::Debug for DuplexConnector<A> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("DuplexConnector")
+            .field("max_buf_size", &self.max_buf_size)
+            .finish()
+    }
+}
+
+/// The accepting side of [`duplex`].
+pub struct DuplexListener<A> {
+    rx: Mutex<mpsc::UnboundedReceiver<(A, DuplexStream)>>,
+}
+
+impl<A> DuplexListener<A> {
+    /// Waits for the next connection, returning the server end of the stream
+    /// and the address that was dialed.
+    ///
+    /// Returns `None` once every [`DuplexConnector`] has been dropped.
+    pub async fn accept(&self) -> Option<(DuplexStream, A)> {
+        self.rx
+            .lock()
+            .await
+            .recv()
+            .await
+            .map(|(addr, stream)| (stream, addr))
+    }
+}
+
+impl<A> fmt::Debug for DuplexListener<A> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("DuplexListener").finish()
+    }
+}
+
+enum Action {
+    Read(Vec<u8>),
+    Write(Vec<u8>),
+    ReadError(Option<io::Error>),
+    WriteError(Option<io::Error>),
+    Wait(Duration),
+    Stall,
+}
+
+impl fmt::Debug for Action {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Action::Read(data) => write!(f, "read {} bytes", data.len()),
+            Action::Write(data) => write!(f, "write {} bytes", data.len()),
+            Action::ReadError(_) => f.write_str("read error"),
+            Action::WriteError(_) => f.write_str("write error"),
+            Action::Wait(d) => write!(f, "wait {d:?}"),
+            Action::Stall => f.write_str("stall"),
+        }
+    }
+}
+
+/// Builds a [`MockIo`] from a script of IO operations.
+///
+/// # Example
+///
+/// ```rust
+/// use std::{io, time::Duration};
+///
+/// use motore::mock::MockIo;
+/// use tokio::io::{AsyncReadExt, AsyncWriteExt};
+///
+/// # #[tokio::main(flavor = "current_thread")]
+/// # async fn main() {
+/// let mut io = MockIo::builder()
+///     .write(b"ping")
+///     .wait(Duration::from_millis(1))
+///     .read(b"pong")
+///     .read_error(io::ErrorKind::ConnectionReset.into())
+///     .build();
+///
+/// io.write_all(b"ping").await.unwrap();
+/// let mut buf = [0; 4];
+/// io.read_exact(&mut buf).await.unwrap();
+/// assert_eq!(&buf, b"pong");
+/// assert!(io.read(&mut buf).await.is_err());
+/// # }
+/// ```
+#[derive(Debug, Default)]
+pub struct MockIoBuilder {
+    actions: VecDeque<Action>,
+}
+
+impl MockIoBuilder {
+    /// The next read returns `data`, possibly over several calls to `poll_read`.
+    pub fn read(mut self, data: &[u8]) -> Self {
+        self.actions.push_back(Action::Read(data.to_vec()));
+        self
+    }
+
+    /// The next write must be `data`, possibly over several calls to `poll_write`.
+    pub fn write(mut self, data: &[u8]) -> Self {
+        self.actions.push_back(Action::Write(data.to_vec()));
+        self
+    }
+
+    /// The next read fails with `err`.
+    pub fn read_error(mut self, err: io::Error) -> Self {
+        self.actions.push_back(Action::ReadError(Some(err)));
+        self
+    }
+
+    /// The next write fails with `err`.
+    pub fn write_error(mut self, err: io::Error) -> Self {
+        self.actions.push_back(Action::WriteError(Some(err)));
+        self
+    }
+
+    /// Both reads and writes are pending for `duration`.
+    pub fn wait(mut self, duration: Duration) -> Self {
+        self.actions.push_back(Action::Wait(duration));
+        self
+    }
+
+    /// Both reads and writes are pending forever.
+    pub fn stall(mut self) -> Self {
+        self.actions.push_back(Action::Stall);
+        self
+    }
+
+    /// Builds the [`MockIo`].
+    pub fn build(self) -> MockIo {
+        MockIo {
+            actions: self.actions,
+            sleep: None,
+            waker: None,
+        }
+    }
+}
+
+/// An `AsyncRead + AsyncWrite` stream following a programmed script.
+///
+/// The actions of the script are consumed in order: a read while the next
+/// action is a write (or the reverse) is pending until the other side of the
+/// stream makes progress. Once the script is exhausted, reads return EOF.
+///
+/// # Panics
+///
+/// Writing unexpected data, or writing after the end of the script, panics.
+/// Dropping the stream before the script is complete panics too, unless
+/// the only remaining actions are waits and stalls.
+#[derive(Debug)]
+pub struct MockIo {
+    actions: VecDeque<Action>,
+    sleep: Option<Pin<Box<Sleep>>>,
+    waker: Option<Waker>,
+}
+
+impl MockIo {
+    /// Returns a builder to program the script of a new [`MockIo`].
+    pub fn builder() -> MockIoBuilder {
+        MockIoBuilder::default()
+    }
+
+    /// Returns `true` once all the reads and writes of the script have happened.
+    pub fn is_done(&self) -> bool {
+        self.actions
+            .iter()
+            .all(|a| matches!(a, Action::Wait(_) | Action::Stall))
+    }
+
+    /// Consumes the leading waits of the script, returning `Pending` while one
+    /// of them has not elapsed yet, or forever on a stall.
+    fn poll_wait(&mut self, cx: &mut Context<'_>) -> Poll<()> {
+        loop {
+            match self.actions.front() {
+                Some(Action::Wait(d)) => {
+                    let d = *d;
+                    let sleep = self
+                        .sleep
+                        .get_or_insert_with(|| Box::pin(tokio::time::sleep(d)));
+                    if sleep.as_mut().poll(cx).is_pending() {
+                        return Poll::Pending;
+                    }
+                    self.sleep = None;
+                    self.actions.pop_front();
+                }
+                Some(Action::Stall) => return Poll::Pending,
+                _ => return Poll::Ready(()),
+            }
+        }
+    }
+
+    fn park(&mut self, cx: &mut Context<'_>) {
+        self.waker = Some(cx.waker().clone());
+    }
+
+    fn progress(&mut self) {
+        if let Some(waker) = self.waker.take() {
+            waker.wake();
+        }
+    }
+}
+
+impl AsyncRead for MockIo {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        let this = self.get_mut();
+        if this.poll_wait(cx).is_pending() {
+            return Poll::Pending;
+        }
+        match this.actions.front_mut() {
+            Some(Action::Read(data)) => {
+                let n = data.len().min(buf.remaining());
+                buf.put_slice(&data[..n]);
+                data.drain(..n);
+                if data.is_empty() {
+                    this.actions.pop_front();
+                    this.progress();
+                }
+                Poll::Ready(Ok(()))
+            }
+            Some(Action::ReadError(err)) => {
+                let err = err.take().unwrap();
+                this.actions.pop_front();
+                this.progress();
+                Poll::Ready(Err(err))
+            }
+            Some(_) => {
+                this.park(cx);
+                Poll::Pending
+            }
+            None => Poll::Ready(Ok(())),
+        }
+    }
+}
+
+impl AsyncWrite for MockIo {
+    fn poll_write(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        let this = self.get_mut();
+        if this.poll_wait(cx).is_pending() {
+            return Poll::Pending;
+        }
+        match this.actions.front_mut() {
+            Some(Action::Write(expected)) => {
+                let n = expected.len().min(buf.len());
+                assert_eq!(&buf[..n], &expected[..n], "unexpected write on the mock io");
+                expected.drain(..n);
+                if expected.is_empty() {
+                    this.actions.pop_front();
+                    this.progress();
+                }
+                Poll::Ready(Ok(n))
+            }
+            Some(Action::WriteError(err)) => {
+                let err = err.take().unwrap();
+                this.actions.pop_front();
+                this.progress();
+                Poll::Ready(Err(err))
+            }
+            Some(_) => {
+                this.park(cx);
+                Poll::Pending
+            }
+            None => panic!(
+                "write of {} bytes after the end of the mock io script",
+                buf.len()
+            ),
+        }
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Poll::Ready(Ok(()))
+    }
+
+    fn poll_shutdown(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Poll::Ready(Ok(()))
+    }
+}
+
+impl Drop for MockIo {
+    fn drop(&mut self) {
+        if !std::thread::panicking() && !self.is_done() {
+            panic!("mock io dropped with remaining actions: {:?}", self.actions);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+    use super::*;
+    use crate::make::MakeConnection;
+
+    async fn dial<M: MakeConnection<&'static str>>(mk: &M) -> M::Connection
+    where
+        M::Error: fmt::Debug,
+    {
+        mk.make_connection("backend:8080").await.unwrap()
+    }
+
+    #[tokio::test]
+    async fn connects_in_memory() {
+        let (connector, listener) = duplex(64);
+
+        let mut client = dial(&connector).await;
+        let (mut server, addr) = listener.accept().await.unwrap();
+        assert_eq!(addr, "backend:8080");
+
+        client.write_all(b"ping").await.unwrap();
+        let mut buf = [0; 4];
+        server.read_exact(&mut buf).await.unwrap();
+        assert_eq!(&buf, b"ping");
+
+        drop(listener);
+        let err = connector.call("backend:8080").await.unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::ConnectionRefused);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn follows_script() {
+        let mut io = MockIo::builder()
+            .read(b"hello")
+            .wait(Duration::from_secs(1))
+            .write(b"world")
+            .stall()
+            .build();
+
+        let mut buf = [0; 3];
+        io.read_exact(&mut buf).await.unwrap();
+        assert_eq!(&buf, b"hel");
+        io.read_exact(&mut buf[..2]).await.unwrap();
+        assert_eq!(&buf[..2], b"lo");
+        assert!(!io.is_done());
+
+        let start = tokio::time::Instant::now();
+        io.write_all(b"world").await.unwrap();
+        assert_eq!(start.elapsed(), Duration::from_secs(1));
+        assert!(io.is_done());
+
+        let stalled = tokio::time::timeout(Duration::from_secs(60), io.read(&mut buf)).await;
+        assert!(stalled.is_err());
+    }
+}
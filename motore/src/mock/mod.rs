@@ -0,0 +1,333 @@
+//! Test doubles for writing tests against Motore middlewares.
+//!
+//! A middleware is usually tested by wrapping a fake inner service and checking
+//! what reaches it. [`pair`] creates such a fake: a [`Mock`] service that can be
+//! wrapped by the middleware under test, and a [`Handle`] that the test body uses
+//! to receive the requests that reached the mock and to answer them.
+//!
+//! # Example
+//!
+//! ```rust
+//! use motore::{mock, Service};
+//!
+//! # #[tokio::main(flavor = "current_thread")]
+//! # async fn main() {
+//! let (svc, mut handle) = mock::pair::<(), &'static str, &'static str, ()>();
+//!
+//! let call = tokio::spawn(async move { svc.call(&mut (), "ping").await });
+//!
+//! let (req, send_response) = handle.next_request().await.unwrap();
+//! assert_eq!(req, "ping");
+//! send_response.send_response("pong");
+//!
+//! assert_eq!(call.await.unwrap(), Ok("pong"));
+//! handle.assert_call_count(1);
+//! # }
+//! ```
+//!
+//! For finer control over timing, [`controlled`] creates futures that stay
+//! pending until released, and [`track`] tells whether a future was dropped,
+//! which makes it possible to check that cancellation propagates to an inner call.
+//! Together with [`tokio::time::pause`], timeout-like behavior can be tested
+//! deterministically without sleeping.
+//!
+//! Trivial fixtures, [`Identity`], [`Echo`], [`AlwaysFail`] and [`Delay`], cover
+//! the inner services that don't need to be controlled by the test body.
+//!
+//! The [`conformance`] module checks that a middleware honors the contract of the
+//! [`Service`] trait, e.g. that it passes errors through and propagates cancellation.
+//!
+//! [`Fuzz`] runs randomized, reproducible schedules of calls, errors and
+//! cancellations against a stack and checks its invariants along the way.
+//!
+//! Transport wrappers can be tested without real sockets with [`duplex`], an
+//! in-memory connector usable wherever a [`MakeConnection`] is expected, and
+//! [`MockIo`], a stream following a script of reads, writes, stalls and errors.
+//!
+//! The balancers and the health checks can be tested without a registry with
+//! [`discover`], a [`Discover`] whose changes are pushed from the test body, and
+//! [`ScriptedEndpoints`], which makes endpoint services whose readiness, load and
+//! failures are set by key.
+//!
+//! [`MakeConnection`]: crate::make::MakeConnection
+
+use std::{
+    fmt,
+    future::Future,
+    marker::PhantomData,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    },
+};
+
+use tokio::sync::{mpsc, oneshot};
+
+use crate::{MaybeSend, Service};
+
+pub mod conformance;
+mod discover;
+mod fixtures;
+mod future;
+mod fuzz;
+mod io;
+
+pub use self::{
+    discover::{discover, Discover, DiscoverHandle, ScriptedEndpoint, ScriptedEndpoints},
+    fixtures::{AlwaysFail, Delay, Echo, Identity},
+    future::{
+        assert_pending, assert_ready, controlled, poll_once, track, Controlled, DropTracker,
+        Release, Tracked,
+    },
+    fuzz::{Event, Fuzz, MockError, Report},
+    io::{duplex, DuplexConnector, DuplexListener, MockIo, MockIoBuilder},
+};
+
+type Message<Req, Res, Err> = (Req, oneshot::Sender<Result<Res, Err>>);
+
+/// Creates a new [`Mock`] service and the [`Handle`] controlling it.
+#[allow(clippy::type_complexity)]
+pub fn pair<Cx, Req, Res, Err>() -> (Mock<Cx, Req, Res, Err>, Handle<Req, Res, Err>) {
+    let (tx, rx) = mpsc::unbounded_channel();
+    let calls = Arc::new(AtomicUsize::new(0));
+
+    let mock = Mock {
+        tx,
+        calls: calls.clone(),
+        _phantom: PhantomData,
+    };
+    let handle = Handle { rx, calls };
+
+    (mock, handle)
+}
+
+/// A mock [`Service`] whose responses are provided by the paired [`Handle`].
+///
+/// Every call is forwarded to the [`Handle`] and resolves once the test sends back
+/// a response or an error.
+///
+/// # Panics
+///
+/// The call panics if the [`Handle`] or the [`SendResponse`] of the request is
+/// dropped without answering it.
+pub struct Mock<Cx, Req, Res, Err> {
+    tx: mpsc::UnboundedSender<Message<Req, Res, Err>>,
+    calls: Arc<AtomicUsize>,
+    _phantom: PhantomData<fn(Cx)>,
+}
+
+impl<Cx, Req, Res, Err> Mock<Cx, Req, Res, Err> {
+    fn send(&self, req: Req) -> oneshot::Receiver<Result<Res, Err>> {
+        self.calls.fetch_add(1, Ordering::AcqRel);
+
+        let (tx, rx) = oneshot::channel();
+        if self.tx.send((req, tx)).is_err() {
+            panic!("the `Handle` of the mock service has been dropped");
+        }
+        rx
+    }
+}
+
+async fn recv<Res, Err>(rx: oneshot::Receiver<Result<Res, Err>>) -> Result<Res, Err> {
+    match rx.await {
+        Ok(res) => res,
+        Err(_) => panic!("the mock request was dropped without a response"),
+    }
+}
+
+impl<Cx, Req, Res, Err> Service<Cx, Req> for Mock<Cx, Req, Res, Err>
+where
+    Res: MaybeSend,
+    Err: MaybeSend,
+{
+    type Response = Res;
+    type Error = Err;
+
+    #[cfg(feature = "service_send")]
+    fn call(
+        &self,
+        _cx: &mut Cx,
+        req: Req,
+    ) -> impl Future<Output = Result<Self::Response, Self::Error>> + Send {
+        recv(self.send(req))
+    }
+
+    #[cfg(not(feature = "service_send"))]
+    fn call(
+        &self,
+        _cx: &mut Cx,
+        req: Req,
+    ) -> impl Future<Output = Result<Self::Response, Self::Error>> {
+        recv(self.send(req))
+    }
+}
+
+impl<Cx, Req, Res, Err> Clone for Mock<Cx, Req, Res, Err> {
+    fn clone(&self) -> Self {
+        Self {
+            tx: self.tx.clone(),
+            calls: self.calls.clone(),
+            _phantom: PhantomData,
+        }
+    }
+}
+
+impl<Cx, Req, Res, Err> fmt::Debug for Mock<Cx, Req, Res, Err> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Mock")
+            .field("calls", &self.calls.load(Ordering::Acquire))
+            .finish()
+    }
+}
+
+/// Handle to the paired [`Mock`] service.
+pub struct Handle<Req, Res, Err> {
+    rx: mpsc::UnboundedReceiver<Message<Req, Res, Err>>,
+    calls: Arc<AtomicUsize>,
+}
+
+impl<Req, Res, Err> Handle<Req, Res, Err> {
+    /// Waits for the next request that reaches the mock service.
+    ///
+    /// Returns `None` once all the clones of the [`Mock`] have been dropped and
+    /// every request has been received.
+    pub async fn next_request(&mut self) -> Option<(Req, SendResponse<Res, Err>)> {
+        self.rx
+            .recv()
+            .await
+            .map(|(req, tx)| (req, SendResponse { tx }))
+    }
+
+    /// Returns the next request if one has already reached the mock service.
+    pub fn try_next_request(&mut self) -> Option<(Req, SendResponse<Res, Err>)> {
+        self.rx
+            .try_recv()
+            .ok()
+            .map(|(req, tx)| (req, SendResponse { tx }))
+    }
+
+    /// Returns how many times the mock service has been called.
+    pub fn call_count(&self) -> usize {
+        self.calls.load(Ordering::Acquire)
+    }
+
+    /// Asserts that the mock service has been called exactly `n` times.
+    #[track_caller]
+    pub fn assert_call_count(&self, n: usize) {
+        let calls = self.call_count();
+        assert_eq!(
+            calls, n,
+            "expected the mock service to be called {n} times, but it was called {calls} times"
+        );
+    }
+
+    /// Asserts that no request reached the mock service without being received
+    /// yet.
+    ///
+    /// The pending requests are left untouched, so their calls can still be
+    /// answered.
+    #[track_caller]
+    pub fn assert_no_pending_request(&self) {
+        assert!(
+            self.rx.is_empty(),
+            "expected no pending request on the mock service, found {}",
+            self.rx.len()
+        );
+    }
+}
+
+impl<Req, Res, Err> fmt::Debug for Handle<Req, Res, Err> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Handle")
+            .field("calls", &self.call_count())
+            .finish()
+    }
+}
+
+/// Sends the response of a request received by a [`Handle`].
+pub struct SendResponse<Res, Err> {
+    tx: oneshot::Sender<Result<Res, Err>>,
+}
+
+impl<Res, Err> SendResponse<Res, Err> {
+    /// Resolves the call with the given response.
+    pub fn send_response(self, res: Res) {
+        let _ = self.tx.send(Ok(res));
+    }
+
+    /// Resolves the call with the given error.
+    pub fn send_error(self, err: Err) {
+        let _ = self.tx.send(Err(err));
+    }
+
+    /// Returns `true` if the caller has stopped waiting for the response, e.g.
+    /// because the call future was dropped.
+    pub fn is_canceled(&self) -> bool {
+        self.tx.is_closed()
+    }
+}
+
+impl<Res, Err> fmt::Debug for SendResponse<Res, Err> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("SendResponse").finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn responds_with_handle_result() {
+        let (svc, mut handle) = pair::<(), u32, u32, &'static str>();
+
+        let call = tokio::spawn({
+            let svc = svc.clone();
+            async move { svc.call(&mut (), 1).await }
+        });
+        let (req, tx) = handle.next_request().await.unwrap();
+        assert_eq!(req, 1);
+        tx.send_error("boom");
+        assert_eq!(call.await.unwrap(), Err("boom"));
+
+        let call = tokio::spawn(async move { svc.call(&mut (), 2).await });
+        let (req, tx) = handle.next_request().await.unwrap();
+        tx.send_response(req * 10);
+        assert_eq!(call.await.unwrap(), Ok(20));
+
+        handle.assert_call_count(2);
+        handle.assert_no_pending_request();
+        assert!(handle.next_request().await.is_none());
+    }
+
+    #[tokio::test]
+    async fn observes_cancellation() {
+        let (svc, mut handle) = pair::<(), (), (), ()>();
+
+        let mut cx = ();
+        let fut = svc.call(&mut cx, ());
+        let (_, tx) = handle.try_next_request().unwrap();
+        assert!(!tx.is_canceled());
+        drop(fut);
+        assert!(tx.is_canceled());
+    }
+
+    #[tokio::test]
+    async fn asserting_no_pending_request_keeps_it() {
+        let (svc, mut handle) = pair::<(), u32, u32, ()>();
+
+        let call = tokio::spawn(async move { svc.call(&mut (), 1).await });
+        while handle.call_count() == 0 {
+            tokio::task::yield_now().await;
+        }
+        let assertion = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+            handle.assert_no_pending_request()
+        }));
+        assert!(assertion.is_err());
+
+        let (req, tx) = handle.next_request().await.unwrap();
+        tx.send_response(req + 1);
+        assert_eq!(call.await.unwrap(), Ok(2));
+        handle.assert_no_pending_request();
+    }
+}
@@ -0,0 +1,13 @@
+//! A prelude for composing services and layers.
+//!
+//! ```rust
+//! use motore::prelude::*;
+//! ```
+
+pub use crate::{
+    builder::ServiceBuilder,
+    layer::{layer_fn, Layer, LayerExt, Layers},
+    service::{
+        service_fn, ArcService, BoxCloneService, BoxService, Service, ServiceExt, UnaryService,
+    },
+};
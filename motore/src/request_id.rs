@@ -0,0 +1,367 @@
+//! Gives every request an ID, to correlate what is logged about it.
+//!
+//! [`SetRequestId`], usually the outermost layer of a stack, makes sure the
+//! context of every request has a [`RequestId`], generating one with an
+//! [`IdGenerator`] when the context doesn't have one yet, like when the caller
+//! didn't send one. The layers below, and the handler, read it from the context to
+//! attach it to their logs, metrics and outgoing requests.
+//!
+//! The context stores the ID by implementing [`RequestIdContext`]. The default
+//! generator makes [UUIDv7](https://www.rfc-editor.org/rfc/rfc9562#name-uuid-version-7)s,
+//! which sort by creation time.
+
+use std::{
+    fmt,
+    sync::Mutex,
+    task::{Context, Poll},
+};
+
+#[cfg(not(all(target_arch = "wasm32", not(target_os = "wasi"))))]
+use std::time::SystemTime;
+
+#[cfg(all(target_arch = "wasm32", not(target_os = "wasi")))]
+use web_time::SystemTime;
+
+use crate::{
+    layer::Layer,
+    load::{Load, Ready},
+    service::Service,
+    utils::rng::Rng,
+    MaybeSend, MaybeSync,
+};
+
+/// The ID of a request.
+#[derive(Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct RequestId(String);
+
+impl RequestId {
+    /// Creates a request ID, for instance from the one sent by the caller.
+    pub fn new(id: impl Into<String>) -> Self {
+        RequestId(id.into())
+    }
+
+    /// Returns the ID as a string.
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+}
+
+impl fmt::Display for RequestId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+impl From<String> for RequestId {
+    fn from(id: String) -> Self {
+        RequestId(id)
+    }
+}
+
+impl From<RequestId> for String {
+    fn from(id: RequestId) -> Self {
+        id.0
+    }
+}
+
+/// A context carrying the [`RequestId`] of its request.
+pub trait RequestIdContext {
+    /// Returns the ID of the request, if it has one.
+    fn request_id(&self) -> Option<&RequestId>;
+
+    /// Sets the ID of the request.
+    fn set_request_id(&mut self, id: RequestId);
+}
+
+/// Generates the IDs of the requests without one.
+///
+/// This is implemented for closures returning a [`RequestId`], and by [`UuidV7`].
+pub trait IdGenerator {
+    /// Returns a new ID.
+    fn generate(&self) -> RequestId;
+}
+
+impl<F> IdGenerator for F
+where
+    F: Fn() -> RequestId,
+{
+    fn generate(&self) -> RequestId {
+        self()
+    }
+}
+
+/// Generates random [UUIDv7](https://www.rfc-editor.org/rfc/rfc9562#name-uuid-version-7)s,
+/// made of the current Unix time in milliseconds followed by random bits.
+#[derive(Debug)]
+pub struct UuidV7 {
+    rng: Mutex<Rng>,
+}
+
+impl UuidV7 {
+    /// Creates a generator with a random seed.
+    pub fn new() -> Self {
+        UuidV7 {
+            rng: Mutex::new(Rng::new()),
+        }
+    }
+}
+
+impl Clone for UuidV7 {
+    /// Returns a generator with random bits of its own.
+    fn clone(&self) -> Self {
+        Self::new()
+    }
+}
+
+impl Default for UuidV7 {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl IdGenerator for UuidV7 {
+    fn generate(&self) -> RequestId {
+        let millis = SystemTime::now()
+            .duration_since(SystemTime::UNIX_EPOCH)
+            .map_or(0, |elapsed| elapsed.as_millis() as u64);
+        let (a, b) = {
+            let mut rng = self.rng.lock().unwrap_or_else(|e| e.into_inner());
+            (rng.next_u64(), rng.next_u64())
+        };
+        // 48 bits of time, the version, 12 random bits, the variant and 62 random
+        // bits.
+        let uuid = (u128::from(millis & 0xffff_ffff_ffff) << 80)
+            | (0x7 << 76)
+            | (u128::from(a & 0xfff) << 64)
+            | (0b10 << 62)
+            | u128::from(b >> 2);
+        RequestId(format!(
+            "{:08x}-{:04x}-{:04x}-{:04x}-{:012x}",
+            uuid >> 96,
+            (uuid >> 80) & 0xffff,
+            (uuid >> 64) & 0xffff,
+            (uuid >> 48) & 0xffff,
+            uuid & 0xffff_ffff_ffff,
+        ))
+    }
+}
+
+/// Sets the [`RequestId`] of the requests without one, before calling the inner
+/// service.
+///
+/// See the [module level docs](self) for details.
+#[derive(Clone)]
+pub struct SetRequestId<S, G = UuidV7> {
+    inner: S,
+    generator: G,
+}
+
+impl<S> SetRequestId<S> {
+    /// Creates a `SetRequestId` generating [`UuidV7`]s.
+    pub fn new(inner: S) -> Self {
+        SetRequestId {
+            inner,
+            generator: UuidV7::new(),
+        }
+    }
+}
+
+impl<S, G> SetRequestId<S, G> {
+    /// Creates a `SetRequestId` generating the IDs with `generator`.
+    pub const fn with_generator(inner: S, generator: G) -> Self {
+        SetRequestId { inner, generator }
+    }
+}
+
+impl<Cx, Req, S, G> Service<Cx, Req> for SetRequestId<S, G>
+where
+    Req: 'static + MaybeSend,
+    S: Service<Cx, Req> + 'static + MaybeSend + MaybeSync,
+    G: IdGenerator + MaybeSync,
+    Cx: RequestIdContext + 'static + MaybeSend,
+{
+    type Response = S::Response;
+
+    type Error = S::Error;
+
+    async fn call(&self, cx: &mut Cx, req: Req) -> Result<Self::Response, Self::Error> {
+        if cx.request_id().is_none() {
+            cx.set_request_id(self.generator.generate());
+        }
+        self.inner.call(cx, req).await
+    }
+}
+
+impl<S, G> Ready for SetRequestId<S, G>
+where
+    S: Ready,
+{
+    fn poll_ready(&self, cx: &mut Context<'_>) -> Poll<()> {
+        self.inner.poll_ready(cx)
+    }
+}
+
+impl<S, G> Load for SetRequestId<S, G>
+where
+    S: Load,
+{
+    type Metric = S::Metric;
+
+    fn load(&self) -> Self::Metric {
+        self.inner.load()
+    }
+}
+
+impl<S: fmt::Debug, G> fmt::Debug for SetRequestId<S, G> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("SetRequestId")
+            .field("inner", &self.inner)
+            .field("generator", &format_args!("{}", std::any::type_name::<G>()))
+            .finish()
+    }
+}
+
+/// Applies a [`SetRequestId`] to a service.
+///
+/// # Example
+///
+/// ```rust
+/// use motore::{
+///     builder::ServiceBuilder,
+///     request_id::{RequestId, RequestIdContext, RequestIdLayer},
+///     service::service_fn,
+///     BoxError, Service,
+/// };
+///
+/// #[derive(Default)]
+/// struct Cx {
+///     request_id: Option<RequestId>,
+/// }
+///
+/// impl RequestIdContext for Cx {
+///     fn request_id(&self) -> Option<&RequestId> {
+///         self.request_id.as_ref()
+///     }
+///
+///     fn set_request_id(&mut self, id: RequestId) {
+///         self.request_id = Some(id);
+///     }
+/// }
+///
+/// # #[tokio::main(flavor = "current_thread")]
+/// # async fn main() {
+/// async fn handle(cx: &mut Cx, req: String) -> Result<String, BoxError> {
+///     let id = cx.request_id().unwrap();
+///     Ok(format!("{id}: {req}"))
+/// }
+///
+/// let svc = ServiceBuilder::new()
+///     .layer(RequestIdLayer::new())
+///     .service(service_fn(handle));
+///
+/// // The ID sent by the caller is kept.
+/// let mut cx = Cx {
+///     request_id: Some(RequestId::new("42")),
+/// };
+/// assert_eq!(svc.call(&mut cx, "ping".into()).await.unwrap(), "42: ping");
+/// # }
+/// ```
+#[derive(Clone)]
+pub struct RequestIdLayer<G = UuidV7> {
+    generator: G,
+}
+
+impl RequestIdLayer {
+    /// Creates a layer generating [`UuidV7`]s.
+    pub fn new() -> Self {
+        RequestIdLayer {
+            generator: UuidV7::new(),
+        }
+    }
+}
+
+impl Default for RequestIdLayer {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<G> RequestIdLayer<G> {
+    /// Sets the generator of the IDs.
+    pub fn generator<H>(self, generator: H) -> RequestIdLayer<H> {
+        RequestIdLayer { generator }
+    }
+}
+
+impl<G> fmt::Debug for RequestIdLayer<G> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("RequestIdLayer")
+            .field("generator", &format_args!("{}", std::any::type_name::<G>()))
+            .finish()
+    }
+}
+
+impl<S, G> Layer<S> for RequestIdLayer<G> {
+    type Service = SetRequestId<S, G>;
+
+    fn layer(self, inner: S) -> Self::Service {
+        SetRequestId::with_generator(inner, self.generator)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::service::service_fn;
+
+    #[derive(Default)]
+    struct Cx {
+        request_id: Option<RequestId>,
+    }
+
+    impl RequestIdContext for Cx {
+        fn request_id(&self) -> Option<&RequestId> {
+            self.request_id.as_ref()
+        }
+
+        fn set_request_id(&mut self, id: RequestId) {
+            self.request_id = Some(id);
+        }
+    }
+
+    #[test]
+    fn generates_uuid_v7s() {
+        let generator = UuidV7::new();
+        let (a, b) = (generator.generate(), generator.generate());
+        assert_ne!(a, b);
+        for id in [a.as_str(), b.as_str()] {
+            let groups: Vec<_> = id.split('-').map(str::len).collect();
+            assert_eq!(groups, [8, 4, 4, 4, 12]);
+            assert!(id.chars().all(|c| c == '-' || c.is_ascii_hexdigit()));
+            assert_eq!(&id[14..15], "7");
+            assert!(matches!(&id[19..20], "8" | "9" | "a" | "b"));
+        }
+        // The IDs start with the time in milliseconds.
+        assert!(a.as_str()[..13] <= b.as_str()[..13]);
+    }
+
+    #[tokio::test]
+    async fn sets_the_missing_ids() {
+        let svc = RequestIdLayer::new()
+            .generator(|| RequestId::new("generated"))
+            .layer(service_fn(|cx: &mut Cx, _req: ()| {
+                let id = cx.request_id().cloned();
+                async move { Ok::<_, String>(id) }
+            }));
+
+        let mut cx = Cx::default();
+        let id = svc.call(&mut cx, ()).await.unwrap();
+        assert_eq!(id, Some(RequestId::new("generated")));
+
+        let mut cx = Cx {
+            request_id: Some(RequestId::new("sent")),
+        };
+        let id = svc.call(&mut cx, ()).await.unwrap();
+        assert_eq!(id, Some(RequestId::new("sent")));
+    }
+}
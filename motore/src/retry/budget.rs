@@ -0,0 +1,217 @@
+//! Caps retries as a share of the regular traffic.
+//!
+//! When a backend struggles, every client retrying its failed requests multiplies
+//! the load on it, and the retries themselves fail, turning a slowdown into an
+//! outage. A [`Budget`] prevents such retry storms: every request deposits into
+//! it, every retry withdraws from it, and retries are refused once it is empty.
+//!
+//! A budget is meant to be shared, behind an [`Arc`](std::sync::Arc), by all the
+//! services of a client, and used by their retry [`Policy`](super::Policy).
+
+use std::{
+    fmt,
+    sync::{Mutex, MutexGuard},
+    time::Duration,
+};
+
+use crate::timer::{DefaultTimer, Instant, Timer};
+
+const SLOTS: usize = 10;
+
+/// A retry budget, allowing retries up to a percentage of the requests made in a
+/// recent time window, plus a minimum number of retries per second.
+///
+/// # Example
+///
+/// ```rust
+/// use std::{sync::Arc, time::Duration};
+///
+/// use motore::retry::{budget::Budget, Policy};
+///
+/// #[derive(Clone)]
+/// struct Budgeted {
+///     budget: Arc<Budget>,
+///     deposited: bool,
+/// }
+///
+/// impl<Cx, Req: Clone, Res, E> Policy<Cx, Req, Res, E> for Budgeted {
+///     fn clone_request(&self, _cx: &Cx, req: &Req) -> Option<Req> {
+///         Some(req.clone())
+///     }
+///
+///     fn retry(&mut self, _cx: &mut Cx, _req: &Req, res: &Result<Res, E>) -> Option<Duration> {
+///         // The policy is cloned for each request, so this deposits once per request.
+///         if !self.deposited {
+///             self.budget.deposit();
+///             self.deposited = true;
+///         }
+///         (res.is_err() && self.budget.withdraw()).then_some(Duration::ZERO)
+///     }
+/// }
+///
+/// // Allows retrying 20% of the requests made in the last 10 seconds, and at
+/// // least 10 retries per second.
+/// let budget = Arc::new(Budget::new(Duration::from_secs(10), 10, 0.2));
+/// ```
+pub struct Budget<T = DefaultTimer> {
+    window: Mutex<Window>,
+    slot: Duration,
+    reserve: i64,
+    deposit_amount: i64,
+    withdraw_amount: i64,
+    timer: T,
+}
+
+struct Window {
+    slots: [i64; SLOTS],
+    current: usize,
+    started: Instant,
+}
+
+impl Budget {
+    /// Creates a budget allowing `retry_percent` retries per request made within the
+    /// last `ttl`, plus `min_per_sec` retries per second.
+    ///
+    /// For example, a `retry_percent` of `0.2` allows one retry every 5 requests.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `ttl` is not between 1 and 60 seconds, or if `retry_percent` is not
+    /// between 0 and 1000.
+    pub fn new(ttl: Duration, min_per_sec: u32, retry_percent: f32) -> Self {
+        assert!(
+            (Duration::from_secs(1)..=Duration::from_secs(60)).contains(&ttl),
+            "the ttl must be between 1 and 60 seconds"
+        );
+        assert!(
+            (0.0..=1000.0).contains(&retry_percent),
+            "the retry percent must be between 0 and 1000"
+        );
+
+        // Tokens are integers, so fractional percents are scaled up.
+        let (deposit_amount, withdraw_amount) = if retry_percent == 0.0 {
+            (0, 1)
+        } else if retry_percent <= 1.0 {
+            (1, (1.0 / retry_percent) as i64)
+        } else {
+            (1000, (1000.0 / retry_percent) as i64)
+        };
+        let reserve = i64::from(min_per_sec) * ttl.as_secs() as i64 * withdraw_amount;
+
+        let timer = DefaultTimer::new();
+        Budget {
+            window: Mutex::new(Window {
+                slots: [0; SLOTS],
+                current: 0,
+                started: timer.now(),
+            }),
+            slot: ttl / SLOTS as u32,
+            reserve,
+            deposit_amount,
+            withdraw_amount,
+            timer,
+        }
+    }
+}
+
+impl<T: Timer> Budget<T> {
+    /// Sets the timer expiring the requests older than the ttl, [`DefaultTimer`]
+    /// by default.
+    pub fn timer<U: Timer>(self, timer: U) -> Budget<U> {
+        let mut window = self.window.into_inner().unwrap_or_else(|e| e.into_inner());
+        window.started = timer.now();
+        Budget {
+            window: Mutex::new(window),
+            slot: self.slot,
+            reserve: self.reserve,
+            deposit_amount: self.deposit_amount,
+            withdraw_amount: self.withdraw_amount,
+            timer,
+        }
+    }
+
+    /// Records a request, making retries available.
+    pub fn deposit(&self) {
+        let mut window = self.window();
+        let current = window.current;
+        window.slots[current] += self.deposit_amount;
+    }
+
+    /// Tries to spend the budget for a retry, returning whether the retry is allowed.
+    pub fn withdraw(&self) -> bool {
+        let mut window = self.window();
+        if self.reserve + window.slots.iter().sum::<i64>() < self.withdraw_amount {
+            return false;
+        }
+        let current = window.current;
+        window.slots[current] -= self.withdraw_amount;
+        true
+    }
+
+    /// Returns the window, with the slots older than the ttl expired.
+    fn window(&self) -> MutexGuard<'_, Window> {
+        let mut window = self.window.lock().unwrap_or_else(|e| e.into_inner());
+        let elapsed = self
+            .timer
+            .now()
+            .saturating_duration_since(window.started)
+            .as_nanos()
+            / self.slot.as_nanos();
+        for _ in 0..elapsed.min(SLOTS as u128) {
+            window.current = (window.current + 1) % SLOTS;
+            let current = window.current;
+            window.slots[current] = 0;
+        }
+        window.started += self.slot * elapsed as u32;
+        window
+    }
+}
+
+impl<T: Timer> fmt::Debug for Budget<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let window = self.window();
+        f.debug_struct("Budget")
+            .field(
+                "balance",
+                &(self.reserve + window.slots.iter().sum::<i64>()),
+            )
+            .field("deposit_amount", &self.deposit_amount)
+            .field("withdraw_amount", &self.withdraw_amount)
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test(start_paused = true)]
+    async fn retries_are_capped_by_recent_requests() {
+        let budget = Budget::new(Duration::from_secs(1), 0, 0.5);
+        assert!(!budget.withdraw());
+
+        for _ in 0..4 {
+            budget.deposit();
+        }
+        assert!(budget.withdraw());
+        assert!(budget.withdraw());
+        assert!(!budget.withdraw());
+
+        for _ in 0..4 {
+            budget.deposit();
+        }
+        tokio::time::advance(Duration::from_millis(1100)).await;
+        assert!(!budget.withdraw());
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn reserve_allows_a_minimum_rate() {
+        let budget = Budget::new(Duration::from_secs(1), 2, 0.0);
+        assert!(budget.withdraw());
+        assert!(budget.withdraw());
+        assert!(!budget.withdraw());
+
+        tokio::time::advance(Duration::from_millis(1100)).await;
+        assert!(budget.withdraw());
+    }
+}
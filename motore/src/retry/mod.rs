@@ -0,0 +1,405 @@
+//! Retries failed requests.
+//!
+//! Whether and when to retry is decided by a [`Policy`]. The policy is cloned for
+//! every request, so it can keep per-request state, like the number of attempts
+//! made so far, while the shared configuration lives behind the clone.
+//!
+//! Since the inner service takes the request by value, the policy also makes the
+//! copy of the request used by the next attempt; requests that can't be copied,
+//! like ones with a streaming body, are simply not retried.
+//!
+//! [`BackoffPolicy`] covers the common case of retrying some errors, waiting as
+//! told by a [`Backoff`].
+//!
+//! A [`Budget`](budget::Budget) can be shared by the policies of a client to cap
+//! the retries as a share of its traffic.
+
+use std::{
+    fmt,
+    task::{Context, Poll},
+    time::Duration,
+};
+
+use crate::{
+    backoff::Backoff,
+    layer::Layer,
+    load::{Load, Ready},
+    service::Service,
+    timer::{DefaultTimer, Timer},
+    MaybeSend, MaybeSync,
+};
+
+pub mod budget;
+
+/// Decides whether and when a request is retried.
+///
+/// # Example
+///
+/// ```rust
+/// use std::time::Duration;
+///
+/// use motore::retry::Policy;
+///
+/// /// Retries failed requests up to 3 times, waiting 100ms between attempts.
+/// #[derive(Clone)]
+/// struct Attempts(usize);
+///
+/// impl<Cx, Req: Clone, Res, E> Policy<Cx, Req, Res, E> for Attempts {
+///     fn clone_request(&self, _cx: &Cx, req: &Req) -> Option<Req> {
+///         Some(req.clone())
+///     }
+///
+///     fn retry(&mut self, _cx: &mut Cx, _req: &Req, res: &Result<Res, E>) -> Option<Duration> {
+///         if res.is_ok() || self.0 == 0 {
+///             return None;
+///         }
+///         self.0 -= 1;
+///         Some(Duration::from_millis(100))
+///     }
+/// }
+/// ```
+pub trait Policy<Cx, Req, Res, E> {
+    /// Returns a copy of the request for the next attempt, or `None` if it can't be
+    /// retried.
+    ///
+    /// This is called before every attempt, as the attempt consumes the request.
+    fn clone_request(&self, cx: &Cx, req: &Req) -> Option<Req>;
+
+    /// Decides whether to retry after an attempt completed with `res`, returning how
+    /// long to wait before the next attempt, or `None` to return `res`.
+    ///
+    /// `req` is the copy of the request the next attempt would use.
+    fn retry(&mut self, cx: &mut Cx, req: &Req, res: &Result<Res, E>) -> Option<Duration>;
+}
+
+/// A [`Policy`] retrying the results matched by a predicate, with the delays of a
+/// [`Backoff`], until the backoff gives up.
+///
+/// The request is cloned for every attempt.
+///
+/// # Example
+///
+/// ```rust
+/// use std::time::Duration;
+///
+/// use motore::{
+///     backoff::{BackoffExt, Exponential},
+///     retry::{BackoffPolicy, RetryLayer},
+/// };
+///
+/// let backoff = Exponential::new(Duration::from_millis(50), Duration::from_secs(1))
+///     .full_jitter()
+///     .max_attempts(3);
+/// let layer = RetryLayer::new(BackoffPolicy::new(
+///     backoff,
+///     |res: &Result<String, std::io::Error>| res.is_err(),
+/// ));
+/// ```
+#[derive(Clone)]
+pub struct BackoffPolicy<B, F> {
+    backoff: B,
+    retryable: F,
+}
+
+impl<B, F> BackoffPolicy<B, F> {
+    /// Creates a policy retrying the results for which `retryable` returns `true`.
+    pub const fn new(backoff: B, retryable: F) -> Self {
+        BackoffPolicy { backoff, retryable }
+    }
+}
+
+impl<Cx, Req, Res, E, B, F> Policy<Cx, Req, Res, E> for BackoffPolicy<B, F>
+where
+    Req: Clone,
+    B: Backoff,
+    F: Fn(&Result<Res, E>) -> bool,
+{
+    fn clone_request(&self, _cx: &Cx, req: &Req) -> Option<Req> {
+        Some(req.clone())
+    }
+
+    fn retry(&mut self, _cx: &mut Cx, _req: &Req, res: &Result<Res, E>) -> Option<Duration> {
+        if !(self.retryable)(res) {
+            return None;
+        }
+        self.backoff.next_backoff()
+    }
+}
+
+impl<B: fmt::Debug, F> fmt::Debug for BackoffPolicy<B, F> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("BackoffPolicy")
+            .field("backoff", &self.backoff)
+            .field("retryable", &format_args!("{}", std::any::type_name::<F>()))
+            .finish()
+    }
+}
+
+/// Retries the requests to the inner service as decided by a [`Policy`].
+#[derive(Clone)]
+pub struct Retry<S, P, T = DefaultTimer> {
+    inner: S,
+    policy: P,
+    timer: T,
+}
+
+impl<S, P> Retry<S, P> {
+    pub const fn new(inner: S, policy: P) -> Self {
+        Self {
+            inner,
+            policy,
+            timer: DefaultTimer::new(),
+        }
+    }
+}
+
+impl<S, P, T> Retry<S, P, T> {
+    /// Sets the timer waiting between the attempts, [`DefaultTimer`] by default.
+    pub fn timer<U>(self, timer: U) -> Retry<S, P, U> {
+        Retry {
+            inner: self.inner,
+            policy: self.policy,
+            timer,
+        }
+    }
+}
+
+impl<Cx, Req, S, P, T> Service<Cx, Req> for Retry<S, P, T>
+where
+    Req: 'static + MaybeSend,
+    S: Service<Cx, Req> + 'static + MaybeSend + MaybeSync,
+    S::Response: MaybeSend,
+    S::Error: MaybeSend,
+    P: Policy<Cx, Req, S::Response, S::Error> + Clone + MaybeSend + MaybeSync,
+    T: Timer + MaybeSend + MaybeSync,
+    Cx: 'static + MaybeSend,
+{
+    type Response = S::Response;
+
+    type Error = S::Error;
+
+    async fn call(&self, cx: &mut Cx, mut req: Req) -> Result<Self::Response, Self::Error> {
+        let mut policy = self.policy.clone();
+        loop {
+            let next = policy.clone_request(cx, &req);
+            let res = self.inner.call(cx, req).await;
+            let Some(next) = next else {
+                return res;
+            };
+            match policy.retry(cx, &next, &res) {
+                Some(backoff) => {
+                    if !backoff.is_zero() {
+                        self.timer.sleep(backoff).await;
+                    }
+                    req = next;
+                }
+                None => return res,
+            }
+        }
+    }
+}
+
+impl<S, P, T> Ready for Retry<S, P, T>
+where
+    S: Ready,
+{
+    fn poll_ready(&self, cx: &mut Context<'_>) -> Poll<()> {
+        self.inner.poll_ready(cx)
+    }
+}
+
+impl<S, P, T> Load for Retry<S, P, T>
+where
+    S: Load,
+{
+    type Metric = S::Metric;
+
+    fn load(&self) -> Self::Metric {
+        self.inner.load()
+    }
+}
+
+/// Applies a [`Retry`] middleware with the given policy.
+#[derive(Clone)]
+pub struct RetryLayer<P, T = DefaultTimer> {
+    policy: P,
+    timer: T,
+}
+
+impl<P> RetryLayer<P> {
+    pub const fn new(policy: P) -> Self {
+        RetryLayer {
+            policy,
+            timer: DefaultTimer::new(),
+        }
+    }
+}
+
+impl<P, T> RetryLayer<P, T> {
+    /// Sets the timer waiting between the attempts, [`DefaultTimer`] by default.
+    pub fn timer<U>(self, timer: U) -> RetryLayer<P, U> {
+        RetryLayer {
+            policy: self.policy,
+            timer,
+        }
+    }
+}
+
+impl<S, P, T> Layer<S> for RetryLayer<P, T> {
+    type Service = Retry<S, P, T>;
+
+    fn layer(self, inner: S) -> Self::Service {
+        Retry {
+            inner,
+            policy: self.policy,
+            timer: self.timer,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    };
+
+    use tokio::time::Instant;
+
+    use super::*;
+    use crate::service::service_fn;
+
+    /// Retries errors up to `max` times, backing off 10ms more on every attempt,
+    /// and recording the attempts in the context.
+    #[derive(Clone)]
+    struct Linear {
+        max: usize,
+        attempts: usize,
+    }
+
+    impl Policy<usize, u32, u32, &'static str> for Linear {
+        fn clone_request(&self, _cx: &usize, req: &u32) -> Option<u32> {
+            (*req != 0).then_some(*req)
+        }
+
+        fn retry(
+            &mut self,
+            cx: &mut usize,
+            _req: &u32,
+            res: &Result<u32, &'static str>,
+        ) -> Option<Duration> {
+            *cx += 1;
+            if res.is_ok() || self.attempts == self.max {
+                return None;
+            }
+            self.attempts += 1;
+            Some(Duration::from_millis(10 * self.attempts as u64))
+        }
+    }
+
+    fn flaky(failures: usize) -> impl Service<usize, u32, Response = u32, Error = &'static str> {
+        let calls = Arc::new(AtomicUsize::new(0));
+        service_fn(move |_cx: &mut usize, req: u32| {
+            let n = calls.fetch_add(1, Ordering::SeqCst);
+            async move {
+                if n < failures {
+                    Err("unavailable")
+                } else {
+                    Ok(req)
+                }
+            }
+        })
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn retries_until_success() {
+        let svc = RetryLayer::new(Linear {
+            max: 3,
+            attempts: 0,
+        })
+        .layer(flaky(2));
+        let start = Instant::now();
+        let mut attempts = 0;
+        assert_eq!(svc.call(&mut attempts, 7).await, Ok(7));
+        assert_eq!(attempts, 3);
+        assert_eq!(start.elapsed(), Duration::from_millis(30));
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn gives_up_after_the_policy_does() {
+        let svc = RetryLayer::new(Linear {
+            max: 1,
+            attempts: 0,
+        })
+        .layer(flaky(5));
+        let mut attempts = 0;
+        assert_eq!(svc.call(&mut attempts, 7).await, Err("unavailable"));
+        assert_eq!(attempts, 2);
+
+        // Requests that can't be copied are not retried.
+        let mut attempts = 0;
+        assert_eq!(svc.call(&mut attempts, 0).await, Err("unavailable"));
+        assert_eq!(attempts, 0);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn backs_off_between_attempts() {
+        use crate::backoff::{BackoffExt, Exponential};
+
+        let backoff =
+            Exponential::new(Duration::from_millis(10), Duration::from_secs(1)).max_attempts(2);
+        let svc = RetryLayer::new(BackoffPolicy::new(
+            backoff,
+            |res: &Result<u32, &'static str>| res.is_err(),
+        ))
+        .layer(flaky(6));
+
+        let start = Instant::now();
+        assert_eq!(svc.call(&mut 0, 7).await, Err("unavailable"));
+        assert_eq!(start.elapsed(), Duration::from_millis(30));
+
+        // Every request starts with a fresh backoff.
+        let start = Instant::now();
+        assert_eq!(svc.call(&mut 0, 7).await, Err("unavailable"));
+        assert_eq!(start.elapsed(), Duration::from_millis(30));
+        assert_eq!(svc.call(&mut 0, 7).await, Ok(7));
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn concurrent_requests_draw_their_own_jitter() {
+        use crate::backoff::{BackoffExt, Exponential};
+
+        let attempts = Arc::new(std::sync::Mutex::new(Vec::new()));
+        let svc = service_fn({
+            let attempts = attempts.clone();
+            move |_cx: &mut usize, req: u32| {
+                attempts.lock().unwrap().push((req, Instant::now()));
+                async { Err::<u32, _>("unavailable") }
+            }
+        });
+        let backoff = Exponential::new(Duration::from_secs(1), Duration::from_secs(10))
+            .full_jitter()
+            .max_attempts(3);
+        let svc = RetryLayer::new(BackoffPolicy::new(
+            backoff,
+            |res: &Result<u32, &'static str>| res.is_err(),
+        ))
+        .layer(svc);
+
+        let (mut cx_a, mut cx_b) = (0, 0);
+        let (a, b) = tokio::join!(svc.call(&mut cx_a, 1), svc.call(&mut cx_b, 2));
+        assert_eq!((a, b), (Err("unavailable"), Err("unavailable")));
+        let attempts = attempts.lock().unwrap();
+        let times = |req| {
+            attempts
+                .iter()
+                .filter(|(r, _)| *r == req)
+                .map(|(_, at)| *at)
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(times(1).len(), 4);
+        // The policy is cloned for every request, without the requests retrying
+        // in lockstep.
+        assert_ne!(times(1), times(2));
+    }
+}
@@ -0,0 +1,280 @@
+//! A loop accepting connections and serving each of them with a [`Service`].
+//!
+//! [`Serve`] takes the connections from a stream of accepted connections, like a
+//! wrapped `TcpListener`, and calls its service with each of them on a task of its
+//! own. The setup of a connection, like a TLS handshake, thus runs on the worker
+//! threads of the runtime rather than on the task accepting the connections.
+//!
+//! Under a connection storm, waking the loop up for every connection is costly.
+//! Once woken, the loop takes all the connections already pending, up to a batch
+//! of [`DEFAULT_BATCH`] set with [`Serve::batch`], before spawning their tasks. It
+//! only waits for the next connections once none is pending, and yields to the
+//! other tasks after every full batch.
+//!
+//! Without the `service_send` feature, the tasks are spawned on the current
+//! [`LocalSet`](tokio::task::LocalSet).
+
+use std::{
+    fmt,
+    future::{poll_fn, Future},
+    marker::PhantomData,
+    task::{Context, Poll},
+};
+
+use futures::{Stream, StreamExt};
+
+use crate::service::Service;
+
+/// The number of connections taken at most per wakeup by default.
+pub const DEFAULT_BATCH: usize = 32;
+
+/// Serves the connections of a stream with a service, each on a task of its own.
+///
+/// See the [module level docs](self) for details.
+///
+/// # Example
+///
+/// ```rust
+/// use std::{convert::Infallible, io};
+///
+/// use motore::{serve::Serve, service::service_fn};
+///
+/// # #[tokio::main(flavor = "current_thread")]
+/// # async fn main() {
+/// # tokio::task::LocalSet::new().run_until(async {
+/// async fn handle(_cx: &mut (), conn: u32) -> Result<(), Infallible> {
+///     println!("serving connection {conn}");
+///     Ok(())
+/// }
+///
+/// let listener = futures::stream::iter((0..4).map(Ok::<_, io::Error>));
+/// Serve::new(listener, service_fn(handle))
+///     .batch(2)
+///     .run()
+///     .await
+///     .unwrap();
+/// # }).await;
+/// # }
+/// ```
+pub struct Serve<L, S, Cx> {
+    listener: L,
+    service: S,
+    batch: usize,
+    _phantom: PhantomData<fn(Cx)>,
+}
+
+impl<L, S, Cx> Serve<L, S, Cx> {
+    /// Creates a loop serving the connections of `listener` with `service`.
+    pub const fn new(listener: L, service: S) -> Self {
+        Serve {
+            listener,
+            service,
+            batch: DEFAULT_BATCH,
+            _phantom: PhantomData,
+        }
+    }
+
+    /// Sets the number of connections taken at most per wakeup.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `batch` is zero.
+    pub fn batch(mut self, batch: usize) -> Self {
+        assert!(batch > 0, "the batch must not be zero");
+        self.batch = batch;
+        self
+    }
+}
+
+/// How the loop ends, once the connections taken before are served.
+enum End<E> {
+    Closed,
+    Failed(E),
+}
+
+/// Takes the pending connections, up to a batch, or returns how the stream ended.
+fn poll_batch<L, C, E>(
+    listener: &mut L,
+    batch: &mut Vec<C>,
+    max: usize,
+    cx: &mut Context<'_>,
+) -> Poll<Option<End<E>>>
+where
+    L: Stream<Item = Result<C, E>> + Unpin,
+{
+    while batch.len() < max {
+        match listener.poll_next_unpin(cx) {
+            Poll::Ready(Some(Ok(conn))) => batch.push(conn),
+            Poll::Ready(Some(Err(e))) => return Poll::Ready(Some(End::Failed(e))),
+            Poll::Ready(None) => return Poll::Ready(Some(End::Closed)),
+            Poll::Pending if batch.is_empty() => return Poll::Pending,
+            Poll::Pending => break,
+        }
+    }
+    Poll::Ready(None)
+}
+
+impl<L, S, Cx, C, E> Serve<L, S, Cx>
+where
+    L: Stream<Item = Result<C, E>> + Unpin,
+{
+    async fn serve<F>(mut self, mut spawn: F) -> Result<(), E>
+    where
+        F: FnMut(S, C),
+        S: Clone,
+    {
+        let mut batch = Vec::with_capacity(self.batch);
+        loop {
+            let end =
+                poll_fn(|cx| poll_batch(&mut self.listener, &mut batch, self.batch, cx)).await;
+            let full = batch.len() == self.batch;
+            for conn in batch.drain(..) {
+                spawn(self.service.clone(), conn);
+            }
+            match end {
+                Some(End::Closed) => return Ok(()),
+                Some(End::Failed(e)) => return Err(e),
+                None if full => tokio::task::yield_now().await,
+                None => {}
+            }
+        }
+    }
+
+    /// Serves the connections until the stream ends, or fails with its first
+    /// error.
+    ///
+    /// The connections taken before the end are still served, and the tasks
+    /// serving the connections keep running once the loop returned. The results
+    /// of the calls are dropped.
+    #[cfg(feature = "service_send")]
+    pub fn run(self) -> impl Future<Output = Result<(), E>>
+    where
+        S: Service<Cx, C> + Clone + Send + Sync + 'static,
+        Cx: Default + Send,
+        C: Send + 'static,
+    {
+        self.serve(|service, conn| {
+            tokio::spawn(async move {
+                let _ = service.call(&mut Cx::default(), conn).await;
+            });
+        })
+    }
+
+    /// Serves the connections until the stream ends, or fails with its first
+    /// error.
+    ///
+    /// The connections taken before the end are still served, and the tasks
+    /// serving the connections keep running once the loop returned. The results
+    /// of the calls are dropped.
+    #[cfg(not(feature = "service_send"))]
+    pub fn run(self) -> impl Future<Output = Result<(), E>>
+    where
+        S: Service<Cx, C> + Clone + 'static,
+        Cx: Default,
+        C: 'static,
+    {
+        self.serve(|service, conn| {
+            tokio::task::spawn_local(async move {
+                let _ = service.call(&mut Cx::default(), conn).await;
+            });
+        })
+    }
+}
+
+impl<L, S: fmt::Debug, Cx> fmt::Debug for Serve<L, S, Cx> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Serve")
+            .field("service", &self.service)
+            .field("batch", &self.batch)
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        convert::Infallible,
+        io,
+        sync::{Arc, Mutex},
+    };
+
+    use tokio::sync::mpsc;
+
+    use super::*;
+    use crate::service::service_fn;
+
+    /// Serves the connections by sending them to the returned receiver.
+    fn reporting() -> (
+        impl Service<(), u32, Response = (), Error = Infallible> + Clone + Send + Sync + 'static,
+        mpsc::UnboundedReceiver<u32>,
+    ) {
+        let (tx, rx) = mpsc::unbounded_channel();
+        let svc = service_fn(move |_cx: &mut (), conn: u32| {
+            let _ = tx.send(conn);
+            async { Ok(()) }
+        });
+        (svc, rx)
+    }
+
+    #[tokio::test]
+    async fn takes_the_pending_connections_in_batches() {
+        tokio::task::LocalSet::new()
+            .run_until(async {
+                let log = Arc::new(Mutex::new(Vec::new()));
+                let (tx, mut rx) = futures::channel::mpsc::unbounded();
+                let listener = futures::stream::poll_fn({
+                    let log = log.clone();
+                    move |cx| {
+                        let poll = rx.poll_next_unpin(cx);
+                        let event = match poll {
+                            Poll::Ready(Some(conn)) => format!("take {conn}"),
+                            Poll::Ready(None) => "end".to_string(),
+                            Poll::Pending => "pending".to_string(),
+                        };
+                        log.lock().unwrap().push(event);
+                        poll.map(|conn| conn.map(Ok::<_, io::Error>))
+                    }
+                });
+                let svc = service_fn({
+                    let log = log.clone();
+                    move |_cx: &mut (), conn: u32| {
+                        log.lock().unwrap().push(format!("serve {conn}"));
+                        async { Ok::<_, Infallible>(()) }
+                    }
+                });
+                for conn in 0..5 {
+                    tx.unbounded_send(conn).unwrap();
+                }
+                drop(tx);
+
+                Serve::new(listener, svc).batch(2).run().await.unwrap();
+                tokio::task::yield_now().await;
+                assert_eq!(
+                    *log.lock().unwrap(),
+                    [
+                        "take 0", "take 1", "serve 0", "serve 1", "take 2", "take 3", "serve 2",
+                        "serve 3", "take 4", "end", "serve 4",
+                    ]
+                );
+            })
+            .await;
+    }
+
+    #[tokio::test]
+    async fn serves_the_connections_taken_before_an_error() {
+        tokio::task::LocalSet::new()
+            .run_until(async {
+                let listener = futures::stream::iter([Ok(1), Ok(2), Err("boom"), Ok(3)]);
+                let (svc, mut served) = reporting();
+                assert_eq!(Serve::new(listener, svc).run().await, Err("boom"));
+
+                let mut conns = Vec::new();
+                while let Some(conn) = served.recv().await {
+                    conns.push(conn);
+                }
+                conns.sort_unstable();
+                assert_eq!(conns, [1, 2]);
+            })
+            .await;
+    }
+}
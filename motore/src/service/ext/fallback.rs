@@ -0,0 +1,146 @@
+use std::{
+    fmt,
+    task::{Context, Poll},
+};
+
+use crate::{
+    load::{Load, Ready},
+    Service,
+};
+
+/// The predicate of the [`fallback`] combinator, which falls back on any error.
+///
+/// [`fallback`]: crate::service::ServiceExt::fallback
+pub type AnyError<E> = fn(&E) -> bool;
+
+/// Service returned by the [`fallback`] and [`fallback_if`] combinators.
+///
+/// [`fallback`]: crate::service::ServiceExt::fallback
+/// [`fallback_if`]: crate::service::ServiceExt::fallback_if
+#[derive(Clone)]
+pub struct Fallback<S, T, P> {
+    pub(crate) inner: S,
+    pub(crate) fallback: T,
+    pub(crate) predicate: P,
+}
+
+#[cfg(feature = "service_send")]
+impl<Cx, Req, S, T, P> Service<Cx, Req> for Fallback<S, T, P>
+where
+    S: Service<Cx, Req> + Sync,
+    T: Service<Cx, Req, Response = S::Response> + Sync,
+    S::Error: Into<T::Error>,
+    P: Fn(&S::Error) -> bool + Sync,
+    Cx: Send,
+    Req: Clone + Send,
+{
+    type Response = S::Response;
+
+    type Error = T::Error;
+
+    async fn call(&self, cx: &mut Cx, req: Req) -> Result<Self::Response, Self::Error> {
+        let retry = req.clone();
+        match self.inner.call(cx, req).await {
+            Err(e) if (self.predicate)(&e) => {}
+            res => return res.map_err(Into::into),
+        }
+        self.fallback.call(cx, retry).await
+    }
+}
+
+#[cfg(not(feature = "service_send"))]
+impl<Cx, Req, S, T, P> Service<Cx, Req> for Fallback<S, T, P>
+where
+    S: Service<Cx, Req>,
+    T: Service<Cx, Req, Response = S::Response>,
+    S::Error: Into<T::Error>,
+    P: Fn(&S::Error) -> bool,
+    Req: Clone,
+{
+    type Response = S::Response;
+
+    type Error = T::Error;
+
+    async fn call(&self, cx: &mut Cx, req: Req) -> Result<Self::Response, Self::Error> {
+        let retry = req.clone();
+        match self.inner.call(cx, req).await {
+            Err(e) if (self.predicate)(&e) => {}
+            res => return res.map_err(Into::into),
+        }
+        self.fallback.call(cx, retry).await
+    }
+}
+
+impl<S, T, P> fmt::Debug for Fallback<S, T, P>
+where
+    S: fmt::Debug,
+    T: fmt::Debug,
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Fallback")
+            .field("inner", &self.inner)
+            .field("fallback", &self.fallback)
+            .field("predicate", &format_args!("{}", std::any::type_name::<P>()))
+            .finish()
+    }
+}
+
+impl<S, T, P> Ready for Fallback<S, T, P>
+where
+    S: Ready,
+{
+    fn poll_ready(&self, cx: &mut Context<'_>) -> Poll<()> {
+        self.inner.poll_ready(cx)
+    }
+}
+
+impl<S, T, P> Load for Fallback<S, T, P>
+where
+    S: Load,
+{
+    type Metric = S::Metric;
+
+    fn load(&self) -> Self::Metric {
+        self.inner.load()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::prelude::*;
+
+    async fn primary(cx: &mut Vec<&'static str>, req: u32) -> Result<u32, String> {
+        cx.push("primary");
+        match req {
+            0 => Err("unavailable".to_string()),
+            1 => Err("invalid".to_string()),
+            n => Ok(n),
+        }
+    }
+
+    async fn secondary(cx: &mut Vec<&'static str>, req: u32) -> Result<u32, String> {
+        cx.push("secondary");
+        Ok(req + 100)
+    }
+
+    #[tokio::test]
+    async fn calls_the_fallback_on_errors() {
+        let svc = service_fn(primary).fallback(service_fn(secondary));
+
+        let mut calls = Vec::new();
+        assert_eq!(svc.call(&mut calls, 2).await, Ok(2));
+        assert_eq!(svc.call(&mut calls, 0).await, Ok(100));
+        assert_eq!(calls, ["primary", "primary", "secondary"]);
+    }
+
+    #[tokio::test]
+    async fn only_falls_back_when_the_predicate_allows() {
+        let svc =
+            service_fn(primary).fallback_if(service_fn(secondary), |e: &String| e == "unavailable");
+
+        let mut calls = Vec::new();
+        assert_eq!(svc.call(&mut calls, 0).await, Ok(100));
+        assert_eq!(svc.call(&mut calls, 1).await, Err("invalid".to_string()));
+        assert_eq!(calls, ["primary", "secondary", "primary"]);
+    }
+}
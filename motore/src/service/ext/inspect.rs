@@ -0,0 +1,132 @@
+use std::{
+    fmt,
+    future::Future,
+    task::{Context, Poll},
+};
+
+use futures::TryFutureExt;
+
+use crate::{
+    load::{Load, Ready},
+    MaybeSend, Service,
+};
+
+macro_rules! inspect {
+    ($(#[$attr:meta])* $name:ident, $method:ident, $output:ident) => {
+        $(#[$attr])*
+        #[derive(Clone)]
+        pub struct $name<S, F> {
+            pub(crate) inner: S,
+            pub(crate) f: F,
+        }
+
+        impl<Cx, Req, S, F> Service<Cx, Req> for $name<S, F>
+        where
+            S: Service<Cx, Req>,
+            F: FnOnce(&S::$output) + Clone + MaybeSend,
+        {
+            type Response = S::Response;
+
+            type Error = S::Error;
+
+            #[cfg(feature = "service_send")]
+            fn call(
+                &self,
+                cx: &mut Cx,
+                req: Req,
+            ) -> impl Future<Output = Result<Self::Response, Self::Error>> + Send {
+                self.inner.call(cx, req).$method(self.f.clone())
+            }
+            #[cfg(not(feature = "service_send"))]
+            fn call(
+                &self,
+                cx: &mut Cx,
+                req: Req,
+            ) -> impl Future<Output = Result<Self::Response, Self::Error>> {
+                self.inner.call(cx, req).$method(self.f.clone())
+            }
+        }
+
+        impl<S, F> fmt::Debug for $name<S, F>
+        where
+            S: fmt::Debug,
+        {
+            fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+                f.debug_struct(stringify!($name))
+                    .field("inner", &self.inner)
+                    .field("f", &format_args!("{}", std::any::type_name::<F>()))
+                    .finish()
+            }
+        }
+
+        impl<S, F> Ready for $name<S, F>
+        where
+            S: Ready,
+        {
+            fn poll_ready(&self, cx: &mut Context<'_>) -> Poll<()> {
+                self.inner.poll_ready(cx)
+            }
+        }
+
+        impl<S, F> Load for $name<S, F>
+        where
+            S: Load,
+        {
+            type Metric = S::Metric;
+
+            fn load(&self) -> Self::Metric {
+                self.inner.load()
+            }
+        }
+    };
+}
+
+inspect!(
+    /// Service returned by the [`inspect_ok`] combinator.
+    ///
+    /// [`inspect_ok`]: crate::service::ServiceExt::inspect_ok
+    InspectOk,
+    inspect_ok,
+    Response
+);
+
+inspect!(
+    /// Service returned by the [`inspect_err`] combinator.
+    ///
+    /// [`inspect_err`]: crate::service::ServiceExt::inspect_err
+    InspectErr,
+    inspect_err,
+    Error
+);
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{Arc, Mutex};
+
+    use crate::prelude::*;
+
+    async fn parse(_cx: &mut (), req: &'static str) -> Result<u32, std::num::ParseIntError> {
+        req.parse()
+    }
+
+    #[tokio::test]
+    async fn taps_without_changing_results() {
+        let log = Arc::new(Mutex::new(Vec::new()));
+        let svc = service_fn(parse)
+            .inspect_ok({
+                let log = log.clone();
+                move |n| log.lock().unwrap().push(format!("ok {n}"))
+            })
+            .inspect_err({
+                let log = log.clone();
+                move |e| log.lock().unwrap().push(format!("err {e}"))
+            });
+
+        assert_eq!(svc.call(&mut (), "1").await, Ok(1));
+        assert!(svc.call(&mut (), "x").await.is_err());
+        assert_eq!(
+            *log.lock().unwrap(),
+            ["ok 1", "err invalid digit found in string"]
+        );
+    }
+}
@@ -0,0 +1,103 @@
+use std::{
+    fmt,
+    future::Future,
+    task::{Context, Poll},
+};
+
+use futures::FutureExt;
+
+use crate::{
+    load::{Load, Ready},
+    MaybeSend, Service,
+};
+
+/// Service returned by the [`map_both`] combinator.
+///
+/// [`map_both`]: crate::service::ServiceExt::map_both
+#[derive(Clone)]
+pub struct MapBoth<S, F, G> {
+    pub(crate) inner: S,
+    pub(crate) f: F,
+    pub(crate) g: G,
+}
+
+impl<S, F, G, Cx, Req, Response, E> Service<Cx, Req> for MapBoth<S, F, G>
+where
+    S: Service<Cx, Req>,
+    F: FnOnce(S::Response) -> Response + Clone + MaybeSend,
+    G: FnOnce(S::Error) -> E + Clone + MaybeSend,
+{
+    type Response = Response;
+    type Error = E;
+
+    #[cfg(feature = "service_send")]
+    fn call(
+        &self,
+        cx: &mut Cx,
+        req: Req,
+    ) -> impl Future<Output = Result<Self::Response, Self::Error>> + Send {
+        let (f, g) = (self.f.clone(), self.g.clone());
+        self.inner.call(cx, req).map(|r| r.map(f).map_err(g))
+    }
+    #[cfg(not(feature = "service_send"))]
+    fn call(
+        &self,
+        cx: &mut Cx,
+        req: Req,
+    ) -> impl Future<Output = Result<Self::Response, Self::Error>> {
+        let (f, g) = (self.f.clone(), self.g.clone());
+        self.inner.call(cx, req).map(|r| r.map(f).map_err(g))
+    }
+}
+
+impl<S, F, G> fmt::Debug for MapBoth<S, F, G>
+where
+    S: fmt::Debug,
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("MapBoth")
+            .field("inner", &self.inner)
+            .field("f", &format_args!("{}", std::any::type_name::<F>()))
+            .field("g", &format_args!("{}", std::any::type_name::<G>()))
+            .finish()
+    }
+}
+
+impl<S, F, G> Ready for MapBoth<S, F, G>
+where
+    S: Ready,
+{
+    fn poll_ready(&self, cx: &mut Context<'_>) -> Poll<()> {
+        self.inner.poll_ready(cx)
+    }
+}
+
+impl<S, F, G> Load for MapBoth<S, F, G>
+where
+    S: Load,
+{
+    type Metric = S::Metric;
+
+    fn load(&self) -> Self::Metric {
+        self.inner.load()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::prelude::*;
+
+    async fn parse(_cx: &mut (), req: &'static str) -> Result<u32, std::num::ParseIntError> {
+        req.parse()
+    }
+
+    #[tokio::test]
+    async fn maps_response_and_error() {
+        let svc = service_fn(parse).map_both(|n| n * 2, |e| e.to_string());
+        assert_eq!(svc.call(&mut (), "21").await, Ok(42));
+        assert_eq!(
+            svc.call(&mut (), "x").await,
+            Err("invalid digit found in string".to_string())
+        );
+    }
+}
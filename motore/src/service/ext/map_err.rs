@@ -1,8 +1,14 @@
-use std::future::Future;
+use std::{
+    future::Future,
+    task::{Context, Poll},
+};
 
 use futures::TryFutureExt;
 
-use crate::Service;
+use crate::{
+    load::{Load, Ready},
+    MaybeSend, Service,
+};
 
 /// Service returned by the [`map_err`] combinator.
 ///
@@ -16,7 +22,7 @@ pub struct MapErr<S, F> {
 impl<Cx, Req, S, F, E> Service<Cx, Req> for MapErr<S, F>
 where
     S: Service<Cx, Req>,
-    F: FnOnce(S::Error) -> E + Clone + Send,
+    F: FnOnce(S::Error) -> E + Clone + MaybeSend,
 {
     type Response = S::Response;
 
@@ -39,3 +45,23 @@ where
         self.inner.call(cx, req).map_err(self.f.clone())
     }
 }
+
+impl<S, F> Ready for MapErr<S, F>
+where
+    S: Ready,
+{
+    fn poll_ready(&self, cx: &mut Context<'_>) -> Poll<()> {
+        self.inner.poll_ready(cx)
+    }
+}
+
+impl<S, F> Load for MapErr<S, F>
+where
+    S: Load,
+{
+    type Metric = S::Metric;
+
+    fn load(&self) -> Self::Metric {
+        self.inner.load()
+    }
+}
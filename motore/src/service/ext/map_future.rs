@@ -0,0 +1,165 @@
+use std::{
+    fmt,
+    future::Future,
+    task::{Context, Poll},
+};
+
+use crate::{
+    load::{Load, Ready},
+    Service,
+};
+
+/// Wraps the future returned by a service.
+///
+/// The futures returned by services can't be named, and closures can't be generic
+/// over them, so the wrapping logic is provided by implementing this trait, usually
+/// with an `async fn`.
+///
+/// # Example
+///
+/// ```rust
+/// use std::{future::Future, time::Duration};
+///
+/// use motore::{service::FutureWrapper, BoxError, MaybeSend};
+///
+/// struct WithTimeout(Duration);
+///
+/// impl<T, E: Into<BoxError>> FutureWrapper<Result<T, E>> for WithTimeout {
+///     type Output = Result<T, BoxError>;
+///
+///     async fn wrap<Fut>(&self, fut: Fut) -> Self::Output
+///     where
+///         Fut: Future<Output = Result<T, E>> + MaybeSend,
+///     {
+///         match tokio::time::timeout(self.0, fut).await {
+///             Ok(res) => res.map_err(Into::into),
+///             Err(elapsed) => Err(elapsed.into()),
+///         }
+///     }
+/// }
+/// ```
+pub trait FutureWrapper<T> {
+    /// The output of the wrapped future.
+    type Output;
+
+    /// Wraps the future of a call.
+    #[cfg(feature = "service_send")]
+    fn wrap<Fut>(&self, fut: Fut) -> impl Future<Output = Self::Output> + Send
+    where
+        Fut: Future<Output = T> + Send;
+
+    /// Wraps the future of a call.
+    #[cfg(not(feature = "service_send"))]
+    fn wrap<Fut>(&self, fut: Fut) -> impl Future<Output = Self::Output>
+    where
+        Fut: Future<Output = T>;
+}
+
+/// Service returned by the [`map_future`] combinator.
+///
+/// [`map_future`]: crate::service::ServiceExt::map_future
+#[derive(Clone)]
+pub struct MapFuture<S, W> {
+    pub(crate) inner: S,
+    pub(crate) wrapper: W,
+}
+
+impl<S, W, Cx, Req, Response, E> Service<Cx, Req> for MapFuture<S, W>
+where
+    S: Service<Cx, Req>,
+    W: FutureWrapper<Result<S::Response, S::Error>, Output = Result<Response, E>>,
+{
+    type Response = Response;
+    type Error = E;
+
+    #[cfg(feature = "service_send")]
+    fn call(
+        &self,
+        cx: &mut Cx,
+        req: Req,
+    ) -> impl Future<Output = Result<Self::Response, Self::Error>> + Send {
+        self.wrapper.wrap(self.inner.call(cx, req))
+    }
+    #[cfg(not(feature = "service_send"))]
+    fn call(
+        &self,
+        cx: &mut Cx,
+        req: Req,
+    ) -> impl Future<Output = Result<Self::Response, Self::Error>> {
+        self.wrapper.wrap(self.inner.call(cx, req))
+    }
+}
+
+impl<S, W> fmt::Debug for MapFuture<S, W>
+where
+    S: fmt::Debug,
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("MapFuture")
+            .field("inner", &self.inner)
+            .field("wrapper", &format_args!("{}", std::any::type_name::<W>()))
+            .finish()
+    }
+}
+
+impl<S, W> Ready for MapFuture<S, W>
+where
+    S: Ready,
+{
+    fn poll_ready(&self, cx: &mut Context<'_>) -> Poll<()> {
+        self.inner.poll_ready(cx)
+    }
+}
+
+impl<S, W> Load for MapFuture<S, W>
+where
+    S: Load,
+{
+    type Metric = S::Metric;
+
+    fn load(&self) -> Self::Metric {
+        self.inner.load()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        sync::atomic::{AtomicUsize, Ordering},
+        time::Duration,
+    };
+
+    use super::*;
+    use crate::{prelude::*, MaybeSend};
+
+    #[derive(Default)]
+    struct Polls(AtomicUsize);
+
+    impl<T> FutureWrapper<T> for Polls {
+        type Output = T;
+
+        async fn wrap<Fut>(&self, fut: Fut) -> T
+        where
+            Fut: Future<Output = T> + MaybeSend,
+        {
+            let mut fut = std::pin::pin!(fut);
+            std::future::poll_fn(|cx| {
+                self.0.fetch_add(1, Ordering::SeqCst);
+                fut.as_mut().poll(cx)
+            })
+            .await
+        }
+    }
+
+    async fn slow(_cx: &mut (), req: u32) -> Result<u32, ()> {
+        tokio::time::sleep(Duration::from_millis(1)).await;
+        Ok(req)
+    }
+
+    #[tokio::test]
+    async fn wraps_the_call_future() {
+        let svc = service_fn(slow).map_future(Polls::default());
+        assert_eq!(svc.call(&mut (), 1).await, Ok(1));
+        assert!(svc.wrapper.0.load(Ordering::SeqCst) >= 2);
+    }
+}
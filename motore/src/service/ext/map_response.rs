@@ -1,8 +1,16 @@
-use std::{fmt, future::Future};
+use std::{
+    fmt,
+    future::Future,
+    task::{Context, Poll},
+};
 
 use futures::TryFutureExt;
 
-use crate::Service;
+use crate::{
+    load::{Load, Ready},
+    MaybeSend, Service,
+};
+
 /// Service returned by the [`map_response`] combinator.
 ///
 /// [`map_response`]: crate::service::ServiceExt::map_response
@@ -15,7 +23,7 @@ pub struct MapResponse<S, F> {
 impl<S, F, Cx, Req, Response> Service<Cx, Req> for MapResponse<S, F>
 where
     S: Service<Cx, Req>,
-    F: FnOnce(S::Response) -> Response + Clone + Send,
+    F: FnOnce(S::Response) -> Response + Clone + MaybeSend,
 {
     type Response = Response;
     type Error = S::Error;
@@ -49,3 +57,23 @@ where
             .finish()
     }
 }
+
+impl<S, F> Ready for MapResponse<S, F>
+where
+    S: Ready,
+{
+    fn poll_ready(&self, cx: &mut Context<'_>) -> Poll<()> {
+        self.inner.poll_ready(cx)
+    }
+}
+
+impl<S, F> Load for MapResponse<S, F>
+where
+    S: Load,
+{
+    type Metric = S::Metric;
+
+    fn load(&self) -> Self::Metric {
+        self.inner.load()
+    }
+}
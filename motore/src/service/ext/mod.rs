@@ -1,8 +1,29 @@
-use crate::Service;
+use std::future::Future;
 
+use crate::{
+    cancel::{CancelSignal, Cancellation},
+    service::BoxCloneService,
+    Service,
+};
+
+mod fallback;
+mod inspect;
+mod map_both;
 mod map_err;
+mod map_future;
 mod map_response;
-pub use self::{map_err::MapErr, map_response::MapResponse};
+mod oneshot;
+mod then;
+pub use self::{
+    fallback::{AnyError, Fallback},
+    inspect::{InspectErr, InspectOk},
+    map_both::MapBoth,
+    map_err::MapErr,
+    map_future::{FutureWrapper, MapFuture},
+    map_response::MapResponse,
+    oneshot::Oneshot,
+    then::Then,
+};
 
 /// An extension trait for `Service`s that provides a variety of convenient
 /// adapters
@@ -23,6 +44,118 @@ pub trait ServiceExt<Cx, Req>: Service<Cx, Req> + Sized {
         self,
         f: F,
     ) -> MapResponse<Self, F>;
+
+    /// Maps this service's response and error values to different values.
+    ///
+    /// This is the same as calling [`map_response`](ServiceExt::map_response) and
+    /// [`map_err`](ServiceExt::map_err), but with a single adapter.
+    fn map_both<F, G, Response, E>(self, f: F, g: G) -> MapBoth<Self, F, G>
+    where
+        F: FnOnce(Self::Response) -> Response,
+        G: FnOnce(Self::Error) -> E;
+
+    /// Passes the result of this service, whether it succeeded or failed, to an
+    /// async closure, and resolves with the closure's result.
+    ///
+    /// This is useful for post-processing that needs to see both branches, like
+    /// logging, metrics or recovering from some errors.
+    fn then<F, Fut, Response, E>(self, f: F) -> Then<Self, F>
+    where
+        F: FnOnce(Result<Self::Response, Self::Error>) -> Fut,
+        Fut: Future<Output = Result<Response, E>>;
+
+    /// Wraps the future returned by this service with a [`FutureWrapper`].
+    ///
+    /// This can be used to apply a timeout, instrumentation or custom polling logic
+    /// to the calls, without writing a new middleware.
+    fn map_future<W>(self, wrapper: W) -> MapFuture<Self, W>
+    where
+        W: FutureWrapper<Result<Self::Response, Self::Error>>;
+
+    /// Calls a closure with a reference to each successful response, leaving it
+    /// unchanged.
+    ///
+    /// This is useful for ad-hoc logging and metrics taps.
+    fn inspect_ok<F>(self, f: F) -> InspectOk<Self, F>
+    where
+        F: FnOnce(&Self::Response);
+
+    /// Calls a closure with a reference to each error, leaving it unchanged.
+    ///
+    /// This is useful for ad-hoc logging and metrics taps.
+    fn inspect_err<F>(self, f: F) -> InspectErr<Self, F>
+    where
+        F: FnOnce(&Self::Error);
+
+    /// Calls `other` with the same request when this service fails.
+    ///
+    /// The request is cloned before each call, and the errors of `other` are
+    /// returned as is.
+    fn fallback<B>(self, other: B) -> Fallback<Self, B, AnyError<Self::Error>>
+    where
+        B: Service<Cx, Req, Response = Self::Response>,
+        Self::Error: Into<B::Error>;
+
+    /// Calls `other` with the same request when this service fails with an error
+    /// for which `predicate` returns `true`.
+    ///
+    /// Other errors are converted into the error type of `other`.
+    fn fallback_if<B, P>(self, other: B, predicate: P) -> Fallback<Self, B, P>
+    where
+        B: Service<Cx, Req, Response = Self::Response>,
+        Self::Error: Into<B::Error>,
+        P: Fn(&Self::Error) -> bool;
+
+    /// Fails the calls with [`Cancelled`](crate::cancel::Cancelled) once `signal`
+    /// fires, like a [`CancellationToken`](crate::cancel::CancellationToken)
+    /// cancelled when the connection of the caller closes.
+    fn with_cancellation<C>(self, signal: C) -> Cancellation<Self, C>
+    where
+        C: CancelSignal;
+
+    /// Consumes this service and calls it once, with the given context and
+    /// request.
+    ///
+    /// This is useful in tests and fire-and-forget code paths, where keeping the
+    /// service alive separately from the call is awkward.
+    #[cfg(feature = "service_send")]
+    fn oneshot(self, cx: Cx, req: Req) -> Oneshot<Self, Cx, Req>
+    where
+        Self: Send + 'static,
+        Cx: Send + 'static,
+        Req: Send + 'static;
+
+    /// Consumes this service and calls it once, with the given context and
+    /// request.
+    ///
+    /// This is useful in tests and fire-and-forget code paths, where keeping the
+    /// service alive separately from the call is awkward.
+    #[cfg(not(feature = "service_send"))]
+    fn oneshot(self, cx: Cx, req: Req) -> Oneshot<Self, Cx, Req>
+    where
+        Self: 'static,
+        Cx: 'static,
+        Req: 'static;
+
+    /// Erases the type of this service, turning it into a [`BoxCloneService`].
+    ///
+    /// The request, response and error types are kept, so this can be used to
+    /// put a boxing boundary in the middle of a large stack of middlewares.
+    #[cfg(feature = "service_send")]
+    fn erase(self) -> BoxCloneService<Cx, Req, Self::Response, Self::Error>
+    where
+        Self: Clone + Send + Sync + 'static,
+        Req: 'static;
+
+    /// Erases the type of this service, turning it into a [`BoxCloneService`].
+    ///
+    /// The request, response and error types are kept, so this can be used to
+    /// put a boxing boundary in the middle of a large stack of middlewares.
+    #[cfg(not(feature = "service_send"))]
+    fn erase(self) -> BoxCloneService<Cx, Req, Self::Response, Self::Error>
+    where
+        Self: Clone + 'static,
+        Req: 'static;
 }
 
 impl<T, Cx, Req> ServiceExt<Cx, Req> for T
@@ -39,4 +172,110 @@ where
     ) -> MapResponse<Self, F> {
         MapResponse { inner: self, f }
     }
+
+    fn map_both<F, G, Response, E>(self, f: F, g: G) -> MapBoth<Self, F, G>
+    where
+        F: FnOnce(Self::Response) -> Response,
+        G: FnOnce(Self::Error) -> E,
+    {
+        MapBoth { inner: self, f, g }
+    }
+
+    fn then<F, Fut, Response, E>(self, f: F) -> Then<Self, F>
+    where
+        F: FnOnce(Result<Self::Response, Self::Error>) -> Fut,
+        Fut: Future<Output = Result<Response, E>>,
+    {
+        Then { inner: self, f }
+    }
+
+    fn map_future<W>(self, wrapper: W) -> MapFuture<Self, W>
+    where
+        W: FutureWrapper<Result<Self::Response, Self::Error>>,
+    {
+        MapFuture {
+            inner: self,
+            wrapper,
+        }
+    }
+
+    fn inspect_ok<F>(self, f: F) -> InspectOk<Self, F>
+    where
+        F: FnOnce(&Self::Response),
+    {
+        InspectOk { inner: self, f }
+    }
+
+    fn inspect_err<F>(self, f: F) -> InspectErr<Self, F>
+    where
+        F: FnOnce(&Self::Error),
+    {
+        InspectErr { inner: self, f }
+    }
+
+    fn fallback<B>(self, other: B) -> Fallback<Self, B, AnyError<Self::Error>>
+    where
+        B: Service<Cx, Req, Response = Self::Response>,
+        Self::Error: Into<B::Error>,
+    {
+        self.fallback_if(other, |_| true)
+    }
+
+    fn fallback_if<B, P>(self, other: B, predicate: P) -> Fallback<Self, B, P>
+    where
+        B: Service<Cx, Req, Response = Self::Response>,
+        Self::Error: Into<B::Error>,
+        P: Fn(&Self::Error) -> bool,
+    {
+        Fallback {
+            inner: self,
+            fallback: other,
+            predicate,
+        }
+    }
+
+    fn with_cancellation<C>(self, signal: C) -> Cancellation<Self, C>
+    where
+        C: CancelSignal,
+    {
+        Cancellation::new(self, signal)
+    }
+
+    #[cfg(feature = "service_send")]
+    fn oneshot(self, cx: Cx, req: Req) -> Oneshot<Self, Cx, Req>
+    where
+        Self: Send + 'static,
+        Cx: Send + 'static,
+        Req: Send + 'static,
+    {
+        Oneshot::new(self, cx, req)
+    }
+
+    #[cfg(not(feature = "service_send"))]
+    fn oneshot(self, cx: Cx, req: Req) -> Oneshot<Self, Cx, Req>
+    where
+        Self: 'static,
+        Cx: 'static,
+        Req: 'static,
+    {
+        Oneshot::new(self, cx, req)
+    }
+
+    #[cfg(feature = "service_send")]
+    fn erase(self) -> BoxCloneService<Cx, Req, Self::Response, Self::Error>
+    where
+        Self: Clone + Send + Sync + 'static,
+        Req: 'static,
+    {
+        BoxCloneService::new(self)
+    }
+
+    #[cfg(not(feature = "service_send"))]
+    fn erase(self) -> BoxCloneService<Cx, Req, Self::Response, Self::Error>
+    where
+        Self: Clone + 'static,
+        Req: 'static,
+    {
+        BoxCloneService::new(self)
+    }
 }
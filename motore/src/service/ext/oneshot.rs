@@ -0,0 +1,106 @@
+use std::{
+    fmt,
+    future::Future,
+    marker::PhantomData,
+    pin::Pin,
+    task::{Context, Poll},
+};
+
+#[cfg(feature = "service_send")]
+use futures::future::BoxFuture;
+#[cfg(not(feature = "service_send"))]
+use futures::future::LocalBoxFuture as BoxFuture;
+
+use crate::Service;
+
+/// Future returned by the [`oneshot`] combinator.
+///
+/// It owns the service, the context and the request, and resolves with the result
+/// of the call.
+///
+/// [`oneshot`]: crate::service::ServiceExt::oneshot
+#[must_use = "futures do nothing unless you `.await` or poll them"]
+pub struct Oneshot<S, Cx, Req>
+where
+    S: Service<Cx, Req>,
+{
+    fut: BoxFuture<'static, Result<S::Response, S::Error>>,
+    _marker: PhantomData<fn(Cx, Req)>,
+}
+
+impl<S, Cx, Req> Oneshot<S, Cx, Req>
+where
+    S: Service<Cx, Req>,
+{
+    #[cfg(feature = "service_send")]
+    pub(crate) fn new(svc: S, mut cx: Cx, req: Req) -> Self
+    where
+        S: Send + 'static,
+        Cx: Send + 'static,
+        Req: Send + 'static,
+    {
+        Oneshot {
+            fut: Box::pin(async move { svc.call(&mut cx, req).await }),
+            _marker: PhantomData,
+        }
+    }
+
+    #[cfg(not(feature = "service_send"))]
+    pub(crate) fn new(svc: S, mut cx: Cx, req: Req) -> Self
+    where
+        S: 'static,
+        Cx: 'static,
+        Req: 'static,
+    {
+        Oneshot {
+            fut: Box::pin(async move { svc.call(&mut cx, req).await }),
+            _marker: PhantomData,
+        }
+    }
+}
+
+impl<S, Cx, Req> Future for Oneshot<S, Cx, Req>
+where
+    S: Service<Cx, Req>,
+{
+    type Output = Result<S::Response, S::Error>;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        self.fut.as_mut().poll(cx)
+    }
+}
+
+impl<S, Cx, Req> fmt::Debug for Oneshot<S, Cx, Req>
+where
+    S: Service<Cx, Req>,
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Oneshot")
+            .field("service", &format_args!("{}", std::any::type_name::<S>()))
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::convert::Infallible;
+
+    use crate::service::{service_fn, ServiceExt};
+
+    #[tokio::test]
+    async fn drives_a_single_call() {
+        async fn handle(cx: &mut u32, req: u32) -> Result<u32, Infallible> {
+            Ok(*cx + req)
+        }
+
+        let fut = service_fn(handle).oneshot(1, 2);
+        #[cfg(feature = "service_send")]
+        let res = tokio::spawn(fut).await.unwrap();
+        #[cfg(not(feature = "service_send"))]
+        let res = {
+            let local = tokio::task::LocalSet::new();
+            local.run_until(local.spawn_local(fut)).await.unwrap()
+        };
+        assert_eq!(res, Ok(3));
+    }
+}
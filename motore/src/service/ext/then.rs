@@ -0,0 +1,112 @@
+use std::{
+    fmt,
+    future::Future,
+    task::{Context, Poll},
+};
+
+use futures::FutureExt;
+
+use crate::{
+    load::{Load, Ready},
+    Service,
+};
+
+/// Service returned by the [`then`] combinator.
+///
+/// [`then`]: crate::service::ServiceExt::then
+#[derive(Clone)]
+pub struct Then<S, F> {
+    pub(crate) inner: S,
+    pub(crate) f: F,
+}
+
+#[cfg(feature = "service_send")]
+impl<S, F, Fut, Cx, Req, Response, E> Service<Cx, Req> for Then<S, F>
+where
+    S: Service<Cx, Req>,
+    F: FnOnce(Result<S::Response, S::Error>) -> Fut + Clone + Send,
+    Fut: Future<Output = Result<Response, E>> + Send,
+{
+    type Response = Response;
+    type Error = E;
+
+    fn call(
+        &self,
+        cx: &mut Cx,
+        req: Req,
+    ) -> impl Future<Output = Result<Self::Response, Self::Error>> + Send {
+        self.inner.call(cx, req).then(self.f.clone())
+    }
+}
+
+#[cfg(not(feature = "service_send"))]
+impl<S, F, Fut, Cx, Req, Response, E> Service<Cx, Req> for Then<S, F>
+where
+    S: Service<Cx, Req>,
+    F: FnOnce(Result<S::Response, S::Error>) -> Fut + Clone,
+    Fut: Future<Output = Result<Response, E>>,
+{
+    type Response = Response;
+    type Error = E;
+
+    fn call(
+        &self,
+        cx: &mut Cx,
+        req: Req,
+    ) -> impl Future<Output = Result<Self::Response, Self::Error>> {
+        self.inner.call(cx, req).then(self.f.clone())
+    }
+}
+
+impl<S, F> fmt::Debug for Then<S, F>
+where
+    S: fmt::Debug,
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Then")
+            .field("inner", &self.inner)
+            .field("f", &format_args!("{}", std::any::type_name::<F>()))
+            .finish()
+    }
+}
+
+impl<S, F> Ready for Then<S, F>
+where
+    S: Ready,
+{
+    fn poll_ready(&self, cx: &mut Context<'_>) -> Poll<()> {
+        self.inner.poll_ready(cx)
+    }
+}
+
+impl<S, F> Load for Then<S, F>
+where
+    S: Load,
+{
+    type Metric = S::Metric;
+
+    fn load(&self) -> Self::Metric {
+        self.inner.load()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::prelude::*;
+
+    async fn parse(_cx: &mut (), req: &'static str) -> Result<u32, std::num::ParseIntError> {
+        req.parse()
+    }
+
+    #[tokio::test]
+    async fn sees_both_branches() {
+        let svc = service_fn(parse).then(|res| async move {
+            match res {
+                Ok(n) => Ok::<_, std::convert::Infallible>(n),
+                Err(_) => Ok(0),
+            }
+        });
+        assert_eq!(svc.call(&mut (), "7").await, Ok(7));
+        assert_eq!(svc.call(&mut (), "x").await, Ok(0));
+    }
+}
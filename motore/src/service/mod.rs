@@ -4,13 +4,24 @@
 //! request / response clients and servers. It is simple but powerful and is
 //! used as the foundation for the rest of Motore.
 
-use std::{fmt, future::Future, sync::Arc};
+use std::{
+    any::Any,
+    cell::UnsafeCell,
+    fmt,
+    future::Future,
+    marker::PhantomData,
+    mem::{self, MaybeUninit},
+    ptr,
+    sync::Arc,
+};
 
 #[cfg(feature = "service_send")]
 use futures::future::BoxFuture;
 #[cfg(not(feature = "service_send"))]
 use futures::future::LocalBoxFuture as BoxFuture;
 
+use crate::MaybeSend;
+
 mod ext;
 mod service_fn;
 #[cfg(feature = "tower")]
@@ -185,13 +196,71 @@ pub trait UnaryService<Request> {
 impl_unary_service_ref!(Arc);
 impl_unary_service_ref!(Box);
 
-/// A [`Send`] + [`Sync`] boxed [`Service`].
+/// The storage of a type-erased service.
+///
+/// Services that fit in [`INLINE_WORDS`] words are stored in place, avoiding a
+/// heap allocation and a pointer chase on every call. Larger services are boxed,
+/// and the pointer to the box is stored instead.
+///
+/// The cell lets the services stored in place mutate through `&self`, with a
+/// `Cell` or an atomic, while only a shared reference to the storage is held.
+type Storage = UnsafeCell<MaybeUninit<[usize; INLINE_WORDS]>>;
+
+const INLINE_WORDS: usize = 3;
+
+const fn is_inline<S>() -> bool {
+    mem::size_of::<S>() <= mem::size_of::<Storage>()
+        && mem::align_of::<S>() <= mem::align_of::<Storage>()
+}
+
+fn store<S>(s: S) -> Storage {
+    let mut storage: Storage = UnsafeCell::new(MaybeUninit::uninit());
+    unsafe {
+        if is_inline::<S>() {
+            storage.get_mut().as_mut_ptr().cast::<S>().write(s);
+        } else {
+            storage
+                .get_mut()
+                .as_mut_ptr()
+                .cast::<*mut S>()
+                .write(Box::into_raw(Box::new(s)));
+        }
+    }
+    storage
+}
+
+/// # Safety
+///
+/// `storage` must have been created by [`store`] with the same `S`, and not dropped.
+unsafe fn get<S>(storage: &Storage) -> &S {
+    if is_inline::<S>() {
+        &*storage.get().cast::<S>()
+    } else {
+        &**storage.get().cast::<*const S>()
+    }
+}
+
+/// A [`Send`] boxed [`Service`].
 ///
 /// [`BoxService`] turns a service into a trait object, allowing the
-/// response future type to be dynamic, and allowing the service to be cloned.
+/// response future type to be dynamic. Services of up to three words, like most
+/// handlers and thin wrappers, are stored in place rather than in a heap
+/// allocation.
+///
+/// Unlike [`BoxCloneService`], the service doesn't need to be [`Clone`] or [`Sync`],
+/// so stateful services can be erased too; the resulting service is neither:
+///
+/// ```compile_fail
+/// fn assert_sync<T: Sync>() {}
+///
+/// assert_sync::<motore::BoxService<(), (), (), ()>>();
+/// ```
 pub struct BoxService<Cx, T, U, E> {
-    raw: *mut (),
+    storage: Storage,
     vtable: ServiceVtable<Cx, T, U, E>,
+    // The storage is `Send` and `Sync` whatever the service, so neither is
+    // implemented automatically.
+    _marker: PhantomData<*const ()>,
 }
 
 impl<Cx, T, U, E> BoxService<Cx, T, U, E> {
@@ -199,16 +268,16 @@ impl<Cx, T, U, E> BoxService<Cx, T, U, E> {
     #[cfg(feature = "service_send")]
     pub fn new<S>(s: S) -> Self
     where
-        S: Service<Cx, T, Response = U, Error = E> + Send + Sync + 'static,
+        S: Service<Cx, T, Response = U, Error = E> + Send + 'static,
         T: 'static,
     {
-        let raw = Box::into_raw(Box::new(s)) as *mut ();
         BoxService {
-            raw,
+            storage: store(s),
             vtable: ServiceVtable {
                 call: call::<Cx, T, S>,
                 drop: drop::<S>,
             },
+            _marker: PhantomData,
         }
     }
 
@@ -219,20 +288,20 @@ impl<Cx, T, U, E> BoxService<Cx, T, U, E> {
         S: Service<Cx, T, Response = U, Error = E> + 'static,
         T: 'static,
     {
-        let raw = Box::into_raw(Box::new(s)) as *mut ();
         BoxService {
-            raw,
+            storage: store(s),
             vtable: ServiceVtable {
                 call: call::<Cx, T, S>,
                 drop: drop::<S>,
             },
+            _marker: PhantomData,
         }
     }
 }
 
 impl<Cx, T, U, E> Drop for BoxService<Cx, T, U, E> {
     fn drop(&mut self) {
-        unsafe { (self.vtable.drop)(self.raw) };
+        unsafe { (self.vtable.drop)(&mut self.storage) };
     }
 }
 
@@ -253,7 +322,7 @@ impl<Cx, T, U, E> Service<Cx, T> for BoxService<Cx, T, U, E> {
         cx: &mut Cx,
         req: T,
     ) -> impl Future<Output = Result<Self::Response, Self::Error>> + Send {
-        unsafe { (self.vtable.call)(self.raw, cx, req) }
+        unsafe { (self.vtable.call)(&self.storage, cx, req) }
     }
     #[cfg(not(feature = "service_send"))]
     fn call(
@@ -261,34 +330,40 @@ impl<Cx, T, U, E> Service<Cx, T> for BoxService<Cx, T, U, E> {
         cx: &mut Cx,
         req: T,
     ) -> impl Future<Output = Result<Self::Response, Self::Error>> {
-        unsafe { (self.vtable.call)(self.raw, cx, req) }
+        unsafe { (self.vtable.call)(&self.storage, cx, req) }
     }
 }
 
 /// # Safety
 ///
-/// The contained `Service` must be `Send` and `Sync` required by the bounds of `new` and `clone`.
+/// The contained `Service` must be `Send` required by the bounds of `new`.
 #[cfg(feature = "service_send")]
 unsafe impl<Cx, T, U, E> Send for BoxService<Cx, T, U, E> {}
-#[cfg(feature = "service_send")]
-unsafe impl<Cx, T, U, E> Sync for BoxService<Cx, T, U, E> {}
 
 struct ServiceVtable<Cx, T, U, E> {
-    call: unsafe fn(raw: *mut (), cx: &mut Cx, req: T) -> BoxFuture<'_, Result<U, E>>,
-    drop: unsafe fn(raw: *mut ()),
+    call: unsafe fn(storage: *const Storage, cx: &mut Cx, req: T) -> BoxFuture<'_, Result<U, E>>,
+    drop: unsafe fn(storage: &mut Storage),
 }
 
+/// A boxed [`Service`] for single-threaded runtimes.
+///
+/// Without the `service_send` feature, [`BoxService`] neither requires nor provides
+/// [`Send`], and boxes the futures as [`LocalBoxFuture`](futures::future::LocalBoxFuture);
+/// this alias names that explicitly.
+#[cfg(not(feature = "service_send"))]
+pub type LocalBoxService<Cx, T, U, E> = BoxService<Cx, T, U, E>;
+
 /// A [`Clone`] + [`Send`] + [`Sync`] boxed [`Service`].
 ///
 /// [`BoxCloneService`] turns a service into a trait object, allowing the
 /// response future type to be dynamic, and allowing the service to be cloned.
 ///
 /// This is similar to [`BoxService`](BoxService) except the resulting
-/// service implements [`Clone`].
+/// service implements [`Clone`]. Unlike [`BoxService`], the service is always
+/// kept in a heap allocation, whatever its size.
 #[cfg(feature = "service_send")]
 pub struct BoxCloneService<Cx, T, U, E> {
-    raw: *mut (),
-    vtable: CloneServiceVtable<Cx, T, U, E>,
+    inner: Box<dyn CloneService<Cx, T, U, E> + Send + Sync>,
 }
 
 /// A [`Clone`] boxed [`Service`].
@@ -297,60 +372,92 @@ pub struct BoxCloneService<Cx, T, U, E> {
 /// response future type to be dynamic, and allowing the service to be cloned.
 ///
 /// This is similar to [`BoxService`](BoxService) except the resulting
-/// service implements [`Clone`].
+/// service implements [`Clone`]. Unlike [`BoxService`], the service is always
+/// kept in a heap allocation, whatever its size.
 #[cfg(not(feature = "service_send"))]
 pub struct BoxCloneService<Cx, T, U, E> {
-    raw: *mut (),
-    vtable: CloneServiceVtable<Cx, T, U, E>,
+    inner: Box<dyn CloneService<Cx, T, U, E>>,
 }
 
+/// A [`Clone`] boxed [`Service`] for single-threaded runtimes.
+///
+/// Without the `service_send` feature, [`BoxCloneService`] neither requires nor
+/// provides [`Send`], and boxes the futures as
+/// [`LocalBoxFuture`](futures::future::LocalBoxFuture); this alias names that
+/// explicitly.
+#[cfg(not(feature = "service_send"))]
+pub type LocalBoxCloneService<Cx, T, U, E> = BoxCloneService<Cx, T, U, E>;
+
 impl<Cx, T, U, E> BoxCloneService<Cx, T, U, E> {
     /// Create a new `BoxCloneService`.
+    ///
+    /// See [`new_static`](Self::new_static) to avoid boxing a `BoxCloneService`
+    /// again.
     #[cfg(feature = "service_send")]
     pub fn new<S>(s: S) -> Self
     where
         S: Service<Cx, T, Response = U, Error = E> + Clone + Send + Sync + 'static,
         T: 'static,
     {
-        let raw = Box::into_raw(Box::new(s)) as *mut ();
-        BoxCloneService {
-            raw,
-            vtable: CloneServiceVtable {
-                call: call::<Cx, T, S>,
-                clone: clone::<Cx, T, S>,
-                drop: drop::<S>,
-            },
-        }
+        BoxCloneService { inner: Box::new(s) }
     }
 
     /// Create a new `BoxCloneService`.
+    ///
+    /// See [`new_static`](Self::new_static) to avoid boxing a `BoxCloneService`
+    /// again.
     #[cfg(not(feature = "service_send"))]
     pub fn new<S>(s: S) -> Self
     where
         S: Service<Cx, T, Response = U, Error = E> + Clone + 'static,
         T: 'static,
     {
-        let raw = Box::into_raw(Box::new(s)) as *mut ();
-        BoxCloneService {
-            raw,
-            vtable: CloneServiceVtable {
-                call: call::<Cx, T, S>,
-                clone: clone::<Cx, T, S>,
-                drop: drop::<S>,
-            },
-        }
+        BoxCloneService { inner: Box::new(s) }
     }
 }
 
-impl<Cx, T, U, E> Drop for BoxCloneService<Cx, T, U, E> {
-    fn drop(&mut self) {
-        unsafe { (self.vtable.drop)(self.raw) };
+impl<Cx: 'static, T: 'static, U: 'static, E: 'static> BoxCloneService<Cx, T, U, E> {
+    /// Create a new `BoxCloneService`, returning a `BoxCloneService` as is instead
+    /// of boxing it, and every future it returns, twice.
+    ///
+    /// Unlike [`new`](Self::new), this requires the context, the request, the
+    /// response and the error to be `'static`, to recognize a `BoxCloneService`.
+    #[cfg(feature = "service_send")]
+    pub fn new_static<S>(s: S) -> Self
+    where
+        S: Service<Cx, T, Response = U, Error = E> + Clone + Send + Sync + 'static,
+    {
+        Self::unbox(s).unwrap_or_else(Self::new)
+    }
+
+    /// Create a new `BoxCloneService`, returning a `BoxCloneService` as is instead
+    /// of boxing it, and every future it returns, twice.
+    ///
+    /// Unlike [`new`](Self::new), this requires the context, the request, the
+    /// response and the error to be `'static`, to recognize a `BoxCloneService`.
+    #[cfg(not(feature = "service_send"))]
+    pub fn new_static<S>(s: S) -> Self
+    where
+        S: Service<Cx, T, Response = U, Error = E> + Clone + 'static,
+    {
+        Self::unbox(s).unwrap_or_else(Self::new)
+    }
+
+    /// Returns `s` itself if it is a `BoxCloneService`.
+    fn unbox<S: 'static>(s: S) -> Result<Self, S> {
+        let mut s = Some(s);
+        match (&mut s as &mut dyn Any).downcast_mut::<Option<Self>>() {
+            Some(boxed) => Ok(boxed.take().unwrap()),
+            None => Err(s.unwrap()),
+        }
     }
 }
 
 impl<Cx, T, U, E> Clone for BoxCloneService<Cx, T, U, E> {
     fn clone(&self) -> Self {
-        unsafe { (self.vtable.clone)(self.raw) }
+        BoxCloneService {
+            inner: self.inner.clone_box(),
+        }
     }
 }
 
@@ -360,45 +467,151 @@ impl<Cx, T, U, E> fmt::Debug for BoxCloneService<Cx, T, U, E> {
     }
 }
 
-impl<Cx, T, U, E> Service<Cx, T> for BoxCloneService<Cx, T, U, E> {
+impl<Cx, T, U, E> Service<Cx, T> for BoxCloneService<Cx, T, U, E>
+where
+    Cx: MaybeSend,
+    T: MaybeSend,
+{
     type Response = U;
 
     type Error = E;
 
+    async fn call(&self, cx: &mut Cx, req: T) -> Result<Self::Response, Self::Error> {
+        // The boxed future borrows the service and the context for the shorter of
+        // both borrows, which only a future capturing both can name.
+        self.inner.call_boxed(cx, req).await
+    }
+}
+
+/// A [`Clone`] + [`Send`] + [`Sync`] type-erased [`Service`] with shared ownership.
+///
+/// [`ArcService`] keeps the service behind an [`Arc`], so cloning it only bumps a
+/// reference count and the service itself doesn't need to be [`Clone`]. This suits
+/// handlers registered in several places, like the same handler mounted in
+/// multiple routers.
+#[cfg(feature = "service_send")]
+pub struct ArcService<Cx, T, U, E> {
+    inner: Arc<dyn DynService<Cx, T, U, E> + Send + Sync>,
+}
+
+/// A [`Clone`] type-erased [`Service`] with shared ownership.
+///
+/// [`ArcService`] keeps the service behind an [`Arc`], so cloning it only bumps a
+/// reference count and the service itself doesn't need to be [`Clone`]. This suits
+/// handlers registered in several places, like the same handler mounted in
+/// multiple routers.
+#[cfg(not(feature = "service_send"))]
+pub struct ArcService<Cx, T, U, E> {
+    inner: Arc<dyn DynService<Cx, T, U, E>>,
+}
+
+impl<Cx, T, U, E> ArcService<Cx, T, U, E> {
+    /// Create a new `ArcService`.
     #[cfg(feature = "service_send")]
-    fn call(
-        &self,
-        cx: &mut Cx,
-        req: T,
-    ) -> impl Future<Output = Result<Self::Response, Self::Error>> + Send {
-        unsafe { (self.vtable.call)(self.raw, cx, req) }
+    pub fn new<S>(s: S) -> Self
+    where
+        S: Service<Cx, T, Response = U, Error = E> + Send + Sync + 'static,
+        T: 'static,
+    {
+        ArcService { inner: Arc::new(s) }
     }
+
+    /// Create a new `ArcService`.
     #[cfg(not(feature = "service_send"))]
-    fn call(
-        &self,
-        cx: &mut Cx,
+    pub fn new<S>(s: S) -> Self
+    where
+        S: Service<Cx, T, Response = U, Error = E> + 'static,
+        T: 'static,
+    {
+        ArcService { inner: Arc::new(s) }
+    }
+}
+
+impl<Cx, T, U, E> Clone for ArcService<Cx, T, U, E> {
+    fn clone(&self) -> Self {
+        ArcService {
+            inner: self.inner.clone(),
+        }
+    }
+}
+
+impl<Cx, T, U, E> fmt::Debug for ArcService<Cx, T, U, E> {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        fmt.debug_struct("ArcService").finish()
+    }
+}
+
+impl<Cx, T, U, E> Service<Cx, T> for ArcService<Cx, T, U, E>
+where
+    Cx: MaybeSend,
+    T: MaybeSend,
+{
+    type Response = U;
+
+    type Error = E;
+
+    async fn call(&self, cx: &mut Cx, req: T) -> Result<Self::Response, Self::Error> {
+        // The boxed future borrows the service and the context for the shorter of
+        // both borrows, which only a future capturing both can name.
+        self.inner.call_boxed(cx, req).await
+    }
+}
+
+/// An object safe version of [`Service`], boxing the futures.
+trait DynService<Cx, T, U, E> {
+    fn call_boxed<'a>(&'a self, cx: &'a mut Cx, req: T) -> BoxFuture<'a, Result<U, E>>;
+}
+
+impl<Cx, T, S> DynService<Cx, T, S::Response, S::Error> for S
+where
+    T: 'static,
+    S: Service<Cx, T> + 'static,
+{
+    fn call_boxed<'a>(
+        &'a self,
+        cx: &'a mut Cx,
         req: T,
-    ) -> impl Future<Output = Result<Self::Response, Self::Error>> {
-        unsafe { (self.vtable.call)(self.raw, cx, req) }
+    ) -> BoxFuture<'a, Result<S::Response, S::Error>> {
+        Box::pin(self.call(cx, req))
     }
 }
 
-/// # Safety
-///
-/// The contained `Service` must be `Send` and `Sync` required by the bounds of `new` and `clone`.
+/// A [`DynService`] that can clone itself into a new box.
 #[cfg(feature = "service_send")]
-unsafe impl<Cx, T, U, E> Send for BoxCloneService<Cx, T, U, E> {}
+trait CloneService<Cx, T, U, E>: DynService<Cx, T, U, E> {
+    fn clone_box(&self) -> Box<dyn CloneService<Cx, T, U, E> + Send + Sync>;
+}
+
 #[cfg(feature = "service_send")]
-unsafe impl<Cx, T, U, E> Sync for BoxCloneService<Cx, T, U, E> {}
+impl<Cx, T, S> CloneService<Cx, T, S::Response, S::Error> for S
+where
+    T: 'static,
+    S: Service<Cx, T> + Clone + Send + Sync + 'static,
+{
+    fn clone_box(&self) -> Box<dyn CloneService<Cx, T, S::Response, S::Error> + Send + Sync> {
+        Box::new(self.clone())
+    }
+}
 
-struct CloneServiceVtable<Cx, T, U, E> {
-    call: unsafe fn(raw: *mut (), cx: &mut Cx, req: T) -> BoxFuture<'_, Result<U, E>>,
-    clone: unsafe fn(raw: *mut ()) -> BoxCloneService<Cx, T, U, E>,
-    drop: unsafe fn(raw: *mut ()),
+/// A [`DynService`] that can clone itself into a new box.
+#[cfg(not(feature = "service_send"))]
+trait CloneService<Cx, T, U, E>: DynService<Cx, T, U, E> {
+    fn clone_box(&self) -> Box<dyn CloneService<Cx, T, U, E>>;
+}
+
+#[cfg(not(feature = "service_send"))]
+impl<Cx, T, S> CloneService<Cx, T, S::Response, S::Error> for S
+where
+    T: 'static,
+    S: Service<Cx, T> + Clone + 'static,
+{
+    fn clone_box(&self) -> Box<dyn CloneService<Cx, T, S::Response, S::Error>> {
+        Box::new(self.clone())
+    }
 }
 
-fn call<Cx, Req, S>(
-    raw: *mut (),
+unsafe fn call<Cx, Req, S>(
+    storage: *const Storage,
     cx: &mut Cx,
     req: Req,
 ) -> BoxFuture<'_, Result<S::Response, S::Error>>
@@ -406,30 +619,213 @@ where
     Req: 'static,
     S: Service<Cx, Req> + 'static,
 {
-    let fut = S::call(unsafe { (raw as *mut S).as_mut().unwrap() }, cx, req);
+    let fut = S::call(get::<S>(&*storage), cx, req);
     Box::pin(fut)
 }
 
-#[cfg(feature = "service_send")]
-fn clone<Cx, Req, S: Clone + Send + Service<Cx, Req> + 'static + Sync>(
-    raw: *mut (),
-) -> BoxCloneService<Cx, Req, S::Response, S::Error>
-where
-    Req: 'static,
-{
-    BoxCloneService::new(S::clone(unsafe { (raw as *mut S).as_ref().unwrap() }))
+unsafe fn drop<S>(storage: &mut Storage) {
+    if is_inline::<S>() {
+        ptr::drop_in_place(storage.get_mut().as_mut_ptr().cast::<S>());
+    } else {
+        mem::drop(Box::from_raw(
+            storage.get_mut().as_ptr().cast::<*mut S>().read(),
+        ));
+    }
 }
 
-#[cfg(not(feature = "service_send"))]
-fn clone<Cx, Req, S: Clone + Service<Cx, Req> + 'static>(
-    raw: *mut (),
-) -> BoxCloneService<Cx, Req, S::Response, S::Error>
-where
-    Req: 'static,
-{
-    BoxCloneService::new(S::clone(unsafe { (raw as *mut S).as_ref().unwrap() }))
-}
+#[cfg(test)]
+mod tests {
+    use std::{
+        convert::Infallible,
+        sync::atomic::{AtomicUsize, Ordering},
+    };
+
+    use super::*;
+    use crate::service::service_fn;
+
+    #[derive(Clone)]
+    struct Large {
+        payload: [u64; 8],
+    }
+
+    impl Service<(), u32> for Large {
+        type Response = u64;
+        type Error = Infallible;
+
+        async fn call(&self, _cx: &mut (), req: u32) -> Result<u64, Infallible> {
+            Ok(self.payload[req as usize])
+        }
+    }
+
+    #[tokio::test]
+    async fn nested_box_clone_service_is_not_boxed_again() {
+        let boxed = BoxCloneService::new(Large { payload: [7; 8] });
+        let heap = &*boxed.inner as *const _ as *const ();
+        let nested = BoxCloneService::new_static(boxed);
+        assert_eq!(&*nested.inner as *const _ as *const (), heap);
+        assert_eq!(nested.call(&mut (), 1).await, Ok(7));
+        assert_eq!(nested.clone().call(&mut (), 2).await, Ok(7));
+    }
+
+    #[tokio::test]
+    async fn small_services_are_stored_inline() {
+        static DROPS: AtomicUsize = AtomicUsize::new(0);
+
+        #[derive(Clone)]
+        struct Small(u32);
+
+        impl Drop for Small {
+            fn drop(&mut self) {
+                DROPS.fetch_add(1, Ordering::SeqCst);
+            }
+        }
 
-fn drop<S>(raw: *mut ()) {
-    std::mem::drop(unsafe { Box::from_raw(raw as *mut S) });
+        impl Service<(), u32> for Small {
+            type Response = u32;
+            type Error = Infallible;
+
+            async fn call(&self, _cx: &mut (), req: u32) -> Result<u32, Infallible> {
+                Ok(self.0 + req)
+            }
+        }
+
+        assert!(is_inline::<Small>());
+        assert!(!is_inline::<Large>());
+
+        let svc = BoxService::new(Small(1));
+        assert_eq!(svc.call(&mut (), 1).await, Ok(2));
+        mem::drop(svc);
+        assert_eq!(DROPS.load(Ordering::SeqCst), 1);
+
+        let svc = BoxService::new(Large { payload: [3; 8] });
+        assert_eq!(svc.call(&mut (), 0).await, Ok(3));
+
+        async fn handle(_cx: &mut (), req: u32) -> Result<u32, Infallible> {
+            Ok(req)
+        }
+        let svc = BoxService::new(service_fn(handle));
+        assert_eq!(svc.call(&mut (), 4).await, Ok(4));
+    }
+
+    #[tokio::test]
+    async fn box_clone_service_clones_the_service() {
+        let svc = BoxCloneService::new(Large {
+            payload: [1, 2, 3, 4, 5, 6, 7, 8],
+        });
+        let clone = svc.clone();
+        mem::drop(svc);
+        assert_eq!(clone.call(&mut (), 2).await, Ok(3));
+    }
+
+    #[tokio::test]
+    async fn box_service_accepts_non_clone_non_sync_services() {
+        struct Counter(std::cell::Cell<u32>);
+
+        impl Service<(), ()> for Counter {
+            type Response = u32;
+            type Error = Infallible;
+
+            #[cfg(feature = "service_send")]
+            fn call(
+                &self,
+                _cx: &mut (),
+                _req: (),
+            ) -> impl Future<Output = Result<u32, Infallible>> + Send {
+                let n = self.0.get() + 1;
+                self.0.set(n);
+                async move { Ok(n) }
+            }
+            #[cfg(not(feature = "service_send"))]
+            fn call(
+                &self,
+                _cx: &mut (),
+                _req: (),
+            ) -> impl Future<Output = Result<u32, Infallible>> {
+                let n = self.0.get() + 1;
+                self.0.set(n);
+                async move { Ok(n) }
+            }
+        }
+
+        // Stored in place, and mutated through a shared reference to the storage.
+        assert!(is_inline::<Counter>());
+        let svc = BoxService::new(Counter(Default::default()));
+        #[cfg(feature = "service_send")]
+        {
+            fn assert_send<T: Send>(_: &T) {}
+            assert_send(&svc);
+        }
+        assert_eq!(svc.call(&mut (), ()).await, Ok(1));
+        assert_eq!(svc.call(&mut (), ()).await, Ok(2));
+    }
+
+    #[tokio::test]
+    async fn arc_service_clones_share_the_service() {
+        struct Counter(AtomicUsize);
+
+        impl Service<(), ()> for Counter {
+            type Response = usize;
+            type Error = Infallible;
+
+            async fn call(&self, _cx: &mut (), _req: ()) -> Result<usize, Infallible> {
+                Ok(self.0.fetch_add(1, Ordering::SeqCst) + 1)
+            }
+        }
+
+        let svc = ArcService::new(Counter(AtomicUsize::new(0)));
+        let clone = svc.clone();
+        let call = async move { clone.call(&mut (), ()).await };
+        #[cfg(feature = "service_send")]
+        let res = tokio::spawn(call).await.unwrap();
+        #[cfg(not(feature = "service_send"))]
+        let res = {
+            let local = tokio::task::LocalSet::new();
+            local.run_until(local.spawn_local(call)).await.unwrap()
+        };
+        assert_eq!(res, Ok(1));
+        assert_eq!(svc.call(&mut (), ()).await, Ok(2));
+    }
+
+    #[cfg(not(feature = "service_send"))]
+    #[tokio::test]
+    async fn erased_services_accept_contexts_and_requests_that_are_not_send() {
+        use std::rc::Rc;
+
+        #[derive(Clone)]
+        struct Len;
+
+        impl Service<Rc<str>, Rc<str>> for Len {
+            type Response = usize;
+            type Error = Infallible;
+
+            async fn call(&self, cx: &mut Rc<str>, req: Rc<str>) -> Result<usize, Infallible> {
+                Ok(cx.len() + req.len())
+            }
+        }
+
+        let mut cx: Rc<str> = Rc::from("cx");
+        let svc = BoxCloneService::new(Len);
+        assert_eq!(svc.call(&mut cx, Rc::from("req")).await, Ok(5));
+        let svc = ArcService::new(Len);
+        assert_eq!(svc.call(&mut cx, Rc::from("req")).await, Ok(5));
+    }
+
+    #[tokio::test]
+    async fn erased_services_accept_borrowed_contexts() {
+        #[derive(Clone)]
+        struct Prefix;
+
+        impl<'a> Service<&'a str, u32> for Prefix {
+            type Response = String;
+            type Error = Infallible;
+
+            async fn call(&self, cx: &mut &'a str, req: u32) -> Result<String, Infallible> {
+                Ok(format!("{cx}{req}"))
+            }
+        }
+
+        let prefix = String::from("n");
+        let mut cx = prefix.as_str();
+        assert_eq!(Prefix.erase().call(&mut cx, 1).await.unwrap(), "n1");
+    }
 }
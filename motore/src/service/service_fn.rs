@@ -2,7 +2,7 @@ use std::fmt;
 
 use futures::Future;
 
-use crate::service::Service;
+use crate::{load::Ready, service::Service, MaybeSend};
 
 /// Returns a new [`ServiceFn`] with the given closure.
 ///
@@ -49,6 +49,15 @@ where
     type Response = R;
     type Error = E;
 
+    #[cfg(feature = "service_send")]
+    fn call(
+        &self,
+        cx: &mut Cx,
+        req: Request,
+    ) -> impl Future<Output = Result<Self::Response, Self::Error>> + Send {
+        (self.f).call(cx, req)
+    }
+    #[cfg(not(feature = "service_send"))]
     fn call(
         &self,
         cx: &mut Cx,
@@ -58,6 +67,9 @@ where
     }
 }
 
+/// A function has no notion of capacity, so it is always ready.
+impl<F> Ready for ServiceFn<F> {}
+
 impl<F> fmt::Debug for ServiceFn<F> {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         f.debug_struct("ServiceFn")
@@ -69,12 +81,16 @@ impl<F> fmt::Debug for ServiceFn<F> {
 /// [`Service`] for binding lifetime to return value while using closure.
 /// This is just a temporary workaround for lifetime issues.
 ///
+/// Unlike the [`Service`] trait itself, a closure returning a future that borrows
+/// its `&mut Cx` argument cannot be expressed with RPITIT, so this is the only
+/// place that still relies on a named future type.
+///
 /// Related issue: https://github.com/rust-lang/rust/issues/70263.
 /// Related RFC: https://github.com/rust-lang/rfcs/pull/3216.
 pub trait Callback<'r, Cx, Request> {
     type Response;
     type Error;
-    type Future: Future<Output = Result<Self::Response, Self::Error>> + Send + 'r;
+    type Future: Future<Output = Result<Self::Response, Self::Error>> + MaybeSend + 'r;
 
     fn call(&self, cx: &'r mut Cx, req: Request) -> Self::Future;
 }
@@ -82,7 +98,7 @@ pub trait Callback<'r, Cx, Request> {
 impl<'r, F, Fut, Cx, Request, R, E> Callback<'r, Cx, Request> for F
 where
     F: Fn(&'r mut Cx, Request) -> Fut,
-    Fut: Future<Output = Result<R, E>> + Send + 'r,
+    Fut: Future<Output = Result<R, E>> + MaybeSend + 'r,
     Cx: 'r,
 {
     type Response = R;
@@ -111,7 +127,7 @@ mod tests {
         }
 
         let uppercase_service = service_fn(handle);
-        let _ = uppercase_service.call(&mut MotoreContext, "req".to_string());
+        drop(uppercase_service.call(&mut MotoreContext, "req".to_string()));
         assert_eq!(
             "ServiceFn { f: motore::service::service_fn::tests::debug_impl_ok::handle }"
                 .to_string(),
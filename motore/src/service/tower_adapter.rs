@@ -18,6 +18,7 @@
 use std::{
     fmt,
     marker::PhantomData,
+    sync::Arc,
     task::{Context, Poll},
 };
 
@@ -27,6 +28,8 @@ use futures::future::BoxFuture;
 use futures::future::LocalBoxFuture;
 use futures::{Future, FutureExt};
 
+use tower::ServiceExt;
+
 use crate::Service;
 
 impl<T: ?Sized, Cx, MotoreReq, TowerReq> TowerAdapter<Cx, MotoreReq, TowerReq> for T where
@@ -38,24 +41,28 @@ impl<T: ?Sized, Cx, MotoreReq, TowerReq> TowerAdapter<Cx, MotoreReq, TowerReq> f
 pub trait TowerAdapter<Cx, MotoreReq, TowerReq>: Service<Cx, MotoreReq> {
     fn tower<F>(self, f: F) -> Tower<Self, F, Cx, MotoreReq>
     where
-        F: FnOnce(TowerReq) -> (Cx, MotoreReq),
+        F: Fn(TowerReq) -> (Cx, MotoreReq),
         Self: Sized,
     {
         Tower::new(self, f)
     }
 }
 
+/// A tower service that calls a motore service.
+///
+/// The motore service is kept behind an [`Arc`], so neither cloning the adapter nor
+/// calling it clones the service itself.
 #[cfg_attr(docsrs, doc(cfg(feature = "tower")))]
 pub struct Tower<S, F, Cx, MotoreReq> {
-    inner: S,
+    inner: Arc<S>,
     f: F,
     _phantom: PhantomData<fn(Cx, MotoreReq)>,
 }
 
 impl<S, F, Cx, MotoreReq> Tower<S, F, Cx, MotoreReq> {
-    pub const fn new(inner: S, f: F) -> Self {
+    pub fn new(inner: S, f: F) -> Self {
         Self {
-            inner,
+            inner: Arc::new(inner),
             f,
             _phantom: PhantomData,
         }
@@ -65,8 +72,8 @@ impl<S, F, Cx, MotoreReq> Tower<S, F, Cx, MotoreReq> {
 #[cfg(feature = "service_send")]
 impl<S, F, Cx, MotoreReq, TowerReq> tower::Service<TowerReq> for Tower<S, F, Cx, MotoreReq>
 where
-    S: Service<Cx, MotoreReq> + Clone + 'static + Send,
-    F: FnOnce(TowerReq) -> (Cx, MotoreReq) + Clone,
+    S: Service<Cx, MotoreReq> + 'static + Send + Sync,
+    F: Fn(TowerReq) -> (Cx, MotoreReq),
     MotoreReq: 'static + Send,
     Cx: 'static + Send,
 {
@@ -82,7 +89,7 @@ where
 
     fn call(&mut self, req: TowerReq) -> Self::Future {
         let inner = self.inner.clone();
-        let (mut cx, r) = (self.f.clone())(req);
+        let (mut cx, r) = (self.f)(req);
         async move { inner.call(&mut cx, r).await }.boxed()
     }
 }
@@ -90,8 +97,8 @@ where
 #[cfg(not(feature = "service_send"))]
 impl<S, F, Cx, MotoreReq, TowerReq> tower::Service<TowerReq> for Tower<S, F, Cx, MotoreReq>
 where
-    S: Service<Cx, MotoreReq> + Clone + 'static,
-    F: FnOnce(TowerReq) -> (Cx, MotoreReq) + Clone,
+    S: Service<Cx, MotoreReq> + 'static,
+    F: Fn(TowerReq) -> (Cx, MotoreReq),
     MotoreReq: 'static,
     Cx: 'static,
 {
@@ -107,14 +114,13 @@ where
 
     fn call(&mut self, req: TowerReq) -> Self::Future {
         let inner = self.inner.clone();
-        let (mut cx, r) = (self.f.clone())(req);
+        let (mut cx, r) = (self.f)(req);
         async move { inner.call(&mut cx, r).await }.boxed_local()
     }
 }
 
 impl<S, F, Cx, MotoreReq> Clone for Tower<S, F, Cx, MotoreReq>
 where
-    S: Clone,
     F: Clone,
 {
     fn clone(&self) -> Self {
@@ -147,13 +153,18 @@ impl<T: ?Sized, Cx, MotoreReq, TowerReq> MotoreAdapter<Cx, MotoreReq, TowerReq>
 pub trait MotoreAdapter<Cx, MotoreReq, TowerReq>: tower::Service<TowerReq> {
     fn motore<F>(self, f: F) -> Motore<Self, F>
     where
-        F: FnOnce(&mut Cx, MotoreReq) -> TowerReq,
+        F: Fn(&mut Cx, MotoreReq) -> TowerReq,
         Self: Sized,
     {
         Motore::new(self, f)
     }
 }
 
+/// A motore service that calls a tower service.
+///
+/// Tower services take `&mut self`, so the tower service is cloned for each call,
+/// and waited on until it is ready; it should be cheap to clone, as tower services
+/// usually are.
 #[derive(Clone)]
 #[cfg_attr(docsrs, doc(cfg(feature = "tower")))]
 pub struct Motore<S, F> {
@@ -167,32 +178,43 @@ impl<S, F> Motore<S, F> {
     }
 }
 
+#[cfg(feature = "service_send")]
 impl<S, F, Cx, MotoreReq, TowerReq> Service<Cx, MotoreReq> for Motore<S, F>
 where
-    S: tower::Service<TowerReq> + Clone,
-    for<'cx> <S as tower::Service<TowerReq>>::Future: Send + 'cx,
-    F: FnOnce(&mut Cx, MotoreReq) -> TowerReq + Clone,
+    S: tower::Service<TowerReq> + Clone + Send,
+    S::Future: Send,
+    F: Fn(&mut Cx, MotoreReq) -> TowerReq,
+    TowerReq: Send,
 {
     type Response = S::Response;
 
     type Error = S::Error;
 
-    #[cfg(feature = "service_send")]
     fn call(
         &self,
         cx: &mut Cx,
         req: MotoreReq,
     ) -> impl Future<Output = Result<Self::Response, Self::Error>> + Send {
-        self.inner.clone().call((self.f.clone())(cx, req))
+        self.inner.clone().oneshot((self.f)(cx, req))
     }
+}
+
+#[cfg(not(feature = "service_send"))]
+impl<S, F, Cx, MotoreReq, TowerReq> Service<Cx, MotoreReq> for Motore<S, F>
+where
+    S: tower::Service<TowerReq> + Clone,
+    F: Fn(&mut Cx, MotoreReq) -> TowerReq,
+{
+    type Response = S::Response;
+
+    type Error = S::Error;
 
-    #[cfg(not(feature = "service_send"))]
     fn call(
         &self,
         cx: &mut Cx,
         req: MotoreReq,
     ) -> impl Future<Output = Result<Self::Response, Self::Error>> {
-        self.inner.clone().call((self.f.clone())(cx, req))
+        self.inner.clone().oneshot((self.f)(cx, req))
     }
 }
 
@@ -207,3 +229,47 @@ where
             .finish()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::convert::Infallible;
+
+    use super::*;
+
+    /// A tower service that must be polled for readiness before every call.
+    #[derive(Clone, Default)]
+    struct Checked {
+        ready: bool,
+    }
+
+    impl tower::Service<u32> for Checked {
+        type Response = u32;
+        type Error = Infallible;
+        type Future = std::future::Ready<Result<u32, Infallible>>;
+
+        fn poll_ready(&mut self, _cx: &mut Context<'_>) -> Poll<Result<(), Infallible>> {
+            self.ready = true;
+            Poll::Ready(Ok(()))
+        }
+
+        fn call(&mut self, req: u32) -> Self::Future {
+            assert!(std::mem::take(&mut self.ready), "called before being ready");
+            std::future::ready(Ok(req * 2))
+        }
+    }
+
+    #[tokio::test]
+    async fn motore_waits_for_the_tower_service_to_be_ready() {
+        let svc = Checked::default().motore(|cx: &mut u32, req: u32| *cx + req);
+        assert_eq!(svc.call(&mut 1, 2).await, Ok(6));
+        assert_eq!(svc.call(&mut 1, 3).await, Ok(8));
+    }
+
+    #[tokio::test]
+    async fn tower_calls_the_motore_service() {
+        let svc = Checked::default()
+            .motore(|_cx: &mut (), req: u32| req)
+            .tower(|req: u32| ((), req + 1));
+        assert_eq!(svc.oneshot(1).await, Ok(4));
+    }
+}
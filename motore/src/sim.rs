@@ -0,0 +1,123 @@
+//! Simulated backends for benchmarking and tuning middleware stacks.
+//!
+//! [`LatencyService`] answers every request after a latency drawn from a
+//! configurable distribution and fails a configurable ratio of them, so that
+//! limits, timeouts and hedging thresholds can be tuned before pointing a stack at
+//! real backends.
+
+use std::{
+    fmt,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc,
+    },
+    time::Duration,
+};
+
+pub use crate::fault::Latency;
+use crate::{utils::rng::Rng, MaybeSend, Service};
+
+/// The error returned by the calls a [`LatencyService`] decides to fail.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct SimulatedError;
+
+impl fmt::Display for SimulatedError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("simulated error")
+    }
+}
+
+impl std::error::Error for SimulatedError {}
+
+/// A [`Service`] simulating a backend with a given latency distribution and
+/// error rate.
+///
+/// It accepts any request type and responds with the request itself. Clones share
+/// the same sequence of random decisions.
+///
+/// # Example
+///
+/// ```rust
+/// use std::time::Duration;
+///
+/// use motore::sim::{Latency, LatencyService};
+///
+/// let backend = LatencyService::new(Latency::LogNormal {
+///     median: Duration::from_millis(20),
+///     sigma: 0.5,
+/// })
+/// .error_rate(0.01);
+/// ```
+#[derive(Clone, Debug)]
+pub struct LatencyService {
+    latency: Latency,
+    error_rate: f64,
+    seed: u64,
+    calls: Arc<AtomicU64>,
+}
+
+impl LatencyService {
+    /// Create a new `LatencyService` with the given latency distribution and no errors.
+    pub fn new(latency: Latency) -> Self {
+        LatencyService {
+            latency,
+            error_rate: 0.0,
+            seed: Rng::new().next_u64(),
+            calls: Arc::new(AtomicU64::new(0)),
+        }
+    }
+
+    /// Sets the ratio of calls failing with a [`SimulatedError`], between `0.0` and `1.0`.
+    pub fn error_rate(mut self, error_rate: f64) -> Self {
+        self.error_rate = error_rate;
+        self
+    }
+
+    /// Sets the seed of the random decisions, making the simulation reproducible
+    /// for a given sequence of calls.
+    pub fn seed(mut self, seed: u64) -> Self {
+        self.seed = seed;
+        self
+    }
+
+    fn sample(&self) -> (Duration, bool) {
+        let n = self.calls.fetch_add(1, Ordering::Relaxed);
+        let mut rng = Rng::with_seed(self.seed ^ n.wrapping_mul(0x9e37_79b9_7f4a_7c15));
+        (self.latency.sample(&mut rng), rng.chance(self.error_rate))
+    }
+}
+
+impl<Cx, Req> Service<Cx, Req> for LatencyService
+where
+    Cx: MaybeSend,
+    Req: MaybeSend,
+{
+    type Response = Req;
+    type Error = SimulatedError;
+
+    async fn call(&self, _cx: &mut Cx, req: Req) -> Result<Self::Response, Self::Error> {
+        let (latency, fail) = self.sample();
+        tokio::time::sleep(latency).await;
+        if fail {
+            Err(SimulatedError)
+        } else {
+            Ok(req)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test(start_paused = true)]
+    async fn simulates_latency_and_errors() {
+        let svc = LatencyService::new(Latency::Constant(Duration::from_millis(10)));
+        let start = tokio::time::Instant::now();
+        assert_eq!(svc.call(&mut (), 1).await, Ok(1));
+        assert_eq!(start.elapsed(), Duration::from_millis(10));
+
+        let svc = svc.error_rate(1.0);
+        assert_eq!(svc.call(&mut (), 1).await, Err(SimulatedError));
+    }
+}
@@ -0,0 +1,267 @@
+//! Coalesces concurrent identical requests into a single call.
+//!
+//! When many callers ask for the same thing at the same time, like a popular
+//! cache entry that just expired, [`Singleflight`] lets only the first of them
+//! call the inner service. The others wait for that call, and each receives a
+//! clone of its result, so the response and the error must both be [`Clone`].
+//!
+//! Requests are identified by a key extracted from them, and are only coalesced
+//! while a call for their key is in flight: a request arriving after the call
+//! completed makes a call of its own.
+//!
+//! Only the caller making the call passes its context to the inner service, the
+//! contexts of the waiting callers are left untouched. When that caller stops
+//! waiting for its call, the call is dropped, and one of the waiting callers makes
+//! it again instead, so a cancelled caller never fails the others.
+//!
+//! # Scalability
+//!
+//! Every request looks its key up among the calls in flight, so these are spread
+//! across shards by the hash of the key, four per available thread, each with its
+//! own lock held only for the lookup. Requests for different keys thus rarely
+//! contend, and the requests for a single hot key only contend on its shard.
+//!
+//! A request joining a call in flight doesn't allocate: it waits on the channel of
+//! the call, whose waiters are kept in an intrusive list. Only the request making
+//! a call allocates that channel.
+
+use std::{
+    fmt,
+    hash::Hash,
+    marker::PhantomData,
+    sync::Arc,
+    task::{Context, Poll},
+};
+
+use crate::{
+    layer::Layer,
+    load::{Load, Ready},
+    service::Service,
+    utils::wait_map::{Entry, WaitMap},
+    MaybeSend, MaybeSync,
+};
+
+/// Makes a single call to the inner service for all the concurrent requests with
+/// the same key, and returns a clone of its result to each of them.
+///
+/// `K` is the type of the keys and `V` the result of the inner service, both are
+/// inferred. The clones of a `Singleflight` share the calls in flight. See the
+/// [module level docs](self) for details.
+pub struct Singleflight<S, F, K, V> {
+    inner: S,
+    key: F,
+    in_flight: Arc<WaitMap<K, V>>,
+}
+
+impl<S, F, K, V> Singleflight<S, F, K, V> {
+    /// Creates a `Singleflight` coalescing the requests for which `key` returns the
+    /// same key.
+    pub fn new(inner: S, key: F) -> Self {
+        Singleflight {
+            inner,
+            key,
+            in_flight: Arc::new(WaitMap::new()),
+        }
+    }
+}
+
+impl<Cx, Req, S, F, K> Service<Cx, Req> for Singleflight<S, F, K, Result<S::Response, S::Error>>
+where
+    Req: 'static + MaybeSend,
+    S: Service<Cx, Req> + 'static + MaybeSend + MaybeSync,
+    S::Response: Clone + MaybeSend + MaybeSync,
+    S::Error: Clone + MaybeSend + MaybeSync,
+    F: Fn(&Req) -> K + MaybeSend + MaybeSync,
+    K: Hash + Eq + Clone + MaybeSend + MaybeSync,
+    Cx: 'static + MaybeSend,
+{
+    type Response = S::Response;
+
+    type Error = S::Error;
+
+    async fn call(&self, cx: &mut Cx, req: Req) -> Result<Self::Response, Self::Error> {
+        let key = (self.key)(&req);
+        loop {
+            match self.in_flight.enter(&key) {
+                Entry::Wait(waiter) => {
+                    // Fails when the call was cancelled, in which case it is made
+                    // again.
+                    if let Some(res) = waiter.wait().await {
+                        return res;
+                    }
+                }
+                Entry::Lead(flight) => {
+                    let res = self.inner.call(cx, req).await;
+                    flight.complete(res.clone());
+                    return res;
+                }
+            }
+        }
+    }
+}
+
+impl<S, F, K, V> Ready for Singleflight<S, F, K, V>
+where
+    S: Ready,
+{
+    fn poll_ready(&self, cx: &mut Context<'_>) -> Poll<()> {
+        self.inner.poll_ready(cx)
+    }
+}
+
+impl<S, F, K, V> Load for Singleflight<S, F, K, V>
+where
+    S: Load,
+{
+    type Metric = S::Metric;
+
+    fn load(&self) -> Self::Metric {
+        self.inner.load()
+    }
+}
+
+impl<S: Clone, F: Clone, K, V> Clone for Singleflight<S, F, K, V> {
+    fn clone(&self) -> Self {
+        Singleflight {
+            inner: self.inner.clone(),
+            key: self.key.clone(),
+            in_flight: self.in_flight.clone(),
+        }
+    }
+}
+
+impl<S: fmt::Debug, F, K, V> fmt::Debug for Singleflight<S, F, K, V> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Singleflight")
+            .field("inner", &self.inner)
+            .field("key", &format_args!("{}", std::any::type_name::<F>()))
+            .finish()
+    }
+}
+
+/// Applies a [`Singleflight`] to a service.
+///
+/// Every service made by the layer has its own calls in flight, shared only by its
+/// clones.
+///
+/// # Example
+///
+/// ```rust
+/// use motore::{
+///     builder::ServiceBuilder, service::service_fn, singleflight::SingleflightLayer, Service,
+/// };
+///
+/// # #[tokio::main(flavor = "current_thread")]
+/// # async fn main() {
+/// async fn get(_cx: &mut (), key: String) -> Result<String, String> {
+///     Ok(key.to_uppercase())
+/// }
+///
+/// let svc = ServiceBuilder::new()
+///     .layer(SingleflightLayer::new(|req: &String| req.clone()))
+///     .service(service_fn(get));
+///
+/// assert_eq!(svc.call(&mut (), "key".into()).await.unwrap(), "KEY");
+/// # }
+/// ```
+pub struct SingleflightLayer<F, K, V> {
+    key: F,
+    _phantom: PhantomData<fn() -> (K, V)>,
+}
+
+impl<F, K, V> SingleflightLayer<F, K, V> {
+    /// Creates a layer coalescing the requests for which `key` returns the same
+    /// key.
+    pub const fn new(key: F) -> Self {
+        SingleflightLayer {
+            key,
+            _phantom: PhantomData,
+        }
+    }
+}
+
+impl<F: Clone, K, V> Clone for SingleflightLayer<F, K, V> {
+    fn clone(&self) -> Self {
+        SingleflightLayer {
+            key: self.key.clone(),
+            _phantom: PhantomData,
+        }
+    }
+}
+
+impl<F, K, V> fmt::Debug for SingleflightLayer<F, K, V> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("SingleflightLayer")
+            .field("key", &format_args!("{}", std::any::type_name::<F>()))
+            .finish()
+    }
+}
+
+impl<S, F, K, V> Layer<S> for SingleflightLayer<F, K, V> {
+    type Service = Singleflight<S, F, K, V>;
+
+    fn layer(self, inner: S) -> Self::Service {
+        Singleflight::new(inner, self.key)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        sync::atomic::{AtomicUsize, Ordering},
+        time::Duration,
+    };
+
+    use super::*;
+    use crate::service::service_fn;
+
+    fn counted(
+        calls: &Arc<AtomicUsize>,
+    ) -> impl Service<(), u32, Response = u32, Error = &'static str> + Clone {
+        let calls = calls.clone();
+        service_fn(move |_cx: &mut (), req: u32| {
+            calls.fetch_add(1, Ordering::SeqCst);
+            async move {
+                tokio::time::sleep(Duration::from_millis(10)).await;
+                Ok(req * 2)
+            }
+        })
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn coalesces_concurrent_requests() {
+        let calls = Arc::new(AtomicUsize::new(0));
+        let svc = SingleflightLayer::new(|req: &u32| *req).layer(counted(&calls));
+
+        let svc = &svc;
+        let responses = futures::future::join_all(
+            [1, 1, 2, 1].map(|i| async move { svc.call(&mut (), i).await }),
+        )
+        .await;
+        assert_eq!(responses, [Ok(2), Ok(2), Ok(4), Ok(2)]);
+        assert_eq!(calls.load(Ordering::SeqCst), 2);
+
+        // The call is over, so the next request makes a new one.
+        assert_eq!(svc.call(&mut (), 1).await, Ok(2));
+        assert_eq!(calls.load(Ordering::SeqCst), 3);
+        assert!(matches!(svc.in_flight.enter(&1), Entry::Lead(_)));
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn waiters_take_over_cancelled_calls() {
+        let calls = Arc::new(AtomicUsize::new(0));
+        let svc = SingleflightLayer::new(|req: &u32| *req).layer(counted(&calls));
+
+        let cancelled = tokio::time::timeout(Duration::from_millis(5), async {
+            svc.call(&mut (), 1).await
+        });
+        let waiting = async {
+            tokio::task::yield_now().await;
+            svc.call(&mut (), 1).await
+        };
+        let (cancelled, waiting) = tokio::join!(cancelled, waiting);
+        assert!(cancelled.is_err());
+        assert_eq!(waiting, Ok(2));
+        assert_eq!(calls.load(Ordering::SeqCst), 2);
+    }
+}
@@ -0,0 +1,257 @@
+//! Runs every call on its own task.
+//!
+//! A service is usually called on the caller's task, so a handler doing a lot of
+//! work between its awaits holds up everything else that task drives, and a
+//! handler panicking takes the task down with it. [`Spawn`] runs each call on a
+//! new task instead, so calls are scheduled independently, can run in parallel on
+//! a multi-threaded runtime, and a panic only fails the call that panicked.
+//!
+//! The context is moved to the task along with the request, and moved back once
+//! the call completed, so it must implement [`Default`] to fill the caller's
+//! context in the meantime.
+
+use std::{
+    error::Error,
+    fmt, mem,
+    sync::Arc,
+    task::{Context, Poll},
+};
+
+use tokio::task::JoinHandle;
+
+use crate::{
+    catch_panic::panic_message,
+    layer::Layer,
+    load::{Load, Ready},
+    service::Service,
+    MaybeSend, MaybeSync,
+};
+
+/// The error returned by [`Spawn`].
+#[derive(Debug, PartialEq, Eq)]
+pub enum SpawnError<E> {
+    /// The call panicked, with the given message.
+    Panicked(String),
+    /// The task running the call was cancelled, as the runtime is shutting down.
+    Cancelled,
+    /// The inner service failed.
+    Service(E),
+}
+
+impl<E: fmt::Display> fmt::Display for SpawnError<E> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SpawnError::Panicked(msg) => write!(f, "call panicked: {msg}"),
+            SpawnError::Cancelled => f.write_str("call cancelled"),
+            SpawnError::Service(e) => fmt::Display::fmt(e, f),
+        }
+    }
+}
+
+impl<E> Error for SpawnError<E>
+where
+    E: Error + 'static,
+{
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            SpawnError::Service(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+/// Aborts the task of a call whose caller went away.
+struct AbortOnDrop<T>(JoinHandle<T>);
+
+impl<T> Drop for AbortOnDrop<T> {
+    fn drop(&mut self) {
+        self.0.abort();
+    }
+}
+
+/// Runs every call to the inner service on a new task.
+///
+/// When the caller stops waiting for a call, its task is aborted. See the [module
+/// level docs](self) for details.
+pub struct Spawn<S> {
+    inner: Arc<S>,
+}
+
+impl<S> Spawn<S> {
+    pub fn new(inner: S) -> Self {
+        Spawn {
+            inner: Arc::new(inner),
+        }
+    }
+}
+
+impl<Cx, Req, S> Service<Cx, Req> for Spawn<S>
+where
+    Req: 'static + MaybeSend,
+    S: Service<Cx, Req> + 'static + MaybeSend + MaybeSync,
+    S::Response: MaybeSend + 'static,
+    S::Error: MaybeSend + 'static,
+    Cx: Default + 'static + MaybeSend,
+{
+    type Response = S::Response;
+
+    type Error = SpawnError<S::Error>;
+
+    async fn call(&self, cx: &mut Cx, req: Req) -> Result<Self::Response, Self::Error> {
+        let inner = self.inner.clone();
+        let mut owned = mem::take(cx);
+        let task = async move {
+            let res = inner.call(&mut owned, req).await;
+            (owned, res)
+        };
+        #[cfg(feature = "service_send")]
+        let mut handle = AbortOnDrop(tokio::spawn(task));
+        #[cfg(not(feature = "service_send"))]
+        let mut handle = AbortOnDrop(tokio::task::spawn_local(task));
+
+        // When the call fails to complete, the context is lost along with it.
+        match (&mut handle.0).await {
+            Ok((owned, res)) => {
+                *cx = owned;
+                res.map_err(SpawnError::Service)
+            }
+            Err(e) if e.is_panic() => Err(SpawnError::Panicked(panic_message(e.into_panic()))),
+            Err(_) => Err(SpawnError::Cancelled),
+        }
+    }
+}
+
+impl<S> Ready for Spawn<S>
+where
+    S: Ready,
+{
+    fn poll_ready(&self, cx: &mut Context<'_>) -> Poll<()> {
+        self.inner.poll_ready(cx)
+    }
+}
+
+impl<S> Load for Spawn<S>
+where
+    S: Load,
+{
+    type Metric = S::Metric;
+
+    fn load(&self) -> Self::Metric {
+        self.inner.load()
+    }
+}
+
+impl<S> Clone for Spawn<S> {
+    fn clone(&self) -> Self {
+        Spawn {
+            inner: self.inner.clone(),
+        }
+    }
+}
+
+impl<S: fmt::Debug> fmt::Debug for Spawn<S> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Spawn").field("inner", &self.inner).finish()
+    }
+}
+
+/// Applies a [`Spawn`] middleware.
+///
+/// # Example
+///
+/// ```rust
+/// use motore::{
+///     builder::ServiceBuilder,
+///     service::service_fn,
+///     spawn::{SpawnError, SpawnLayer},
+///     Service,
+/// };
+///
+/// # #[tokio::main(flavor = "current_thread")]
+/// # async fn main() {
+/// # tokio::task::LocalSet::new().run_until(async {
+/// async fn parse(_cx: &mut (), req: String) -> Result<u32, std::num::ParseIntError> {
+///     Ok(req.parse::<u32>()? * 2)
+/// }
+///
+/// let svc = ServiceBuilder::new()
+///     .layer(SpawnLayer::new())
+///     .service(service_fn(parse));
+///
+/// assert_eq!(svc.call(&mut (), "21".into()).await, Ok(42));
+/// assert!(matches!(svc.call(&mut (), "x".into()).await, Err(SpawnError::Service(_))));
+/// # }).await;
+/// # }
+/// ```
+#[derive(Clone, Copy, Debug, Default)]
+pub struct SpawnLayer;
+
+impl SpawnLayer {
+    pub const fn new() -> Self {
+        SpawnLayer
+    }
+}
+
+impl<S> Layer<S> for SpawnLayer {
+    type Service = Spawn<S>;
+
+    fn layer(self, inner: S) -> Self::Service {
+        Spawn::new(inner)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::service::service_fn;
+
+    async fn checked_div(cx: &mut u32, req: u32) -> Result<u32, &'static str> {
+        *cx += 1;
+        if req == 0 {
+            panic!("division by zero");
+        }
+        Ok(100 / req)
+    }
+
+    #[tokio::test]
+    async fn isolates_panics() {
+        tokio::task::LocalSet::new()
+            .run_until(async {
+                let svc = SpawnLayer::new().layer(service_fn(checked_div));
+
+                let mut calls = 0;
+                assert_eq!(svc.call(&mut calls, 4).await, Ok(25));
+                assert_eq!(calls, 1);
+
+                assert_eq!(
+                    svc.call(&mut calls, 0).await,
+                    Err(SpawnError::Panicked("division by zero".to_string()))
+                );
+                assert_eq!(calls, 0);
+            })
+            .await;
+    }
+
+    #[tokio::test]
+    async fn aborts_calls_no_longer_awaited() {
+        tokio::task::LocalSet::new()
+            .run_until(async {
+                let (tx, rx) = tokio::sync::oneshot::channel::<()>();
+                let tx = std::sync::Mutex::new(Some(tx));
+                let svc = SpawnLayer::new().layer(service_fn(move |_cx: &mut (), _req: ()| {
+                    // Dropped along with the task when it is aborted.
+                    let tx = tx.lock().unwrap().take();
+                    async move {
+                        let _tx = tx;
+                        std::future::pending::<Result<(), &'static str>>().await
+                    }
+                }));
+
+                let timeout = std::time::Duration::from_millis(10);
+                let call = async { svc.call(&mut (), ()).await };
+                assert!(tokio::time::timeout(timeout, call).await.is_err());
+                assert!(rx.await.is_err());
+            })
+            .await;
+    }
+}
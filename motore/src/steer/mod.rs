@@ -0,0 +1,189 @@
+//! Routes each request to one of several services.
+//!
+//! [`Steer`] owns a list of services and a [`Picker`] choosing, for every call,
+//! which of them handles it. This is the building block for sharding, multi-backend
+//! dispatch and routing. [`Router`] builds on it to dispatch by a key, like a
+//! method name or a tenant.
+
+use std::{
+    fmt,
+    future::Future,
+    task::{Context, Poll},
+};
+
+use crate::{load::Ready, Service};
+
+mod router;
+pub use self::router::{RouteError, Router};
+
+/// Chooses which service handles a request.
+///
+/// This is implemented for closures taking the context, the request and the
+/// services, and returning the index of the chosen service.
+pub trait Picker<Cx, Req, S> {
+    /// Returns the index in `services` of the service handling the request.
+    fn pick(&self, cx: &Cx, req: &Req, services: &[S]) -> usize;
+}
+
+impl<F, Cx, Req, S> Picker<Cx, Req, S> for F
+where
+    F: Fn(&Cx, &Req, &[S]) -> usize,
+{
+    fn pick(&self, cx: &Cx, req: &Req, services: &[S]) -> usize {
+        self(cx, req, services)
+    }
+}
+
+/// Calls one of several services, chosen by a [`Picker`] for each request.
+///
+/// # Panics
+///
+/// Calls panic if the picker returns an index out of bounds.
+///
+/// # Example
+///
+/// ```rust
+/// use std::convert::Infallible;
+///
+/// use motore::{
+///     service::{service_fn, Service, ServiceExt},
+///     steer::Steer,
+/// };
+///
+/// # #[tokio::main(flavor = "current_thread")]
+/// # async fn main() {
+/// async fn even(_cx: &mut (), _req: u32) -> Result<&'static str, Infallible> {
+///     Ok("even")
+/// }
+///
+/// async fn odd(_cx: &mut (), _req: u32) -> Result<&'static str, Infallible> {
+///     Ok("odd")
+/// }
+///
+/// // The services are erased to store them together.
+/// let services = [service_fn(even).erase(), service_fn(odd).erase()];
+/// let steer = Steer::new(services, |_cx: &(), req: &u32, _: &[_]| *req as usize % 2);
+/// assert_eq!(steer.call(&mut (), 3).await, Ok("odd"));
+/// # }
+/// ```
+#[derive(Clone)]
+pub struct Steer<S, P> {
+    services: Vec<S>,
+    picker: P,
+}
+
+impl<S, P> Steer<S, P> {
+    /// Creates a `Steer` choosing among `services` with `picker`.
+    pub fn new(services: impl IntoIterator<Item = S>, picker: P) -> Self {
+        Steer {
+            services: services.into_iter().collect(),
+            picker,
+        }
+    }
+
+    /// Returns the services, in the order the picker indexes them.
+    pub fn services(&self) -> &[S] {
+        &self.services
+    }
+}
+
+impl<Cx, Req, S, P> Service<Cx, Req> for Steer<S, P>
+where
+    S: Service<Cx, Req>,
+    P: Picker<Cx, Req, S>,
+{
+    type Response = S::Response;
+
+    type Error = S::Error;
+
+    #[cfg(feature = "service_send")]
+    fn call(
+        &self,
+        cx: &mut Cx,
+        req: Req,
+    ) -> impl Future<Output = Result<Self::Response, Self::Error>> + Send {
+        let idx = self.picker.pick(cx, &req, &self.services);
+        self.services[idx].call(cx, req)
+    }
+    #[cfg(not(feature = "service_send"))]
+    fn call(
+        &self,
+        cx: &mut Cx,
+        req: Req,
+    ) -> impl Future<Output = Result<Self::Response, Self::Error>> {
+        let idx = self.picker.pick(cx, &req, &self.services);
+        self.services[idx].call(cx, req)
+    }
+}
+
+/// Ready when all the services are ready, as any of them may be picked.
+impl<S, P> Ready for Steer<S, P>
+where
+    S: Ready,
+{
+    fn poll_ready(&self, cx: &mut Context<'_>) -> Poll<()> {
+        let mut ready = Poll::Ready(());
+        for svc in &self.services {
+            if svc.poll_ready(cx).is_pending() {
+                ready = Poll::Pending;
+            }
+        }
+        ready
+    }
+}
+
+impl<S, P> fmt::Debug for Steer<S, P>
+where
+    S: fmt::Debug,
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Steer")
+            .field("services", &self.services)
+            .field("picker", &format_args!("{}", std::any::type_name::<P>()))
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::convert::Infallible;
+
+    use super::*;
+    use crate::service::service_fn;
+
+    #[derive(Clone, Copy)]
+    struct Shard(u32);
+
+    impl Service<u32, u64> for Shard {
+        type Response = (u32, u64);
+        type Error = Infallible;
+
+        async fn call(&self, _cx: &mut u32, req: u64) -> Result<(u32, u64), Infallible> {
+            Ok((self.0, req))
+        }
+    }
+
+    #[tokio::test]
+    async fn picks_by_context_and_request() {
+        let steer = Steer::new(
+            (0..4).map(Shard),
+            |tenant: &u32, key: &u64, shards: &[Shard]| {
+                (*tenant as u64 + key) as usize % shards.len()
+            },
+        );
+        assert_eq!(steer.call(&mut 1, 2).await, Ok((3, 2)));
+        assert_eq!(steer.call(&mut 2, 2).await, Ok((0, 2)));
+        assert_eq!(steer.services().len(), 4);
+    }
+
+    #[tokio::test]
+    #[should_panic]
+    async fn panics_on_out_of_bounds_picks() {
+        async fn handle(_cx: &mut (), _req: ()) -> Result<(), Infallible> {
+            Ok(())
+        }
+
+        let steer = Steer::new([service_fn(handle)], |_: &(), _: &(), _: &[_]| 1);
+        let _ = steer.call(&mut (), ()).await;
+    }
+}
@@ -0,0 +1,329 @@
+use std::{
+    collections::HashMap, error::Error, fmt, future::Future, hash::Hash, marker::PhantomData,
+};
+
+use super::{Picker, Steer};
+use crate::service::{BoxCloneService, Service, ServiceExt};
+
+/// The error returned by a [`Router`].
+#[derive(Debug, PartialEq, Eq)]
+pub enum RouteError<E> {
+    /// No route matches the request, and no fallback is set.
+    NotFound,
+    /// The service handling the request failed.
+    Service(E),
+}
+
+impl<E: fmt::Display> fmt::Display for RouteError<E> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RouteError::NotFound => f.write_str("no route matches the request"),
+            RouteError::Service(e) => fmt::Display::fmt(e, f),
+        }
+    }
+}
+
+impl<E: Error + 'static> Error for RouteError<E> {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            RouteError::NotFound => None,
+            RouteError::Service(e) => Some(e),
+        }
+    }
+}
+
+/// Dispatches requests to services by a key extracted from the context and the
+/// request.
+///
+/// The services are boxed, so services of different types can be registered, as
+/// long as they share the response and error types. A request whose key has no
+/// route goes to the fallback service, or fails with [`RouteError::NotFound`] when
+/// none is set.
+///
+/// # Example
+///
+/// ```rust
+/// use std::convert::Infallible;
+///
+/// use motore::{
+///     service::{service_fn, Service},
+///     steer::{RouteError, Router},
+/// };
+///
+/// # #[tokio::main(flavor = "current_thread")]
+/// # async fn main() {
+/// async fn get(_cx: &mut (), _req: (&'static str, u32)) -> Result<String, Infallible> {
+///     Ok("get".to_string())
+/// }
+///
+/// async fn put(_cx: &mut (), req: (&'static str, u32)) -> Result<String, Infallible> {
+///     Ok(format!("put {}", req.1))
+/// }
+///
+/// let router = Router::new(|_cx: &(), req: &(&'static str, u32)| req.0)
+///     .route("get", service_fn(get))
+///     .route("put", service_fn(put));
+///
+/// assert_eq!(router.call(&mut (), ("put", 1)).await, Ok("put 1".to_string()));
+/// assert_eq!(router.call(&mut (), ("del", 1)).await, Err(RouteError::NotFound));
+/// # }
+/// ```
+pub struct Router<K, F, Cx, Req, Resp, E> {
+    steer: Steer<Route<Cx, Req, Resp, E>, RoutePicker<K, F>>,
+}
+
+type Route<Cx, Req, Resp, E> = BoxCloneService<Cx, Req, Resp, RouteError<E>>;
+
+#[cfg(feature = "service_send")]
+impl<K, F, Cx, Req, Resp, E> Router<K, F, Cx, Req, Resp, E>
+where
+    K: Hash + Eq,
+    Cx: Send + 'static,
+    Req: Send + 'static,
+    Resp: 'static,
+    E: 'static,
+{
+    /// Creates a router dispatching by the key `key` returns, without any route.
+    pub fn new(key: F) -> Self
+    where
+        F: Fn(&Cx, &Req) -> K,
+    {
+        Router {
+            steer: Steer::new(
+                [BoxCloneService::new(NotFound(PhantomData))],
+                RoutePicker {
+                    key,
+                    routes: HashMap::new(),
+                },
+            ),
+        }
+    }
+
+    /// Routes the requests with the key `key` to `svc`, replacing any previous
+    /// route for it.
+    pub fn route<S>(self, key: K, svc: S) -> Self
+    where
+        S: Service<Cx, Req, Response = Resp, Error = E> + Clone + Send + Sync + 'static,
+    {
+        self.insert(
+            Some(key),
+            BoxCloneService::new(svc.map_err(RouteError::Service)),
+        )
+    }
+
+    /// Routes the requests matching no route to `svc`.
+    pub fn fallback<S>(self, svc: S) -> Self
+    where
+        S: Service<Cx, Req, Response = Resp, Error = E> + Clone + Send + Sync + 'static,
+    {
+        self.insert(None, BoxCloneService::new(svc.map_err(RouteError::Service)))
+    }
+}
+
+#[cfg(not(feature = "service_send"))]
+impl<K, F, Cx, Req, Resp, E> Router<K, F, Cx, Req, Resp, E>
+where
+    K: Hash + Eq,
+    Cx: 'static,
+    Req: 'static,
+    Resp: 'static,
+    E: 'static,
+{
+    /// Creates a router dispatching by the key `key` returns, without any route.
+    pub fn new(key: F) -> Self
+    where
+        F: Fn(&Cx, &Req) -> K,
+    {
+        Router {
+            steer: Steer::new(
+                [BoxCloneService::new(NotFound(PhantomData))],
+                RoutePicker {
+                    key,
+                    routes: HashMap::new(),
+                },
+            ),
+        }
+    }
+
+    /// Routes the requests with the key `key` to `svc`, replacing any previous
+    /// route for it.
+    pub fn route<S>(self, key: K, svc: S) -> Self
+    where
+        S: Service<Cx, Req, Response = Resp, Error = E> + Clone + 'static,
+    {
+        self.insert(
+            Some(key),
+            BoxCloneService::new(svc.map_err(RouteError::Service)),
+        )
+    }
+
+    /// Routes the requests matching no route to `svc`.
+    pub fn fallback<S>(self, svc: S) -> Self
+    where
+        S: Service<Cx, Req, Response = Resp, Error = E> + Clone + 'static,
+    {
+        self.insert(None, BoxCloneService::new(svc.map_err(RouteError::Service)))
+    }
+}
+
+impl<K, F, Cx, Req, Resp, E> Router<K, F, Cx, Req, Resp, E>
+where
+    K: Hash + Eq,
+{
+    fn insert(mut self, key: Option<K>, svc: Route<Cx, Req, Resp, E>) -> Self {
+        let services = &mut self.steer.services;
+        let routes = &mut self.steer.picker.routes;
+        match key {
+            // The fallback is always the first service.
+            None => services[0] = svc,
+            Some(key) => match routes.get(&key) {
+                Some(&idx) => services[idx] = svc,
+                None => {
+                    routes.insert(key, services.len());
+                    services.push(svc);
+                }
+            },
+        }
+        self
+    }
+}
+
+impl<K, F, Cx, Req, Resp, E> Service<Cx, Req> for Router<K, F, Cx, Req, Resp, E>
+where
+    K: Hash + Eq,
+    F: Fn(&Cx, &Req) -> K,
+    Route<Cx, Req, Resp, E>: Service<Cx, Req, Response = Resp, Error = RouteError<E>>,
+{
+    type Response = Resp;
+
+    type Error = RouteError<E>;
+
+    #[cfg(feature = "service_send")]
+    fn call(
+        &self,
+        cx: &mut Cx,
+        req: Req,
+    ) -> impl Future<Output = Result<Self::Response, Self::Error>> + Send {
+        self.steer.call(cx, req)
+    }
+    #[cfg(not(feature = "service_send"))]
+    fn call(
+        &self,
+        cx: &mut Cx,
+        req: Req,
+    ) -> impl Future<Output = Result<Self::Response, Self::Error>> {
+        self.steer.call(cx, req)
+    }
+}
+
+impl<K, F, Cx, Req, Resp, E> Clone for Router<K, F, Cx, Req, Resp, E>
+where
+    K: Clone,
+    F: Clone,
+{
+    fn clone(&self) -> Self {
+        Router {
+            steer: self.steer.clone(),
+        }
+    }
+}
+
+impl<K, F, Cx, Req, Resp, E> fmt::Debug for Router<K, F, Cx, Req, Resp, E>
+where
+    K: fmt::Debug,
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Router")
+            .field("routes", &self.steer.picker.routes.keys())
+            .finish()
+    }
+}
+
+/// The [`Picker`] of a [`Router`].
+#[derive(Clone)]
+struct RoutePicker<K, F> {
+    key: F,
+    routes: HashMap<K, usize>,
+}
+
+impl<K, F, Cx, Req, S> Picker<Cx, Req, S> for RoutePicker<K, F>
+where
+    K: Hash + Eq,
+    F: Fn(&Cx, &Req) -> K,
+{
+    fn pick(&self, cx: &Cx, req: &Req, _services: &[S]) -> usize {
+        self.routes.get(&(self.key)(cx, req)).copied().unwrap_or(0)
+    }
+}
+
+/// The fallback of a [`Router`] without one.
+struct NotFound<Resp, E>(PhantomData<fn() -> (Resp, E)>);
+
+impl<Resp, E> Clone for NotFound<Resp, E> {
+    fn clone(&self) -> Self {
+        NotFound(PhantomData)
+    }
+}
+
+impl<Cx, Req, Resp, E> Service<Cx, Req> for NotFound<Resp, E> {
+    type Response = Resp;
+
+    type Error = RouteError<E>;
+
+    // An `async fn` would capture the context and the request, requiring them to
+    // be `Send`.
+    #[cfg(feature = "service_send")]
+    #[allow(clippy::manual_async_fn)]
+    fn call(
+        &self,
+        _cx: &mut Cx,
+        _req: Req,
+    ) -> impl Future<Output = Result<Self::Response, Self::Error>> + Send {
+        async { Err(RouteError::NotFound) }
+    }
+    #[cfg(not(feature = "service_send"))]
+    async fn call(&self, _cx: &mut Cx, _req: Req) -> Result<Self::Response, Self::Error> {
+        Err(RouteError::NotFound)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::convert::Infallible;
+
+    use super::*;
+    use crate::service::service_fn;
+
+    #[derive(Clone)]
+    struct Echo(&'static str);
+
+    impl Service<u32, String> for Echo {
+        type Response = String;
+        type Error = Infallible;
+
+        async fn call(&self, tenant: &mut u32, req: String) -> Result<String, Infallible> {
+            Ok(format!("{} {tenant} {req}", self.0))
+        }
+    }
+
+    #[tokio::test]
+    async fn dispatches_by_key() {
+        async fn other(_cx: &mut u32, _req: String) -> Result<String, Infallible> {
+            Ok("fallback".to_string())
+        }
+
+        let router = Router::new(|tenant: &u32, _req: &String| *tenant)
+            .route(1, Echo("one"))
+            .route(2, Echo("two"))
+            .route(1, Echo("uno"));
+        assert_eq!(router.call(&mut 1, "a".into()).await, Ok("uno 1 a".into()));
+        assert_eq!(router.call(&mut 2, "b".into()).await, Ok("two 2 b".into()));
+        assert_eq!(
+            router.call(&mut 3, "c".into()).await,
+            Err(RouteError::NotFound)
+        );
+
+        let router = router.fallback(service_fn(other));
+        assert_eq!(router.call(&mut 3, "c".into()).await, Ok("fallback".into()));
+    }
+}
@@ -0,0 +1,147 @@
+use std::future::Future;
+
+use futures::{stream, FutureExt, TryFutureExt, TryStreamExt};
+
+use super::StreamService;
+
+/// An extension trait for [`StreamService`]s that provides adapters for the
+/// items and errors of the stream.
+pub trait StreamServiceExt<Cx, Req>: StreamService<Cx, Req> + Sized {
+    /// Maps each item of the stream to a different value.
+    fn map_item<F, T>(self, f: F) -> MapItem<Self, F>
+    where
+        F: FnMut(Self::Item) -> T,
+    {
+        MapItem { inner: self, f }
+    }
+
+    /// Maps the error of the call, and of each item of the stream, to a different
+    /// value.
+    fn map_err<F, E>(self, f: F) -> MapErr<Self, F>
+    where
+        F: FnMut(Self::Error) -> E,
+    {
+        MapErr { inner: self, f }
+    }
+}
+
+impl<Cx, Req, S> StreamServiceExt<Cx, Req> for S where S: StreamService<Cx, Req> {}
+
+/// Stream service returned by the [`map_item`] combinator.
+///
+/// [`map_item`]: StreamServiceExt::map_item
+#[derive(Clone, Debug)]
+pub struct MapItem<S, F> {
+    inner: S,
+    f: F,
+}
+
+impl<Cx, Req, S, F, T> StreamService<Cx, Req> for MapItem<S, F>
+where
+    S: StreamService<Cx, Req>,
+    F: FnMut(S::Item) -> T + Clone + Send,
+{
+    type Item = T;
+
+    type Error = S::Error;
+
+    type Stream = stream::MapOk<S::Stream, F>;
+
+    #[cfg(feature = "service_send")]
+    fn call(
+        &self,
+        cx: &mut Cx,
+        req: Req,
+    ) -> impl Future<Output = Result<Self::Stream, Self::Error>> + Send {
+        let f = self.f.clone();
+        self.inner.call(cx, req).map_ok(|s| s.map_ok(f))
+    }
+    #[cfg(not(feature = "service_send"))]
+    fn call(
+        &self,
+        cx: &mut Cx,
+        req: Req,
+    ) -> impl Future<Output = Result<Self::Stream, Self::Error>> {
+        let f = self.f.clone();
+        self.inner.call(cx, req).map_ok(|s| s.map_ok(f))
+    }
+}
+
+/// Stream service returned by the [`map_err`] combinator.
+///
+/// [`map_err`]: StreamServiceExt::map_err
+#[derive(Clone, Debug)]
+pub struct MapErr<S, F> {
+    inner: S,
+    f: F,
+}
+
+impl<Cx, Req, S, F, E> StreamService<Cx, Req> for MapErr<S, F>
+where
+    S: StreamService<Cx, Req>,
+    F: FnMut(S::Error) -> E + Clone + Send,
+{
+    type Item = S::Item;
+
+    type Error = E;
+
+    type Stream = stream::MapErr<S::Stream, F>;
+
+    #[cfg(feature = "service_send")]
+    fn call(
+        &self,
+        cx: &mut Cx,
+        req: Req,
+    ) -> impl Future<Output = Result<Self::Stream, Self::Error>> + Send {
+        let mut f = self.f.clone();
+        self.inner.call(cx, req).map(move |r| match r {
+            Ok(s) => Ok(s.map_err(f)),
+            Err(e) => Err(f(e)),
+        })
+    }
+    #[cfg(not(feature = "service_send"))]
+    fn call(
+        &self,
+        cx: &mut Cx,
+        req: Req,
+    ) -> impl Future<Output = Result<Self::Stream, Self::Error>> {
+        let mut f = self.f.clone();
+        self.inner.call(cx, req).map(move |r| match r {
+            Ok(s) => Ok(s.map_err(f)),
+            Err(e) => Err(f(e)),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use futures::stream::{BoxStream, StreamExt};
+
+    use super::*;
+
+    struct Parse;
+
+    impl StreamService<(), BoxStream<'static, &'static str>> for Parse {
+        type Item = u32;
+        type Error = String;
+        type Stream = BoxStream<'static, Result<u32, String>>;
+
+        async fn call(
+            &self,
+            _cx: &mut (),
+            requests: BoxStream<'static, &'static str>,
+        ) -> Result<Self::Stream, Self::Error> {
+            Ok(requests
+                .map(|s| s.parse().map_err(|_| s.to_string()))
+                .boxed())
+        }
+    }
+
+    #[tokio::test]
+    async fn maps_items_and_errors() {
+        let svc = Parse.map_item(|n| n * 10).map_err(|s| format!("bad: {s}"));
+        let requests = stream::iter(["1", "x", "3"]).boxed();
+        let items: Vec<_> = svc.call(&mut (), requests).await.unwrap().collect().await;
+        assert_eq!(items, [Ok(10), Err("bad: x".to_string()), Ok(30)]);
+    }
+}
@@ -0,0 +1,296 @@
+//! Services producing a stream of responses.
+//!
+//! A [`StreamService`] answers a request with a [`Stream`] of items instead of a
+//! single response, which is how RPC frameworks model server streaming. Streaming
+//! middlewares, like idle timeouts or metrics on every item, can be written against
+//! this trait once and reused across protocols.
+//!
+//! A [`Service`] whose response is already a stream can be used as a
+//! [`StreamService`] through [`FromService`], and a [`StreamService`] can be used
+//! where a [`Service`] is expected through [`IntoService`], which boxes the stream.
+//!
+//! Bidirectional streaming is the case where the request is a stream as well, and
+//! is expressed by [`BidiService`].
+
+use std::{future::Future, sync::Arc};
+
+#[cfg(feature = "service_send")]
+use futures::stream::BoxStream;
+#[cfg(not(feature = "service_send"))]
+use futures::stream::LocalBoxStream as BoxStream;
+use futures::{Stream, StreamExt, TryFutureExt};
+
+use crate::Service;
+
+mod ext;
+mod timeout;
+pub use self::{
+    ext::{MapErr, MapItem, StreamServiceExt},
+    timeout::{StreamTimeout, StreamTimeoutError, StreamTimeoutLayer, TimeoutStream},
+};
+
+/// An asynchronous function from a `Request` to a [`Stream`] of items.
+///
+/// The call itself may fail before producing any item, and each item of the
+/// stream may fail, both with [`Error`](StreamService::Error).
+///
+/// # Example
+///
+/// ```rust
+/// use std::convert::Infallible;
+///
+/// use futures::stream::{self, BoxStream, StreamExt};
+/// use motore::stream::StreamService;
+///
+/// struct Countdown;
+///
+/// impl<Cx: Send> StreamService<Cx, u32> for Countdown {
+///     type Item = u32;
+///     type Error = Infallible;
+///     type Stream = BoxStream<'static, Result<u32, Infallible>>;
+///
+///     async fn call(&self, _cx: &mut Cx, from: u32) -> Result<Self::Stream, Self::Error> {
+///         Ok(stream::iter((0..from).rev().map(Ok)).boxed())
+///     }
+/// }
+/// ```
+pub trait StreamService<Cx, Request> {
+    /// Items produced by the stream.
+    type Item;
+    /// Errors produced by the call or by the stream.
+    type Error;
+    /// The stream of items.
+    type Stream: Stream<Item = Result<Self::Item, Self::Error>>;
+
+    /// Process the request and return the stream of items asynchronously.
+    #[cfg(feature = "service_send")]
+    fn call(
+        &self,
+        cx: &mut Cx,
+        req: Request,
+    ) -> impl Future<Output = Result<Self::Stream, Self::Error>> + Send;
+
+    /// Process the request and return the stream of items asynchronously.
+    #[cfg(not(feature = "service_send"))]
+    fn call(
+        &self,
+        cx: &mut Cx,
+        req: Request,
+    ) -> impl Future<Output = Result<Self::Stream, Self::Error>>;
+}
+
+macro_rules! impl_stream_service_ref {
+    ($t: tt) => {
+        impl<Cx, Req, T> StreamService<Cx, Req> for $t<T>
+        where
+            T: StreamService<Cx, Req>,
+        {
+            type Item = T::Item;
+
+            type Error = T::Error;
+
+            type Stream = T::Stream;
+
+            #[cfg(feature = "service_send")]
+            fn call(
+                &self,
+                cx: &mut Cx,
+                req: Req,
+            ) -> impl Future<Output = Result<Self::Stream, Self::Error>> + Send {
+                (&**self).call(cx, req)
+            }
+            #[cfg(not(feature = "service_send"))]
+            fn call(
+                &self,
+                cx: &mut Cx,
+                req: Req,
+            ) -> impl Future<Output = Result<Self::Stream, Self::Error>> {
+                (&**self).call(cx, req)
+            }
+        }
+    };
+}
+
+impl_stream_service_ref!(Arc);
+impl_stream_service_ref!(Box);
+
+/// A [`StreamService`] taking a stream of requests, as in bidirectional streaming.
+///
+/// This is implemented for every [`StreamService`] whose request is a [`Stream`], so
+/// middlewares written for [`StreamService`] apply to bidirectional services too;
+/// the bound only documents the intent.
+///
+/// # Example
+///
+/// ```rust
+/// use std::convert::Infallible;
+///
+/// use futures::stream::{BoxStream, StreamExt};
+/// use motore::stream::{BidiService, StreamService};
+///
+/// struct Double;
+///
+/// impl<Cx: Send> StreamService<Cx, BoxStream<'static, u32>> for Double {
+///     type Item = u32;
+///     type Error = Infallible;
+///     type Stream = BoxStream<'static, Result<u32, Infallible>>;
+///
+///     async fn call(
+///         &self,
+///         _cx: &mut Cx,
+///         requests: BoxStream<'static, u32>,
+///     ) -> Result<Self::Stream, Self::Error> {
+///         Ok(requests.map(|n| Ok(n * 2)).boxed())
+///     }
+/// }
+///
+/// fn assert_bidi<S: BidiService<(), BoxStream<'static, u32>>>(_: &S) {}
+/// assert_bidi(&Double);
+/// ```
+pub trait BidiService<Cx, InStream: Stream>: StreamService<Cx, InStream> {}
+
+impl<Cx, InStream, S> BidiService<Cx, InStream> for S
+where
+    InStream: Stream,
+    S: StreamService<Cx, InStream>,
+{
+}
+
+/// A [`StreamService`] calling a [`Service`] whose response is a stream.
+#[derive(Clone, Debug)]
+pub struct FromService<S> {
+    inner: S,
+}
+
+impl<S> FromService<S> {
+    pub const fn new(inner: S) -> Self {
+        Self { inner }
+    }
+
+    /// Returns the wrapped service.
+    pub fn into_inner(self) -> S {
+        self.inner
+    }
+}
+
+impl<Cx, Req, S, St, T> StreamService<Cx, Req> for FromService<S>
+where
+    S: Service<Cx, Req, Response = St>,
+    St: Stream<Item = Result<T, S::Error>>,
+{
+    type Item = T;
+
+    type Error = S::Error;
+
+    type Stream = St;
+
+    #[cfg(feature = "service_send")]
+    fn call(
+        &self,
+        cx: &mut Cx,
+        req: Req,
+    ) -> impl Future<Output = Result<Self::Stream, Self::Error>> + Send {
+        self.inner.call(cx, req)
+    }
+    #[cfg(not(feature = "service_send"))]
+    fn call(
+        &self,
+        cx: &mut Cx,
+        req: Req,
+    ) -> impl Future<Output = Result<Self::Stream, Self::Error>> {
+        self.inner.call(cx, req)
+    }
+}
+
+/// A [`Service`] calling a [`StreamService`], responding with the boxed stream.
+#[derive(Clone, Debug)]
+pub struct IntoService<S> {
+    inner: S,
+}
+
+impl<S> IntoService<S> {
+    pub const fn new(inner: S) -> Self {
+        Self { inner }
+    }
+
+    /// Returns the wrapped stream service.
+    pub fn into_inner(self) -> S {
+        self.inner
+    }
+}
+
+#[cfg(feature = "service_send")]
+impl<Cx, Req, S> Service<Cx, Req> for IntoService<S>
+where
+    S: StreamService<Cx, Req>,
+    S::Stream: Send + 'static,
+{
+    type Response = BoxStream<'static, Result<S::Item, S::Error>>;
+
+    type Error = S::Error;
+
+    fn call(
+        &self,
+        cx: &mut Cx,
+        req: Req,
+    ) -> impl Future<Output = Result<Self::Response, Self::Error>> + Send {
+        self.inner.call(cx, req).map_ok(StreamExt::boxed)
+    }
+}
+
+#[cfg(not(feature = "service_send"))]
+impl<Cx, Req, S> Service<Cx, Req> for IntoService<S>
+where
+    S: StreamService<Cx, Req>,
+    S::Stream: 'static,
+{
+    type Response = BoxStream<'static, Result<S::Item, S::Error>>;
+
+    type Error = S::Error;
+
+    fn call(
+        &self,
+        cx: &mut Cx,
+        req: Req,
+    ) -> impl Future<Output = Result<Self::Response, Self::Error>> {
+        self.inner.call(cx, req).map_ok(StreamExt::boxed_local)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::convert::Infallible;
+
+    use futures::{stream, TryStreamExt};
+
+    use super::*;
+
+    struct Repeat;
+
+    impl StreamService<(), (char, usize)> for Repeat {
+        type Item = char;
+        type Error = Infallible;
+        type Stream = stream::Iter<std::vec::IntoIter<Result<char, Infallible>>>;
+
+        async fn call(
+            &self,
+            _cx: &mut (),
+            (c, n): (char, usize),
+        ) -> Result<Self::Stream, Self::Error> {
+            Ok(stream::iter(vec![Ok(c); n]))
+        }
+    }
+
+    #[tokio::test]
+    async fn round_trip_through_service() {
+        let svc = FromService::new(IntoService::new(Repeat));
+        let items: Vec<_> = svc
+            .call(&mut (), ('m', 3))
+            .await
+            .unwrap()
+            .try_collect()
+            .await
+            .unwrap();
+        assert_eq!(items, ['m', 'm', 'm']);
+    }
+}
@@ -0,0 +1,328 @@
+use std::{
+    fmt,
+    pin::Pin,
+    task::{Context, Poll},
+    time::Duration,
+};
+
+#[cfg(feature = "service_send")]
+use futures::future::BoxFuture;
+#[cfg(not(feature = "service_send"))]
+use futures::future::LocalBoxFuture as BoxFuture;
+use futures::Stream;
+use pin_project::pin_project;
+
+use super::StreamService;
+use crate::{
+    layer::Layer,
+    timer::{DefaultTimer, Instant, Timer},
+    BoxError, MaybeSend, MaybeSync,
+};
+
+/// The error produced by a [`StreamTimeout`] stream when it times out.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum StreamTimeoutError {
+    /// No item was produced within the idle timeout.
+    Idle(Duration),
+    /// The call and its whole stream took longer than the total timeout.
+    Total(Duration),
+}
+
+impl fmt::Display for StreamTimeoutError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            StreamTimeoutError::Idle(d) => write!(f, "stream idle for {d:?}"),
+            StreamTimeoutError::Total(d) => write!(f, "stream did not complete within {d:?}"),
+        }
+    }
+}
+
+impl std::error::Error for StreamTimeoutError {}
+
+/// Applies timeouts to the stream of a [`StreamService`].
+///
+/// The idle timeout fails the stream when no item is produced for the configured
+/// duration, while the total timeout bounds the call and the whole stream. When a
+/// timeout elapses the stream yields a [`StreamTimeoutError`], then ends.
+#[derive(Clone)]
+pub struct StreamTimeout<S, T = DefaultTimer> {
+    inner: S,
+    idle: Option<Duration>,
+    total: Option<Duration>,
+    timer: T,
+}
+
+impl<S> StreamTimeout<S> {
+    pub const fn new(inner: S, idle: Option<Duration>, total: Option<Duration>) -> Self {
+        Self {
+            inner,
+            idle,
+            total,
+            timer: DefaultTimer::new(),
+        }
+    }
+}
+
+impl<S, T> StreamTimeout<S, T> {
+    /// Sets the timer measuring the timeouts, [`DefaultTimer`] by default.
+    pub fn timer<U>(self, timer: U) -> StreamTimeout<S, U> {
+        StreamTimeout {
+            inner: self.inner,
+            idle: self.idle,
+            total: self.total,
+            timer,
+        }
+    }
+}
+
+impl<Cx, Req, S, T> StreamService<Cx, Req> for StreamTimeout<S, T>
+where
+    Req: 'static + MaybeSend,
+    S: StreamService<Cx, Req> + 'static + MaybeSend + MaybeSync,
+    Cx: 'static + MaybeSend,
+    S::Error: MaybeSend + MaybeSync + Into<BoxError>,
+    T: Timer + Clone + 'static + MaybeSend + MaybeSync,
+{
+    type Item = S::Item;
+
+    type Error = BoxError;
+
+    type Stream = TimeoutStream<S::Stream, T>;
+
+    async fn call(&self, cx: &mut Cx, req: Req) -> Result<Self::Stream, Self::Error> {
+        let (stream, deadline) = match self.total {
+            Some(total) => {
+                let deadline = self.timer.now() + total;
+                let sleep = self.timer.sleep(total);
+                tokio::select! {
+                    r = self.inner.call(cx, req) => (r.map_err(Into::into)?, Some(deadline)),
+                    _ = sleep => {
+                        return Err(StreamTimeoutError::Total(total).into());
+                    }
+                }
+            }
+            None => (self.inner.call(cx, req).await.map_err(Into::into)?, None),
+        };
+        let now = self.timer.now();
+        Ok(TimeoutStream {
+            inner: stream,
+            idle: self.idle.map(|idle| Idle {
+                duration: idle,
+                deadline: now + idle,
+                sleep: sleep(&self.timer, idle),
+            }),
+            total: self.total.zip(deadline).map(|(total, deadline)| {
+                (
+                    total,
+                    sleep(&self.timer, deadline.saturating_duration_since(now)),
+                )
+            }),
+            timer: self.timer.clone(),
+            done: false,
+        })
+    }
+}
+
+/// Returns a sleep owning a clone of `timer`, so that it can be kept by the stream.
+fn sleep<T>(timer: &T, duration: Duration) -> BoxFuture<'static, ()>
+where
+    T: Timer + Clone + 'static + MaybeSend + MaybeSync,
+{
+    let timer = timer.clone();
+    Box::pin(async move { timer.sleep(duration).await })
+}
+
+/// The idle timeout of a [`TimeoutStream`].
+///
+/// The items only push the deadline back, and the sleep is replaced once it
+/// elapsed before the deadline, so that the items don't allocate a sleep each.
+struct Idle {
+    duration: Duration,
+    deadline: Instant,
+    sleep: BoxFuture<'static, ()>,
+}
+
+/// The stream returned by [`StreamTimeout`].
+#[pin_project]
+pub struct TimeoutStream<St, T = DefaultTimer> {
+    #[pin]
+    inner: St,
+    idle: Option<Idle>,
+    total: Option<(Duration, BoxFuture<'static, ()>)>,
+    timer: T,
+    done: bool,
+}
+
+impl<St, I, E, T> Stream for TimeoutStream<St, T>
+where
+    St: Stream<Item = Result<I, E>>,
+    E: Into<BoxError>,
+    T: Timer + Clone + 'static + MaybeSend + MaybeSync,
+{
+    type Item = Result<I, BoxError>;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let this = self.project();
+        if *this.done {
+            return Poll::Ready(None);
+        }
+
+        match this.inner.poll_next(cx) {
+            Poll::Ready(Some(item)) => {
+                if let Some(idle) = this.idle {
+                    idle.deadline = this.timer.now() + idle.duration;
+                }
+                return Poll::Ready(Some(item.map_err(Into::into)));
+            }
+            Poll::Ready(None) => {
+                *this.done = true;
+                return Poll::Ready(None);
+            }
+            Poll::Pending => {}
+        }
+
+        if let Some((total, sleep)) = this.total {
+            if sleep.as_mut().poll(cx).is_ready() {
+                *this.done = true;
+                return Poll::Ready(Some(Err(StreamTimeoutError::Total(*total).into())));
+            }
+        }
+        if let Some(idle) = this.idle {
+            while idle.sleep.as_mut().poll(cx).is_ready() {
+                let left = idle.deadline.saturating_duration_since(this.timer.now());
+                if left.is_zero() {
+                    *this.done = true;
+                    return Poll::Ready(Some(Err(StreamTimeoutError::Idle(idle.duration).into())));
+                }
+                idle.sleep = sleep(this.timer, left);
+            }
+        }
+        Poll::Pending
+    }
+}
+
+impl<St, T> fmt::Debug for TimeoutStream<St, T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("TimeoutStream")
+            .field("idle", &self.idle.as_ref().map(|idle| idle.duration))
+            .field("total", &self.total.as_ref().map(|(total, _)| total))
+            .finish()
+    }
+}
+
+/// Applies a [`StreamTimeout`] to a stream service.
+#[derive(Clone, Default)]
+pub struct StreamTimeoutLayer<T = DefaultTimer> {
+    idle: Option<Duration>,
+    total: Option<Duration>,
+    timer: T,
+}
+
+impl StreamTimeoutLayer {
+    /// Creates a layer without any timeout.
+    pub const fn new() -> Self {
+        StreamTimeoutLayer {
+            idle: None,
+            total: None,
+            timer: DefaultTimer::new(),
+        }
+    }
+}
+
+impl<T> StreamTimeoutLayer<T> {
+    /// Fails the stream when no item is produced for `idle`.
+    pub fn idle(mut self, idle: Duration) -> Self {
+        self.idle = Some(idle);
+        self
+    }
+
+    /// Fails the stream when the call and the whole stream take longer than `total`.
+    pub fn total(mut self, total: Duration) -> Self {
+        self.total = Some(total);
+        self
+    }
+
+    /// Sets the timer measuring the timeouts, [`DefaultTimer`] by default.
+    pub fn timer<U>(self, timer: U) -> StreamTimeoutLayer<U> {
+        StreamTimeoutLayer {
+            idle: self.idle,
+            total: self.total,
+            timer,
+        }
+    }
+}
+
+impl<S, T> Layer<S> for StreamTimeoutLayer<T> {
+    type Service = StreamTimeout<S, T>;
+
+    fn layer(self, inner: S) -> Self::Service {
+        StreamTimeout {
+            inner,
+            idle: self.idle,
+            total: self.total,
+            timer: self.timer,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::convert::Infallible;
+
+    use futures::{
+        stream::{self, BoxStream},
+        StreamExt,
+    };
+
+    use super::*;
+
+    /// Yields `0, 1, 2, ...`, waiting the given number of milliseconds before each.
+    struct Ticks;
+
+    impl StreamService<(), Vec<u64>> for Ticks {
+        type Item = u64;
+        type Error = Infallible;
+        type Stream = BoxStream<'static, Result<u64, Infallible>>;
+
+        async fn call(&self, _cx: &mut (), waits: Vec<u64>) -> Result<Self::Stream, Infallible> {
+            Ok(stream::iter(waits.into_iter().enumerate())
+                .then(|(i, ms)| async move {
+                    tokio::time::sleep(Duration::from_millis(ms)).await;
+                    Ok(i as u64)
+                })
+                .boxed())
+        }
+    }
+
+    async fn run(layer: StreamTimeoutLayer, waits: Vec<u64>) -> Vec<Result<u64, String>> {
+        let svc = layer.layer(Ticks);
+        let stream = svc.call(&mut (), waits).await.unwrap();
+        stream.map(|r| r.map_err(|e| e.to_string())).collect().await
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn idle_timeout_resets_on_each_item() {
+        let layer = StreamTimeoutLayer::new().idle(Duration::from_millis(100));
+        assert_eq!(
+            run(layer.clone(), vec![90, 90, 90]).await,
+            [Ok(0), Ok(1), Ok(2)]
+        );
+        assert_eq!(
+            run(layer, vec![90, 110, 90]).await,
+            [Ok(0), Err("stream idle for 100ms".to_string())]
+        );
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn total_timeout_bounds_the_stream() {
+        let layer = StreamTimeoutLayer::new().total(Duration::from_millis(200));
+        assert_eq!(
+            run(layer, vec![90, 90, 90]).await,
+            [
+                Ok(0),
+                Ok(1),
+                Err("stream did not complete within 200ms".to_string())
+            ]
+        );
+    }
+}
@@ -1,43 +1,146 @@
 //! Applies a timeout to request
 //! if the inner service's call does not complete within specified timeout, the response will be
 //! aborted.
+//!
+//! Stacked timeouts, like the timeout of a whole call around the timeout of a
+//! connection attempt, count from zero independently, so an inner one may keep
+//! waiting after the outer one gave up. A [`BudgetedTimeout`] sets its end as the
+//! [`Deadline`] of the context, and is clamped to the deadline already there, so
+//! the nested ones only get the time left, along with the
+//! [`WithDeadline`](crate::deadline::WithDeadline) services.
 
-use std::time::Duration;
+use std::{
+    fmt,
+    task::{Context, Poll},
+    time::Duration,
+};
 
-use crate::{layer::Layer, service::Service, BoxError};
+use crate::{
+    deadline::{Deadline, DeadlineContext, DeadlineGuard},
+    layer::Layer,
+    load::{Load, Ready},
+    service::Service,
+    timer::{DefaultTimer, Timer},
+    BoxError, MaybeSend, MaybeSync,
+};
+
+/// The error returned by [`Timeout`] when a call times out.
+///
+/// It is boxed into a [`BoxError`], and can be recognized with
+/// `err.downcast_ref::<TimeoutError>()`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct TimeoutError {
+    /// How long the call ran before it was aborted.
+    pub elapsed: Duration,
+}
+
+impl fmt::Display for TimeoutError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "service timed out after {:?}", self.elapsed)
+    }
+}
+
+impl std::error::Error for TimeoutError {}
+
+/// Gives the timeout of each call made by a [`Timeout`].
+///
+/// It is implemented by `Option<Duration>`, for the same timeout on every call, and
+/// by the closures reading the timeout from the context, `Fn(&Cx) ->
+/// Option<Duration>`. No timeout is applied when it returns `None`.
+pub trait TimeoutSource<Cx> {
+    /// Returns the timeout of a call made with `cx`.
+    fn timeout(&self, cx: &Cx) -> Option<Duration>;
+}
+
+impl<Cx> TimeoutSource<Cx> for Option<Duration> {
+    fn timeout(&self, _cx: &Cx) -> Option<Duration> {
+        *self
+    }
+}
+
+impl<Cx, F> TimeoutSource<Cx> for F
+where
+    F: Fn(&Cx) -> Option<Duration>,
+{
+    fn timeout(&self, cx: &Cx) -> Option<Duration> {
+        self(cx)
+    }
+}
 
 #[derive(Clone)]
-pub struct Timeout<S> {
+pub struct Timeout<S, D = Option<Duration>, T = DefaultTimer> {
     inner: S,
-    duration: Option<Duration>,
+    duration: D,
+    timer: T,
 }
 
 impl<S> Timeout<S> {
     pub const fn new(inner: S, duration: Option<Duration>) -> Self {
-        Self { inner, duration }
+        Self {
+            inner,
+            duration,
+            timer: DefaultTimer::new(),
+        }
+    }
+}
+
+impl<S, F> Timeout<S, F> {
+    /// Creates a `Timeout` reading the timeout of each call from the context with
+    /// `f`, so the caller of a request can choose how long to wait for it.
+    pub const fn from_cx(inner: S, f: F) -> Self {
+        Self {
+            inner,
+            duration: f,
+            timer: DefaultTimer::new(),
+        }
     }
 }
 
-impl<Cx, Req, S> Service<Cx, Req> for Timeout<S>
+impl<S, D, T> Timeout<S, D, T> {
+    /// Sets the timer measuring the timeouts, [`DefaultTimer`] by default.
+    pub fn timer<U>(self, timer: U) -> Timeout<S, D, U> {
+        Timeout {
+            inner: self.inner,
+            duration: self.duration,
+            timer,
+        }
+    }
+}
+
+impl<S, D, T> Timeout<S, D, T> {
+    /// Shares the deadline of this timeout with the [`BudgetedTimeout`]s nested in
+    /// it, and clamps it to the [`Deadline`] of the context.
+    pub fn budgeted(self) -> BudgetedTimeout<S, D, T> {
+        BudgetedTimeout {
+            inner: self.inner,
+            duration: self.duration,
+            timer: self.timer,
+        }
+    }
+}
+
+impl<Cx, Req, S, D, T> Service<Cx, Req> for Timeout<S, D, T>
 where
-    Req: 'static + Send,
-    S: Service<Cx, Req> + 'static + Send + Sync,
-    Cx: 'static + Send,
-    S::Error: Send + Sync + Into<BoxError>,
+    Req: 'static + MaybeSend,
+    S: Service<Cx, Req> + 'static + MaybeSend + MaybeSync,
+    D: TimeoutSource<Cx> + MaybeSend + MaybeSync,
+    T: Timer + MaybeSend + MaybeSync,
+    Cx: 'static + MaybeSend,
+    S::Error: MaybeSend + MaybeSync + Into<BoxError>,
 {
     type Response = S::Response;
 
     type Error = BoxError;
 
     async fn call(&self, cx: &mut Cx, req: Req) -> Result<Self::Response, Self::Error> {
-        match self.duration {
+        match self.duration.timeout(cx) {
             Some(duration) => {
-                let sleep = tokio::time::sleep(duration);
+                let sleep = self.timer.sleep(duration);
                 tokio::select! {
                     r = self.inner.call(cx, req) => {
                         r.map_err(Into::into)
                     },
-                    _ = sleep => Err(std::io::Error::new(std::io::ErrorKind::TimedOut, "service time out").into()),
+                    _ = sleep => Err(TimeoutError { elapsed: duration }.into()),
                 }
             }
             None => self.inner.call(cx, req).await.map_err(Into::into),
@@ -45,24 +148,334 @@ where
     }
 }
 
+impl<S, D, T> Ready for Timeout<S, D, T>
+where
+    S: Ready,
+{
+    fn poll_ready(&self, cx: &mut Context<'_>) -> Poll<()> {
+        self.inner.poll_ready(cx)
+    }
+}
+
+impl<S, D, T> Load for Timeout<S, D, T>
+where
+    S: Load,
+{
+    type Metric = S::Metric;
+
+    fn load(&self) -> Self::Metric {
+        self.inner.load()
+    }
+}
+
 #[derive(Clone)]
-pub struct TimeoutLayer {
-    duration: Option<Duration>,
+pub struct TimeoutLayer<D = Option<Duration>, T = DefaultTimer> {
+    duration: D,
+    timer: T,
 }
 
 impl TimeoutLayer {
     pub const fn new(duration: Option<Duration>) -> Self {
-        TimeoutLayer { duration }
+        TimeoutLayer {
+            duration,
+            timer: DefaultTimer::new(),
+        }
+    }
+}
+
+impl<D, T> TimeoutLayer<D, T> {
+    /// Sets the timer measuring the timeouts, [`DefaultTimer`] by default.
+    pub fn timer<U>(self, timer: U) -> TimeoutLayer<D, U> {
+        TimeoutLayer {
+            duration: self.duration,
+            timer,
+        }
+    }
+}
+
+impl<D, T> TimeoutLayer<D, T> {
+    /// Makes [`BudgetedTimeout`]s rather than [`Timeout`]s.
+    pub fn budgeted(self) -> BudgetedTimeoutLayer<D, T> {
+        BudgetedTimeoutLayer {
+            duration: self.duration,
+            timer: self.timer,
+        }
+    }
+}
+
+impl<F> TimeoutLayer<F> {
+    /// Creates a layer reading the timeout of each call from the context with `f`.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// use std::time::Duration;
+    ///
+    /// use motore::{
+    ///     builder::ServiceBuilder, service::service_fn, timeout::TimeoutLayer, BoxError, Service,
+    /// };
+    ///
+    /// struct Cx {
+    ///     timeout: Option<Duration>,
+    /// }
+    ///
+    /// # #[tokio::main(flavor = "current_thread")]
+    /// # async fn main() {
+    /// async fn slow(_cx: &mut Cx, req: String) -> Result<String, BoxError> {
+    ///     tokio::time::sleep(Duration::from_millis(50)).await;
+    ///     Ok(req)
+    /// }
+    ///
+    /// let svc = ServiceBuilder::new()
+    ///     .layer(TimeoutLayer::from_cx(|cx: &Cx| cx.timeout))
+    ///     .service(service_fn(slow));
+    ///
+    /// let mut cx = Cx {
+    ///     timeout: Some(Duration::from_millis(10)),
+    /// };
+    /// assert!(svc.call(&mut cx, "ping".into()).await.is_err());
+    /// # }
+    /// ```
+    pub const fn from_cx(f: F) -> Self {
+        TimeoutLayer {
+            duration: f,
+            timer: DefaultTimer::new(),
+        }
     }
 }
 
-impl<S> Layer<S> for TimeoutLayer {
-    type Service = Timeout<S>;
+impl<S, D, T> Layer<S> for TimeoutLayer<D, T> {
+    type Service = Timeout<S, D, T>;
 
     fn layer(self, inner: S) -> Self::Service {
         Timeout {
             inner,
             duration: self.duration,
+            timer: self.timer,
+        }
+    }
+}
+
+/// A [`Timeout`] sharing its deadline with the `BudgetedTimeout`s nested in it.
+///
+/// The timeout of a call ends at the sooner of its own end and of the [`Deadline`]
+/// of the context, left by the outer budgeted timeouts or a
+/// [`WithDeadline`](crate::deadline::WithDeadline), and is set as the deadline of
+/// the inner calls until the call completed or was dropped. A call without a
+/// timeout of its own is still bound by the deadline. See the
+/// [module level docs](self) for details.
+#[derive(Clone)]
+pub struct BudgetedTimeout<S, D = Option<Duration>, T = DefaultTimer> {
+    inner: S,
+    duration: D,
+    timer: T,
+}
+
+impl<Cx, Req, S, D, T> Service<Cx, Req> for BudgetedTimeout<S, D, T>
+where
+    Req: 'static + MaybeSend,
+    S: Service<Cx, Req> + 'static + MaybeSend + MaybeSync,
+    D: TimeoutSource<Cx> + MaybeSend + MaybeSync,
+    T: Timer + MaybeSend + MaybeSync,
+    Cx: DeadlineContext + 'static + MaybeSend,
+    S::Error: MaybeSend + MaybeSync + Into<BoxError>,
+{
+    type Response = S::Response;
+
+    type Error = BoxError;
+
+    async fn call(&self, cx: &mut Cx, req: Req) -> Result<Self::Response, Self::Error> {
+        let now = self.timer.now();
+        let own = self
+            .duration
+            .timeout(cx)
+            .map(|duration| Deadline::at(now + duration));
+        let deadline = match (own, cx.deadline()) {
+            (Some(own), Some(outer)) => own.min(outer),
+            (Some(deadline), None) | (None, Some(deadline)) => deadline,
+            (None, None) => return self.inner.call(cx, req).await.map_err(Into::into),
+        };
+
+        let duration = deadline.remaining(now);
+        // Gives the outer calls their deadline back, even if this call is dropped.
+        let guard = DeadlineGuard::set(cx, deadline);
+        let sleep = self.timer.sleep(duration);
+        tokio::select! {
+            r = self.inner.call(guard.cx, req) => r.map_err(Into::into),
+            _ = sleep => Err(TimeoutError { elapsed: duration }.into()),
         }
     }
 }
+
+impl<S, D, T> Ready for BudgetedTimeout<S, D, T>
+where
+    S: Ready,
+{
+    fn poll_ready(&self, cx: &mut Context<'_>) -> Poll<()> {
+        self.inner.poll_ready(cx)
+    }
+}
+
+impl<S, D, T> Load for BudgetedTimeout<S, D, T>
+where
+    S: Load,
+{
+    type Metric = S::Metric;
+
+    fn load(&self) -> Self::Metric {
+        self.inner.load()
+    }
+}
+
+/// Applies a [`BudgetedTimeout`] to a service, made by [`TimeoutLayer::budgeted`].
+///
+/// # Example
+///
+/// ```rust
+/// use std::time::Duration;
+///
+/// use motore::{
+///     builder::ServiceBuilder,
+///     context::Extensions,
+///     service::service_fn,
+///     timeout::{TimeoutError, TimeoutLayer},
+///     BoxError, Service,
+/// };
+///
+/// # #[tokio::main(flavor = "current_thread")]
+/// # async fn main() {
+/// async fn connect(_cx: &mut Extensions, req: String) -> Result<String, BoxError> {
+///     tokio::time::sleep(Duration::from_millis(50)).await;
+///     Ok(req)
+/// }
+///
+/// let svc = ServiceBuilder::new()
+///     // The whole call.
+///     .layer(TimeoutLayer::new(Some(Duration::from_millis(10))).budgeted())
+///     // The connection, clamped to what is left of the whole call.
+///     .layer(TimeoutLayer::new(Some(Duration::from_secs(1))).budgeted())
+///     .service(service_fn(connect));
+///
+/// let err = svc
+///     .call(&mut Extensions::new(), "ping".into())
+///     .await
+///     .unwrap_err();
+/// let elapsed = err.downcast_ref::<TimeoutError>().unwrap().elapsed;
+/// assert!(elapsed <= Duration::from_millis(10));
+/// # }
+/// ```
+#[derive(Clone)]
+pub struct BudgetedTimeoutLayer<D = Option<Duration>, T = DefaultTimer> {
+    duration: D,
+    timer: T,
+}
+
+impl<D, T> BudgetedTimeoutLayer<D, T> {
+    /// Sets the timer measuring the timeouts, [`DefaultTimer`] by default.
+    pub fn timer<U>(self, timer: U) -> BudgetedTimeoutLayer<D, U> {
+        BudgetedTimeoutLayer {
+            duration: self.duration,
+            timer,
+        }
+    }
+}
+
+impl<S, D, T> Layer<S> for BudgetedTimeoutLayer<D, T> {
+    type Service = BudgetedTimeout<S, D, T>;
+
+    fn layer(self, inner: S) -> Self::Service {
+        BudgetedTimeout {
+            inner,
+            duration: self.duration,
+            timer: self.timer,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{context::Extensions, service::service_fn};
+
+    #[tokio::test(start_paused = true)]
+    async fn times_out_with_a_typed_error() {
+        let svc = TimeoutLayer::new(Some(Duration::from_millis(10))).layer(service_fn(
+            |_cx: &mut (), millis: u64| async move {
+                tokio::time::sleep(Duration::from_millis(millis)).await;
+                Ok::<_, BoxError>(millis)
+            },
+        ));
+
+        assert_eq!(svc.call(&mut (), 5).await.unwrap(), 5);
+        let err = svc.call(&mut (), 20).await.unwrap_err();
+        assert_eq!(
+            err.downcast_ref::<TimeoutError>(),
+            Some(&TimeoutError {
+                elapsed: Duration::from_millis(10)
+            })
+        );
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn nested_timeouts_share_the_budget() {
+        let connect = service_fn(|cx: &mut Extensions, millis: u64| {
+            let deadline = cx.deadline();
+            async move {
+                tokio::time::sleep(Duration::from_millis(millis)).await;
+                Ok::<_, BoxError>(deadline)
+            }
+        });
+        let inner = TimeoutLayer::new(Some(Duration::from_millis(100)))
+            .budgeted()
+            .layer(connect);
+        let svc = TimeoutLayer::new(Some(Duration::from_millis(30)))
+            .budgeted()
+            .layer(inner.clone());
+
+        let mut cx = Extensions::new();
+        let start = DefaultTimer::new().now();
+        let deadline = svc.call(&mut cx, 5).await.unwrap().unwrap();
+        assert_eq!(deadline.instant(), start + Duration::from_millis(30));
+        // The deadline is removed once the call completed.
+        assert!(cx.is_empty());
+
+        let err = svc.call(&mut cx, 50).await.unwrap_err();
+        assert_eq!(
+            err.downcast_ref::<TimeoutError>(),
+            Some(&TimeoutError {
+                elapsed: Duration::from_millis(30)
+            })
+        );
+
+        // An outer deadline later than the end of the inner timeout.
+        cx.set_deadline(Some(Deadline::at(
+            DefaultTimer::new().now() + Duration::from_secs(1),
+        )));
+        let err = inner.call(&mut cx, 500).await.unwrap_err();
+        assert_eq!(
+            err.downcast_ref::<TimeoutError>(),
+            Some(&TimeoutError {
+                elapsed: Duration::from_millis(100)
+            })
+        );
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn dropped_calls_restore_the_deadline() {
+        let svc = TimeoutLayer::new(Some(Duration::from_millis(30)))
+            .budgeted()
+            .layer(service_fn(|_cx: &mut Extensions, millis: u64| async move {
+                tokio::time::sleep(Duration::from_millis(millis)).await;
+                Ok::<_, BoxError>(millis)
+            }));
+
+        let mut cx = Extensions::new();
+        let call = tokio::time::timeout(Duration::from_millis(10), svc.call(&mut cx, 20));
+        assert!(call.await.is_err());
+        assert!(cx.is_empty());
+
+        // A later call, like a retry, gets a timeout of its own.
+        tokio::time::advance(Duration::from_millis(100)).await;
+        assert_eq!(svc.call(&mut cx, 20).await.unwrap(), 20);
+    }
+}
@@ -0,0 +1,192 @@
+//! Abstracts the clock and the sleeps of the time-based middlewares.
+//!
+//! The middlewares reading the time or sleeping, like
+//! [`Timeout`](crate::timeout::Timeout), [`Retry`](crate::retry::Retry),
+//! [`RateLimit`](crate::limit::RateLimit), [`Hedge`](crate::hedge::Hedge) or
+//! [`CoDel`](crate::load_shed::codel::CoDel), do so through a [`Timer`],
+//! [`DefaultTimer`] by default. Another timer can be given to them or their layers,
+//! to run them on another runtime, or on a simulated clock in tests.
+//!
+//! With the `tokio` feature, enabled by default, the default timer is
+//! `TokioTimer`, following the clock of the tokio runtime. Without it, and on
+//! `wasm32` targets other than WASI, where the tokio timer is unavailable, it is
+//! [`FuturesTimer`], and on the latter [`Instant`] is the one of the `web-time`
+//! crate.
+
+use std::{future::Future, time::Duration};
+
+/// A measurement of a monotonically nondecreasing clock.
+#[cfg(not(all(target_arch = "wasm32", not(target_os = "wasi"))))]
+pub use std::time::Instant;
+/// A measurement of a monotonically nondecreasing clock.
+#[cfg(all(target_arch = "wasm32", not(target_os = "wasi")))]
+pub use web_time::Instant;
+
+/// The timer used when none is given, `TokioTimer` or [`FuturesTimer`] depending
+/// on the features and the target.
+#[cfg(all(
+    feature = "tokio",
+    not(all(target_arch = "wasm32", not(target_os = "wasi")))
+))]
+pub type DefaultTimer = TokioTimer;
+/// The timer used when none is given, `TokioTimer` or [`FuturesTimer`] depending
+/// on the features and the target.
+#[cfg(not(all(
+    feature = "tokio",
+    not(all(target_arch = "wasm32", not(target_os = "wasi")))
+)))]
+pub type DefaultTimer = FuturesTimer;
+
+/// A source of time, able to sleep.
+pub trait Timer {
+    /// Returns the current time.
+    fn now(&self) -> Instant;
+
+    /// Returns a future completing once `duration` has elapsed.
+    #[cfg(feature = "service_send")]
+    fn sleep(&self, duration: Duration) -> impl Future<Output = ()> + Send;
+
+    /// Returns a future completing once `duration` has elapsed.
+    #[cfg(not(feature = "service_send"))]
+    fn sleep(&self, duration: Duration) -> impl Future<Output = ()>;
+}
+
+/// The [`Timer`] of the tokio runtime.
+///
+/// It follows the clock of the runtime, so it is paused along with it in tests. It
+/// is unavailable on `wasm32` targets other than WASI.
+#[cfg(all(
+    feature = "tokio",
+    not(all(target_arch = "wasm32", not(target_os = "wasi")))
+))]
+#[cfg_attr(docsrs, doc(cfg(feature = "tokio")))]
+#[derive(Clone, Copy, Debug, Default)]
+pub struct TokioTimer;
+
+#[cfg(all(
+    feature = "tokio",
+    not(all(target_arch = "wasm32", not(target_os = "wasi")))
+))]
+impl TokioTimer {
+    /// Creates a timer following the clock of the tokio runtime.
+    pub const fn new() -> Self {
+        TokioTimer
+    }
+}
+
+#[cfg(all(
+    feature = "tokio",
+    not(all(target_arch = "wasm32", not(target_os = "wasi")))
+))]
+impl Timer for TokioTimer {
+    fn now(&self) -> Instant {
+        tokio::time::Instant::now().into_std()
+    }
+
+    #[cfg(feature = "service_send")]
+    fn sleep(&self, duration: Duration) -> impl Future<Output = ()> + Send {
+        tokio::time::sleep(duration)
+    }
+
+    #[cfg(not(feature = "service_send"))]
+    fn sleep(&self, duration: Duration) -> impl Future<Output = ()> {
+        tokio::time::sleep(duration)
+    }
+}
+
+/// The [`Timer`] of the `futures-timer` crate, independent of any runtime.
+///
+/// Its timers run on a background thread, or on the timers of the JavaScript host
+/// on `wasm32` targets other than WASI. It doesn't follow the paused clock of tokio
+/// tests.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct FuturesTimer;
+
+impl FuturesTimer {
+    /// Creates a timer on the timers of the `futures-timer` crate.
+    pub const fn new() -> Self {
+        FuturesTimer
+    }
+}
+
+impl Timer for FuturesTimer {
+    fn now(&self) -> Instant {
+        Instant::now()
+    }
+
+    #[cfg(feature = "service_send")]
+    fn sleep(&self, duration: Duration) -> impl Future<Output = ()> + Send {
+        futures_timer::Delay::new(duration)
+    }
+
+    #[cfg(not(feature = "service_send"))]
+    fn sleep(&self, duration: Duration) -> impl Future<Output = ()> {
+        futures_timer::Delay::new(duration)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{Arc, Mutex};
+
+    use super::*;
+    use crate::{
+        cache::{CacheStore, InMemoryStore},
+        layer::Layer,
+        limit::RateLimitLayer,
+        service::service_fn,
+        timeout::TimeoutLayer,
+        BoxError, Service,
+    };
+
+    /// A clock only moving forward when something sleeps on it.
+    #[derive(Clone)]
+    struct ManualTimer {
+        now: Arc<Mutex<Instant>>,
+    }
+
+    impl Timer for ManualTimer {
+        fn now(&self) -> Instant {
+            *self.now.lock().unwrap()
+        }
+
+        async fn sleep(&self, duration: Duration) {
+            *self.now.lock().unwrap() += duration;
+        }
+    }
+
+    #[tokio::test]
+    async fn middlewares_use_the_given_timer() {
+        let timer = ManualTimer {
+            now: Arc::new(Mutex::new(Instant::now())),
+        };
+        let start = timer.now();
+
+        let svc = TimeoutLayer::new(Some(Duration::from_secs(3600)))
+            .timer(timer.clone())
+            .layer(service_fn(|_cx: &mut (), _req: ()| {
+                std::future::pending::<Result<(), BoxError>>()
+            }));
+        assert!(svc.call(&mut (), ()).await.is_err());
+        assert_eq!(timer.now() - start, Duration::from_secs(3600));
+
+        let svc = RateLimitLayer::new(1, Duration::from_secs(60))
+            .wait()
+            .timer(timer.clone())
+            .layer(service_fn(|_cx: &mut (), req: u32| async move {
+                Ok::<_, BoxError>(req)
+            }));
+        for i in 0..3 {
+            assert_eq!(svc.call(&mut (), i).await.unwrap(), i);
+        }
+        assert_eq!(timer.now() - start, Duration::from_secs(3600 + 120));
+
+        let store = InMemoryStore::new(1)
+            .ttl(Duration::from_secs(60))
+            .timer(timer.clone());
+        store.put("key", 1).await;
+        assert_eq!(store.get(&"key").await, Some(1));
+        timer.sleep(Duration::from_secs(60)).await;
+        assert_eq!(store.get(&"key").await, None);
+    }
+}
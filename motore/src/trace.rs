@@ -0,0 +1,342 @@
+//! Instruments the calls of a service with [`tracing`].
+//!
+//! [`Trace`] enters a span for every call, made from the context and the request
+//! by a [`MakeSpan`], so everything the inner services log is attached to the
+//! request it is about. When the call completes, its latency and outcome are
+//! recorded in the `latency` and `outcome` fields of the span, when the span
+//! declared them, and an event reports the call, at the `DEBUG` level when it
+//! succeeded and the `ERROR` level with the error when it failed.
+
+use std::{
+    fmt,
+    task::{Context, Poll},
+};
+
+use tracing::{field, instrument::Instrument, Span};
+
+use crate::{
+    layer::Layer,
+    load::{Load, Ready},
+    service::Service,
+    timer::{DefaultTimer, Timer},
+    MaybeSend, MaybeSync,
+};
+
+/// Makes the span of a call.
+///
+/// This is implemented for closures taking the context and the request, and by
+/// [`DefaultMakeSpan`].
+///
+/// # Example
+///
+/// ```rust
+/// use motore::trace::MakeSpan;
+///
+/// struct Cx {
+///     method: &'static str,
+/// }
+///
+/// let make_span = |cx: &Cx, _req: &String| {
+///     tracing::info_span!(
+///         "rpc",
+///         method = cx.method,
+///         latency = tracing::field::Empty,
+///         outcome = tracing::field::Empty,
+///     )
+/// };
+/// let span = make_span.make_span(&Cx { method: "echo" }, &"ping".to_string());
+/// ```
+pub trait MakeSpan<Cx, Req> {
+    /// Returns the span of the call made with `cx` and `req`.
+    fn make_span(&self, cx: &Cx, req: &Req) -> Span;
+}
+
+impl<F, Cx, Req> MakeSpan<Cx, Req> for F
+where
+    F: Fn(&Cx, &Req) -> Span,
+{
+    fn make_span(&self, cx: &Cx, req: &Req) -> Span {
+        self(cx, req)
+    }
+}
+
+/// The [`MakeSpan`] used when none is given, making an `INFO` span named `call`
+/// with the `latency` and `outcome` fields.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct DefaultMakeSpan;
+
+impl<Cx, Req> MakeSpan<Cx, Req> for DefaultMakeSpan {
+    fn make_span(&self, _cx: &Cx, _req: &Req) -> Span {
+        tracing::info_span!("call", latency = field::Empty, outcome = field::Empty)
+    }
+}
+
+/// Runs each call to the inner service in a span, and reports its latency and
+/// outcome.
+///
+/// See the [module level docs](self) for details.
+#[derive(Clone)]
+pub struct Trace<S, M = DefaultMakeSpan, T = DefaultTimer> {
+    inner: S,
+    make_span: M,
+    timer: T,
+}
+
+impl<S> Trace<S> {
+    /// Creates a `Trace` making the spans with [`DefaultMakeSpan`].
+    pub const fn new(inner: S) -> Self {
+        Trace {
+            inner,
+            make_span: DefaultMakeSpan,
+            timer: DefaultTimer::new(),
+        }
+    }
+}
+
+impl<S, M, T> Trace<S, M, T> {
+    /// Sets the timer measuring the latency, [`DefaultTimer`] by default.
+    pub fn timer<U>(self, timer: U) -> Trace<S, M, U> {
+        Trace {
+            inner: self.inner,
+            make_span: self.make_span,
+            timer,
+        }
+    }
+}
+
+impl<Cx, Req, S, M, T> Service<Cx, Req> for Trace<S, M, T>
+where
+    Req: 'static + MaybeSend,
+    S: Service<Cx, Req> + 'static + MaybeSend + MaybeSync,
+    M: MakeSpan<Cx, Req> + MaybeSync,
+    Cx: 'static + MaybeSend,
+    S::Error: fmt::Display,
+    T: Timer + MaybeSync,
+{
+    type Response = S::Response;
+
+    type Error = S::Error;
+
+    async fn call(&self, cx: &mut Cx, req: Req) -> Result<Self::Response, Self::Error> {
+        let span = self.make_span.make_span(cx, &req);
+        let start = self.timer.now();
+        let res = self.inner.call(cx, req).instrument(span.clone()).await;
+        let latency = self.timer.now().saturating_duration_since(start);
+
+        span.record("latency", field::debug(latency));
+        match &res {
+            Ok(_) => {
+                span.record("outcome", "ok");
+                tracing::debug!(parent: &span, ?latency, "call succeeded");
+            }
+            Err(e) => {
+                span.record("outcome", "error");
+                tracing::error!(parent: &span, ?latency, error = %e, "call failed");
+            }
+        }
+        res
+    }
+}
+
+impl<S, M, T> Ready for Trace<S, M, T>
+where
+    S: Ready,
+{
+    fn poll_ready(&self, cx: &mut Context<'_>) -> Poll<()> {
+        self.inner.poll_ready(cx)
+    }
+}
+
+impl<S, M, T> Load for Trace<S, M, T>
+where
+    S: Load,
+{
+    type Metric = S::Metric;
+
+    fn load(&self) -> Self::Metric {
+        self.inner.load()
+    }
+}
+
+impl<S: fmt::Debug, M, T> fmt::Debug for Trace<S, M, T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Trace")
+            .field("inner", &self.inner)
+            .field("make_span", &format_args!("{}", std::any::type_name::<M>()))
+            .finish()
+    }
+}
+
+/// Applies a [`Trace`] to a service.
+///
+/// # Example
+///
+/// ```rust
+/// use motore::{
+///     builder::ServiceBuilder, service::service_fn, trace::TraceLayer, BoxError, Service,
+/// };
+///
+/// struct Cx {
+///     method: &'static str,
+/// }
+///
+/// # #[tokio::main(flavor = "current_thread")]
+/// # async fn main() {
+/// async fn echo(_cx: &mut Cx, req: String) -> Result<String, BoxError> {
+///     tracing::info!("echoing");
+///     Ok(req)
+/// }
+///
+/// let svc = ServiceBuilder::new()
+///     .layer(TraceLayer::new().make_span(|cx: &Cx, req: &String| {
+///         tracing::info_span!(
+///             "rpc",
+///             method = cx.method,
+///             len = req.len(),
+///             latency = tracing::field::Empty,
+///             outcome = tracing::field::Empty,
+///         )
+///     }))
+///     .service(service_fn(echo));
+///
+/// let mut cx = Cx { method: "echo" };
+/// assert_eq!(svc.call(&mut cx, "ping".into()).await.unwrap(), "ping");
+/// # }
+/// ```
+#[derive(Clone, Copy, Debug, Default)]
+pub struct TraceLayer<M = DefaultMakeSpan, T = DefaultTimer> {
+    make_span: M,
+    timer: T,
+}
+
+impl TraceLayer {
+    /// Creates a layer making the spans with [`DefaultMakeSpan`].
+    pub const fn new() -> Self {
+        TraceLayer {
+            make_span: DefaultMakeSpan,
+            timer: DefaultTimer::new(),
+        }
+    }
+}
+
+impl<M, T> TraceLayer<M, T> {
+    /// Sets how the span of each call is made.
+    pub fn make_span<N>(self, make_span: N) -> TraceLayer<N, T> {
+        TraceLayer {
+            make_span,
+            timer: self.timer,
+        }
+    }
+
+    /// Sets the timer measuring the latency, [`DefaultTimer`] by default.
+    pub fn timer<U>(self, timer: U) -> TraceLayer<M, U> {
+        TraceLayer {
+            make_span: self.make_span,
+            timer,
+        }
+    }
+}
+
+impl<S, M, T> Layer<S> for TraceLayer<M, T> {
+    type Service = Trace<S, M, T>;
+
+    fn layer(self, inner: S) -> Self::Service {
+        Trace {
+            inner,
+            make_span: self.make_span,
+            timer: self.timer,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc, Mutex,
+    };
+
+    use tracing::{
+        field::{Field, Visit},
+        span, Event, Metadata, Subscriber,
+    };
+
+    use super::*;
+    use crate::{service::service_fn, BoxError};
+
+    /// Collects the fields recorded in the spans and the events, as `name=value`.
+    #[derive(Clone, Default)]
+    struct Recorder {
+        records: Arc<Mutex<Vec<String>>>,
+        next_id: Arc<AtomicU64>,
+    }
+
+    impl Visit for Recorder {
+        fn record_debug(&mut self, field: &Field, value: &dyn fmt::Debug) {
+            if field.name() != "latency" {
+                let record = format!("{}={value:?}", field.name());
+                self.records.lock().unwrap().push(record);
+            }
+        }
+    }
+
+    impl Subscriber for Recorder {
+        fn enabled(&self, _metadata: &Metadata<'_>) -> bool {
+            true
+        }
+
+        fn new_span(&self, span: &span::Attributes<'_>) -> span::Id {
+            span.record(&mut self.clone());
+            span::Id::from_u64(self.next_id.fetch_add(1, Ordering::Relaxed) + 1)
+        }
+
+        fn record(&self, _span: &span::Id, values: &span::Record<'_>) {
+            values.record(&mut self.clone());
+        }
+
+        fn record_follows_from(&self, _span: &span::Id, _follows: &span::Id) {}
+
+        fn event(&self, event: &Event<'_>) {
+            event.record(&mut self.clone());
+        }
+
+        fn enter(&self, _span: &span::Id) {}
+
+        fn exit(&self, _span: &span::Id) {}
+    }
+
+    #[tokio::test]
+    async fn records_the_outcome_of_the_calls() {
+        let recorder = Recorder::default();
+        let _guard = tracing::subscriber::set_default(recorder.clone());
+
+        let svc = TraceLayer::new()
+            .make_span(|_cx: &(), req: &u32| {
+                tracing::info_span!("test", req, latency = field::Empty, outcome = field::Empty)
+            })
+            .layer(service_fn(|_cx: &mut (), req: u32| async move {
+                tracing::info!("handling");
+                if req == 0 {
+                    return Err(BoxError::from("zero"));
+                }
+                Ok(req)
+            }));
+
+        assert_eq!(svc.call(&mut (), 1).await.unwrap(), 1);
+        assert!(svc.call(&mut (), 0).await.is_err());
+        assert_eq!(
+            *recorder.records.lock().unwrap(),
+            [
+                "req=1",
+                "message=handling",
+                "outcome=\"ok\"",
+                "message=call succeeded",
+                "req=0",
+                "message=handling",
+                "outcome=\"error\"",
+                "message=call failed",
+                "error=zero",
+            ]
+        );
+    }
+}
@@ -0,0 +1,73 @@
+//! A value read on every call, and replaced as a whole off the path of the calls.
+
+use std::sync::Arc;
+
+use super::sync::RwLock;
+
+/// Holds the current version of a value, read by cloning an [`Arc`] of it.
+///
+/// Like with RCU, the value is never changed in place: a new version is built on
+/// the side and swapped in, while the readers holding the previous one keep it
+/// until they are done. The lock is only held to clone or to swap the [`Arc`], so
+/// a reader never waits for a new version to be built, and is never blocked for
+/// longer than a swap.
+pub(crate) struct ArcCell<T>(RwLock<Arc<T>>);
+
+impl<T> ArcCell<T> {
+    pub(crate) fn new(value: T) -> Self {
+        ArcCell(RwLock::new(Arc::new(value)))
+    }
+
+    /// Returns the current version.
+    pub(crate) fn load(&self) -> Arc<T> {
+        self.0.read().unwrap_or_else(|e| e.into_inner()).clone()
+    }
+
+    /// Swaps in a new version, the readers of the previous one keep it.
+    pub(crate) fn store(&self, value: T) {
+        let previous = std::mem::replace(
+            &mut *self.0.write().unwrap_or_else(|e| e.into_inner()),
+            Arc::new(value),
+        );
+        // Dropped once the lock is released, in case it was the last reference.
+        drop(previous);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn readers_keep_their_version() {
+        let cell = ArcCell::new(vec![1]);
+        let before = cell.load();
+        cell.store(vec![1, 2]);
+        assert_eq!(*before, [1]);
+        assert_eq!(*cell.load(), [1, 2]);
+    }
+}
+
+#[cfg(all(test, loom))]
+mod loom_tests {
+    use super::*;
+
+    #[test]
+    fn readers_see_a_whole_version() {
+        loom::model(|| {
+            let cell = Arc::new(ArcCell::new((0, 0)));
+            let writer = loom::thread::spawn({
+                let cell = cell.clone();
+                move || {
+                    cell.store((1, 1));
+                    cell.store((2, 2));
+                }
+            });
+
+            let (a, b) = *cell.load();
+            assert_eq!(a, b);
+            writer.join().unwrap();
+            assert_eq!(*cell.load(), (2, 2));
+        });
+    }
+}
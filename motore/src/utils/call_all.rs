@@ -0,0 +1,348 @@
+//! Drives streams of requests through a service.
+
+use std::{
+    fmt, mem,
+    pin::Pin,
+    sync::Arc,
+    task::{Context, Poll},
+};
+
+#[cfg(feature = "service_send")]
+use futures::future::BoxFuture;
+#[cfg(not(feature = "service_send"))]
+use futures::future::LocalBoxFuture as BoxFuture;
+use futures::{
+    stream::{Fuse, FuturesUnordered},
+    Stream, StreamExt,
+};
+use pin_project::pin_project;
+
+use crate::Service;
+
+/// A stream calling a service with each request of another stream, and yielding
+/// the results in order.
+///
+/// All the calls share the same context, so they are made one at a time: the next
+/// request is only pulled once the previous call completes. Errors are yielded like
+/// responses and don't end the stream, which ends with the stream of requests.
+///
+/// # Example
+///
+/// ```rust
+/// use std::convert::Infallible;
+///
+/// use futures::{stream, StreamExt};
+/// use motore::{service::service_fn, utils::CallAll};
+///
+/// # #[tokio::main(flavor = "current_thread")]
+/// # async fn main() {
+/// async fn double(_cx: &mut (), req: u32) -> Result<u32, Infallible> {
+///     Ok(req * 2)
+/// }
+///
+/// let responses: Vec<_> = CallAll::new(service_fn(double), (), stream::iter([1, 2, 3]))
+///     .collect()
+///     .await;
+/// assert_eq!(responses, [Ok(2), Ok(4), Ok(6)]);
+/// # }
+/// ```
+#[pin_project]
+pub struct CallAll<S, Cx, St>
+where
+    S: Service<Cx, St::Item>,
+    St: Stream,
+{
+    svc: Arc<S>,
+    #[pin]
+    requests: St,
+    state: State<Cx, Result<S::Response, S::Error>>,
+}
+
+enum State<Cx, T> {
+    Idle(Cx),
+    Calling(BoxFuture<'static, (Cx, T)>),
+    Empty,
+}
+
+impl<S, Cx, St> CallAll<S, Cx, St>
+where
+    S: Service<Cx, St::Item>,
+    St: Stream,
+{
+    /// Creates a stream calling `svc` with each request of `requests`, and the
+    /// context `cx`.
+    pub fn new(svc: S, cx: Cx, requests: St) -> Self {
+        CallAll {
+            svc: Arc::new(svc),
+            requests,
+            state: State::Idle(cx),
+        }
+    }
+
+    /// Returns the context, unless a call is in flight.
+    pub fn into_context(self) -> Option<Cx> {
+        match self.state {
+            State::Idle(cx) => Some(cx),
+            _ => None,
+        }
+    }
+}
+
+impl<S, Cx, St> CallAll<S, Cx, St>
+where
+    S: Service<Cx, St::Item>,
+    St: Stream,
+{
+    fn poll_calls<F>(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        start: F,
+    ) -> Poll<Option<Result<S::Response, S::Error>>>
+    where
+        F: Fn(Arc<S>, Cx, St::Item) -> BoxFuture<'static, (Cx, Result<S::Response, S::Error>)>,
+    {
+        let mut this = self.project();
+        loop {
+            match mem::replace(this.state, State::Empty) {
+                State::Idle(call_cx) => match this.requests.as_mut().poll_next(cx) {
+                    Poll::Ready(Some(req)) => {
+                        *this.state = State::Calling(start(this.svc.clone(), call_cx, req));
+                    }
+                    Poll::Ready(None) => {
+                        *this.state = State::Idle(call_cx);
+                        return Poll::Ready(None);
+                    }
+                    Poll::Pending => {
+                        *this.state = State::Idle(call_cx);
+                        return Poll::Pending;
+                    }
+                },
+                State::Calling(mut fut) => match fut.as_mut().poll(cx) {
+                    Poll::Ready((call_cx, res)) => {
+                        *this.state = State::Idle(call_cx);
+                        return Poll::Ready(Some(res));
+                    }
+                    Poll::Pending => {
+                        *this.state = State::Calling(fut);
+                        return Poll::Pending;
+                    }
+                },
+                State::Empty => unreachable!("the state is restored after each poll"),
+            }
+        }
+    }
+}
+
+#[cfg(feature = "service_send")]
+impl<S, Cx, St> Stream for CallAll<S, Cx, St>
+where
+    S: Service<Cx, St::Item> + Send + Sync + 'static,
+    St: Stream,
+    St::Item: Send + 'static,
+    Cx: Send + 'static,
+{
+    type Item = Result<S::Response, S::Error>;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        self.poll_calls(cx, |svc, mut call_cx, req| {
+            Box::pin(async move {
+                let res = svc.call(&mut call_cx, req).await;
+                (call_cx, res)
+            })
+        })
+    }
+}
+
+#[cfg(not(feature = "service_send"))]
+impl<S, Cx, St> Stream for CallAll<S, Cx, St>
+where
+    S: Service<Cx, St::Item> + 'static,
+    St: Stream,
+    St::Item: 'static,
+    Cx: 'static,
+{
+    type Item = Result<S::Response, S::Error>;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        self.poll_calls(cx, |svc, mut call_cx, req| {
+            Box::pin(async move {
+                let res = svc.call(&mut call_cx, req).await;
+                (call_cx, res)
+            })
+        })
+    }
+}
+
+impl<S, Cx, St> fmt::Debug for CallAll<S, Cx, St>
+where
+    S: Service<Cx, St::Item> + fmt::Debug,
+    St: Stream,
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("CallAll")
+            .field("svc", &self.svc)
+            .field("in_flight", &matches!(self.state, State::Calling(_)))
+            .finish()
+    }
+}
+
+/// A stream calling a service with each request of another stream, up to a number
+/// of calls at a time, and yielding the results as they complete.
+///
+/// Each call gets its own clone of the context. This is similar to
+/// [`buffer_unordered`](futures::StreamExt::buffer_unordered), and suits fan-out
+/// workloads where the order of the results doesn't matter. Errors are yielded like
+/// responses and don't end the stream, which ends once the stream of requests is
+/// exhausted and all the calls completed.
+#[pin_project]
+pub struct CallAllUnordered<S, Cx, St>
+where
+    S: Service<Cx, St::Item>,
+    St: Stream,
+{
+    svc: Arc<S>,
+    cx: Cx,
+    #[pin]
+    requests: Fuse<St>,
+    in_flight: FuturesUnordered<BoxFuture<'static, Result<S::Response, S::Error>>>,
+    limit: usize,
+}
+
+impl<S, Cx, St> CallAllUnordered<S, Cx, St>
+where
+    S: Service<Cx, St::Item>,
+    St: Stream,
+{
+    /// Creates a stream calling `svc` with each request of `requests` and a clone of
+    /// `cx`, with at most `limit` calls in flight.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `limit` is zero.
+    pub fn new(svc: S, cx: Cx, requests: St, limit: usize) -> Self {
+        assert!(limit > 0, "the limit must not be zero");
+        CallAllUnordered {
+            svc: Arc::new(svc),
+            cx,
+            requests: requests.fuse(),
+            in_flight: FuturesUnordered::new(),
+            limit,
+        }
+    }
+
+    fn poll_calls<F>(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        start: F,
+    ) -> Poll<Option<Result<S::Response, S::Error>>>
+    where
+        F: Fn(Arc<S>, Cx, St::Item) -> BoxFuture<'static, Result<S::Response, S::Error>>,
+        Cx: Clone,
+    {
+        let mut this = self.project();
+        while this.in_flight.len() < *this.limit {
+            match this.requests.as_mut().poll_next(cx) {
+                Poll::Ready(Some(req)) => {
+                    this.in_flight
+                        .push(start(this.svc.clone(), this.cx.clone(), req));
+                }
+                Poll::Ready(None) | Poll::Pending => break,
+            }
+        }
+        match this.in_flight.poll_next_unpin(cx) {
+            Poll::Ready(None) if !this.requests.is_done() => Poll::Pending,
+            res => res,
+        }
+    }
+}
+
+#[cfg(feature = "service_send")]
+impl<S, Cx, St> Stream for CallAllUnordered<S, Cx, St>
+where
+    S: Service<Cx, St::Item> + Send + Sync + 'static,
+    St: Stream,
+    St::Item: Send + 'static,
+    Cx: Clone + Send + 'static,
+{
+    type Item = Result<S::Response, S::Error>;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        self.poll_calls(cx, |svc, mut call_cx, req| {
+            Box::pin(async move { svc.call(&mut call_cx, req).await })
+        })
+    }
+}
+
+#[cfg(not(feature = "service_send"))]
+impl<S, Cx, St> Stream for CallAllUnordered<S, Cx, St>
+where
+    S: Service<Cx, St::Item> + 'static,
+    St: Stream,
+    St::Item: 'static,
+    Cx: Clone + 'static,
+{
+    type Item = Result<S::Response, S::Error>;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        self.poll_calls(cx, |svc, mut call_cx, req| {
+            Box::pin(async move { svc.call(&mut call_cx, req).await })
+        })
+    }
+}
+
+impl<S, Cx, St> fmt::Debug for CallAllUnordered<S, Cx, St>
+where
+    S: Service<Cx, St::Item> + fmt::Debug,
+    St: Stream,
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("CallAllUnordered")
+            .field("svc", &self.svc)
+            .field("in_flight", &self.in_flight.len())
+            .field("limit", &self.limit)
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use futures::{stream, StreamExt};
+
+    use super::*;
+    use crate::service::service_fn;
+
+    #[tokio::test(start_paused = true)]
+    async fn yields_results_in_order_with_a_shared_context() {
+        async fn parse(calls: &mut u64, req: &'static str) -> Result<u32, String> {
+            *calls += 1;
+            tokio::time::sleep(Duration::from_millis(30 - *calls * 10)).await;
+            req.parse().map_err(|_| format!("bad: {req}"))
+        }
+
+        let mut responses = CallAll::new(service_fn(parse), 0, stream::iter(["1", "x", "3"]));
+        let mut results = Vec::new();
+        while let Some(res) = responses.next().await {
+            results.push(res);
+        }
+        assert_eq!(results, [Ok(1), Err("bad: x".to_string()), Ok(3)]);
+        assert_eq!(responses.into_context(), Some(3));
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn unordered_calls_run_concurrently_up_to_the_limit() {
+        async fn sleep(_cx: &mut (), ms: u64) -> Result<u64, ()> {
+            tokio::time::sleep(Duration::from_millis(ms)).await;
+            Ok(ms)
+        }
+
+        let start = tokio::time::Instant::now();
+        let requests = stream::iter([300, 100, 150, 10]);
+        let results: Vec<_> = CallAllUnordered::new(service_fn(sleep), (), requests, 2)
+            .collect()
+            .await;
+        assert_eq!(results, [Ok(100), Ok(150), Ok(10), Ok(300)]);
+        assert_eq!(start.elapsed(), Duration::from_millis(300));
+    }
+}
@@ -1,4 +1,11 @@
-use crate::{layer::Layer, service::Service};
+use std::task::{Context, Poll};
+
+use crate::{
+    layer::Layer,
+    load::{Load, Ready},
+    service::Service,
+    MaybeSend, MaybeSync,
+};
 
 /// Combine two different service types into a single type.
 ///
@@ -28,10 +35,10 @@ where
 
 impl<A, B, Cx, Req> Service<Cx, Req> for Either<A, B>
 where
-    Req: 'static + Send,
-    Cx: Send + 'static,
-    A: Service<Cx, Req> + Send + 'static + Sync,
-    B: Service<Cx, Req, Response = A::Response, Error = A::Error> + Send + 'static + Sync,
+    Req: 'static + MaybeSend,
+    Cx: MaybeSend + 'static,
+    A: Service<Cx, Req> + MaybeSend + 'static + MaybeSync,
+    B: Service<Cx, Req, Response = A::Response, Error = A::Error> + MaybeSend + 'static + MaybeSync,
 {
     type Response = A::Response;
 
@@ -44,3 +51,31 @@ where
         }
     }
 }
+
+impl<A, B> Ready for Either<A, B>
+where
+    A: Ready,
+    B: Ready,
+{
+    fn poll_ready(&self, cx: &mut Context<'_>) -> Poll<()> {
+        match self {
+            Either::A(s) => s.poll_ready(cx),
+            Either::B(s) => s.poll_ready(cx),
+        }
+    }
+}
+
+impl<A, B> Load for Either<A, B>
+where
+    A: Load,
+    B: Load<Metric = A::Metric>,
+{
+    type Metric = A::Metric;
+
+    fn load(&self) -> Self::Metric {
+        match self {
+            Either::A(s) => s.load(),
+            Either::B(s) => s.load(),
+        }
+    }
+}
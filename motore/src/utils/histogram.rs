@@ -0,0 +1,110 @@
+use std::time::Duration;
+
+/// The number of buckets per power of two, as a power of two.
+const PRECISION: u32 = 3;
+const SUB_BUCKETS: usize = 1 << PRECISION;
+const BUCKETS: usize = SUB_BUCKETS + (64 - PRECISION as usize) * SUB_BUCKETS;
+
+/// A histogram of durations, with a precision of 1/8th of their magnitude.
+///
+/// Durations are recorded in microseconds, in log-linear buckets: each power of two
+/// is split in 8 buckets of equal width.
+#[derive(Clone, Debug)]
+pub(crate) struct Histogram {
+    buckets: Box<[u64; BUCKETS]>,
+    count: u64,
+}
+
+impl Histogram {
+    pub(crate) fn new() -> Self {
+        Histogram {
+            buckets: Box::new([0; BUCKETS]),
+            count: 0,
+        }
+    }
+
+    pub(crate) fn count(&self) -> u64 {
+        self.count
+    }
+
+    pub(crate) fn record(&mut self, value: Duration) {
+        let micros = u64::try_from(value.as_micros()).unwrap_or(u64::MAX);
+        self.buckets[index(micros)] += 1;
+        self.count += 1;
+    }
+
+    /// Returns the duration under which `percentile` of the recorded durations
+    /// fall, rounded up to the bucket boundary, or `None` if nothing was recorded.
+    pub(crate) fn percentile(&self, percentile: f64) -> Option<Duration> {
+        if self.count == 0 {
+            return None;
+        }
+        let rank = ((percentile * self.count as f64).ceil() as u64).clamp(1, self.count);
+        let mut seen = 0;
+        for (i, &n) in self.buckets.iter().enumerate() {
+            seen += n;
+            if seen >= rank {
+                return Some(Duration::from_micros(upper_bound(i)));
+            }
+        }
+        unreachable!("the buckets add up to the count")
+    }
+
+    pub(crate) fn clear(&mut self) {
+        self.buckets.fill(0);
+        self.count = 0;
+    }
+}
+
+fn index(micros: u64) -> usize {
+    if micros < SUB_BUCKETS as u64 {
+        return micros as usize;
+    }
+    let exp = 63 - micros.leading_zeros();
+    let sub = (micros >> (exp - PRECISION)) as usize & (SUB_BUCKETS - 1);
+    SUB_BUCKETS + (exp - PRECISION) as usize * SUB_BUCKETS + sub
+}
+
+/// Returns the first value past the bucket at `index`, which is the lower bound of
+/// the next bucket.
+fn upper_bound(index: usize) -> u64 {
+    if index + 1 < SUB_BUCKETS {
+        return index as u64 + 1;
+    }
+    if index + 1 == BUCKETS {
+        return u64::MAX;
+    }
+    let next = index + 1 - SUB_BUCKETS;
+    let exp = (next / SUB_BUCKETS) as u32;
+    let sub = (next % SUB_BUCKETS) as u64;
+    (SUB_BUCKETS as u64 + sub) << exp
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn buckets_are_contiguous() {
+        for micros in [0, 1, 7, 8, 9, 15, 16, 17, 1000, 123_456, u64::MAX / 2] {
+            let i = index(micros);
+            assert!(micros < upper_bound(i), "{micros} in bucket {i}");
+            if i > 0 {
+                assert!(micros >= upper_bound(i - 1), "{micros} in bucket {i}");
+            }
+        }
+        assert_eq!(index(u64::MAX), BUCKETS - 1);
+    }
+
+    #[test]
+    fn percentiles() {
+        let mut histogram = Histogram::new();
+        assert_eq!(histogram.percentile(0.5), None);
+        for ms in 1..=100 {
+            histogram.record(Duration::from_millis(ms));
+        }
+        let p90 = histogram.percentile(0.9).unwrap();
+        assert!(Duration::from_millis(90) <= p90 && p90 <= Duration::from_millis(90) * 9 / 8);
+        assert!(histogram.percentile(1.0).unwrap() >= Duration::from_millis(100));
+    }
+}
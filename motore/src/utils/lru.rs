@@ -0,0 +1,147 @@
+//! Values kept in least recently used order, expiring in the order they were
+//! stored.
+
+use std::{
+    collections::{BTreeMap, HashMap},
+    hash::Hash,
+};
+
+use crate::timer::Instant;
+
+pub(crate) struct Entry<V> {
+    pub(crate) value: V,
+    pub(crate) expires: Option<Instant>,
+    /// The position of the entry in the `recency` map.
+    used: u64,
+    /// The position of the entry in the `stored` map.
+    stored: u64,
+}
+
+/// Values evicting the least recently used one once full.
+///
+/// The values expiring are meant to all live for the same time, so they expire in
+/// the order they were stored: the map keeps that order, so that
+/// [`purge`](Lru::purge) drops the expired values from the front, rather than
+/// letting them hold on to the capacity until they are read or evicted.
+pub(crate) struct Lru<K, V> {
+    map: HashMap<K, Entry<V>>,
+    /// The keys, from the least to the most recently used.
+    recency: BTreeMap<u64, K>,
+    /// The keys of the values expiring, from the first to the last stored.
+    stored: BTreeMap<u64, K>,
+    next: u64,
+}
+
+impl<K: Hash + Eq + Clone, V> Lru<K, V> {
+    pub(crate) fn new() -> Self {
+        Lru {
+            map: HashMap::new(),
+            recency: BTreeMap::new(),
+            stored: BTreeMap::new(),
+            next: 0,
+        }
+    }
+
+    /// Returns the number of values, expired or not.
+    pub(crate) fn len(&self) -> usize {
+        self.map.len()
+    }
+
+    fn next(&mut self) -> u64 {
+        self.next += 1;
+        self.next
+    }
+
+    /// Returns the entry of `key`, making it the most recently used.
+    pub(crate) fn touch(&mut self, key: &K) -> Option<&mut Entry<V>> {
+        let used = self.next();
+        let entry = self.map.get_mut(key)?;
+        self.recency.remove(&entry.used);
+        entry.used = used;
+        self.recency.insert(entry.used, key.clone());
+        Some(entry)
+    }
+
+    /// Stores a value, evicting the least recently used one if there are
+    /// `capacity` values already.
+    pub(crate) fn insert(&mut self, key: K, value: V, expires: Option<Instant>, capacity: usize) {
+        self.remove(&key);
+        if self.map.len() >= capacity {
+            if let Some((_, lru)) = self.recency.pop_first() {
+                self.remove(&lru);
+            }
+        }
+
+        let used = self.next();
+        self.recency.insert(used, key.clone());
+        if expires.is_some() {
+            self.stored.insert(used, key.clone());
+        }
+        self.map.insert(
+            key,
+            Entry {
+                value,
+                expires,
+                used,
+                stored: used,
+            },
+        );
+    }
+
+    pub(crate) fn remove(&mut self, key: &K) {
+        if let Some(entry) = self.map.remove(key) {
+            self.recency.remove(&entry.used);
+            self.stored.remove(&entry.stored);
+        }
+    }
+
+    /// Drops the values expired at `now`.
+    pub(crate) fn purge(&mut self, now: Instant) {
+        while let Some((_, key)) = self.stored.first_key_value() {
+            let expired = self.map[key].expires.is_some_and(|expires| expires <= now);
+            if !expired {
+                break;
+            }
+            let key = key.clone();
+            self.remove(&key);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::*;
+
+    #[test]
+    fn evicts_the_least_recently_used_value() {
+        let mut lru = Lru::new();
+        lru.insert(1, "one", None, 2);
+        lru.insert(2, "two", None, 2);
+        assert!(lru.touch(&1).is_some());
+        lru.insert(3, "three", None, 2);
+        assert!(lru.touch(&2).is_none());
+        assert_eq!(lru.touch(&1).map(|e| e.value), Some("one"));
+        assert_eq!(lru.len(), 2);
+    }
+
+    #[test]
+    fn purges_the_expired_values() {
+        let mut lru = Lru::new();
+        let now = Instant::now();
+        let ttl = Duration::from_secs(10);
+        lru.insert("a", 1, Some(now + ttl), 8);
+        lru.insert("b", 2, None, 8);
+        lru.insert("c", 3, Some(now + 2 * ttl), 8);
+        // Reading a value doesn't change when it expires.
+        assert!(lru.touch(&"a").is_some());
+
+        lru.purge(now + ttl);
+        assert!(lru.touch(&"a").is_none());
+        assert_eq!(lru.len(), 2);
+        lru.purge(now + 2 * ttl);
+        assert_eq!(lru.touch(&"b").map(|e| e.value), Some(2));
+        assert_eq!(lru.len(), 1);
+    }
+}
@@ -1,4 +1,21 @@
+pub(crate) mod arc_cell;
+pub mod call_all;
 pub mod either;
+pub(crate) mod histogram;
+pub(crate) mod lru;
 pub mod option;
+pub(crate) mod reply;
+pub(crate) mod rng;
+pub mod schedule;
+pub(crate) mod semaphore;
+pub(crate) mod sharded;
+pub(crate) mod sync;
+pub(crate) mod token_bucket;
+pub(crate) mod wait_map;
+pub(crate) mod waiters;
 
-pub use self::{either::Either, option::option_layer};
+pub use self::{
+    call_all::{CallAll, CallAllUnordered},
+    either::Either,
+    option::option_layer,
+};
@@ -0,0 +1,196 @@
+//! Reply slots reused from one request to the next.
+//!
+//! A request handed to another task usually comes with a oneshot channel for its
+//! reply, allocated for every request. A [`Pool`] keeps the slots of the replies
+//! read instead, so a steady flow of requests takes the slot of a previous one.
+
+use std::{
+    mem,
+    sync::Arc,
+    task::{Context, Poll, Waker},
+};
+
+use super::sync::{AtomicU64, Mutex, MutexGuard, Ordering};
+
+enum State<T> {
+    /// No reply was sent yet, and the requester waits with the given waker.
+    Waiting(Option<Waker>),
+    Sent(T),
+    /// The responder was dropped without replying.
+    Closed,
+}
+
+/// Where the reply to a request is written, reused by the next requests.
+pub(crate) struct Slot<T>(Mutex<State<T>>);
+
+impl<T> Slot<T> {
+    fn lock(&self) -> MutexGuard<'_, State<T>> {
+        self.0.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// Polls for the reply, leaving the slot ready for another request once it
+    /// was sent or the responder is gone.
+    pub(crate) fn poll_reply(&self, cx: &mut Context<'_>) -> Poll<Option<T>> {
+        let mut state = self.lock();
+        match mem::replace(&mut *state, State::Waiting(None)) {
+            State::Waiting(_) => {
+                *state = State::Waiting(Some(cx.waker().clone()));
+                Poll::Pending
+            }
+            State::Sent(reply) => Poll::Ready(Some(reply)),
+            State::Closed => Poll::Ready(None),
+        }
+    }
+
+    fn complete(&self, state: State<T>) {
+        let waiting = mem::replace(&mut *self.lock(), state);
+        if let State::Waiting(Some(waker)) = waiting {
+            waker.wake();
+        }
+    }
+}
+
+/// The responding end of a [`Slot`], closing it when dropped without replying.
+///
+/// The slot is only touched once, so the requester can reuse it as soon as it read
+/// the reply, even if the responder didn't drop its reference yet.
+pub(crate) struct Responder<T>(Option<Arc<Slot<T>>>);
+
+impl<T> Responder<T> {
+    pub(crate) fn new(slot: Arc<Slot<T>>) -> Self {
+        Responder(Some(slot))
+    }
+
+    pub(crate) fn send(mut self, reply: T) {
+        if let Some(slot) = self.0.take() {
+            slot.complete(State::Sent(reply));
+        }
+    }
+}
+
+impl<T> Drop for Responder<T> {
+    fn drop(&mut self) {
+        if let Some(slot) = self.0.take() {
+            slot.complete(State::Closed);
+        }
+    }
+}
+
+/// The slots of the replies read, and how often a request found one.
+pub(crate) struct Pool<T> {
+    slots: Mutex<Vec<Arc<Slot<T>>>>,
+    /// The most slots kept.
+    capacity: usize,
+    hits: AtomicU64,
+    misses: AtomicU64,
+}
+
+impl<T> Pool<T> {
+    pub(crate) fn new(capacity: usize) -> Self {
+        Pool {
+            slots: Mutex::new(Vec::new()),
+            capacity,
+            hits: AtomicU64::new(0),
+            misses: AtomicU64::new(0),
+        }
+    }
+
+    fn lock(&self) -> MutexGuard<'_, Vec<Arc<Slot<T>>>> {
+        self.slots.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// Takes a slot from the pool, or allocates one if it is empty.
+    pub(crate) fn take(&self) -> Arc<Slot<T>> {
+        let slot = self.lock().pop();
+        match slot {
+            Some(slot) => {
+                self.hits.fetch_add(1, Ordering::Relaxed);
+                slot
+            }
+            None => {
+                self.misses.fetch_add(1, Ordering::Relaxed);
+                Arc::new(Slot(Mutex::new(State::Waiting(None))))
+            }
+        }
+    }
+
+    /// Puts back a slot whose reply was read, unless the pool is full.
+    ///
+    /// A requester going away before reading its reply drops its slot instead, as
+    /// the responder may still write to it.
+    pub(crate) fn put(&self, slot: Arc<Slot<T>>) {
+        let mut slots = self.lock();
+        if slots.len() < self.capacity {
+            slots.push(slot);
+        }
+    }
+
+    /// Returns the number of slots taken from the pool.
+    pub(crate) fn hits(&self) -> u64 {
+        self.hits.load(Ordering::Relaxed)
+    }
+
+    /// Returns the number of slots allocated.
+    pub(crate) fn misses(&self) -> u64 {
+        self.misses.load(Ordering::Relaxed)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::future::poll_fn;
+
+    use super::*;
+
+    #[tokio::test]
+    async fn sends_the_reply_through_the_slot() {
+        let pool = Pool::new(1);
+        let slot = pool.take();
+        let responder = Responder::new(slot.clone());
+        tokio::spawn(async move { responder.send("pong") });
+        assert_eq!(poll_fn(|cx| slot.poll_reply(cx)).await, Some("pong"));
+
+        // A responder dropped without replying closes the slot.
+        drop(Responder::<&str>::new(slot.clone()));
+        assert_eq!(poll_fn(|cx| slot.poll_reply(cx)).await, None);
+    }
+
+    #[test]
+    fn reuses_the_slots_up_to_the_capacity() {
+        let pool = Pool::<()>::new(1);
+        let (a, b) = (pool.take(), pool.take());
+        pool.put(a.clone());
+        pool.put(b);
+        assert!(Arc::ptr_eq(&pool.take(), &a));
+        assert!(pool.lock().is_empty());
+        assert_eq!((pool.hits(), pool.misses()), (1, 2));
+    }
+}
+
+#[cfg(all(test, loom))]
+mod loom_tests {
+    use std::future::poll_fn;
+
+    use super::*;
+
+    #[test]
+    fn the_reply_is_read_once_sent() {
+        loom::model(|| {
+            let pool = Pool::new(1);
+            let slot = pool.take();
+            let responder = Responder::new(slot.clone());
+            let other = loom::thread::spawn(move || responder.send(1));
+
+            let reply = loom::future::block_on(poll_fn(|cx| slot.poll_reply(cx)));
+            assert_eq!(reply, Some(1));
+            other.join().unwrap();
+
+            // The slot is ready for the next request.
+            pool.put(slot);
+            let slot = pool.take();
+            Responder::new(slot.clone()).send(2);
+            let reply = loom::future::block_on(poll_fn(|cx| slot.poll_reply(cx)));
+            assert_eq!(reply, Some(2));
+        });
+    }
+}
@@ -0,0 +1,320 @@
+//! Calls a service periodically.
+//!
+//! Config refreshers, health probes and cache warmers are naturally written as
+//! services, but need to be called on an interval rather than on incoming requests.
+//! [`Schedule`] drives such a service: it calls it every period, optionally with
+//! some jitter, decides what to do when a call outlives its period, and backs off
+//! while the service fails.
+//!
+//! # Example
+//!
+//! ```rust
+//! use std::{convert::Infallible, ops::ControlFlow, time::Duration};
+//!
+//! use motore::{service::service_fn, utils::schedule::Schedule};
+//!
+//! # #[tokio::main(flavor = "current_thread")]
+//! # async fn main() {
+//! async fn probe(_cx: &mut (), _req: ()) -> Result<bool, Infallible> {
+//!     Ok(true)
+//! }
+//!
+//! let mut healthy = 0;
+//! Schedule::every(Duration::from_millis(10))
+//!     .jitter(0.1)
+//!     .run(&service_fn(probe), || ((), ()), |res| {
+//!         if res == Ok(true) {
+//!             healthy += 1;
+//!         }
+//!         if healthy == 3 {
+//!             ControlFlow::Break(())
+//!         } else {
+//!             ControlFlow::Continue(())
+//!         }
+//!     })
+//!     .await;
+//! # }
+//! ```
+
+use std::{ops::ControlFlow, time::Duration};
+
+use futures::{stream::FuturesUnordered, StreamExt};
+
+use crate::{
+    backoff::{Backoff, Exponential},
+    timer::{DefaultTimer, Instant, Timer},
+    utils::rng::Rng,
+    Service, UnaryService,
+};
+
+/// What to do when a call is still running when the next one is due.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Overlap {
+    /// Skips the ticks missed while the call was running; the next call happens at
+    /// the next tick.
+    #[default]
+    Skip,
+    /// Waits for a full period after the call completes.
+    Delay,
+    /// Starts the next call on time, running it concurrently with the previous ones.
+    Concurrent,
+}
+
+/// A driver calling a service on a fixed period.
+///
+/// The first call is made immediately. See the [module level docs](self) for an
+/// example.
+#[derive(Clone, Debug)]
+pub struct Schedule<T = DefaultTimer> {
+    period: Duration,
+    jitter: f64,
+    overlap: Overlap,
+    backoff: Option<Exponential>,
+    timer: T,
+}
+
+impl Schedule {
+    /// Creates a schedule calling the service every `period`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `period` is zero.
+    pub fn every(period: Duration) -> Self {
+        assert!(period > Duration::ZERO, "the period must not be zero");
+        Schedule {
+            period,
+            jitter: 0.0,
+            overlap: Overlap::default(),
+            backoff: None,
+            timer: DefaultTimer::new(),
+        }
+    }
+}
+
+impl<T> Schedule<T> {
+    /// Delays each call by a random duration up to `ratio` times the period, so
+    /// that many instances started together don't call in lockstep.
+    pub fn jitter(mut self, ratio: f64) -> Self {
+        self.jitter = ratio.clamp(0.0, 1.0);
+        self
+    }
+
+    /// Sets what to do when a call is still running when the next one is due.
+    pub fn overlap(mut self, overlap: Overlap) -> Self {
+        self.overlap = overlap;
+        self
+    }
+
+    /// Waits longer after failed calls: `initial` after the first failure, doubling
+    /// after each consecutive failure up to `max`. A successful call restores the
+    /// regular period.
+    pub fn backoff(mut self, initial: Duration, max: Duration) -> Self {
+        self.backoff = Some(Exponential::new(initial, max));
+        self
+    }
+
+    /// Sets the timer the calls are scheduled with, [`DefaultTimer`] by default.
+    pub fn timer<U>(self, timer: U) -> Schedule<U> {
+        Schedule {
+            period: self.period,
+            jitter: self.jitter,
+            overlap: self.overlap,
+            backoff: self.backoff,
+            timer,
+        }
+    }
+
+    /// Calls `svc` with the context and request returned by `make`, handing every
+    /// result to `on_result`, until it returns [`ControlFlow::Break`].
+    ///
+    /// When calls run concurrently, the calls still in flight when `on_result`
+    /// breaks are dropped.
+    pub async fn run<Cx, Req, S, M, F>(&self, svc: &S, mut make: M, mut on_result: F)
+    where
+        S: Service<Cx, Req>,
+        M: FnMut() -> (Cx, Req),
+        F: FnMut(Result<S::Response, S::Error>) -> ControlFlow<()>,
+        T: Timer,
+    {
+        let call = |(mut cx, req): (Cx, Req)| async move { svc.call(&mut cx, req).await };
+        let sleep_until = |due: Instant| {
+            self.timer
+                .sleep(due.saturating_duration_since(self.timer.now()))
+        };
+        let mut rng = Rng::new();
+        let mut backoff = self.backoff.clone();
+        let mut tick = self.timer.now();
+        let mut due = tick;
+        let mut in_flight = FuturesUnordered::new();
+
+        loop {
+            let res = if self.overlap == Overlap::Concurrent {
+                tokio::select! {
+                    _ = sleep_until(due) => {
+                        in_flight.push(call(make()));
+                        tick += self.period;
+                        due = self.jittered(tick, &mut rng);
+                        continue;
+                    }
+                    Some(res) = in_flight.next() => res,
+                }
+            } else {
+                sleep_until(due).await;
+                let res = call(make()).await;
+                let now = self.timer.now();
+                tick = match self.overlap {
+                    Overlap::Delay => now + self.period,
+                    _ => {
+                        let missed = (now - tick).as_nanos() / self.period.as_nanos();
+                        tick + self.period * (missed as u32 + 1)
+                    }
+                };
+                due = self.jittered(tick, &mut rng);
+                res
+            };
+
+            match (&res, &mut backoff) {
+                (Err(_), Some(backoff)) => {
+                    if let Some(delay) = backoff.next_backoff() {
+                        tick = tick.max(self.timer.now() + delay);
+                        due = self.jittered(tick, &mut rng);
+                    }
+                }
+                (Ok(_), Some(backoff)) => backoff.reset(),
+                _ => {}
+            }
+
+            if on_result(res).is_break() {
+                return;
+            }
+        }
+    }
+
+    /// Calls the unary service `svc` with the request returned by `make`, handing
+    /// every result to `on_result`, until it returns [`ControlFlow::Break`].
+    ///
+    /// See [`run`](Schedule::run) for details.
+    pub async fn run_unary<Req, S, M, F>(&self, svc: &S, mut make: M, on_result: F)
+    where
+        S: UnaryService<Req>,
+        M: FnMut() -> Req,
+        F: FnMut(Result<S::Response, S::Error>) -> ControlFlow<()>,
+        T: Timer,
+    {
+        self.run(&Unary(svc), || ((), make()), on_result).await
+    }
+
+    fn jittered(&self, tick: Instant, rng: &mut Rng) -> Instant {
+        if self.jitter > 0.0 {
+            tick + self.period.mul_f64(self.jitter * rng.next_f64())
+        } else {
+            tick
+        }
+    }
+}
+
+struct Unary<'a, S>(&'a S);
+
+impl<Req, S> Service<(), Req> for Unary<'_, S>
+where
+    S: UnaryService<Req>,
+{
+    type Response = S::Response;
+
+    type Error = S::Error;
+
+    #[cfg(feature = "service_send")]
+    fn call(
+        &self,
+        _cx: &mut (),
+        req: Req,
+    ) -> impl std::future::Future<Output = Result<Self::Response, Self::Error>> + Send {
+        self.0.call(req)
+    }
+    #[cfg(not(feature = "service_send"))]
+    fn call(
+        &self,
+        _cx: &mut (),
+        req: Req,
+    ) -> impl std::future::Future<Output = Result<Self::Response, Self::Error>> {
+        self.0.call(req)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{Arc, Mutex};
+
+    use tokio::time::Instant;
+
+    use super::*;
+    use crate::service::service_fn;
+
+    /// Runs `schedule` for `calls` calls of a service taking `busy` and failing the
+    /// calls listed in `fail`, returning the start time of each call in milliseconds.
+    async fn starts(schedule: Schedule, busy: u64, fail: &[usize], calls: usize) -> Vec<u64> {
+        let origin = Instant::now();
+        let starts = Arc::new(Mutex::new(Vec::new()));
+        let svc = service_fn({
+            let starts = starts.clone();
+            move |_cx: &mut (), _req: ()| {
+                let n = {
+                    let mut starts = starts.lock().unwrap();
+                    starts.push((Instant::now() - origin).as_millis() as u64);
+                    starts.len() - 1
+                };
+                let fail = fail.contains(&n);
+                async move {
+                    tokio::time::sleep(Duration::from_millis(busy)).await;
+                    if fail {
+                        Err(())
+                    } else {
+                        Ok(())
+                    }
+                }
+            }
+        });
+        let mut done = 0;
+        schedule
+            .run(
+                &svc,
+                || ((), ()),
+                |_| {
+                    done += 1;
+                    if done == calls {
+                        ControlFlow::Break(())
+                    } else {
+                        ControlFlow::Continue(())
+                    }
+                },
+            )
+            .await;
+        let starts = starts.lock().unwrap().clone();
+        starts
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn overlap_policies() {
+        let every = Schedule::every(Duration::from_millis(100));
+        assert_eq!(starts(every.clone(), 10, &[], 3).await, [0, 100, 200]);
+        assert_eq!(starts(every.clone(), 150, &[], 3).await, [0, 200, 400]);
+        assert_eq!(
+            starts(every.clone().overlap(Overlap::Delay), 150, &[], 3).await,
+            [0, 250, 500]
+        );
+        assert_eq!(
+            starts(every.overlap(Overlap::Concurrent), 150, &[], 3).await,
+            [0, 100, 200, 300]
+        );
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn backs_off_on_errors() {
+        let schedule = Schedule::every(Duration::from_millis(100))
+            .backoff(Duration::from_millis(300), Duration::from_millis(500));
+        assert_eq!(
+            starts(schedule, 0, &[0, 1, 2], 5).await,
+            [0, 300, 800, 1300, 1400]
+        );
+    }
+}
@@ -0,0 +1,112 @@
+//! A semaphore whose permits are split across shards.
+
+use std::sync::{
+    atomic::{AtomicUsize, Ordering},
+    Arc,
+};
+
+use futures::future::BoxFuture;
+
+use super::sync::{AcquireError, OwnedSemaphorePermit, Semaphore, SemaphorePermit};
+
+thread_local! {
+    /// The shard the current thread takes its permits from first.
+    static HOME_SHARD: usize = {
+        static NEXT: AtomicUsize = AtomicUsize::new(0);
+        NEXT.fetch_add(1, Ordering::Relaxed)
+    };
+}
+
+/// Splits the permits of a semaphore across shards, so that the threads taking
+/// and releasing them mostly update different semaphores.
+///
+/// A thread takes the permits of its own shard first, steals them from the other
+/// shards once it has none left, and otherwise waits for a permit of any shard.
+pub(crate) struct ShardedSemaphore {
+    shards: Box<[Arc<Semaphore>]>,
+}
+
+impl ShardedSemaphore {
+    /// Creates a semaphore with `permits` permits split evenly across `shards`
+    /// shards.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `shards` is zero.
+    pub(crate) fn new(permits: usize, shards: usize) -> Self {
+        assert!(shards > 0, "the number of shards must not be zero");
+        ShardedSemaphore {
+            shards: (0..shards)
+                .map(|i| {
+                    let share = permits / shards + usize::from(i < permits % shards);
+                    Arc::new(Semaphore::new(share))
+                })
+                .collect(),
+        }
+    }
+
+    /// Returns the number of permits available in all the shards.
+    pub(crate) fn available_permits(&self) -> usize {
+        self.shards.iter().map(|s| s.available_permits()).sum()
+    }
+
+    /// Takes a permit, from the shard of the current thread if it has one left.
+    pub(crate) async fn acquire(&self) -> Result<SemaphorePermit<'_>, AcquireError> {
+        let home = HOME_SHARD.with(|home| *home);
+        let stolen = (0..self.shards.len())
+            .map(|i| &self.shards[(home + i) % self.shards.len()])
+            .find_map(|shard| shard.try_acquire().ok());
+        if let Some(permit) = stolen {
+            return Ok(permit);
+        }
+        let acquires = self.shards.iter().map(|shard| Box::pin(shard.acquire()));
+        futures::future::select_all(acquires).await.0
+    }
+
+    /// Returns a future taking a permit from any shard.
+    pub(crate) fn acquire_owned(
+        &self,
+    ) -> BoxFuture<'static, Result<OwnedSemaphorePermit, AcquireError>> {
+        let acquires = futures::future::select_all(
+            self.shards
+                .iter()
+                .map(|shard| Box::pin(shard.clone().acquire_owned())),
+        );
+        Box::pin(async move { acquires.await.0 })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::pin::pin;
+
+    use super::*;
+
+    #[tokio::test]
+    async fn steals_then_waits_for_any_shard() {
+        let semaphore = ShardedSemaphore::new(3, 2);
+        assert_eq!(semaphore.available_permits(), 3);
+
+        // The permits of the other shards are stolen once the home shard has none.
+        let permits = [
+            semaphore.acquire().await.unwrap(),
+            semaphore.acquire().await.unwrap(),
+            semaphore.acquire().await.unwrap(),
+        ];
+        assert_eq!(semaphore.available_permits(), 0);
+
+        let mut waiting = pin!(semaphore.acquire());
+        assert!(futures::poll!(waiting.as_mut()).is_pending());
+        let mut owned = semaphore.acquire_owned();
+        assert!(futures::poll!(&mut owned).is_pending());
+
+        let [first, second, _third] = permits;
+        drop(first);
+        let _fourth = waiting.await.unwrap();
+        drop(second);
+        let owned = owned.await.unwrap();
+        assert_eq!(semaphore.available_permits(), 0);
+        drop(owned);
+        assert_eq!(semaphore.available_permits(), 1);
+    }
+}
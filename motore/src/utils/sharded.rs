@@ -0,0 +1,72 @@
+//! Values spread across shards by the hash of their key, each behind its own lock.
+
+use std::{
+    collections::hash_map::RandomState,
+    hash::{BuildHasher, Hash},
+    num::NonZeroUsize,
+};
+
+use super::sync::{Mutex, MutexGuard};
+
+/// Returns the number of shards of the state shared by all the threads, four per
+/// available thread.
+pub(crate) fn default_shards() -> usize {
+    std::thread::available_parallelism().map_or(1, NonZeroUsize::get) * 4
+}
+
+/// Splits a state touched on every call in shards, so that the concurrent calls
+/// mostly lock different shards instead of all waiting for a single lock.
+pub(crate) struct Sharded<T> {
+    hasher: RandomState,
+    shards: Box<[Mutex<T>]>,
+}
+
+impl<T> Sharded<T> {
+    /// Creates `shards` shards, each with the state returned by `f`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `shards` is zero.
+    pub(crate) fn new(shards: usize, mut f: impl FnMut() -> T) -> Self {
+        assert!(shards > 0, "the number of shards must not be zero");
+        Sharded {
+            hasher: RandomState::new(),
+            shards: (0..shards).map(|_| Mutex::new(f())).collect(),
+        }
+    }
+
+    /// Returns the number of shards.
+    pub(crate) fn len(&self) -> usize {
+        self.shards.len()
+    }
+
+    /// Locks the shard at `index`.
+    pub(crate) fn lock(&self, index: usize) -> MutexGuard<'_, T> {
+        self.shards[index].lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// Locks the shard of `key`.
+    pub(crate) fn shard<K: Hash + ?Sized>(&self, key: &K) -> MutexGuard<'_, T> {
+        self.lock(self.hasher.hash_one(key) as usize % self.shards.len())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashSet;
+
+    use super::*;
+
+    #[test]
+    fn a_key_always_locks_its_shard() {
+        let sharded = Sharded::new(4, HashSet::new);
+        for key in 0..100 {
+            sharded.shard(&key).insert(key);
+        }
+        for key in 0..100 {
+            assert!(sharded.shard(&key).contains(&key));
+        }
+        let len: usize = (0..sharded.len()).map(|i| sharded.lock(i).len()).sum();
+        assert_eq!(len, 100);
+    }
+}
@@ -0,0 +1,31 @@
+//! The synchronization primitives of the state shared between tasks.
+//!
+//! The loom tests swap them for the ones of `loom`, which checks every interleaving
+//! of the threads using them. They are built with `RUSTFLAGS="--cfg loom"`, and
+//! only the tests in the `loom_tests` modules run under that cfg.
+//!
+//! The semaphore and the watch channel of tokio are swapped for models of their
+//! own, built on the `loom` mutex, as loom doesn't see through the atomics of
+//! tokio.
+
+#[cfg(all(test, loom))]
+mod semaphore;
+#[cfg(all(test, loom))]
+pub(crate) mod watch;
+
+#[cfg(all(test, loom))]
+pub(crate) use self::semaphore::{AcquireError, OwnedSemaphorePermit, Semaphore, SemaphorePermit};
+#[cfg(all(test, loom))]
+pub(crate) use loom::sync::{
+    atomic::{AtomicU64, Ordering},
+    Mutex, MutexGuard, RwLock,
+};
+#[cfg(not(all(test, loom)))]
+pub(crate) use std::sync::{
+    atomic::{AtomicU64, Ordering},
+    Mutex, MutexGuard, RwLock,
+};
+#[cfg(not(all(test, loom)))]
+pub(crate) use tokio::sync::{
+    watch, AcquireError, OwnedSemaphorePermit, Semaphore, SemaphorePermit,
+};
@@ -0,0 +1,125 @@
+//! A model of the semaphore of tokio, for the loom tests.
+//!
+//! It only has the methods used by the crate. The tasks waiting for a permit are
+//! all woken whenever one is released, and race for it.
+
+use std::{
+    fmt,
+    future::poll_fn,
+    mem,
+    sync::Arc,
+    task::{Context, Poll, Waker},
+};
+
+use super::{Mutex, MutexGuard};
+
+/// The error of taking a permit from a closed semaphore, which the model never
+/// returns.
+#[derive(Debug)]
+pub struct AcquireError(());
+
+impl fmt::Display for AcquireError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("semaphore closed")
+    }
+}
+
+impl std::error::Error for AcquireError {}
+
+/// The error of trying to take a permit when none is available.
+#[derive(Debug)]
+pub struct TryAcquireError(());
+
+struct State {
+    permits: usize,
+    waiters: Vec<Waker>,
+}
+
+pub struct Semaphore(Mutex<State>);
+
+impl Semaphore {
+    pub fn new(permits: usize) -> Self {
+        Semaphore(Mutex::new(State {
+            permits,
+            waiters: Vec::new(),
+        }))
+    }
+
+    fn lock(&self) -> MutexGuard<'_, State> {
+        self.0.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    pub fn available_permits(&self) -> usize {
+        self.lock().permits
+    }
+
+    fn take(&self) -> bool {
+        let mut state = self.lock();
+        let available = state.permits > 0;
+        if available {
+            state.permits -= 1;
+        }
+        available
+    }
+
+    fn poll_take(&self, cx: &mut Context<'_>) -> Poll<()> {
+        let mut state = self.lock();
+        if state.permits > 0 {
+            state.permits -= 1;
+            return Poll::Ready(());
+        }
+        state.waiters.push(cx.waker().clone());
+        Poll::Pending
+    }
+
+    fn release(&self) {
+        let waiters = {
+            let mut state = self.lock();
+            state.permits += 1;
+            mem::take(&mut state.waiters)
+        };
+        waiters.into_iter().for_each(Waker::wake);
+    }
+
+    pub fn try_acquire(&self) -> Result<SemaphorePermit<'_>, TryAcquireError> {
+        if self.take() {
+            Ok(SemaphorePermit(self))
+        } else {
+            Err(TryAcquireError(()))
+        }
+    }
+
+    pub async fn acquire(&self) -> Result<SemaphorePermit<'_>, AcquireError> {
+        poll_fn(|cx| self.poll_take(cx)).await;
+        Ok(SemaphorePermit(self))
+    }
+
+    pub async fn acquire_owned(self: Arc<Self>) -> Result<OwnedSemaphorePermit, AcquireError> {
+        poll_fn(|cx| self.poll_take(cx)).await;
+        Ok(OwnedSemaphorePermit(self))
+    }
+}
+
+impl fmt::Debug for Semaphore {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Semaphore")
+            .field("permits", &self.available_permits())
+            .finish()
+    }
+}
+
+pub struct SemaphorePermit<'a>(&'a Semaphore);
+
+impl Drop for SemaphorePermit<'_> {
+    fn drop(&mut self) {
+        self.0.release();
+    }
+}
+
+pub struct OwnedSemaphorePermit(Arc<Semaphore>);
+
+impl Drop for OwnedSemaphorePermit {
+    fn drop(&mut self) {
+        self.0.release();
+    }
+}
@@ -0,0 +1,108 @@
+//! A model of the watch channel of tokio, for the loom tests.
+//!
+//! It only has the methods used by the crate. The receivers waiting for a value
+//! are all woken whenever one is sent, or once the sender is dropped.
+
+use std::{
+    future::poll_fn,
+    mem,
+    ops::Deref,
+    sync::Arc,
+    task::{Poll, Waker},
+};
+
+use super::{Mutex, MutexGuard};
+
+pub mod error {
+    /// The error of waiting for a value once the sender was dropped.
+    #[derive(Debug)]
+    pub struct RecvError(pub(super) ());
+}
+
+struct State<T> {
+    value: T,
+    closed: bool,
+    waiters: Vec<Waker>,
+}
+
+type Shared<T> = Arc<Mutex<State<T>>>;
+
+fn lock<T>(shared: &Shared<T>) -> MutexGuard<'_, State<T>> {
+    shared.lock().unwrap_or_else(|e| e.into_inner())
+}
+
+fn wake<T>(mut state: MutexGuard<'_, State<T>>) {
+    let waiters = mem::take(&mut state.waiters);
+    drop(state);
+    waiters.into_iter().for_each(Waker::wake);
+}
+
+pub fn channel<T>(init: T) -> (Sender<T>, Receiver<T>) {
+    let shared = Arc::new(Mutex::new(State {
+        value: init,
+        closed: false,
+        waiters: Vec::new(),
+    }));
+    (Sender(shared.clone()), Receiver(shared))
+}
+
+pub struct Sender<T>(Shared<T>);
+
+impl<T> Sender<T> {
+    pub fn send_replace(&self, value: T) -> T {
+        let mut state = lock(&self.0);
+        let old = mem::replace(&mut state.value, value);
+        wake(state);
+        old
+    }
+}
+
+impl<T> Drop for Sender<T> {
+    fn drop(&mut self) {
+        let mut state = lock(&self.0);
+        state.closed = true;
+        wake(state);
+    }
+}
+
+pub struct Receiver<T>(Shared<T>);
+
+impl<T> Clone for Receiver<T> {
+    fn clone(&self) -> Self {
+        Receiver(self.0.clone())
+    }
+}
+
+impl<T> Receiver<T> {
+    /// Waits for a value matching `f`, checking the current one first, even once
+    /// the sender was dropped.
+    pub async fn wait_for(
+        &mut self,
+        mut f: impl FnMut(&T) -> bool,
+    ) -> Result<Ref<'_, T>, error::RecvError> {
+        let shared = &self.0;
+        poll_fn(|cx| {
+            let mut state = lock(shared);
+            if f(&state.value) {
+                Poll::Ready(Ok(Ref(state)))
+            } else if state.closed {
+                Poll::Ready(Err(error::RecvError(())))
+            } else {
+                state.waiters.push(cx.waker().clone());
+                Poll::Pending
+            }
+        })
+        .await
+    }
+}
+
+/// The value of a channel, locked while it is borrowed.
+pub struct Ref<'a, T>(MutexGuard<'a, State<T>>);
+
+impl<T> Deref for Ref<'_, T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        &self.0.value
+    }
+}
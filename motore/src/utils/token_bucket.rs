@@ -0,0 +1,146 @@
+//! A token bucket refilled lazily, with a compare-and-swap per token taken.
+
+use std::{sync::OnceLock, time::Duration};
+
+use super::sync::{AtomicU64, Ordering};
+use crate::timer::Instant;
+
+/// A bucket of `num` tokens, refilled at the rate of `num` tokens per `per`.
+///
+/// Rather than counting the tokens and refilling them, the bucket only stores the
+/// time at which it will be full again, in nanoseconds since the first request.
+/// Taking a token pushes that time back by the refill interval of a token, and is
+/// allowed as long as it stays within `per` of now. This is the same as a bucket
+/// refilled continuously, and takes a single compare-and-swap, with no background
+/// task refilling it and no allocation.
+#[derive(Debug)]
+pub(crate) struct TokenBucket {
+    pub(crate) num: u64,
+    pub(crate) per: Duration,
+    /// The time of the first request, which the bucket time is relative to.
+    origin: OnceLock<Instant>,
+    /// When the bucket will be full again.
+    full_at: AtomicU64,
+}
+
+impl TokenBucket {
+    pub(crate) fn new(num: u64, per: Duration) -> Self {
+        TokenBucket {
+            num,
+            per,
+            origin: OnceLock::new(),
+            full_at: AtomicU64::new(0),
+        }
+    }
+
+    /// The time it takes to refill a token, in nanoseconds.
+    fn interval(&self) -> u64 {
+        (self.per.as_nanos() / u128::from(self.num)).max(1) as u64
+    }
+
+    /// Returns `now` in nanoseconds since the first request.
+    fn elapsed(&self, now: Instant) -> u64 {
+        let origin = *self.origin.get_or_init(|| now);
+        now.saturating_duration_since(origin).as_nanos() as u64
+    }
+
+    /// Takes a token, or returns how long until the next one is available.
+    pub(crate) fn acquire(&self, now: Instant) -> Result<(), Duration> {
+        let now = self.elapsed(now);
+        let interval = self.interval();
+        // Taking a token is allowed while all the others are not taken yet.
+        let burst = interval * (self.num - 1);
+        let mut full_at = self.full_at.load(Ordering::Relaxed);
+        loop {
+            let from = full_at.max(now);
+            if from - now > burst {
+                return Err(Duration::from_nanos(from - now - burst));
+            }
+            match self.full_at.compare_exchange_weak(
+                full_at,
+                from + interval,
+                Ordering::Relaxed,
+                Ordering::Relaxed,
+            ) {
+                Ok(_) => return Ok(()),
+                Err(actual) => full_at = actual,
+            }
+        }
+    }
+
+    /// Returns whether the bucket is full at `now`, and thus the same as a new one.
+    pub(crate) fn is_full(&self, now: Instant) -> bool {
+        match self.origin.get() {
+            Some(&origin) => {
+                let now = now.saturating_duration_since(origin).as_nanos() as u64;
+                self.full_at.load(Ordering::Relaxed) <= now
+            }
+            None => true,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use super::*;
+
+    #[test]
+    fn refills_the_tokens_over_time() {
+        let bucket = TokenBucket::new(2, Duration::from_secs(1));
+        let now = Instant::now();
+        assert!(bucket.is_full(now));
+        assert_eq!(bucket.acquire(now), Ok(()));
+        assert_eq!(bucket.acquire(now), Ok(()));
+        assert_eq!(bucket.acquire(now), Err(Duration::from_millis(500)));
+
+        let later = now + Duration::from_millis(500);
+        assert_eq!(bucket.acquire(later), Ok(()));
+        assert_eq!(bucket.acquire(later), Err(Duration::from_millis(500)));
+        assert!(bucket.is_full(later + Duration::from_secs(1)));
+    }
+
+    #[test]
+    fn concurrent_requests_share_the_tokens() {
+        let bucket = Arc::new(TokenBucket::new(100, Duration::from_secs(1)));
+        let now = Instant::now();
+        let threads: Vec<_> = (0..8)
+            .map(|_| {
+                let bucket = bucket.clone();
+                std::thread::spawn(move || (0..50).filter(|_| bucket.acquire(now).is_ok()).count())
+            })
+            .collect();
+        let acquired: usize = threads.into_iter().map(|t| t.join().unwrap()).sum();
+        assert_eq!(acquired, 100);
+        assert!(!bucket.is_full(now));
+        assert!(bucket.is_full(now + Duration::from_secs(1)));
+    }
+}
+
+#[cfg(all(test, loom))]
+mod loom_tests {
+    use std::sync::Arc;
+
+    use super::*;
+
+    #[test]
+    fn hands_out_each_token_once() {
+        loom::model(|| {
+            let bucket = Arc::new(TokenBucket::new(2, Duration::from_secs(1)));
+            let now = Instant::now();
+
+            let threads: Vec<_> = (0..2)
+                .map(|_| {
+                    let bucket = bucket.clone();
+                    loom::thread::spawn(move || bucket.acquire(now).is_ok())
+                })
+                .collect();
+            let mut taken = usize::from(bucket.acquire(now).is_ok());
+            for thread in threads {
+                taken += usize::from(thread.join().unwrap());
+            }
+            assert_eq!(taken, 2);
+        });
+    }
+}
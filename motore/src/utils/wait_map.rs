@@ -0,0 +1,181 @@
+//! The calls in flight by key, and the tasks waiting for their result.
+
+use std::{collections::HashMap, hash::Hash};
+
+use super::{
+    sharded::{default_shards, Sharded},
+    sync::watch,
+};
+
+type Calls<K, V> = HashMap<K, watch::Receiver<Option<V>>>;
+
+/// The calls in flight, sharded by the hash of their key.
+///
+/// The first task entering a key makes the call, and the tasks entering it while
+/// the call is in flight wait for its result. The map is spread across
+/// [`Sharded`] shards, each locked only for the lookup, so the tasks entering
+/// different keys rarely contend. A waiting task doesn't allocate: it waits on
+/// the channel of the call, whose waiters are kept in an intrusive list. Only the
+/// task making a call allocates that channel.
+pub(crate) struct WaitMap<K, V> {
+    calls: Sharded<Calls<K, V>>,
+}
+
+/// What a task entering a key of a [`WaitMap`] does.
+pub(crate) enum Entry<'a, K: Hash + Eq, V> {
+    /// Another task makes the call.
+    Wait(Waiter<V>),
+    /// The task makes the call, and hands its result to the waiting ones.
+    Lead(Flight<'a, K, V>),
+}
+
+impl<K, V> WaitMap<K, V> {
+    pub(crate) fn new() -> Self {
+        WaitMap {
+            calls: Sharded::new(default_shards(), HashMap::new),
+        }
+    }
+
+    /// Creates a map spread across `shards` shards, a single one being enough for
+    /// the calls of a few keys.
+    pub(crate) fn with_shards(shards: usize) -> Self {
+        WaitMap {
+            calls: Sharded::new(shards, HashMap::new),
+        }
+    }
+}
+
+impl<K: Hash + Eq + Clone, V> WaitMap<K, V> {
+    /// Joins the call in flight for `key`, or makes it if there is none.
+    pub(crate) fn enter<'a>(&'a self, key: &'a K) -> Entry<'a, K, V> {
+        let mut calls = self.calls.shard(key);
+        match calls.get(key) {
+            Some(rx) => Entry::Wait(Waiter(rx.clone())),
+            None => {
+                let (tx, rx) = watch::channel(None);
+                calls.insert(key.clone(), rx);
+                Entry::Lead(Flight { map: self, key, tx })
+            }
+        }
+    }
+}
+
+/// Waits for the result of a call made by another task.
+pub(crate) struct Waiter<V>(watch::Receiver<Option<V>>);
+
+impl<V: Clone> Waiter<V> {
+    /// Returns the result of the call, or `None` if it was cancelled, in which case
+    /// the key should be entered again.
+    pub(crate) async fn wait(mut self) -> Option<V> {
+        let value = self.0.wait_for(Option::is_some).await.ok()?;
+        value.clone()
+    }
+}
+
+/// The call of a key, removed from the calls in flight once it completed or was
+/// cancelled.
+pub(crate) struct Flight<'a, K: Hash + Eq, V> {
+    map: &'a WaitMap<K, V>,
+    key: &'a K,
+    tx: watch::Sender<Option<V>>,
+}
+
+impl<K: Hash + Eq, V> Flight<'_, K, V> {
+    /// Hands the result of the call to the waiting tasks.
+    pub(crate) fn complete(self, value: V) {
+        self.tx.send_replace(Some(value));
+    }
+}
+
+impl<K: Hash + Eq, V> Drop for Flight<'_, K, V> {
+    fn drop(&mut self) {
+        // Removed before `tx` is dropped, so the tasks entering the key again after
+        // a cancellation don't find this call anymore.
+        self.map.calls.shard(self.key).remove(self.key);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn waiters_get_the_result_of_the_call() {
+        let map = WaitMap::new();
+        let Entry::Lead(flight) = map.enter(&1) else {
+            panic!("no call in flight");
+        };
+        let Entry::Wait(waiter) = map.enter(&1) else {
+            panic!("the call is in flight");
+        };
+        assert!(matches!(map.enter(&2), Entry::Lead(_)));
+
+        flight.complete("one");
+        assert_eq!(waiter.wait().await, Some("one"));
+        assert!(matches!(map.enter(&1), Entry::Lead(_)));
+        assert!((0..map.calls.len()).all(|i| map.calls.lock(i).is_empty()));
+    }
+
+    #[tokio::test]
+    async fn cancelled_calls_are_made_again() {
+        let map = WaitMap::<_, ()>::new();
+        let flight = map.enter(&1);
+        let Entry::Wait(waiter) = map.enter(&1) else {
+            panic!("the call is in flight");
+        };
+        drop(flight);
+        assert_eq!(waiter.wait().await, None);
+        assert!(matches!(map.enter(&1), Entry::Lead(_)));
+    }
+}
+
+#[cfg(all(test, loom))]
+mod loom_tests {
+    use std::sync::Arc;
+
+    use loom::sync::atomic::{AtomicUsize, Ordering};
+
+    use super::*;
+
+    /// Returns the result of the call for the key, and whether this task made it.
+    fn call(map: &WaitMap<(), usize>, calls: &AtomicUsize) -> (usize, bool) {
+        loop {
+            match map.enter(&()) {
+                Entry::Wait(waiter) => {
+                    if let Some(n) = loom::future::block_on(waiter.wait()) {
+                        return (n, false);
+                    }
+                }
+                Entry::Lead(flight) => {
+                    let n = calls.fetch_add(1, Ordering::SeqCst) + 1;
+                    loom::thread::yield_now();
+                    flight.complete(n);
+                    return (n, true);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn concurrent_tasks_share_or_repeat_the_call() {
+        loom::model(|| {
+            let map = Arc::new(WaitMap::new());
+            let calls = Arc::new(AtomicUsize::new(0));
+
+            let other = loom::thread::spawn({
+                let (map, calls) = (map.clone(), calls.clone());
+                move || call(&map, &calls)
+            });
+            let mine = call(&map, &calls);
+            let theirs = other.join().unwrap();
+
+            // Either both tasks got the result of a single call, or each made its
+            // own, one after the other.
+            match calls.load(Ordering::SeqCst) {
+                1 => assert_eq!((mine.0, theirs.0), (1, 1)),
+                2 => assert!(mine.1 && theirs.1),
+                n => panic!("{n} calls for two tasks"),
+            }
+        });
+    }
+}
@@ -0,0 +1,75 @@
+//! The tasks waiting for a shared resource.
+
+use std::task::Waker;
+
+use super::sync::Mutex;
+
+/// The wakers of the tasks waiting for a resource shared by several services or
+/// clones, like a semaphore or the endpoints of a balancer.
+///
+/// The resource is polled with a waker calling [`wake_all`](Waiters::wake_all),
+/// so none of the tasks misses a wakeup meant for another, whichever task polled
+/// the resource last.
+#[derive(Debug, Default)]
+pub(crate) struct Waiters(Mutex<Vec<Waker>>);
+
+impl Waiters {
+    /// Registers `waker` to be woken by the next wakeup.
+    pub(crate) fn register(&self, waker: &Waker) {
+        let mut wakers = self.0.lock().unwrap_or_else(|e| e.into_inner());
+        if !wakers.iter().any(|w| w.will_wake(waker)) {
+            wakers.push(waker.clone());
+        }
+    }
+
+    /// Wakes all the registered tasks.
+    pub(crate) fn wake_all(&self) {
+        let wakers = std::mem::take(&mut *self.0.lock().unwrap_or_else(|e| e.into_inner()));
+        wakers.into_iter().for_each(Waker::wake);
+    }
+}
+
+#[cfg(all(test, loom))]
+mod loom_tests {
+    use std::{sync::Arc, task::Poll};
+
+    use loom::sync::atomic::{AtomicBool, Ordering};
+
+    use super::*;
+
+    /// Waits for `released`, registering in `waiters` before checking it.
+    fn wait(waiters: &Waiters, released: &AtomicBool) {
+        loom::future::block_on(futures::future::poll_fn(|cx| {
+            waiters.register(cx.waker());
+            if released.load(Ordering::SeqCst) {
+                Poll::Ready(())
+            } else {
+                Poll::Pending
+            }
+        }));
+    }
+
+    #[test]
+    fn every_waiter_is_woken() {
+        loom::model(|| {
+            let waiters = Arc::new(Waiters::default());
+            let released = Arc::new(AtomicBool::new(false));
+
+            let other = loom::thread::spawn({
+                let (waiters, released) = (waiters.clone(), released.clone());
+                move || wait(&waiters, &released)
+            });
+            let release = loom::thread::spawn({
+                let (waiters, released) = (waiters.clone(), released.clone());
+                move || {
+                    released.store(true, Ordering::SeqCst);
+                    waiters.wake_all();
+                }
+            });
+
+            wait(&waiters, &released);
+            other.join().unwrap();
+            release.join().unwrap();
+        });
+    }
+}
@@ -0,0 +1,345 @@
+//! Validates and normalizes requests before they reach a service.
+//!
+//! A [`Validator`] takes the request by value, so it can also sanitize it, for
+//! example trimming strings or filling defaults, before passing it on. Requests can
+//! carry their own validation logic by implementing [`Validatable`], and closures
+//! can be used as validators as well.
+//!
+//! Invalid requests never reach the inner service, and fail with
+//! [`ValidateError::Invalid`] carrying a [`ValidationError`] with per-field details.
+
+use std::{
+    borrow::Cow,
+    error::Error,
+    fmt,
+    task::{Context, Poll},
+};
+
+use crate::{
+    layer::Layer,
+    load::{Load, Ready},
+    service::Service,
+    MaybeSend, MaybeSync,
+};
+
+/// The reason a field of a request is invalid.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct FieldError {
+    /// The name of the field, or a path to it.
+    pub field: Cow<'static, str>,
+    /// Why the field is invalid.
+    pub message: Cow<'static, str>,
+}
+
+/// The details of why a request is invalid.
+///
+/// # Example
+///
+/// ```rust
+/// use motore::validate::ValidationError;
+///
+/// let name = "";
+/// let age = 200;
+///
+/// let mut errors = ValidationError::new();
+/// if name.is_empty() {
+///     errors.add("name", "must not be empty");
+/// }
+/// if age > 150 {
+///     errors.add("age", "out of range");
+/// }
+/// assert_eq!(errors.fields().len(), 2);
+/// assert!(errors.into_result().is_err());
+/// ```
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct ValidationError {
+    fields: Vec<FieldError>,
+}
+
+impl ValidationError {
+    /// Creates an empty `ValidationError`.
+    pub const fn new() -> Self {
+        ValidationError { fields: Vec::new() }
+    }
+
+    /// Creates a `ValidationError` for a single field.
+    pub fn field(
+        field: impl Into<Cow<'static, str>>,
+        message: impl Into<Cow<'static, str>>,
+    ) -> Self {
+        let mut errors = Self::new();
+        errors.add(field, message);
+        errors
+    }
+
+    /// Records that a field is invalid.
+    pub fn add(
+        &mut self,
+        field: impl Into<Cow<'static, str>>,
+        message: impl Into<Cow<'static, str>>,
+    ) {
+        self.fields.push(FieldError {
+            field: field.into(),
+            message: message.into(),
+        });
+    }
+
+    /// Returns the invalid fields, in the order they were recorded.
+    pub fn fields(&self) -> &[FieldError] {
+        &self.fields
+    }
+
+    /// Returns whether no field has been recorded as invalid.
+    pub fn is_empty(&self) -> bool {
+        self.fields.is_empty()
+    }
+
+    /// Returns `Ok` if no field has been recorded as invalid, or `self` otherwise.
+    pub fn into_result(self) -> Result<(), Self> {
+        if self.is_empty() {
+            Ok(())
+        } else {
+            Err(self)
+        }
+    }
+}
+
+impl fmt::Display for ValidationError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("invalid request")?;
+        for (i, e) in self.fields.iter().enumerate() {
+            let sep = if i == 0 { ": " } else { ", " };
+            write!(f, "{sep}{} {}", e.field, e.message)?;
+        }
+        Ok(())
+    }
+}
+
+impl Error for ValidationError {}
+
+/// A request that knows how to validate itself.
+pub trait Validatable: Sized {
+    /// Validates the request, returning it, possibly normalized, if it is valid.
+    fn validate(self) -> Result<Self, ValidationError>;
+}
+
+/// Validates, and possibly normalizes, requests.
+///
+/// This is implemented for closures taking the request and returning it back.
+pub trait Validator<Req> {
+    /// Validates the request, returning it, possibly normalized, if it is valid.
+    fn validate(&self, req: Req) -> Result<Req, ValidationError>;
+}
+
+impl<F, Req> Validator<Req> for F
+where
+    F: Fn(Req) -> Result<Req, ValidationError>,
+{
+    fn validate(&self, req: Req) -> Result<Req, ValidationError> {
+        self(req)
+    }
+}
+
+/// A [`Validator`] using the request's own [`Validatable`] implementation.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Inherent;
+
+impl<Req: Validatable> Validator<Req> for Inherent {
+    fn validate(&self, req: Req) -> Result<Req, ValidationError> {
+        req.validate()
+    }
+}
+
+/// The error returned by [`Validate`].
+#[derive(Debug, PartialEq, Eq)]
+pub enum ValidateError<E> {
+    /// The request is invalid.
+    Invalid(ValidationError),
+    /// The inner service failed.
+    Service(E),
+}
+
+impl<E: fmt::Display> fmt::Display for ValidateError<E> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ValidateError::Invalid(e) => fmt::Display::fmt(e, f),
+            ValidateError::Service(e) => fmt::Display::fmt(e, f),
+        }
+    }
+}
+
+impl<E: Error + 'static> Error for ValidateError<E> {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            ValidateError::Invalid(e) => Some(e),
+            ValidateError::Service(e) => Some(e),
+        }
+    }
+}
+
+/// Validates every request with a [`Validator`] before calling the inner service.
+#[derive(Clone)]
+pub struct Validate<S, V> {
+    inner: S,
+    validator: V,
+}
+
+impl<S, V> Validate<S, V> {
+    pub const fn new(inner: S, validator: V) -> Self {
+        Self { inner, validator }
+    }
+}
+
+impl<Cx, Req, S, V> Service<Cx, Req> for Validate<S, V>
+where
+    Req: 'static + MaybeSend,
+    S: Service<Cx, Req> + 'static + MaybeSend + MaybeSync,
+    V: Validator<Req> + MaybeSync,
+    Cx: 'static + MaybeSend,
+{
+    type Response = S::Response;
+
+    type Error = ValidateError<S::Error>;
+
+    async fn call(&self, cx: &mut Cx, req: Req) -> Result<Self::Response, Self::Error> {
+        let req = self
+            .validator
+            .validate(req)
+            .map_err(ValidateError::Invalid)?;
+        self.inner
+            .call(cx, req)
+            .await
+            .map_err(ValidateError::Service)
+    }
+}
+
+impl<S, V> Ready for Validate<S, V>
+where
+    S: Ready,
+{
+    fn poll_ready(&self, cx: &mut Context<'_>) -> Poll<()> {
+        self.inner.poll_ready(cx)
+    }
+}
+
+impl<S, V> Load for Validate<S, V>
+where
+    S: Load,
+{
+    type Metric = S::Metric;
+
+    fn load(&self) -> Self::Metric {
+        self.inner.load()
+    }
+}
+
+/// Applies a [`Validate`] middleware with the given validator.
+#[derive(Clone)]
+pub struct ValidateLayer<V> {
+    validator: V,
+}
+
+impl<V> ValidateLayer<V> {
+    pub const fn new(validator: V) -> Self {
+        ValidateLayer { validator }
+    }
+}
+
+impl ValidateLayer<Inherent> {
+    /// Validates requests with their own [`Validatable`] implementation.
+    pub const fn inherent() -> Self {
+        ValidateLayer {
+            validator: Inherent,
+        }
+    }
+}
+
+impl<S, V> Layer<S> for ValidateLayer<V> {
+    type Service = Validate<S, V>;
+
+    fn layer(self, inner: S) -> Self::Service {
+        Validate {
+            inner,
+            validator: self.validator,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::convert::Infallible;
+
+    use super::*;
+    use crate::service::service_fn;
+
+    #[derive(Debug, PartialEq)]
+    struct SignUp {
+        name: String,
+        age: u32,
+    }
+
+    impl Validatable for SignUp {
+        fn validate(mut self) -> Result<Self, ValidationError> {
+            self.name = self.name.trim().to_string();
+            let mut errors = ValidationError::new();
+            if self.name.is_empty() {
+                errors.add("name", "must not be empty");
+            }
+            if self.age < 18 {
+                errors.add("age", "must be at least 18");
+            }
+            errors.into_result().map(|_| self)
+        }
+    }
+
+    async fn accept(_cx: &mut (), req: SignUp) -> Result<String, &'static str> {
+        Ok(req.name)
+    }
+
+    #[tokio::test]
+    async fn normalizes_valid_requests() {
+        let svc = ValidateLayer::inherent().layer(service_fn(accept));
+        let req = SignUp {
+            name: "  motore ".to_string(),
+            age: 20,
+        };
+        assert_eq!(svc.call(&mut (), req).await, Ok("motore".to_string()));
+    }
+
+    #[tokio::test]
+    async fn reports_invalid_fields() {
+        let svc = ValidateLayer::inherent().layer(service_fn(accept));
+        let req = SignUp {
+            name: " ".to_string(),
+            age: 3,
+        };
+        let ValidateError::Invalid(err) = svc.call(&mut (), req).await.unwrap_err() else {
+            panic!("expected a validation error");
+        };
+        assert_eq!(
+            err.to_string(),
+            "invalid request: name must not be empty, age must be at least 18"
+        );
+        assert_eq!(err.fields()[1].field, "age");
+    }
+
+    #[tokio::test]
+    async fn closures_are_validators() {
+        let svc = ValidateLayer::new(|n: u32| {
+            if n == 0 {
+                Err(ValidationError::field("n", "must not be zero"))
+            } else {
+                Ok(n)
+            }
+        })
+        .layer(service_fn(|_cx: &mut (), n: u32| async move {
+            Ok::<_, Infallible>(100 / n)
+        }));
+
+        assert_eq!(svc.call(&mut (), 5).await, Ok(20));
+        assert!(matches!(
+            svc.call(&mut (), 0).await,
+            Err(ValidateError::Invalid(_))
+        ));
+    }
+}